@@ -0,0 +1,222 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::days;
+use crate::events::{parse_event, EventVerb};
+use crate::models::DayMetric;
+use crate::projections::{DailyRow, SessionProjector};
+
+/// Days in a row GAME may outweigh THEORY + PRACTICE before alerting
+pub const DEFAULT_GAME_STREAK_DAYS: usize = 3;
+
+/// Logged minutes in one day before alerting
+pub const DEFAULT_DAILY_CAP_MINUTES: f64 = 600.0;
+
+/// Thresholds for the built-in rules
+/// `ALERT-RULE GAME_STREAK <days>` and `ALERT-RULE DAILY_CAP <minutes>`
+/// lines in the log override these, the latest line winning
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlertRules {
+    pub game_streak_days: usize,
+    pub daily_cap_minutes: f64,
+}
+
+impl Default for AlertRules {
+    fn default() -> Self {
+        Self {
+            game_streak_days: DEFAULT_GAME_STREAK_DAYS,
+            daily_cap_minutes: DEFAULT_DAILY_CAP_MINUTES,
+        }
+    }
+}
+
+/// A positive number of days, as `ALERT-RULE GAME_STREAK` and the
+/// `alert_game_streak_days` setting take it
+pub fn parse_days(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|days| *days > 0)
+}
+
+/// Where a rule is
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Ok,
+    /// Not enough timed history to judge; never fires
+    InsufficientData,
+}
+
+/// One rule's verdict and the numbers behind it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RuleEvaluation {
+    /// `game_streak` or `daily_cap`
+    pub rule: String,
+    pub state: AlertState,
+    /// Days for `game_streak`, minutes for `daily_cap`
+    pub threshold: f64,
+    /// Index of the `ALERT-RULE` line that set the threshold, null when
+    /// it comes from the server config
+    pub set_by_event: Option<usize>,
+    pub message: String,
+    /// Daily minutes per category the rule looked at, oldest first
+    pub days: Vec<DailyRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AlertReport {
+    /// Every rule, firing or not
+    pub rules: Vec<RuleEvaluation>,
+    /// Names of the firing rules
+    pub firing: Vec<String>,
+}
+
+/// Rules with thresholds from `ALERT-RULE` lines applied over `configured`,
+/// plus the index of the line that set each one
+fn rules_from_log(lines: &[String], configured: AlertRules) -> (AlertRules, Option<usize>, Option<usize>) {
+    let mut rules = configured;
+    let (mut streak_idx, mut cap_idx) = (None, None);
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(event) = parse_event(line) else { continue };
+        if event.verb != EventVerb::AlertRule {
+            continue;
+        }
+        let Some(value) = event.activity.as_deref() else { continue };
+        match event.category.as_deref() {
+            Some("GAME_STREAK") => {
+                if let Some(days) = parse_days(value) {
+                    rules.game_streak_days = days;
+                    streak_idx = Some(idx);
+                }
+            }
+            Some("DAILY_CAP") => {
+                if let Ok(minutes) = days::parse_minutes(value) {
+                    rules.daily_cap_minutes = minutes;
+                    cap_idx = Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    (rules, streak_idx, cap_idx)
+}
+
+/// Evaluate the built-in rules on daily minutes up to and including `today`
+/// - `game_streak`: GAME minutes above THEORY + PRACTICE minutes on each
+///   of the last `game_streak_days` days
+/// - `daily_cap`: today's minutes above `daily_cap_minutes`
+pub fn evaluate(projector: &SessionProjector, lines: &[String], configured: AlertRules, today: NaiveDate) -> AlertReport {
+    let (rules, streak_idx, cap_idx) = rules_from_log(lines, configured);
+    // From the first day with a timed session, zeros filled in
+    let rows = projector.recent_daily_rows(DayMetric::Minutes, Some(today));
+    let minutes = |row: &DailyRow, category: &str| row.categories.get(category).copied().unwrap_or(0.0);
+
+    let n = rules.game_streak_days;
+    let streak = if rows.len() < n {
+        RuleEvaluation {
+            rule: "game_streak".to_string(),
+            state: AlertState::InsufficientData,
+            threshold: n as f64,
+            set_by_event: streak_idx,
+            message: format!("{} day(s) of timed sessions, {} needed", rows.len(), n),
+            days: rows.clone(),
+        }
+    } else {
+        let recent = &rows[rows.len() - n..];
+        let firing = recent
+            .iter()
+            .all(|row| minutes(row, "GAME") > minutes(row, "THEORY") + minutes(row, "PRACTICE"));
+        RuleEvaluation {
+            rule: "game_streak".to_string(),
+            state: if firing { AlertState::Firing } else { AlertState::Ok },
+            threshold: n as f64,
+            set_by_event: streak_idx,
+            message: match firing {
+                true => format!("GAME outweighed THEORY + PRACTICE on each of the last {} days", n),
+                false => format!("THEORY + PRACTICE kept up with GAME on at least one of the last {} days", n),
+            },
+            days: recent.to_vec(),
+        }
+    };
+
+    let cap = rules.daily_cap_minutes;
+    let cap = match rows.last() {
+        None => RuleEvaluation {
+            rule: "daily_cap".to_string(),
+            state: AlertState::InsufficientData,
+            threshold: cap,
+            set_by_event: cap_idx,
+            message: "No timed sessions".to_string(),
+            days: Vec::new(),
+        },
+        Some(row) => RuleEvaluation {
+            rule: "daily_cap".to_string(),
+            state: if row.total > cap { AlertState::Firing } else { AlertState::Ok },
+            threshold: cap,
+            set_by_event: cap_idx,
+            message: format!("{:.0} of {:.0} minutes logged on {}", row.total, cap, row.date),
+            days: vec![row.clone()],
+        },
+    };
+
+    let rules = vec![streak, cap];
+    AlertReport {
+        firing: rules
+            .iter()
+            .filter(|r| r.state == AlertState::Firing)
+            .map(|r| r.rule.clone())
+            .collect(),
+        rules,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn report(lines: &[&str], today: &str) -> AlertReport {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        for line in lines {
+            writeln!(temp_file, "{}", line).unwrap();
+        }
+        let projector = SessionProjector::new(temp_file.path());
+        let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        evaluate(&projector, &lines, AlertRules::default(), today.parse().unwrap())
+    }
+
+    #[test]
+    fn test_game_streak_and_daily_cap() {
+        let mut lines = Vec::new();
+        for day in 1..=3 {
+            lines.push(format!("2024-01-0{}T09:00:00Z START THEORY pandas", day));
+            lines.push(format!("2024-01-0{}T09:30:00Z START GAME chess", day));
+            lines.push(format!("2024-01-0{}T11:00:00Z STOP GAME chess", day));
+        }
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+        let firing = report(&lines, "2024-01-03");
+        assert_eq!(firing.firing, vec!["game_streak"]);
+        assert_eq!(firing.rules[0].days.len(), 3);
+        assert_eq!(firing.rules[0].days[2].categories["GAME"], 90.0);
+        assert_eq!((firing.rules[1].state, firing.rules[1].days[0].total), (AlertState::Ok, 120.0));
+
+        // Nothing logged today breaks the run
+        assert!(report(&lines, "2024-01-04").firing.is_empty());
+
+        // Thresholds from the log, latest line winning
+        let mut overridden = lines.clone();
+        overridden.extend(["ALERT-RULE GAME_STREAK 5", "ALERT-RULE DAILY_CAP 600", "ALERT-RULE DAILY_CAP 100"]);
+        let overridden = report(&overridden, "2024-01-03");
+        assert_eq!(overridden.firing, vec!["daily_cap"]);
+        assert_eq!(overridden.rules[0].state, AlertState::InsufficientData);
+        assert_eq!((overridden.rules[1].threshold, overridden.rules[1].set_by_event), (100.0, Some(11)));
+    }
+
+    #[test]
+    fn test_untimed_logs_have_insufficient_data() {
+        let report = report(&["START GAME chess", "START THEORY pandas"], "2024-01-03");
+        assert!(report.firing.is_empty());
+        assert!(report.rules.iter().all(|r| r.state == AlertState::InsufficientData));
+    }
+}
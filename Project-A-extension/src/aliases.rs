@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Category alias map applied at projection time (e.g. PRAC -> PRACTICE)
+/// The raw log is never rewritten
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CategoryAliases {
+    map: HashMap<String, String>,
+}
+
+impl CategoryAliases {
+    pub fn new(map: HashMap<String, String>) -> Self {
+        Self { map }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json)
+            .map(Self::new)
+            .map_err(|e| format!("Invalid category aliases: {}", e))
+    }
+
+    /// Canonical name for a raw category
+    pub fn resolve(&self, category: &str) -> String {
+        self.map
+            .get(category)
+            .cloned()
+            .unwrap_or_else(|| category.to_string())
+    }
+}
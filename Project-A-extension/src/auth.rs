@@ -0,0 +1,225 @@
+use std::fmt;
+use std::str::FromStr;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+use crate::error::AppError;
+use crate::AppState;
+
+/// What a token may do; each scope includes the ones before it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// GETs, plus the read-only POSTs (`/query`, `/parse`)
+    Read,
+    /// Appending: POST /events, /sessions/close, /ws
+    Write,
+    /// `/admin/*` and `/config`
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("Scope must be read, write or admin, got {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+/// One `[[tokens]]` entry of the config file
+#[derive(Clone, PartialEq)]
+pub struct ApiToken {
+    /// Who the token was given to, for logs and GET /config
+    pub name: Option<String>,
+    pub token: String,
+    pub scope: Scope,
+}
+
+/// Never prints the secret
+impl fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiToken")
+            .field("name", &self.name)
+            .field("token", &"<redacted>")
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+/// A configured token as GET /config shows it, without the secret
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TokenSummary {
+    pub name: Option<String>,
+    pub scope: Scope,
+}
+
+impl ApiToken {
+    /// From a `[[tokens]]` table: `token` and `scope` required, `name`
+    /// optional, nothing else
+    pub fn from_table(table: &Map<String, Value>) -> Result<Self, String> {
+        let string = |key: &str| match table.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(format!("{} must be a string", key)),
+        };
+        if let Some(key) = table.keys().find(|key| !["name", "token", "scope"].contains(&key.as_str())) {
+            return Err(format!("unknown key {}", key));
+        }
+        let token = string("token")?.filter(|token| !token.trim().is_empty()).ok_or("token is required")?;
+        let scope = string("scope")?.ok_or("scope is required")?.parse()?;
+        Ok(ApiToken { name: string("name")?, token, scope })
+    }
+
+    pub fn summary(&self) -> TokenSummary {
+        TokenSummary { name: self.name.clone(), scope: self.scope }
+    }
+}
+
+/// The scope a request needs, None for the health checks load balancers
+/// and orchestrators poll without credentials, and for the dashboard page,
+/// which holds no data and sends its own token for what it fetches
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    if path == "/health" || path.starts_with("/health/") || path == "/dashboard" {
+        return None;
+    }
+    Some(if path == "/config" || path.starts_with("/admin/") {
+        Scope::Admin
+    } else if path == "/ws" {
+        // Sockets append
+        Scope::Write
+    } else if matches!(*method, Method::GET | Method::HEAD) || matches!(path, "/query" | "/parse") {
+        Scope::Read
+    } else {
+        Scope::Write
+    })
+}
+
+/// The token in an `Authorization: Bearer <token>` header; the scheme is
+/// case-insensitive, as HTTP auth schemes are
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.trim_start().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// The configured token matching `presented`
+/// Every token is compared in full so timing doesn't reveal how much of
+/// a guess was right
+fn find<'a>(tokens: &'a [ApiToken], presented: &str) -> Option<&'a ApiToken> {
+    tokens.iter().fold(None, |found, token| {
+        let matches = token.token.len() == presented.len()
+            && token.token.bytes().zip(presented.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+        found.or(matches.then_some(token))
+    })
+}
+
+/// Check `Authorization: Bearer <token>` against the configured tokens
+/// With none configured every request passes, as before tokens existed
+pub async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if state.tokens.is_empty() {
+        return next.run(request).await;
+    }
+    let Some(required) = required_scope(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let Some(presented) = bearer_token(request.headers()) else {
+        return AppError::Unauthorized("Missing bearer token".to_string()).into_response();
+    };
+    let Some(token) = find(&state.tokens, presented) else {
+        return AppError::Unauthorized("Unknown bearer token".to_string()).into_response();
+    };
+    if token.scope < required {
+        return AppError::Forbidden(format!("This needs the {} scope; the token has {}", required, token.scope))
+            .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(value: &str, scope: Scope) -> ApiToken {
+        ApiToken { name: None, token: value.to_string(), scope }
+    }
+
+    #[test]
+    fn test_required_scopes() {
+        assert_eq!(required_scope(&Method::GET, "/health"), None);
+        assert_eq!(required_scope(&Method::GET, "/health/ready"), None);
+        assert_eq!(required_scope(&Method::GET, "/healthz"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::GET, "/dashboard"), None);
+        assert_eq!(required_scope(&Method::GET, "/projections/sessions"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::POST, "/query"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::POST, "/events"), Some(Scope::Write));
+        assert_eq!(required_scope(&Method::GET, "/ws"), Some(Scope::Write));
+        assert_eq!(required_scope(&Method::POST, "/admin/rebuild"), Some(Scope::Admin));
+        assert_eq!(required_scope(&Method::GET, "/config"), Some(Scope::Admin));
+    }
+
+    #[test]
+    fn test_tokens_from_tables() {
+        let table = |value: Value| value.as_object().unwrap().clone();
+        let parsed = ApiToken::from_table(&table(serde_json::json!({"name": "phone", "token": "s3cret", "scope": "write"})));
+        assert_eq!(parsed, Ok(ApiToken { name: Some("phone".to_string()), token: "s3cret".to_string(), scope: Scope::Write }));
+        assert!(!format!("{:?}", parsed).contains("s3cret"));
+
+        for bad in [
+            serde_json::json!({"scope": "read"}),
+            serde_json::json!({"token": " ", "scope": "read"}),
+            serde_json::json!({"token": "t"}),
+            serde_json::json!({"token": "t", "scope": "root"}),
+            serde_json::json!({"token": 1, "scope": "read"}),
+            serde_json::json!({"token": "t", "scope": "read", "expires": "never"}),
+        ] {
+            assert!(ApiToken::from_table(&table(bad.clone())).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_bearer_scheme_is_case_insensitive() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+        assert_eq!(bearer_token(&headers("Bearer s3cret")), Some("s3cret"));
+        assert_eq!(bearer_token(&headers("bearer s3cret")), Some("s3cret"));
+        assert_eq!(bearer_token(&headers("BEARER  s3cret ")), Some("s3cret"));
+        assert_eq!(bearer_token(&headers("Basic s3cret")), None);
+        assert_eq!(bearer_token(&headers("Bearer ")), None);
+        assert_eq!(bearer_token(&headers("Bearers3cret")), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_find_compares_whole_tokens() {
+        let tokens = [token("reader", Scope::Read), token("writer", Scope::Write)];
+        assert_eq!(find(&tokens, "writer").map(|t| t.scope), Some(Scope::Write));
+        assert!(find(&tokens, "write").is_none());
+        assert!(find(&tokens, "writers").is_none());
+        assert!(find(&[], "reader").is_none());
+    }
+}
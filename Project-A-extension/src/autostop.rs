@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crate::days;
+use crate::projections::SessionProjector;
+
+/// How often the background task looks for a stale session
+pub const AUTOSTOP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest a session may run before an `AUTOSTOP` ends it
+/// Off unless MAX_SESSION_MINUTES or a per-category limit is set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AutoStopConfig {
+    /// Limit for every category without its own
+    pub max_session_minutes: Option<f64>,
+    /// Limits by canonical category; null means never auto-stop it
+    pub by_category: HashMap<String, Option<f64>>,
+}
+
+impl AutoStopConfig {
+    /// Per-category limits as JSON, e.g. `{"GAME": null}`
+    pub fn by_category_from_json(json: &str) -> Result<HashMap<String, Option<f64>>, String> {
+        let limits: HashMap<String, Option<f64>> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        match limits.iter().find(|(_, limit)| limit.is_some_and(|m| days::check_minutes(m).is_err())) {
+            Some((category, _)) => Err(format!("invalid limit for {}", category)),
+            None => Ok(limits),
+        }
+    }
+
+    /// Whether any category can be auto-stopped
+    pub fn enabled(&self) -> bool {
+        self.max_session_minutes.is_some() || self.by_category.values().any(Option::is_some)
+    }
+
+    /// Limit for a canonical category, None when it's never auto-stopped
+    pub fn limit(&self, category: &str) -> Option<f64> {
+        match self.by_category.get(category) {
+            Some(limit) => *limit,
+            None => self.max_session_minutes,
+        }
+    }
+}
+
+/// The `AUTOSTOP` line for the active session once it has run (wall
+/// clock, breaks included) past its category's limit
+/// Stamped when the limit was reached, so the session lasts exactly
+/// that long however late the check runs; None when nothing is due
+pub fn due(projector: &SessionProjector, config: &AutoStopConfig, now: DateTime<Utc>) -> Option<String> {
+    let session = projector.get_current_session()?;
+    let start = session.start_time?;
+    let limit = config.limit(&session.category)?;
+    let at = start.checked_add_signed(chrono::Duration::try_seconds((limit * 60.0) as i64)?)?;
+    (now > at).then(|| format!("{} AUTOSTOP {} {} reason=stale", at.to_rfc3339(), session.category, session.activity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn due_at(lines: &[&str], config: &AutoStopConfig, now: &str) -> Option<String> {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        for line in lines {
+            writeln!(temp_file, "{}", line).unwrap();
+        }
+        due(&SessionProjector::new(temp_file.path()), config, now.parse().unwrap())
+    }
+
+    #[test]
+    fn test_due_past_the_category_limit() {
+        let config = AutoStopConfig {
+            max_session_minutes: Some(240.0),
+            by_category: HashMap::from([("GAME".to_string(), None), ("PRACTICE".to_string(), Some(60.0))]),
+        };
+        let theory = ["2024-01-01T09:00:00Z START THEORY pandas"];
+
+        assert_eq!(due_at(&theory, &config, "2024-01-01T13:00:00Z"), None);
+        assert_eq!(
+            due_at(&theory, &config, "2024-01-01T23:00:00Z").as_deref(),
+            Some("2024-01-01T13:00:00+00:00 AUTOSTOP THEORY pandas reason=stale")
+        );
+        let practice = ["2024-01-01T09:00:00Z START PRACTICE rust"];
+        assert!(due_at(&practice, &config, "2024-01-01T10:01:00Z").unwrap().starts_with("2024-01-01T10:00:00"));
+        // GAME is exempt, ended and untimed sessions never are due
+        assert_eq!(due_at(&["2024-01-01T09:00:00Z START GAME chess"], &config, "2024-01-02T09:00:00Z"), None);
+        let stopped = ["2024-01-01T09:00:00Z START THEORY pandas", "2024-01-01T10:00:00Z STOP THEORY pandas"];
+        assert_eq!(due_at(&stopped, &config, "2024-01-02T09:00:00Z"), None);
+        assert_eq!(due_at(&["START THEORY pandas"], &config, "2024-01-02T09:00:00Z"), None);
+
+        assert!(!AutoStopConfig::default().enabled());
+        assert!(AutoStopConfig::by_category_from_json(r#"{"GAME": -1}"#).is_err());
+        assert!(AutoStopConfig::by_category_from_json(r#"{"GAME": 1e16}"#).is_err());
+        // A limit too long to add to a timestamp is never due rather than a panic
+        let huge = AutoStopConfig { max_session_minutes: Some(1e300), by_category: HashMap::new() };
+        assert_eq!(due_at(&theory, &huge, "2024-01-02T09:00:00Z"), None);
+        assert_eq!(AutoStopConfig::by_category_from_json(r#"{"GAME": null}"#).unwrap()["GAME"], None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Cached projection results, keyed by projection name
+/// Cleared whenever the log changes (our appends or external writes)
+#[derive(Clone, Default)]
+pub struct ProjectionCache {
+    entries: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+/// Lookups since startup, for GET /metrics
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ProjectionCache {
+    /// Return the cached projection or compute and store it
+    pub fn get_or_compute(
+        &self,
+        key: &str,
+        compute: impl FnOnce() -> serde_json::Value,
+    ) -> serde_json::Value {
+        match self.try_get_or_compute(key, || Ok::<_, std::convert::Infallible>(compute())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like `get_or_compute` for projections that can fail; errors
+    /// aren't cached
+    pub fn try_get_or_compute<E>(
+        &self,
+        key: &str,
+        compute: impl FnOnce() -> Result<serde_json::Value, E>,
+    ) -> Result<serde_json::Value, E> {
+        if let Some(value) = self.entries.read().unwrap().get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(value.clone());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = tracing::debug_span!("projection", key).in_scope(compute)?;
+        self.entries
+            .write()
+            .unwrap()
+            .insert(key.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Drop every cached projection
+    pub fn invalidate(&self) {
+        self.entries.write().unwrap().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
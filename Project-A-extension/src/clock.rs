@@ -0,0 +1,60 @@
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+
+/// Where "now" comes from for elapsed times, relative windows and
+/// timestamps on appends
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Shared between the server state and the projectors it builds
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for reproducible answers
+#[derive(Debug)]
+pub struct FixedClock {
+    now: RwLock<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: RwLock::new(now) }
+    }
+
+    #[cfg(test)]
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.now.write().unwrap() += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.read().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_fixed_clock_only_moves_when_advanced() {
+        let start: DateTime<Utc> = "2024-01-01T09:00:00Z".parse().unwrap();
+        let clock = FixedClock::new(start);
+        assert_eq!((clock.now(), clock.now()), (start, start));
+        clock.advance(Duration::minutes(90));
+        assert_eq!(clock.now(), start + Duration::minutes(90));
+        assert!(SystemClock.now() > start);
+    }
+}
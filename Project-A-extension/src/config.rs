@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+use crate::alerts::{self, AlertRules};
+use crate::aliases::CategoryAliases;
+use crate::autostop::AutoStopConfig;
+use crate::days::{self, DayBoundary, DayZone, WeekStart, WorkingHours};
+use crate::display::CategoryDisplayConfig;
+use crate::auth::{ApiToken, TokenSummary};
+use crate::logging::LogFormat;
+
+/// Names the config file when there's no `--config`
+pub const CONFIG_FILE_ENV: &str = "CONFIG_FILE";
+
+/// Every server setting: its key in the config file (also the CLI flag,
+/// `--day-start-hour`), its environment variable and its default; an
+/// empty default leaves it unset
+const SETTINGS: &[(&str, &str, &str)] = &[
+    ("log_path", "LOG_PATH", "log/master.log"),
+    ("fallback_log_path", "FALLBACK_LOG_PATH", ""),
+    ("bind", "BIND_ADDR", "127.0.0.1:8080"),
+    ("timezone", "TZ_OFFSET", "UTC"),
+    ("day_start_hour", "DAY_START_HOUR", "0"),
+    ("week_start", "WEEK_START", "monday"),
+    ("working_hours", "WORKING_HOURS", "9-17"),
+    ("max_event_len", "MAX_EVENT_LEN", "1024"),
+    ("max_body_bytes", "MAX_BODY_BYTES", "65536"),
+    ("request_timeout_ms", "REQUEST_TIMEOUT_MS", "30000"),
+    ("projection_refresh_secs", "PROJECTION_REFRESH_SECS", "0"),
+    ("watch_log", "WATCH_LOG", "false"),
+    ("legacy_query_fallback", "LEGACY_QUERY_FALLBACK", "true"),
+    ("log_format", "LOG_FORMAT", "text"),
+    ("rate_limit_per_minute", "RATE_LIMIT_PER_MINUTE", "60"),
+    ("rate_limit_burst", "RATE_LIMIT_BURST", "20"),
+    ("category_aliases", "CATEGORY_ALIASES", ""),
+    ("category_aliases_file", "CATEGORY_ALIASES_FILE", ""),
+    ("category_display", "CATEGORY_DISPLAY", ""),
+    ("category_display_file", "CATEGORY_DISPLAY_FILE", ""),
+    ("alert_game_streak_days", "ALERT_GAME_STREAK_DAYS", "3"),
+    ("alert_daily_cap_minutes", "ALERT_DAILY_CAP_MINUTES", "600"),
+    ("max_session_minutes", "MAX_SESSION_MINUTES", ""),
+    ("max_session_minutes_by_category", "MAX_SESSION_MINUTES_BY_CATEGORY", ""),
+    ("fixed_now", "FIXED_NOW", ""),
+];
+
+/// Settings the server starts with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub log_path: PathBuf,
+    /// Read while `log_path` can't be; empty for none
+    pub fallback_log_path: Option<PathBuf>,
+    pub bind: SocketAddr,
+    pub timezone: DayZone,
+    pub day_start_hour: u32,
+    pub week_start: WeekStart,
+    pub working_hours: WorkingHours,
+    pub max_event_len: usize,
+    pub max_body_bytes: usize,
+    pub request_timeout_ms: u64,
+    /// 0 computes the bundle on demand
+    pub projection_refresh_secs: u64,
+    pub watch_log: bool,
+    pub legacy_query_fallback: bool,
+    pub log_format: LogFormat,
+    /// Appends each client may make a minute, 0 for no limit
+    pub rate_limit_per_minute: u32,
+    /// Appends a client may make at once before the rate applies
+    pub rate_limit_burst: u32,
+    /// Only from the config file's `[[tokens]]`; none leaves the API open
+    pub tokens: Vec<ApiToken>,
+    /// `category_aliases`, else the JSON file `category_aliases_file` names
+    pub category_aliases: CategoryAliases,
+    /// `category_display`, else the JSON file `category_display_file` names
+    pub category_display: CategoryDisplayConfig,
+    pub alert_rules: AlertRules,
+    pub autostop: AutoStopConfig,
+    /// Pinned "now"; only `project` may run with it
+    pub fixed_now: Option<DateTime<Utc>>,
+}
+
+/// The config file: settings by key, and the `[[tokens]]` tables
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    tokens: Vec<Map<String, Value>>,
+    #[serde(flatten)]
+    settings: BTreeMap<String, toml::Value>,
+}
+
+/// Where a setting's value came from, highest precedence first
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Cli,
+    Env,
+    File,
+    Default,
+}
+
+/// One setting as the server runs with it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EffectiveSetting {
+    /// As given, before parsing
+    pub value: String,
+    pub source: Source,
+}
+
+/// GET /config: the merged configuration
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReport {
+    /// Config file read, null when there was none
+    pub file: Option<String>,
+    /// By file key
+    pub settings: BTreeMap<String, EffectiveSetting>,
+    /// The file's `[[tokens]]`, secrets left out
+    pub tokens: Vec<TokenSummary>,
+}
+
+/// A valid configuration, what it was merged from and anything worth
+/// warning about
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    pub config: Config,
+    pub report: ConfigReport,
+    pub warnings: Vec<String>,
+}
+
+impl Loaded {
+    /// Nothing set anywhere
+    pub fn defaults() -> Self {
+        Config::load(&[], |_| None).expect("the defaults are valid")
+    }
+}
+
+impl Config {
+    /// Merge CLI flags over environment variables over the config file
+    /// (`--config`, else CONFIG_FILE) over the defaults
+    /// Every invalid value, unknown flag and file error is reported, not
+    /// just the first; unknown keys in the file only warn
+    pub fn load(args: &[String], env: impl Fn(&str) -> Option<String>) -> Result<Loaded, Vec<String>> {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        let (flags, file) = parse_args(args, &mut errors);
+        let file = file.or_else(|| env(CONFIG_FILE_ENV)).map(PathBuf::from);
+        let mut from_file = BTreeMap::new();
+        let mut tokens = Vec::new();
+        if let Some(path) = &file {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(text) => toml::from_str::<ConfigFile>(&text).map_err(|e| located(&text, &e)),
+                Err(e) => Err(e.to_string()),
+            };
+            let contents = contents.unwrap_or_else(|e| {
+                errors.push(format!("{}: {}", path.display(), e));
+                ConfigFile::default()
+            });
+            for (n, entry) in contents.tokens.iter().enumerate() {
+                match ApiToken::from_table(entry) {
+                    Ok(token) => tokens.push(token),
+                    Err(e) => errors.push(format!("{}: tokens entry {}: {}", path.display(), n + 1, e)),
+                }
+            }
+            for (key, value) in contents.settings {
+                if !SETTINGS.iter().any(|(name, _, _)| *name == key) {
+                    warnings.push(format!("{}: unknown key {} ignored", path.display(), key));
+                    continue;
+                }
+                match scalar(&value) {
+                    Some(value) => {
+                        from_file.insert(key, value);
+                    }
+                    None => errors.push(format!("{}: {} must be a single value", path.display(), key)),
+                }
+            }
+        }
+
+        let mut settings = BTreeMap::new();
+        for &(key, var, default) in SETTINGS {
+            let (value, source) = if let Some(value) = flags.get(key) {
+                (value.clone(), Source::Cli)
+            } else if let Some(value) = env(var) {
+                (value, Source::Env)
+            } else if let Some(value) = from_file.get(key) {
+                (value.clone(), Source::File)
+            } else {
+                (default.to_string(), Source::Default)
+            };
+            settings.insert(key.to_string(), EffectiveSetting { value, source });
+        }
+
+        let mut layered = Layered { settings: &settings, file: file.as_ref(), errors: &mut errors };
+        let config = Config {
+            log_path: layered.get("log_path", |v| v.parse::<PathBuf>()),
+            fallback_log_path: layered.get("fallback_log_path", |v| optional(v, str::parse::<PathBuf>)),
+            bind: layered.get("bind", |v| v.parse::<SocketAddr>()),
+            timezone: layered.get("timezone", |v| v.parse::<DayZone>()),
+            day_start_hour: layered.get("day_start_hour", |v| {
+                let hour = v.trim().parse::<u32>().map_err(|e| e.to_string())?;
+                DayBoundary::new(DayZone::default(), hour).map(|_| hour)
+            }),
+            week_start: layered.get("week_start", |v| v.parse::<WeekStart>()),
+            working_hours: layered.get("working_hours", |v| v.parse::<WorkingHours>()),
+            max_event_len: layered.get("max_event_len", |v| v.trim().parse::<usize>()),
+            max_body_bytes: layered.get("max_body_bytes", |v| v.trim().parse::<usize>()),
+            request_timeout_ms: layered.get("request_timeout_ms", |v| v.trim().parse::<u64>()),
+            projection_refresh_secs: layered.get("projection_refresh_secs", |v| v.trim().parse::<u64>()),
+            watch_log: layered.get("watch_log", flag),
+            legacy_query_fallback: layered.get("legacy_query_fallback", flag),
+            log_format: layered.get("log_format", |v| v.parse::<LogFormat>()),
+            rate_limit_per_minute: layered.get("rate_limit_per_minute", |v| v.trim().parse::<u32>()),
+            rate_limit_burst: layered.get("rate_limit_burst", |v| v.trim().parse::<u32>()),
+            tokens,
+            category_aliases: match layered.get("category_aliases", |v| optional(v, CategoryAliases::from_json)) {
+                Some(aliases) => aliases,
+                None => layered
+                    .get("category_aliases_file", |v| optional(v, |path| CategoryAliases::from_file(Path::new(path))))
+                    .unwrap_or_default(),
+            },
+            category_display: match layered.get("category_display", |v| optional(v, CategoryDisplayConfig::from_json)) {
+                Some(display) => display,
+                None => layered
+                    .get("category_display_file", |v| optional(v, |path| CategoryDisplayConfig::from_file(Path::new(path))))
+                    .unwrap_or_default(),
+            },
+            alert_rules: AlertRules {
+                game_streak_days: layered.get("alert_game_streak_days", |v| {
+                    alerts::parse_days(v.trim()).ok_or("expected a positive number of days")
+                }),
+                daily_cap_minutes: layered.get("alert_daily_cap_minutes", days::parse_minutes),
+            },
+            autostop: AutoStopConfig {
+                max_session_minutes: layered.get("max_session_minutes", |v| optional(v, days::parse_minutes)),
+                by_category: layered
+                    .get("max_session_minutes_by_category", |v| optional(v, AutoStopConfig::by_category_from_json))
+                    .unwrap_or_default(),
+            },
+            fixed_now: layered.get("fixed_now", |v| optional(v, |v| v.trim().parse::<DateTime<Utc>>())),
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let file = file.map(|path| path.display().to_string());
+        let tokens = config.tokens.iter().map(ApiToken::summary).collect();
+        Ok(Loaded { config, report: ConfigReport { file, settings, tokens }, warnings })
+    }
+}
+
+/// The merged values, parsed one at a time so every bad one is reported
+struct Layered<'a> {
+    settings: &'a BTreeMap<String, EffectiveSetting>,
+    file: Option<&'a PathBuf>,
+    errors: &'a mut Vec<String>,
+}
+
+impl Layered<'_> {
+    /// The setting's value, or after recording why it's invalid, its default's
+    fn get<T, E: Display>(&mut self, key: &str, parse: impl Fn(&str) -> Result<T, E>) -> T {
+        let setting = &self.settings[key];
+        let &(_, var, default) = SETTINGS.iter().find(|(name, _, _)| *name == key).expect("a known setting");
+        parse(&setting.value).unwrap_or_else(|e| {
+            let origin = match (setting.source, self.file) {
+                (Source::Cli, _) => format!("--{}", key.replace('_', "-")),
+                (Source::Env, _) => var.to_string(),
+                (Source::File, Some(path)) => format!("{} in {}", key, path.display()),
+                _ => key.to_string(),
+            };
+            self.errors.push(format!("Invalid {} {:?}: {}", origin, setting.value, e));
+            parse(default).unwrap_or_else(|_| panic!("default {} is invalid", key))
+        })
+    }
+}
+
+/// None for an empty value, the settings that are off unless set
+fn optional<T, E>(value: &str, parse: impl Fn(&str) -> Result<T, E>) -> Result<Option<T>, E> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    parse(value).map(Some)
+}
+
+/// `true`/`false`, or `1`/`0` as the environment flags always accepted
+fn flag(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        other => Err(format!("expected true or false, got {}", other)),
+    }
+}
+
+/// Setting flags by key, and `--config`'s path
+/// `--day-start-hour 4` and `--day-start-hour=4` both work
+fn parse_args(args: &[String], errors: &mut Vec<String>) -> (BTreeMap<String, String>, Option<String>) {
+    let mut flags = BTreeMap::new();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            errors.push(format!("Unexpected argument {}", arg));
+            continue;
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name, None),
+        };
+        let key = name.replace('-', "_");
+        if key != "config" && !SETTINGS.iter().any(|(setting, _, _)| *setting == key) {
+            errors.push(format!("Unknown flag --{}", name));
+            continue;
+        }
+        let Some(value) = value.or_else(|| args.next().cloned()) else {
+            errors.push(format!("--{} needs a value", name));
+            continue;
+        };
+        if key == "config" {
+            file = Some(value);
+        } else {
+            flags.insert(key, value);
+        }
+    }
+    (flags, file)
+}
+
+/// A file value as the string a flag or variable would give; the
+/// settings that take JSON also take a table
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(n) => Some(n.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        toml::Value::Datetime(d) => Some(d.to_string()),
+        toml::Value::Table(table) => serde_json::to_string(table).ok(),
+        toml::Value::Array(_) => None,
+    }
+}
+
+/// A TOML error as `line N: message`, on one line like the file's other
+/// problems
+fn located(text: &str, e: &toml::de::Error) -> String {
+    match e.span() {
+        Some(span) => format!("line {}: {}", text[..span.start].matches('\n').count() + 1, e.message()),
+        None => e.message().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use crate::auth::Scope;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_defaults_match_the_server() {
+        let loaded = Loaded::defaults();
+        assert_eq!(loaded.config.max_event_len, crate::DEFAULT_MAX_EVENT_LEN);
+        assert_eq!(loaded.config.max_body_bytes, crate::DEFAULT_MAX_BODY_BYTES);
+        assert_eq!(loaded.config.request_timeout_ms as u128, crate::DEFAULT_REQUEST_TIMEOUT.as_millis());
+        assert_eq!(loaded.config.week_start, WeekStart::default());
+        assert!(loaded.config.legacy_query_fallback && !loaded.config.watch_log);
+        assert_eq!(loaded.config.alert_rules, AlertRules::default());
+        assert!(!loaded.config.autostop.enabled() && loaded.config.fixed_now.is_none());
+        assert!(loaded.report.file.is_none());
+        assert!(loaded.report.settings.values().all(|s| s.source == Source::Default));
+        assert!(loaded.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_cli_over_env_over_file() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "# Server settings").unwrap();
+        writeln!(file, "log_path = \"/var/log/project-a/master.log\"").unwrap();
+        writeln!(file, "day_start_hour = 4").unwrap();
+        writeln!(file, "week_start = 'sunday'  # for the weekly review").unwrap();
+        writeln!(file, "watch_log = true").unwrap();
+        writeln!(file, "colour = \"blue\"").unwrap();
+        let path = file.path().display().to_string();
+
+        let env = |name: &str| match name {
+            "CONFIG_FILE" => Some(path.clone()),
+            "DAY_START_HOUR" => Some("5".to_string()),
+            "WEEK_START" => Some("monday".to_string()),
+            _ => None,
+        };
+        let loaded = Config::load(&args("--day-start-hour=6 --bind 0.0.0.0:9000"), env).unwrap();
+
+        assert_eq!(loaded.config.day_start_hour, 6);
+        assert_eq!(loaded.config.bind, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(loaded.config.week_start, WeekStart::Monday);
+        assert_eq!(loaded.config.log_path, PathBuf::from("/var/log/project-a/master.log"));
+        assert!(loaded.config.watch_log);
+        let source = |key: &str| loaded.report.settings[key].source;
+        assert_eq!(
+            [source("day_start_hour"), source("week_start"), source("watch_log"), source("max_event_len")],
+            [Source::Cli, Source::Env, Source::File, Source::Default],
+        );
+        assert_eq!(loaded.report.file.as_deref(), Some(path.as_str()));
+        assert_eq!(loaded.warnings, vec![format!("{}: unknown key colour ignored", path)]);
+    }
+
+    #[test]
+    fn test_tokens_from_file_only_summarized() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[[tokens]]\nname = \"phone\"\ntoken = \"s3cret\"\nscope = \"write\"").unwrap();
+        writeln!(file, "[[tokens]]\ntoken = \"r34d\"\nscope = \"read\"").unwrap();
+        let path = file.path().display().to_string();
+        let loaded = Config::load(&args(&format!("--config {}", path)), |_| None).unwrap();
+
+        let scopes: Vec<Scope> = loaded.config.tokens.iter().map(|t| t.scope).collect();
+        assert_eq!(scopes, [Scope::Write, Scope::Read]);
+        assert_eq!(loaded.config.tokens[1].token, "r34d");
+        let report = serde_json::to_string(&loaded.report).unwrap();
+        assert!(report.contains("\"name\":\"phone\"") && !report.contains("s3cret"), "{}", report);
+
+        writeln!(file, "[[tokens]]\ntoken = \"x\"\nscope = \"root\"").unwrap();
+        let errors = Config::load(&args(&format!("--config {}", path)), |_| None).unwrap_err();
+        assert_eq!(errors, vec![format!("{}: tokens entry 3: Scope must be read, write or admin, got root", path)]);
+    }
+
+    #[test]
+    fn test_every_problem_reported() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "timezone = \"Mars/Olympus\"").unwrap();
+        writeln!(file, "max_event_len = [1, 2]").unwrap();
+        writeln!(file, "alert_game_streak_days = 0").unwrap();
+        let path = file.path().display().to_string();
+
+        let env = |name: &str| (name == "WATCH_LOG").then(|| "sometimes".to_string());
+        let errors = Config::load(&args(&format!("--config {} --day-start-hour 24 --colour=red --bind", path)), env)
+            .unwrap_err();
+
+        assert_eq!(errors.len(), 7, "{:?}", errors);
+        assert!(errors.contains(&"Unknown flag --colour".to_string()));
+        assert!(errors.contains(&"--bind needs a value".to_string()));
+        assert!(errors.iter().any(|e| e.starts_with(&format!("Invalid alert_game_streak_days in {} \"0\"", path))));
+        assert!(errors.iter().any(|e| e.starts_with("Invalid --day-start-hour \"24\"")));
+        assert!(errors.iter().any(|e| e.starts_with("Invalid WATCH_LOG \"sometimes\"")));
+        // The file's other lines are still checked
+        assert!(errors.iter().any(|e| e.starts_with(&format!("Invalid timezone in {} \"Mars/Olympus\"", path))));
+        assert!(errors.contains(&format!("{}: max_event_len must be a single value", path)));
+    }
+
+    #[test]
+    fn test_toml_syntax_error_located() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "day_start_hour = 4\nthis line is not toml").unwrap();
+        let path = file.path().display().to_string();
+
+        let errors = Config::load(&args(&format!("--config {}", path)), |_| None).unwrap_err();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].starts_with(&format!("{}: line 2: ", path)), "{:?}", errors);
+        assert!(!errors[0].contains('\n'), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_json_settings_take_tables() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "fixed_now = 2024-01-01T12:00:00Z\nmax_session_minutes = \"4h\"").unwrap();
+        writeln!(file, "[category_aliases]\nPRAC = \"PRACTICE\"").unwrap();
+        writeln!(file, "[max_session_minutes_by_category]\nTHEORY = 60").unwrap();
+        let path = file.path().display().to_string();
+        let env = |name: &str| (name == "ALERT_DAILY_CAP_MINUTES").then(|| "8h".to_string());
+        let loaded = Config::load(&args(&format!("--config {}", path)), env).unwrap();
+
+        assert_eq!(loaded.config.category_aliases.resolve("PRAC"), "PRACTICE");
+        assert_eq!(loaded.config.autostop.limit("THEORY"), Some(60.0));
+        assert_eq!(loaded.config.autostop.limit("GAME"), Some(240.0));
+        assert_eq!(loaded.config.alert_rules.daily_cap_minutes, 480.0);
+        assert_eq!(loaded.config.fixed_now, Some("2024-01-01T12:00:00Z".parse().unwrap()));
+        let setting = |key: &str| loaded.report.settings[key].clone();
+        assert_eq!(setting("category_aliases"), EffectiveSetting { value: r#"{"PRAC":"PRACTICE"}"#.to_string(), source: Source::File });
+        assert_eq!(setting("alert_daily_cap_minutes").source, Source::Env);
+        assert_eq!(setting("category_display").source, Source::Default);
+    }
+}
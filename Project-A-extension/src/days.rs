@@ -0,0 +1,467 @@
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono_tz::Tz;
+
+/// Inclusive start and exclusive end of a span of time, either open
+pub type Bounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Timezone deciding which calendar day a timestamp belongs to
+/// Every day-bucketing projection goes through this, so they never disagree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DayZone {
+    Offset(FixedOffset),
+    Named(Tz),
+}
+
+impl Default for DayZone {
+    fn default() -> Self {
+        DayZone::Offset(Utc.fix())
+    }
+}
+
+impl FromStr for DayZone {
+    type Err = String;
+
+    /// Accepts `UTC`, a fixed offset like `+02:00` / `-0530`, or an IANA name
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("utc") || value == "Z" {
+            return Ok(DayZone::default());
+        }
+        if value.starts_with('+') || value.starts_with('-') {
+            return parse_offset(value)
+                .map(DayZone::Offset)
+                .ok_or_else(|| format!("Invalid UTC offset: {}", value));
+        }
+        value
+            .parse::<Tz>()
+            .map(DayZone::Named)
+            .map_err(|_| format!("Unknown timezone: {}", value))
+    }
+}
+
+impl DayZone {
+    /// Local calendar day of a timestamp
+    pub fn day_of(&self, ts: DateTime<Utc>) -> NaiveDate {
+        self.local(ts).date()
+    }
+
+    /// Local wall-clock time of a timestamp
+    pub fn local(&self, ts: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            DayZone::Offset(offset) => ts.with_timezone(offset).naive_local(),
+            DayZone::Named(tz) => ts.with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// Instant the local day begins
+    pub fn day_start(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        match self {
+            DayZone::Offset(offset) => offset
+                .from_local_datetime(&midnight)
+                .earliest()
+                .map(|t| t.with_timezone(&Utc)),
+            DayZone::Named(tz) => tz
+                .from_local_datetime(&midnight)
+                .earliest()
+                .map(|t| t.with_timezone(&Utc)),
+        }
+        // Midnight skipped by a DST jump: fall back to naive UTC
+        .unwrap_or_else(|| midnight.and_utc())
+    }
+}
+
+/// Where one "day" ends and the next begins: a timezone plus the local
+/// hour the day rolls over (e.g. 4 so a 2am session still counts as yesterday)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DayBoundary {
+    pub zone: DayZone,
+    pub start_hour: u32,
+}
+
+impl DayBoundary {
+    pub fn new(zone: DayZone, start_hour: u32) -> Result<Self, String> {
+        if start_hour > 23 {
+            return Err(format!("Day start hour must be 0-23, got {}", start_hour));
+        }
+        Ok(Self { zone, start_hour })
+    }
+
+    /// Day a timestamp is attributed to
+    pub fn day_of(&self, ts: DateTime<Utc>) -> NaiveDate {
+        self.zone.day_of(ts - Duration::hours(self.start_hour as i64))
+    }
+
+    /// Instant a day begins
+    pub fn day_start(&self, date: NaiveDate) -> DateTime<Utc> {
+        self.zone.day_start(date) + Duration::hours(self.start_hour as i64)
+    }
+
+    /// Instants an inclusive day range covers: from `from`'s start up to,
+    /// but excluding, the start of the day after `to`
+    pub fn bounds(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Bounds {
+        (from.map(|from| self.day_start(from)), to.and_then(|to| to.succ_opt()).map(|end| self.day_start(end)))
+    }
+}
+
+/// Local hours counted as working time, `start_hour` inclusive to `end_hour` exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkingHours {
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        Self { start_hour: 9, end_hour: 17 }
+    }
+}
+
+impl FromStr for WorkingHours {
+    type Err = String;
+
+    /// Accepts `9-17` or `09:00-17:00` (whole hours only)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Working hours must look like 9-17, got {}", value);
+        let hour = |part: &str| -> Option<u32> {
+            let part = part.trim();
+            let part = part.strip_suffix(":00").unwrap_or(part);
+            part.parse().ok()
+        };
+        let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+        let (start_hour, end_hour) = (hour(start).ok_or_else(invalid)?, hour(end).ok_or_else(invalid)?);
+        if start_hour >= end_hour || end_hour > 24 {
+            return Err(invalid());
+        }
+        Ok(Self { start_hour, end_hour })
+    }
+}
+
+impl WorkingHours {
+    /// Whether `start..end` lies within working hours of a single local day
+    pub fn contains(&self, zone: &DayZone, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        let date = zone.day_of(start);
+        if zone.day_of(end) != date && end != zone.day_start(date + Duration::days(1)) {
+            return false;
+        }
+        let open = zone.day_start(date) + Duration::hours(self.start_hour as i64);
+        let close = zone.day_start(date) + Duration::hours(self.end_hour as i64);
+        open <= start && end <= close
+    }
+}
+
+/// First day of a week for weekly rollups
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl FromStr for WeekStart {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "monday" | "mon" => Ok(WeekStart::Monday),
+            "sunday" | "sun" => Ok(WeekStart::Sunday),
+            other => Err(format!("Week start must be monday or sunday, got {}", other)),
+        }
+    }
+}
+
+/// Calendar period a rollup groups days into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Week(WeekStart),
+    Month,
+}
+
+impl Period {
+    /// First day of the period containing `date`
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Week(start) => {
+                let weekday = match start {
+                    WeekStart::Monday => Weekday::Mon,
+                    WeekStart::Sunday => Weekday::Sun,
+                };
+                date.week(weekday).first_day()
+            }
+            Period::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// First day of the period after the one starting at `start`
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Week(_) => start + Duration::days(7),
+            Period::Month => start + Months::new(1),
+        }
+    }
+
+    /// `2024-W09` for weeks, `2024-03` for months
+    /// Sunday-start weeks take the ISO number of the Monday that follows
+    pub fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Period::Week(week_start) => {
+                let monday = match week_start {
+                    WeekStart::Monday => start,
+                    WeekStart::Sunday => start + Duration::days(1),
+                };
+                let week = monday.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Period::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+
+    /// First day of the week labelled `2024-W09`, the inverse of `label`
+    /// Months have no parseable label
+    pub fn parse_label(&self, label: &str) -> Result<NaiveDate, String> {
+        let invalid = || format!("Invalid week: {} (expected e.g. 2024-W09)", label);
+        let Period::Week(week_start) = self else { return Err(invalid()) };
+        let (year, week) = label.split_once("-W").ok_or_else(invalid)?;
+        let (year, week) = (year.parse().map_err(|_| invalid())?, week.parse().map_err(|_| invalid())?);
+        let monday = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).ok_or_else(invalid)?;
+        Ok(match week_start {
+            WeekStart::Monday => monday,
+            WeekStart::Sunday => monday - Duration::days(1),
+        })
+    }
+}
+
+/// `30d` as its amount and one-character unit
+fn split_unit(value: &str) -> Option<(&str, char)> {
+    let unit = value.chars().next_back()?;
+    Some((&value[..value.len() - unit.len_utf8()], unit))
+}
+
+/// Parse a look-back window like `30d`, `12h` or `2w`, at most
+/// MAX_RANGE_DAYS long
+pub fn parse_window(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let invalid = || format!("Invalid window: {} (expected e.g. 30d, 12h, 2w)", value);
+    let (amount, unit) = split_unit(value).ok_or_else(invalid)?;
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    if amount <= 0 {
+        return Err(invalid());
+    }
+    let window = match unit {
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    if window > Duration::days(MAX_RANGE_DAYS) {
+        return Err(format!("Window longer than {} days", MAX_RANGE_DAYS));
+    }
+    Ok(window)
+}
+
+/// Longest duration in minutes a setting or query may give: a year
+pub const MAX_MINUTES: f64 = 366.0 * 24.0 * 60.0;
+
+/// Parse a length like `5m`, `1h` or `1d` into minutes; a bare number
+/// is minutes
+pub fn parse_minutes(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let invalid = || format!("Invalid duration: {} (expected e.g. 5m, 1h, 1d)", value);
+    let (amount, per_unit) = match split_unit(value).ok_or_else(invalid)? {
+        (amount, 'm') => (amount, 1.0),
+        (amount, 'h') => (amount, 60.0),
+        (amount, 'd') => (amount, 24.0 * 60.0),
+        _ => (value, 1.0),
+    };
+    let amount: f64 = amount.parse().map_err(|_| invalid())?;
+    check_minutes(amount * per_unit).map_err(|e| format!("Invalid duration: {} ({})", value, e))
+}
+
+/// `minutes` if it's a positive length no longer than MAX_MINUTES
+pub fn check_minutes(minutes: f64) -> Result<f64, String> {
+    if !minutes.is_finite() || minutes <= 0.0 {
+        return Err("must be positive".to_string());
+    }
+    if minutes > MAX_MINUTES {
+        return Err(format!("longer than {}", minutes_label(MAX_MINUTES)));
+    }
+    Ok(minutes)
+}
+
+/// Parse histogram bounds like `5m,15m,30m,1h`, in minutes; they must
+/// strictly increase
+pub fn parse_duration_buckets(value: &str) -> Result<Vec<f64>, String> {
+    let bounds = value.split(',').map(parse_minutes).collect::<Result<Vec<f64>, String>>()?;
+    if let Some(pair) = bounds.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(format!(
+            "Buckets must be in ascending order: {} doesn't come after {}",
+            minutes_label(pair[1]),
+            minutes_label(pair[0])
+        ));
+    }
+    Ok(bounds)
+}
+
+/// `90` as `90m`, `120` as `2h`, `1440` as `1d`
+pub fn minutes_label(minutes: f64) -> String {
+    match minutes {
+        m if m >= 1440.0 && m % 1440.0 == 0.0 => format!("{}d", m / 1440.0),
+        m if m >= 60.0 && m % 60.0 == 0.0 => format!("{}h", m / 60.0),
+        m => format!("{}m", m),
+    }
+}
+
+/// Longest range a day-by-day response may cover
+pub const MAX_RANGE_DAYS: i64 = 3660;
+
+/// A YYYY-MM-DD day
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid date: {}", value))
+}
+
+/// Parse optional `YYYY-MM-DD` bounds, checking order and size
+pub fn parse_date_range(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String> {
+    let (from, to) = (from.map(parse_date).transpose()?, to.map(parse_date).transpose()?);
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+        check_range_len(from, to)?;
+    }
+    Ok((from, to))
+}
+
+/// Refuse day ranges over MAX_RANGE_DAYS, including ones whose open
+/// ends were filled in from the log
+pub fn check_range_len(from: NaiveDate, to: NaiveDate) -> Result<(), String> {
+    if (to - from).num_days() > MAX_RANGE_DAYS {
+        return Err(format!("Range longer than {} days", MAX_RANGE_DAYS));
+    }
+    Ok(())
+}
+
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let sign = if value.starts_with('-') { -1 } else { 1 };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse::<i32>().ok()?),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_late_local_event_lands_on_local_day() {
+        // 23:30 in New York on Jan 2 is 04:30 UTC on Jan 3
+        let ts = DateTime::parse_from_rfc3339("2024-01-03T04:30:00Z").unwrap().with_timezone(&Utc);
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        assert_eq!(DayZone::default().day_of(ts), date("2024-01-03"));
+        assert_eq!("-05:00".parse::<DayZone>().unwrap().day_of(ts), date("2024-01-02"));
+        assert_eq!("America/New_York".parse::<DayZone>().unwrap().day_of(ts), date("2024-01-02"));
+    }
+
+    #[test]
+    fn test_working_hours() {
+        let hours: WorkingHours = "09:00-17:00".parse().unwrap();
+        assert_eq!(hours, WorkingHours::default());
+        assert!("17-9".parse::<WorkingHours>().is_err());
+
+        let ts = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let utc = DayZone::default();
+        assert!(hours.contains(&utc, ts("2024-01-02T10:00:00Z"), ts("2024-01-02T12:00:00Z")));
+        assert!(!hours.contains(&utc, ts("2024-01-02T16:00:00Z"), ts("2024-01-02T18:00:00Z")));
+        // 10:00-12:00 in New York is 15:00-17:00 UTC
+        let ny: DayZone = "America/New_York".parse().unwrap();
+        assert!(hours.contains(&ny, ts("2024-01-02T15:00:00Z"), ts("2024-01-02T17:00:00Z")));
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("30d").unwrap(), Duration::days(30));
+        assert_eq!(parse_window("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_window("2w").unwrap(), Duration::weeks(2));
+        for bad in ["", "d", "0d", "-1d", "30", "30m", "30é", "é", "100000000w", "3661d"] {
+            assert!(parse_window(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_buckets() {
+        assert_eq!(parse_duration_buckets("5m,15m,30m,1h").unwrap(), vec![5.0, 15.0, 30.0, 60.0]);
+        assert_eq!(parse_duration_buckets(" 90m , 2h,1d").unwrap(), vec![90.0, 120.0, 1440.0]);
+        assert_eq!(parse_duration_buckets("5,1.5h").unwrap(), vec![5.0, 90.0]);
+        for bad in ["", "5m,,1h", "0m", "5s", "-5m", "5é", "é", "NaNm", "367d", "1e300m"] {
+            assert!(parse_duration_buckets(bad).is_err(), "{}", bad);
+        }
+        let unordered = parse_duration_buckets("5m,1h,30m").unwrap_err();
+        assert!(unordered.contains("30m doesn't come after 1h"), "{}", unordered);
+        assert!(parse_duration_buckets("60m,1h").is_err());
+    }
+
+    #[test]
+    fn test_period_boundaries() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        // Wednesday
+        let wed = date("2024-03-06");
+
+        let monday = Period::Week(WeekStart::Monday);
+        assert_eq!(monday.start_of(wed), date("2024-03-04"));
+        assert_eq!(monday.label(date("2024-03-04")), "2024-W10");
+
+        let sunday = Period::Week(WeekStart::Sunday);
+        assert_eq!(sunday.start_of(wed), date("2024-03-03"));
+        assert_eq!(sunday.label(date("2024-03-03")), "2024-W10");
+        assert_eq!(sunday.next(date("2024-03-03")), date("2024-03-10"));
+        assert_eq!(monday.parse_label("2024-W10").unwrap(), date("2024-03-04"));
+        assert_eq!(sunday.parse_label("2024-W10").unwrap(), date("2024-03-03"));
+        for bad in ["2024-10", "2024-W54", "2024-Wx", "W10"] {
+            assert!(monday.parse_label(bad).is_err(), "{}", bad);
+        }
+
+        assert_eq!(Period::Month.start_of(wed), date("2024-03-01"));
+        assert_eq!(Period::Month.next(date("2024-12-01")), date("2025-01-01"));
+        assert_eq!(Period::Month.label(date("2024-03-01")), "2024-03");
+    }
+
+    #[test]
+    fn test_day_start_hour_rolls_over_late() {
+        let boundary = DayBoundary::new(DayZone::default(), 4).unwrap();
+        let ts = |s| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        assert_eq!(boundary.day_of(ts("2024-01-03T03:59:00Z")), date("2024-01-02"));
+        assert_eq!(boundary.day_of(ts("2024-01-03T04:00:00Z")), date("2024-01-03"));
+        assert_eq!(boundary.day_start(date("2024-01-03")), ts("2024-01-03T04:00:00Z"));
+        assert!(DayBoundary::new(DayZone::default(), 24).is_err());
+    }
+
+    #[test]
+    fn test_parse_zones() {
+        assert_eq!("UTC".parse::<DayZone>().unwrap(), DayZone::default());
+        assert!("+0530".parse::<DayZone>().is_ok());
+        assert!("+25:00".parse::<DayZone>().is_err());
+        assert!("Mars/Olympus".parse::<DayZone>().is_err());
+
+        let zone: DayZone = "+02:00".parse().unwrap();
+        let start = zone.day_start(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        assert_eq!(start.to_rfc3339(), "2024-01-01T22:00:00+00:00");
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// How a dashboard should show one category
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryDisplay {
+    pub display_name: Option<String>,
+    /// Any CSS color, e.g. `#4e79a7`
+    pub color: Option<String>,
+}
+
+/// Display names and colors per (canonical) category, echoed into
+/// projections; categories not listed get nulls
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CategoryDisplayConfig {
+    map: HashMap<String, CategoryDisplay>,
+}
+
+impl CategoryDisplayConfig {
+    pub fn new(map: HashMap<String, CategoryDisplay>) -> Self {
+        Self { map }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json)
+            .map(Self::new)
+            .map_err(|e| format!("Invalid category display config: {}", e))
+    }
+
+    /// Display settings for a category, all null when unconfigured
+    pub fn get(&self, category: &str) -> CategoryDisplay {
+        self.map.get(category).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,152 @@
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Why a request failed, answered as an `ErrorBody`
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// Reading or writing the log failed; a missing log is a 404, a full
+    /// disk a 507, anything else a 500
+    #[error("{0}")]
+    Io(std::io::Error),
+    /// Bad params or input: a 400
+    #[error("{message}")]
+    Validation { message: String, detail: Option<Value> },
+    /// No bearer token, or one that isn't configured
+    #[error("{0}")]
+    Unauthorized(String),
+    /// A token whose scope doesn't cover the request
+    #[error("{0}")]
+    Forbidden(String),
+    /// The client's appends are coming too fast
+    #[error("Too many appends, retry in {retry_after_secs}s")]
+    RateLimited { retry_after_secs: u64 },
+    #[error("{0}")]
+    NotFound(String),
+    /// A body larger than MAX_BODY_BYTES
+    #[error("{0}")]
+    TooLarge(String),
+    /// The request doesn't fit the log's current state
+    #[error("{0}")]
+    Conflict(String),
+    /// Something stored in the log can't be used as it is: a 422
+    #[error("{message}")]
+    Unprocessable { message: String, detail: Option<Value> },
+}
+
+/// `InvalidInput` is how appends reject a line; every other IO error
+/// stays one
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::InvalidInput => AppError::invalid(e),
+            _ => AppError::Io(e),
+        }
+    }
+}
+
+impl AppError {
+    pub fn invalid(message: impl ToString) -> Self {
+        AppError::Validation { message: message.to_string(), detail: None }
+    }
+
+    /// Extra context for clients, on the variants that carry it
+    pub fn with_detail(self, detail: Value) -> Self {
+        match self {
+            AppError::Validation { message, .. } => AppError::Validation { message, detail: Some(detail) },
+            AppError::Unprocessable { message, .. } => AppError::Unprocessable { message, detail: Some(detail) },
+            other => other,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Io(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                std::io::ErrorKind::StorageFull => StatusCode::INSUFFICIENT_STORAGE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Validation { .. } => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Stable, machine-readable name of the failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Io(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => "log_missing",
+                std::io::ErrorKind::StorageFull => "storage_full",
+                _ => "io_error",
+            },
+            AppError::Validation { .. } => "invalid_input",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::NotFound(_) => "not_found",
+            AppError::TooLarge(_) => "too_large",
+            AppError::Conflict(_) => "conflict",
+            AppError::Unprocessable { .. } => "unprocessable",
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let detail = match self {
+            AppError::Io(e) => Some(serde_json::json!({ "kind": format!("{:?}", e.kind()) })),
+            AppError::Validation { detail, .. } | AppError::Unprocessable { detail, .. } => detail.clone(),
+            AppError::RateLimited { retry_after_secs } => Some(serde_json::json!({ "retry_after_secs": retry_after_secs })),
+            AppError::Unauthorized(_) | AppError::Forbidden(_) | AppError::NotFound(_) | AppError::TooLarge(_) | AppError::Conflict(_) => None,
+        };
+        ErrorBody {
+            error: ErrorInfo { code: self.code().to_string(), message: self.to_string(), detail },
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!(code = self.code(), "{}", self);
+        }
+        let mut response = (status, Json(self.body())).into_response();
+        match self {
+            AppError::Unauthorized(_) => {
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            }
+            AppError::RateLimited { retry_after_secs } => {
+                response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+            }
+            _ => {}
+        }
+        response
+    }
+}
+
+/// Body of every handler error
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorBody {
+    pub error: ErrorInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorInfo {
+    /// `log_missing`, `storage_full`, `io_error`, `invalid_input`,
+    /// `unauthorized`, `forbidden`, `rate_limited`, `not_found`,
+    /// `too_large`, `conflict` or `unprocessable`
+    pub code: String,
+    pub message: String,
+    /// Null unless the error has more to say, e.g. the IO error `kind`
+    pub detail: Option<Value>,
+}
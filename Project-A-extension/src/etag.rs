@@ -0,0 +1,81 @@
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use crate::AppState;
+
+/// Cheap validator derived from the log's length and mtime
+/// Changes on any append, ours or external, without reading the file
+pub fn log_etag(log_path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(log_path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some(format!("W/\"{:x}-{:x}\"", metadata.len(), mtime))
+}
+
+/// `etag` with `part` folded in
+fn extended(etag: &str, part: impl fmt::LowerHex) -> String {
+    format!("{}-{:x}\"", etag.trim_end_matches('"'), part)
+}
+
+/// `etag` for the representation the Accept header picks: JSON, CSV and
+/// the rest of one resource each get their own tag
+fn with_accept(etag: String, accept: Option<&HeaderValue>) -> String {
+    let Some(accept) = accept else { return etag };
+    let mut hasher = DefaultHasher::new();
+    accept.as_bytes().hash(&mut hasher);
+    extended(&etag, hasher.finish())
+}
+
+/// Answer GETs with 304 when If-None-Match still matches, tag everything else
+/// Responses vary with Accept, so caches keep a copy per Accept header
+pub async fn conditional_get(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    respond(request, next, || log_etag(state.read_path())).await
+}
+
+/// `conditional_get` for answers that also move with the clock (elapsed
+/// time, relative windows, "today"): the clock's current minute is part
+/// of the tag, so a copy is reused within that minute at most
+pub async fn conditional_get_by_minute(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let minute = state.clock.now().timestamp().div_euclid(60);
+    respond(request, next, || log_etag(state.read_path()).map(|etag| extended(&etag, minute))).await
+}
+
+async fn respond(request: Request, next: Next, etag: impl FnOnce() -> Option<String>) -> Response {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return next.run(request).await;
+    }
+
+    let Some(etag) = etag() else {
+        return next.run(request).await;
+    };
+    let etag = with_accept(etag, request.headers().get(header::ACCEPT));
+
+    let matches = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    let mut response = if matches {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        next.run(request).await
+    };
+
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+        response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+    response
+}
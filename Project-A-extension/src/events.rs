@@ -0,0 +1,452 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::days::DayBoundary;
+use regex::{Regex, RegexBuilder};
+
+/// The verb of a log line, the one place the grammar is defined
+/// Projectors match on it exhaustively, so a new verb has to be given a
+/// meaning everywhere rather than being picked up by accident
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub enum EventVerb {
+    /// Opens a session, ending the one before
+    Start,
+    /// Ends the active session early
+    Stop,
+    /// STOP appended by the server for a session left running past its
+    /// limit, e.g. `AUTOSTOP THEORY pandas reason=stale`
+    AutoStop,
+    Pause,
+    Resume,
+    /// Free-text annotation on the current session
+    Note,
+    /// Something finished, outside any session
+    Done,
+    /// Goal setting, e.g. `TARGET RATIO THEORY PRACTICE 2.0`
+    Target,
+    /// Alert threshold, e.g. `ALERT-RULE DAILY_CAP 480`
+    AlertRule,
+    /// Named `/query` body, e.g. `SAVEQUERY weekly {"type":"ratios"}`
+    SaveQuery,
+    /// Uppercase word the grammar doesn't know: parsed and listed, but
+    /// ignored by every projection
+    Other(String),
+}
+
+impl EventVerb {
+    /// None unless `word` is an uppercase word (letters and `_`, with
+    /// `-` allowed between them)
+    pub fn parse(word: &str) -> Option<Self> {
+        if word.is_empty()
+            || word.starts_with('-')
+            || word.ends_with('-')
+            || !word.chars().all(|c| c.is_ascii_uppercase() || c == '_' || c == '-')
+        {
+            return None;
+        }
+        Some(match word {
+            "START" => EventVerb::Start,
+            "STOP" => EventVerb::Stop,
+            "AUTOSTOP" => EventVerb::AutoStop,
+            "PAUSE" => EventVerb::Pause,
+            "RESUME" => EventVerb::Resume,
+            "NOTE" => EventVerb::Note,
+            "DONE" => EventVerb::Done,
+            "TARGET" => EventVerb::Target,
+            "ALERT-RULE" => EventVerb::AlertRule,
+            "SAVEQUERY" => EventVerb::SaveQuery,
+            other => EventVerb::Other(other.to_string()),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            EventVerb::Start => "START",
+            EventVerb::Stop => "STOP",
+            EventVerb::AutoStop => "AUTOSTOP",
+            EventVerb::Pause => "PAUSE",
+            EventVerb::Resume => "RESUME",
+            EventVerb::Note => "NOTE",
+            EventVerb::Done => "DONE",
+            EventVerb::Target => "TARGET",
+            EventVerb::AlertRule => "ALERT-RULE",
+            EventVerb::SaveQuery => "SAVEQUERY",
+            EventVerb::Other(word) => word,
+        }
+    }
+
+    /// Whether the event counts toward ratios: only activity starts do
+    /// STOP/PAUSE/RESUME would count a session twice, NOTE annotates one,
+    /// and a DONE isn't time put into its category
+    pub fn counts_toward_ratios(&self) -> bool {
+        match self {
+            EventVerb::Start => true,
+            EventVerb::Done
+            | EventVerb::Stop
+            | EventVerb::AutoStop
+            | EventVerb::Pause
+            | EventVerb::Resume
+            | EventVerb::Note
+            | EventVerb::Target
+            | EventVerb::AlertRule
+            | EventVerb::SaveQuery
+            | EventVerb::Other(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for EventVerb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<EventVerb> for String {
+    fn from(verb: EventVerb) -> Self {
+        verb.as_str().to_string()
+    }
+}
+
+impl From<String> for EventVerb {
+    fn from(word: String) -> Self {
+        EventVerb::parse(&word).unwrap_or(EventVerb::Other(word))
+    }
+}
+
+/// Event parsed from a single log line
+/// Line format: `[<rfc3339 timestamp>] VERB CATEGORY ACTIVITY [key=value ...]`
+/// The timestamp is optional so pre-timestamp history still parses
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ParsedEvent {
+    pub timestamp: Option<DateTime<Utc>>,
+    #[schema(value_type = String, example = "START")]
+    pub verb: EventVerb,
+    pub category: Option<String>,
+    pub activity: Option<String>,
+    /// `key=value` tokens after the activity
+    pub metadata: BTreeMap<String, String>,
+    /// Position in the log, 0 for a line parsed on its own
+    #[serde(skip)]
+    pub index: usize,
+    /// The line as logged
+    #[serde(skip)]
+    pub line: String,
+}
+
+/// Parse one log line
+/// Returns None for blank lines, comments and lines whose verb isn't an
+/// uppercase word
+pub fn parse_event(line: &str) -> Option<ParsedEvent> {
+    if is_comment(line) {
+        return None;
+    }
+    let mut parts = line.split_whitespace().peekable();
+
+    // Leading timestamp is detected, not required
+    let timestamp = parts
+        .peek()
+        .and_then(|first| DateTime::parse_from_rfc3339(first).ok())
+        .map(|ts| ts.with_timezone(&Utc));
+    if timestamp.is_some() {
+        parts.next();
+    }
+
+    let verb = EventVerb::parse(parts.next()?)?;
+    // Category and activity are the plain words up to the first tag, so
+    // `START THEORY focus=high` has no activity rather than a tag for one
+    let category = parts.next_if(|token| tag(token).is_none()).map(|s| s.to_string());
+    let activity = category
+        .as_ref()
+        .and_then(|_| parts.next_if(|token| tag(token).is_none()))
+        .map(|s| s.to_string());
+    let metadata = parts
+        .filter_map(tag)
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    Some(ParsedEvent {
+        timestamp,
+        verb,
+        category,
+        activity,
+        metadata,
+        index: 0,
+        line: line.to_string(),
+    })
+}
+
+/// Every event in `lines`, numbered by its position in the log
+pub fn parse_log(lines: &[String]) -> Vec<ParsedEvent> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| parse_event(line).map(|event| ParsedEvent { index, ..event }))
+        .collect()
+}
+
+/// A `key=value` token, both sides non-empty
+fn tag(token: &str) -> Option<(&str, &str)> {
+    token.split_once('=').filter(|(key, value)| !key.is_empty() && !value.is_empty())
+}
+
+/// `# ...` annotation, optionally after a timestamp (appends get stamped)
+/// Kept in the log and listed with the events, but never an event itself
+pub fn is_comment(line: &str) -> bool {
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some(first) if DateTime::parse_from_rfc3339(first).is_ok() => parts.next(),
+        first => first,
+    }
+    .is_some_and(|token| token.starts_with('#'))
+}
+
+/// Stamp a line with the given time unless it already carries one
+pub fn stamp_line(line: &str, now: DateTime<Utc>) -> String {
+    match parse_event(line) {
+        Some(event) if event.timestamp.is_some() => line.to_string(),
+        _ => format!("{} {}", now.to_rfc3339(), line),
+    }
+}
+
+/// Longest `pattern` accepted on event listings
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Compiled-program size cap for `pattern`, so a pathological regex is
+/// rejected at compile time rather than eating memory
+/// (the regex crate matches in linear time, so matching can't blow up)
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// Compile a user-supplied pattern, rejecting overlong or oversized ones
+/// The error is the regex engine's message, suitable for a 400 body
+pub fn compile_pattern(pattern: &str) -> Result<Regex, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("Pattern longer than {} characters", MAX_PATTERN_LEN));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .dfa_size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Filter applied to event listings (category, date range and/or regex)
+/// Events without a timestamp never match a date range
+#[derive(Debug, Default, Clone)]
+pub struct EventFilter {
+    pub category: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Matched against the raw line, so unparseable lines can match too
+    pub pattern: Option<Regex>,
+    /// Leave out `# ...` comment lines
+    pub hide_comments: bool,
+}
+
+impl EventFilter {
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none()
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.pattern.is_none()
+            && !self.hide_comments
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if self.hide_comments && is_comment(line) {
+            return false;
+        }
+        if self.pattern.as_ref().is_some_and(|p| !p.is_match(line)) {
+            return false;
+        }
+        if self.category.is_none() && self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        let Some(event) = parse_event(line) else { return false };
+
+        if let Some(category) = &self.category {
+            if !event.category.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(category)) {
+                return false;
+            }
+        }
+
+        if self.from.is_some() || self.to.is_some() {
+            let Some(ts) = event.timestamp else { return false };
+            if self.from.is_some_and(|from| ts < from) || self.to.is_some_and(|to| ts > to) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Parse a range bound given as RFC3339 or a plain `YYYY-MM-DD` date
+/// Plain dates are days as `days` defines them; as an upper bound they cover the whole day
+pub fn parse_bound(value: &str, upper: bool, days: &DayBoundary) -> Option<DateTime<Utc>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Some(ts.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    if upper {
+        Some(days.day_start(date.succ_opt()?) - chrono::Duration::nanoseconds(1))
+    } else {
+        Some(days.day_start(date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_filter() {
+        let filter = EventFilter {
+            pattern: Some(compile_pattern(r"^(\S+ )?START \S+ \S*book$").unwrap()),
+            ..Default::default()
+        };
+        assert!(filter.matches("START THEORY rustbook"));
+        assert!(filter.matches("2024-01-01T10:00:00Z START THEORY pandas-book"));
+        assert!(!filter.matches("NOTE THEORY rustbook"));
+        assert!(!filter.matches("START THEORY bookclub"));
+
+        assert!(compile_pattern("(unclosed").unwrap_err().contains("unclosed"));
+        assert!(compile_pattern(&"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+        // Compiles to far more than the size limit
+        assert!(compile_pattern(r"\w{1000}\w{1000}").is_err());
+    }
+
+    #[test]
+    fn test_parse_untimestamped_line() {
+        let event = parse_event("START THEORY pandas").unwrap();
+        assert_eq!(event.timestamp, None);
+        assert_eq!(event.verb, EventVerb::Start);
+        assert_eq!(event.category.as_deref(), Some("THEORY"));
+        assert_eq!(event.activity.as_deref(), Some("pandas"));
+    }
+
+    #[test]
+    fn test_parse_log_keeps_positions() {
+        let lines: Vec<String> = ["START THEORY pandas", "not an event", "STOP THEORY pandas"]
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let events = parse_log(&lines);
+        assert_eq!(events.iter().map(|e| e.index).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(events[1].line, "STOP THEORY pandas");
+    }
+
+    #[test]
+    fn test_parse_timestamped_line() {
+        let event = parse_event("2024-01-02T10:00:00Z START THEORY pandas").unwrap();
+        assert_eq!(
+            event.timestamp.map(|t| t.to_rfc3339()),
+            Some("2024-01-02T10:00:00+00:00".to_string())
+        );
+        assert_eq!(event.verb, EventVerb::Start);
+        assert_eq!(event.category.as_deref(), Some("THEORY"));
+    }
+
+    #[test]
+    fn test_event_verbs() {
+        assert_eq!(parse_event("STOP THEORY pandas").unwrap().verb, EventVerb::Stop);
+        assert_eq!(parse_event("DONE TASK refactor").unwrap().verb, EventVerb::Done);
+        assert_eq!(parse_event("AUTOSTOP THEORY pandas reason=stale").unwrap().verb, EventVerb::AutoStop);
+
+        // Unknown uppercase words parse, but as Other, and round-trip as text
+        let event = parse_event("LEARN THEORY pandas").unwrap();
+        assert_eq!(event.verb, EventVerb::Other("LEARN".to_string()));
+        assert!(!event.verb.counts_toward_ratios());
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["verb"], "LEARN");
+        assert_eq!(serde_json::from_value::<ParsedEvent>(json).unwrap().verb, event.verb);
+
+        assert_eq!(EventVerb::parse("Start"), None);
+        assert_eq!(EventVerb::parse("-START"), None);
+        assert_eq!(parse_event("ALERT-RULE DAILY_CAP 480").unwrap().verb, EventVerb::AlertRule);
+        assert_eq!(parse_event(r#"SAVEQUERY weekly {"type": "ratios"}"#).unwrap().verb, EventVerb::SaveQuery);
+        assert_eq!(EventVerb::parse("START").unwrap().to_string(), "START");
+    }
+
+    #[test]
+    fn test_parse_metadata_after_activity() {
+        let event = parse_event("START PRACTICE api project=api difficulty=3 notes =x y=").unwrap();
+        assert_eq!(event.activity.as_deref(), Some("api"));
+        assert_eq!(event.metadata.len(), 2);
+        assert_eq!(event.metadata["project"], "api");
+        assert_eq!(event.metadata["difficulty"], "3");
+        assert!(parse_event("START THEORY pandas").unwrap().metadata.is_empty());
+    }
+
+    #[test]
+    fn test_tags_start_after_the_activity() {
+        let event = parse_event("2024-01-01T09:00:00Z START THEORY pandas focus=high mood=tired focus=low").unwrap();
+        assert_eq!((event.category.as_deref(), event.activity.as_deref()), (Some("THEORY"), Some("pandas")));
+        // Repeated keys: the last one wins
+        assert_eq!(event.metadata.len(), 2);
+        assert_eq!((event.metadata["focus"].as_str(), event.metadata["mood"].as_str()), ("low", "tired"));
+
+        // A tag is never taken for the category or activity
+        let event = parse_event("START THEORY focus=high mood=tired").unwrap();
+        assert_eq!((event.category.as_deref(), event.activity), (Some("THEORY"), None));
+        assert_eq!(event.metadata.len(), 2);
+        let event = parse_event("START focus=high").unwrap();
+        assert_eq!((event.category, event.activity), (None, None));
+        // Plain words between tags are skipped; values keep any later `=`
+        let event = parse_event("START THEORY pandas focus=high chapter 3 url=a=b").unwrap();
+        assert_eq!((event.metadata.len(), event.metadata["url"].as_str()), (2, "a=b"));
+    }
+
+    #[test]
+    fn test_parse_rejects_non_verb_lines() {
+        assert!(parse_event("").is_none());
+        assert!(parse_event("started theory at some point").is_none());
+        assert!(parse_event("2024-01-02T10:00:00Z").is_none());
+    }
+
+    #[test]
+    fn test_comments_are_not_events() {
+        for line in ["# START THEORY pandas", "  #TODO", "2024-01-02T10:00:00Z # back from lunch"] {
+            assert!(is_comment(line), "{}", line);
+            assert!(parse_event(line).is_none(), "{}", line);
+        }
+        assert!(!is_comment("NOTE THEORY see #42"));
+        assert!(parse_event("NOTE THEORY see #42").is_some());
+
+        let filter = EventFilter { hide_comments: true, ..Default::default() };
+        assert!(!filter.is_empty());
+        assert!(!filter.matches("# START THEORY pandas"));
+        assert!(filter.matches("START THEORY pandas"));
+        // Lines that are neither comments nor events still pass
+        assert!(filter.matches("started theory"));
+    }
+
+    #[test]
+    fn test_stamp_line_keeps_existing_timestamp() {
+        let now = Utc::now();
+        let line = "2024-01-02T10:00:00Z START THEORY pandas";
+        assert_eq!(stamp_line(line, now), line);
+        assert!(stamp_line("START GAME valorant", now).ends_with(" START GAME valorant"));
+    }
+
+    #[test]
+    fn test_event_filter_category_and_range() {
+        let filter = EventFilter {
+            category: Some("theory".to_string()),
+            from: parse_bound("2024-01-02", false, &DayBoundary::default()),
+            to: parse_bound("2024-01-02", true, &DayBoundary::default()),
+            pattern: None,
+            hide_comments: false,
+        };
+
+        assert!(filter.matches("2024-01-02T23:00:00Z START THEORY pandas"));
+        assert!(!filter.matches("2024-01-03T00:00:00Z START THEORY pandas"));
+        assert!(!filter.matches("2024-01-02T10:00:00Z START GAME valorant"));
+        assert!(!filter.matches("START THEORY pandas"));
+    }
+}
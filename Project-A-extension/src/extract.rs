@@ -0,0 +1,82 @@
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use serde::de::DeserializeOwned;
+use crate::error::AppError;
+
+/// `axum::extract::Query` whose rejection is an `AppError`, so a bad
+/// query string is answered with the same JSON body as any other
+/// invalid input
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+/// `axum::Json` as a request body, rejected the same way as `Query`
+/// except that a body over the limit stays a 413
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(|rejection| AppError::invalid(rejection.body_text()))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        axum::Json::from_request(request, state)
+            .await
+            .map(|axum::Json(value)| Json(value))
+            .map_err(|rejection| match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => AppError::TooLarge(rejection.body_text()),
+                _ => AppError::invalid(rejection.body_text()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Params {
+        #[allow(dead_code)]
+        n: usize,
+    }
+
+    #[tokio::test]
+    async fn test_rejections_are_validation_errors() {
+        let (mut parts, _) = Request::builder().uri("/?n=abc").body(()).unwrap().into_parts();
+        let err = Query::<Params>::from_request_parts(&mut parts, &()).await.unwrap_err();
+        assert_eq!(err.code(), "invalid_input");
+        assert!(err.to_string().contains("invalid digit"), "{}", err);
+
+        let request = Request::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"m":1}"#))
+            .unwrap();
+        let err = Json::<Params>::from_request(request, &()).await.unwrap_err();
+        assert_eq!(err.code(), "invalid_input");
+        assert!(err.to_string().contains("missing field `n`"), "{}", err);
+    }
+}
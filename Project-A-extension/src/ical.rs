@@ -0,0 +1,147 @@
+use chrono::{DateTime, Utc};
+use crate::models::Session;
+
+/// Content type of the calendar export
+pub const ICALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// Longest content line RFC 5545 allows, in octets, before folding
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Sessions as a VCALENDAR, one VEVENT per closed session with both
+/// timestamps; the summary is "CATEGORY: activity" and the description
+/// holds the session's notes
+/// Closed sessions missing a timestamp can't be placed on a calendar and
+/// are counted in `X-PROJECT-A-UNTIMED-SESSIONS`; the active one is left out
+pub fn calendar(sessions: &[(Session, Vec<String>)]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Project A//Sessions//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    let mut events = Vec::new();
+    let mut untimed = 0;
+    for (session, notes) in sessions.iter().filter(|(s, _)| !s.is_active) {
+        let (Some(start), Some(end)) = (session.start_time, session.end_time) else {
+            untimed += 1;
+            continue;
+        };
+        events.push("BEGIN:VEVENT".to_string());
+        events.push(format!("UID:session-{}@project-a", session.start_event_idx));
+        // When the session ended is when its entry became final
+        events.push(format!("DTSTAMP:{}", timestamp(end)));
+        events.push(format!("DTSTART:{}", timestamp(start)));
+        events.push(format!("DTEND:{}", timestamp(end)));
+        events.push(format!("SUMMARY:{}", escape(&format!("{}: {}", session.category, session.activity))));
+        events.push(format!("CATEGORIES:{}", escape(&session.category)));
+        if !notes.is_empty() {
+            events.push(format!("DESCRIPTION:{}", escape(&notes.join("\n"))));
+        }
+        events.push("END:VEVENT".to_string());
+    }
+    lines.push(format!("X-PROJECT-A-UNTIMED-SESSIONS:{}", untimed));
+    lines.extend(events);
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in &lines {
+        fold(line, &mut out);
+    }
+    out
+}
+
+/// UTC date-time form, e.g. `20240101T090000Z`
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// TEXT value escaping: backslash, semicolon, comma and newlines
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Appends `line` CRLF-terminated, folded so no physical line passes
+/// 75 octets; continuations start with a space and never split a
+/// multi-byte character
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn session(idx: usize, start: Option<&str>, end: Option<&str>, is_active: bool) -> Session {
+        Session {
+            category: "THEORY".to_string(),
+            activity: "pandas".to_string(),
+            start_event_idx: idx,
+            end_event_idx: None,
+            is_active,
+            start_time: start.map(|s| s.parse().unwrap()),
+            end_time: end.map(|s| s.parse().unwrap()),
+            duration_minutes: None,
+            gross_minutes: None,
+            metadata: BTreeMap::new(),
+            closed_by: None,
+            spans_days: false,
+            fragments: None,
+        }
+    }
+
+    #[test]
+    fn test_calendar_events_and_untimed_count() {
+        let sessions = vec![
+            (session(0, Some("2024-01-01T09:00:00Z"), Some("2024-01-01T10:30:00Z"), false), vec!["groupby, then merge".to_string()]),
+            (session(2, None, None, false), vec![]),
+            (session(3, Some("2024-01-01T11:00:00Z"), None, true), vec![]),
+        ];
+        let ics = calendar(&sessions);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("\r\nUID:session-0@project-a\r\n"));
+        assert!(ics.contains("\r\nDTSTART:20240101T090000Z\r\nDTEND:20240101T103000Z\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:THEORY: pandas\r\n"));
+        assert!(ics.contains("\r\nDESCRIPTION:groupby\\, then merge\r\n"));
+        // The untimed session is counted, the active one just left out
+        assert!(ics.contains("\r\nX-PROJECT-A-UNTIMED-SESSIONS:1\r\n"));
+        assert!(!ics.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn test_escape_and_fold() {
+        assert_eq!(escape("a;b,c\\d\r\ne"), "a\\;b\\,c\\\\d\\ne");
+
+        let mut out = String::new();
+        fold(&format!("DESCRIPTION:{}", "é".repeat(40)), &mut out);
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1].starts_with(' '));
+        // Unfolding gives the line back
+        assert_eq!(out.replace("\r\n ", ""), format!("DESCRIPTION:{}\r\n", "é".repeat(40)));
+    }
+}
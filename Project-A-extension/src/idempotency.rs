@@ -0,0 +1,84 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Keys clients sent with POST /events, mapped to the response they got
+/// Bounded LRU: once full, the least recently used key is forgotten
+#[derive(Clone)]
+pub struct IdempotencyKeys {
+    inner: Arc<Mutex<Lru>>,
+}
+
+pub struct Lru {
+    capacity: usize,
+    entries: HashMap<String, serde_json::Value>,
+    order: VecDeque<String>,
+}
+
+impl IdempotencyKeys {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru {
+                capacity: capacity.max(1),
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Hold the key table for a whole check-append-remember sequence, so
+    /// two concurrent retries with the same key can't both append
+    pub async fn lock(&self) -> MutexGuard<'_, Lru> {
+        self.inner.lock().await
+    }
+}
+
+impl Lru {
+    /// Response previously recorded for `key`, marking it recently used
+    pub fn get(&mut self, key: &str) -> Option<serde_json::Value> {
+        let value = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: String, value: serde_json::Value) {
+        if self.entries.insert(key.clone(), value).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_least_recently_used_key_evicted() {
+        let keys = IdempotencyKeys::new(2);
+        let mut lru = keys.lock().await;
+        lru.insert("a".to_string(), json!(1));
+        lru.insert("b".to_string(), json!(2));
+        assert_eq!(lru.get("a"), Some(json!(1)));
+
+        lru.insert("c".to_string(), json!(3));
+        assert_eq!(lru.get("b"), None);
+        assert_eq!(lru.get("a"), Some(json!(1)));
+        assert_eq!(lru.get("c"), Some(json!(3)));
+    }
+}
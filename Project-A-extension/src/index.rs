@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::models::IndexedEvent;
+
+/// Trailing line offsets kept, enough for typical `since` catch-ups
+pub const DEFAULT_RECENT_LINES: usize = 1024;
+
+/// Event count and where the most recent lines start, kept in step with
+/// the log so counts and short catch-up reads never rescan the file
+/// Only complete, non-empty lines are indexed, matching event numbering
+/// everywhere else
+#[derive(Clone)]
+pub struct EventIndex {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The file the offsets are into: the log, or its fallback while the
+    /// log can't be read
+    path: Option<PathBuf>,
+    /// Bytes indexed so far; always just past a newline
+    scanned: u64,
+    count: usize,
+    /// Start offsets of the last `capacity` events, oldest first
+    recent: VecDeque<u64>,
+    capacity: usize,
+}
+
+impl EventIndex {
+    /// Empty index keeping the offsets of the last `capacity` events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner { capacity, ..Inner::default() })),
+        }
+    }
+
+    /// Index an existing log from the start (missing is empty)
+    pub fn build(log_path: &Path, capacity: usize) -> std::io::Result<Self> {
+        let index = Self::new(capacity);
+        index.scan(log_path, |_| {})?;
+        Ok(index)
+    }
+
+    /// Forget what was indexed and index the log again from the start, for
+    /// when lines before the end changed
+    pub fn rebuild(&self, log_path: &Path) -> std::io::Result<()> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            *inner = Inner { capacity: inner.capacity, ..Inner::default() };
+        }
+        self.scan(log_path, |_| {})
+    }
+
+    /// Index lines appended since the last update and return them
+    /// A trailing partial line is left for the next call
+    /// Given another file than last time (failing over to the fallback or
+    /// back), that one is indexed from the start, returning only events
+    /// numbered past those already returned
+    pub fn update(&self, log_path: &Path) -> std::io::Result<Vec<IndexedEvent>> {
+        let mut events = Vec::new();
+        self.scan(log_path, |event| events.push(event))?;
+        Ok(events)
+    }
+
+    /// Number of complete events indexed
+    pub fn count(&self) -> usize {
+        self.inner.lock().unwrap().count
+    }
+
+    /// Bytes indexed: the log's size up to its last complete line
+    pub fn bytes(&self) -> u64 {
+        self.inner.lock().unwrap().scanned
+    }
+
+    /// `bytes()` and `count()` read together, so an update can't land
+    /// between them
+    pub fn position(&self) -> (u64, usize) {
+        let inner = self.inner.lock().unwrap();
+        (inner.scanned, inner.count)
+    }
+
+    /// Whether the offsets are into `path`
+    pub fn indexes(&self, path: &Path) -> bool {
+        self.inner.lock().unwrap().path.as_deref() == Some(path)
+    }
+
+    /// Byte offset where event `idx` starts, if it's one of the recent
+    /// ones; `idx == count` gives the end of the indexed bytes
+    pub fn offset_of(&self, idx: usize) -> Option<u64> {
+        let inner = self.inner.lock().unwrap();
+        if idx == inner.count {
+            return Some(inner.scanned);
+        }
+        let first = inner.count - inner.recent.len();
+        idx.checked_sub(first).and_then(|i| inner.recent.get(i).copied())
+    }
+
+    fn scan(&self, log_path: &Path, mut on_event: impl FnMut(IndexedEvent)) -> std::io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut returned = 0;
+        if inner.path.as_deref() != Some(log_path) {
+            returned = inner.count;
+            *inner = Inner { capacity: inner.capacity, path: Some(log_path.to_path_buf()), ..Inner::default() };
+        }
+
+        let mut file = match File::open(log_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        // Log was replaced by something shorter: start over
+        if file.metadata()?.len() < inner.scanned {
+            *inner = Inner { capacity: inner.capacity, path: inner.path.take(), ..Inner::default() };
+            returned = 0;
+        }
+
+        file.seek(SeekFrom::Start(inner.scanned))?;
+        let mut reader = BufReader::new(file);
+        let mut segment = Vec::new();
+        loop {
+            segment.clear();
+            let read = reader.read_until(b'\n', &mut segment)?;
+            if read == 0 || segment.last() != Some(&b'\n') {
+                break;
+            }
+
+            let start = inner.scanned;
+            inner.scanned += read as u64;
+            let line = String::from_utf8_lossy(&segment);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            inner.recent.push_back(start);
+            if inner.recent.len() > inner.capacity {
+                inner.recent.pop_front();
+            }
+            if inner.count >= returned {
+                on_event(IndexedEvent {
+                    idx: inner.count,
+                    line: line.to_string(),
+                });
+            }
+            inner.count += 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Events from `since` up to the indexed count, read from the recorded
+/// offset instead of the start of the file
+/// None when `since` is older than the recent offsets kept, or the index
+/// is for another file
+pub fn read_since(index: &EventIndex, log_path: &Path, since: usize) -> std::io::Result<Option<Vec<IndexedEvent>>> {
+    if !index.indexes(log_path) {
+        return Ok(None);
+    }
+    let count = index.count();
+    let Some(offset) = index.offset_of(since.min(count)) else {
+        return Ok(None);
+    };
+    if since >= count {
+        return Ok(Some(Vec::new()));
+    }
+
+    let mut file = File::open(log_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let events = crate::reader::lossy_lines(BufReader::new(file))
+        .filter(|line| !line.trim().is_empty())
+        .take(count - since)
+        .enumerate()
+        .map(|(i, line)| IndexedEvent { idx: since + i, line })
+        .collect();
+    Ok(Some(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_rebuilds_from_existing_log() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file).unwrap();
+        write!(temp_file, "START GAME valorant\r\n  \nNOTE café\n").unwrap();
+        write!(temp_file, "START PRAC").unwrap();
+
+        let index = EventIndex::build(temp_file.path(), 2).unwrap();
+        assert_eq!(index.count(), 3);
+        // Only the last two offsets are kept
+        assert_eq!(index.offset_of(0), None);
+        assert_eq!(index.offset_of(1), Some(21));
+        assert_eq!(index.offset_of(2), Some(45));
+        assert_eq!(index.offset_of(3), Some(56));
+        assert_eq!(index.offset_of(4), None);
+
+        let events = read_since(&index, temp_file.path(), 1).unwrap().unwrap();
+        let lines: Vec<_> = events.iter().map(|e| (e.idx, e.line.as_str())).collect();
+        // The partial line isn't indexed yet, so it isn't returned either
+        assert_eq!(lines, vec![(1, "START GAME valorant"), (2, "NOTE café")]);
+        assert!(read_since(&index, temp_file.path(), 0).unwrap().is_none());
+        assert!(read_since(&index, temp_file.path(), 9).unwrap().unwrap().is_empty());
+
+        let missing = EventIndex::build(Path::new("/nonexistent/master.log"), 2).unwrap();
+        assert_eq!(missing.count(), 0);
+    }
+
+    #[test]
+    fn test_read_since_decodes_like_the_scan() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b"START THEORY pandas\nNOTE bad \xff bytes\nSTART GAME chess\n").unwrap();
+        let index = EventIndex::build(temp_file.path(), 2).unwrap();
+
+        let events = read_since(&index, temp_file.path(), 1).unwrap().unwrap();
+        let lines: Vec<_> = events.iter().map(|e| (e.idx, e.line.as_str())).collect();
+        assert_eq!(lines, vec![(1, "NOTE bad \u{FFFD} bytes"), (2, "START GAME chess")]);
+    }
+
+    #[test]
+    fn test_switching_files_returns_only_later_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let (primary, backup) = (dir.path().join("master.log"), dir.path().join("backup.log"));
+        std::fs::write(&primary, "START THEORY pandas\n").unwrap();
+        std::fs::write(&backup, "START THEORY pandas\nSTART GAME chess\n").unwrap();
+        let index = EventIndex::build(&primary, 2).unwrap();
+
+        let events = index.update(&backup).unwrap();
+        assert_eq!(events.iter().map(|e| (e.idx, e.line.as_str())).collect::<Vec<_>>(), [(1, "START GAME chess")]);
+        assert!(index.indexes(&backup) && !index.indexes(&primary));
+        assert_eq!(index.count(), 2);
+        assert!(read_since(&index, &primary, 1).unwrap().is_none());
+        assert_eq!(read_since(&index, &backup, 1).unwrap().unwrap()[0].line, "START GAME chess");
+    }
+
+    #[test]
+    fn test_count_tracks_appends() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        let index = EventIndex::build(temp_file.path(), DEFAULT_RECENT_LINES).unwrap();
+
+        for i in 0..100 {
+            writeln!(temp_file, "START PRACTICE rust{}", i).unwrap();
+            if i % 10 == 0 {
+                writeln!(temp_file).unwrap();
+            }
+            if i % 7 == 0 {
+                index.update(temp_file.path()).unwrap();
+            }
+        }
+        write!(temp_file, "START PRAC").unwrap();
+        let new = index.update(temp_file.path()).unwrap();
+        assert_eq!(new.last().unwrap().idx, 100);
+        assert_eq!(index.count(), 101);
+
+        writeln!(temp_file, "TICE done").unwrap();
+        let new = index.update(temp_file.path()).unwrap();
+        assert_eq!((new[0].idx, new[0].line.as_str()), (101, "START PRACTICE done"));
+
+        // A fresh scan agrees with the incrementally maintained index
+        let rebuilt = EventIndex::build(temp_file.path(), DEFAULT_RECENT_LINES).unwrap();
+        assert_eq!(rebuilt.count(), index.count());
+        assert_eq!(rebuilt.offset_of(50), index.offset_of(50));
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Directives used when RUST_LOG is unset
+pub const DEFAULT_FILTER: &str = "info";
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LogFormat {
+    /// One human-readable line per event
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Log format must be text or json, got {}", other)),
+        }
+    }
+}
+
+/// Log to stderr (stdout is the `project` command's output) with the
+/// filter from RUST_LOG; an invalid RUST_LOG falls back to the default
+/// after saying so
+pub fn init(format: LogFormat) {
+    let (filter, invalid) = match std::env::var("RUST_LOG") {
+        Ok(directives) => match EnvFilter::try_new(&directives) {
+            Ok(filter) => (filter, None),
+            Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(e)),
+        },
+        Err(_) => (EnvFilter::new(DEFAULT_FILTER), None),
+    };
+    let ansi = format == LogFormat::Text && std::io::stderr().is_terminal();
+    if tracing::subscriber::set_global_default(subscriber(format, filter, std::io::stderr, ansi)).is_err() {
+        return;
+    }
+    if let Some(e) = invalid {
+        tracing::warn!(error = %e, "Ignoring RUST_LOG");
+    }
+}
+
+/// Each event on one line with the spans it happened in
+pub fn subscriber<W>(format: LogFormat, filter: EnvFilter, writer: W, ansi: bool) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().with_current_span(false).finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use serde_json::Value;
+
+    /// Everything written, shared with the test
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn logged(format: LogFormat, filter: &str, log: impl FnOnce()) -> Vec<String> {
+        let buffer = Buffer::default();
+        let subscriber = subscriber(format, EnvFilter::try_new(filter).unwrap(), buffer.clone(), false);
+        tracing::subscriber::with_default(subscriber, log);
+        let bytes = buffer.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn request() {
+        let span = tracing::info_span!("request", method = "POST", path = "/events");
+        let _entered = span.enter();
+        let append = tracing::info_span!("append", bytes_written = tracing::field::Empty);
+        let _appended = append.enter();
+        tracing::debug!("not shown at info");
+        append.record("bytes_written", 42);
+        tracing::info!(lines = 3_u64, "appended");
+    }
+
+    #[test]
+    fn test_text_lines_carry_their_spans() {
+        let lines = logged(LogFormat::Text, "info", request);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(
+            lines[0].ends_with(
+                " INFO request{method=\"POST\" path=\"/events\"}:append{bytes_written=42}: project_a_api::logging::tests: appended lines=3"
+            ),
+            "{}",
+            lines[0]
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let lines = logged(LogFormat::Json, "warn,project_a_api::logging=info", request);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"], serde_json::json!({ "message": "appended", "lines": 3 }));
+        assert_eq!(
+            line["spans"],
+            serde_json::json!([
+                { "name": "request", "method": "POST", "path": "/events" },
+                { "name": "append", "bytes_written": 42 },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_filter_directives() {
+        assert_eq!(logged(LogFormat::Text, "warn,project_a_api::logging=debug", request).len(), 2);
+        assert!(logged(LogFormat::Text, "off", request).is_empty());
+        assert!(EnvFilter::try_new("info,tower_http=loud").is_err());
+    }
+}
@@ -1,24 +1,156 @@
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
+    response::{Html, IntoResponse},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
     Router,
     Json,
     http::StatusCode,
 };
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
-use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use chrono::{DateTime, Utc};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use utoipa_swagger_ui::SwaggerUi;
 
+mod alerts;
+mod auth;
+mod aliases;
+mod autostop;
+mod cache;
+mod clock;
+mod config;
+mod days;
+mod display;
+mod error;
+mod etag;
+mod events;
+mod extract;
+mod ical;
+mod idempotency;
+mod index;
+mod logging;
+mod metadata;
+mod metrics;
 mod models;
+mod negotiate;
+mod openapi;
+mod pretty;
 mod projections;
+mod queries;
+mod ratelimit;
+mod reader;
+mod report;
+mod registry;
+mod saved;
+mod search;
+mod snapshot;
+mod stream;
+mod tail;
+mod verify;
+mod watcher;
+mod ws;
 
 #[cfg(test)]
+#[allow(clippy::module_inception, unused_mut, clippy::writeln_empty_string)]
 mod tests;
 
-use models::{EventInput, ApiResponse, QueryResult};
-use projections::{SessionProjector, RatioAnalyzer};
+use models::{EventInput, ParseInput, ParseResult, ApiResponse, QueryResponse, QueryInput, SearchParams, TailParams, IndexedEvent, EventsParams, StreamParams, ContextSwitchParams, EventRecord, DailyParams, RollupParams, ActivityParams, TopParams, StaleParams, GapParams, RangeParams, StreakParams, RecordsParams, TransitionParams, SwitchingParams, MovingAverageParams, ForecastParams, HeatmapParams, SessionStatsParams, RatiosParams, RatioWeight, RollingParams, SessionsParams, TargetParams, StatusParams, StatusFormat, SessionDetailParams, ReportParams, ReportFormat, RebuildReport, Status, Session, DayMetric, AllocationParams, DurationHistogramParams, TagParams};
+use events::EventFilter;
+use projections::{SessionProjector, RatioAnalyzer, LogSpan};
+use reader::EventReader;
+use registry::{ProjectionError, ProjectorRegistry, QueryLog, QuerySettings};
+use search::LogSearcher;
+use alerts::AlertRules;
+use autostop::AutoStopConfig;
+use aliases::CategoryAliases;
+use display::CategoryDisplayConfig;
+use days::{DayBoundary, DayZone, Period, WeekStart, WorkingHours};
+use cache::ProjectionCache;
+use clock::{SharedClock, SystemClock};
+use auth::ApiToken;
+use config::{Config, ConfigReport};
+use error::AppError;
+use idempotency::IdempotencyKeys;
+use snapshot::ProjectionSnapshot;
+use stream::EventBroadcaster;
+use index::EventIndex;
+use metrics::{Gauges, ServiceMetrics};
+use ratelimit::RateLimiter;
+use negotiate::Format;
+
+/// Default cap on search results
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+/// Default number of events returned by /events/tail
+const DEFAULT_TAIL_SIZE: usize = 20;
+
+/// Lines buffered ahead of a slow streaming client
+const STREAM_BUFFER: usize = 16;
+
+/// Representations GET /events can answer with
+const EVENT_FORMATS: &[Format] = &[Format::Json, Format::Csv, Format::Ndjson, Format::Text];
+
+/// Read size for GET /log/raw
+const RAW_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Comment frames keep idle SSE connections alive through proxies
+const SSE_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// Default per-request timeout, override with REQUEST_TIMEOUT_MS
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sessions shorter than this count as context switches by default
+const DEFAULT_SWITCH_THRESHOLD_MINUTES: f64 = 5.0;
+
+/// Default cap on event length, override with MAX_EVENT_LEN
+const DEFAULT_MAX_EVENT_LEN: usize = 1024;
+
+/// Default cap on POST request bodies, override with MAX_BODY_BYTES
+/// Real payloads are an event line or a small query, far below this
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Gaps shorter than this aren't reported by default
+const DEFAULT_GAP_MINUTES: f64 = 30.0;
+
+/// Default days per moving average
+const DEFAULT_MOVING_AVERAGE_DAYS: usize = 7;
+
+/// Longest moving average window accepted
+const MAX_MOVING_AVERAGE_DAYS: usize = 366;
+
+/// Default weeks a forecast projects
+const DEFAULT_FORECAST_HORIZON: usize = 4;
+
+/// Most weeks a forecast projects
+const MAX_FORECAST_HORIZON: usize = 52;
+
+/// Default switches a day may have before it's flagged as high-switch
+const DEFAULT_DAILY_SWITCH_THRESHOLD: usize = 5;
+
+/// Default break after which a session no longer "follows" the last
+const DEFAULT_TRANSITION_GAP_HOURS: f64 = 8.0;
+
+/// Defaults for GET /projections/ratios/rolling
+const DEFAULT_ROLLING_WINDOW: &str = "7d";
+const DEFAULT_ROLLING_STEP: &str = "1d";
+
+/// Default row count for GET /projections/top
+const DEFAULT_TOP_N: usize = 10;
+
+/// Default histogram bucket width for GET /projections/sessions/stats
+const DEFAULT_BUCKET_MINUTES: f64 = 15.0;
+
+/// How many idempotency keys POST /events remembers
+const IDEMPOTENCY_KEY_CAPACITY: usize = 1024;
 
 /// Event-driven HTTP API
 /// Never edits master.log, only appends
@@ -26,179 +158,2810 @@ use projections::{SessionProjector, RatioAnalyzer};
 #[derive(Clone)]
 struct AppState {
     log_path: PathBuf,
+    /// A synced copy reads go to while `log_path` is missing or
+    /// unreadable; writes only ever go to `log_path`
+    fallback_log_path: Option<PathBuf>,
+    /// Shared, cached copy of the log's lines for projections and reads
+    reader: EventReader,
+    /// Unrecognized free-text queries fall back to recent events
+    legacy_query_fallback: bool,
+    cache: ProjectionCache,
+    /// Event count and recent line offsets, rebuilt at startup and
+    /// advanced whenever new lines are published
+    index: EventIndex,
+    broadcaster: EventBroadcaster,
+    /// Serializes appends so no two writes interleave
+    write_lock: Arc<std::sync::Mutex<()>>,
+    /// Requests running longer than this get a 408
+    request_timeout: Duration,
+    aliases: CategoryAliases,
+    /// Display names and colors echoed into ratio and allocation output
+    category_display: CategoryDisplayConfig,
+    /// Default timezone and day start hour for day bucketing,
+    /// both overridable per request
+    timezone: DayZone,
+    day_start_hour: u32,
+    /// First day of the week for weekly rollups
+    week_start: WeekStart,
+    /// Local hours gaps are checked against
+    working_hours: WorkingHours,
+    /// Alert thresholds, before `ALERT-RULE` lines in the log
+    alert_rules: AlertRules,
+    /// Session limits past which an `AUTOSTOP` is appended
+    autostop: AutoStopConfig,
+    /// Longest event (in bytes, after trimming) appends accept
+    max_event_len: usize,
+    /// Larger POST bodies are refused with 413 before being deserialized
+    max_body_bytes: usize,
+    /// Recently seen idempotency keys from POST /events
+    idempotency_keys: IdempotencyKeys,
+    /// What `/query` dispatches to, by query `type`
+    projectors: Arc<ProjectorRegistry>,
+    /// Background-refreshed bundle behind GET /projections/bundle
+    snapshot: ProjectionSnapshot,
+    /// "Now" for elapsed times, relative windows and append timestamps
+    clock: SharedClock,
+    /// The merged startup configuration, for GET /config
+    config: Arc<ConfigReport>,
+    /// Counters and latencies behind GET /metrics
+    metrics: ServiceMetrics,
+    /// Bearer tokens requests must carry; empty leaves the API open
+    tokens: Arc<Vec<ApiToken>>,
+    /// Per-client token buckets over the appending routes
+    rate_limiter: RateLimiter,
+}
+
+impl AppState {
+    fn new(log_path: PathBuf) -> Self {
+        let index = EventIndex::build(&log_path, index::DEFAULT_RECENT_LINES).unwrap_or_else(|e| {
+            // The first publish retries the scan
+            tracing::error!(error = %e, "Error indexing log");
+            EventIndex::new(index::DEFAULT_RECENT_LINES)
+        });
+        Self {
+            broadcaster: EventBroadcaster::new(index.clone()),
+            index,
+            reader: EventReader::new(&log_path),
+            log_path,
+            fallback_log_path: None,
+            legacy_query_fallback: true,
+            cache: ProjectionCache::default(),
+            write_lock: Arc::new(std::sync::Mutex::new(())),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            aliases: CategoryAliases::default(),
+            category_display: CategoryDisplayConfig::default(),
+            timezone: DayZone::default(),
+            day_start_hour: 0,
+            week_start: WeekStart::default(),
+            working_hours: WorkingHours::default(),
+            alert_rules: AlertRules::default(),
+            autostop: AutoStopConfig::default(),
+            max_event_len: DEFAULT_MAX_EVENT_LEN,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            idempotency_keys: IdempotencyKeys::new(IDEMPOTENCY_KEY_CAPACITY),
+            projectors: Arc::new(ProjectorRegistry::builtin()),
+            snapshot: ProjectionSnapshot::new(Duration::ZERO),
+            clock: Arc::new(SystemClock),
+            config: Arc::new(config::Loaded::defaults().report),
+            metrics: ServiceMetrics::default(),
+            tokens: Arc::new(Vec::new()),
+            rate_limiter: RateLimiter::disabled(),
+        }
+    }
+
+    /// What `/query` projectors answer with, read per query
+    fn query_settings(&self) -> QuerySettings {
+        QuerySettings {
+            aliases: self.aliases.clone(),
+            display: self.category_display.clone(),
+            days: DayBoundary { zone: self.timezone, start_hour: self.day_start_hour },
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// The lines a query reads: the last `tail` through the index, or the
+    /// whole log; a log that doesn't exist yet reads as empty
+    fn query_log(&self, tail: Option<usize>) -> std::io::Result<QueryLog> {
+        let read = match tail {
+            Some(n) => self.tail(n).map(|events| {
+                let first = events.first().map_or(0, |e| e.idx);
+                (Arc::new(events.into_iter().map(|e| e.line).collect()), first)
+            }),
+            None => self.reader.lines().map(|lines| (lines, 0)),
+        };
+        match read {
+            Ok((lines, first)) => Ok(QueryLog::new(lines, first, tail.is_some())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(QueryLog::new(Arc::default(), 0, tail.is_some())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Total events from the index
+    /// Only bytes appended since the last check are read, which also
+    /// catches external appends when the watcher is off
+    fn total_events(&self) -> std::io::Result<usize> {
+        if self.broadcaster.publish_new_lines(self.read_path())? > 0 {
+            self.cache.invalidate();
+            self.reader.invalidate();
+        }
+        Ok(self.index.count())
+    }
+
+    /// Last `n` events, numbered from the index
+    fn tail(&self, n: usize) -> std::io::Result<Vec<IndexedEvent>> {
+        self.total_events()?;
+        let path = self.read_path();
+        let index = self.index.indexes(path).then_some(&self.index);
+        tail::tail_events(path, n, index)
+    }
+
+    fn session_projector(&self) -> SessionProjector<'static> {
+        SessionProjector::from_reader(&self.reader)
+            .with_aliases(&self.aliases)
+            .with_days(DayBoundary { zone: self.timezone, start_hour: self.day_start_hour })
+    }
+
+    /// Per-request day boundary overrides, falling back to the server defaults
+    fn days(&self, tz: Option<&str>, start_hour: Option<u32>) -> Result<DayBoundary, AppError> {
+        let zone = match tz {
+            Some(tz) => tz.parse().map_err(AppError::invalid)?,
+            None => self.timezone,
+        };
+        DayBoundary::new(zone, start_hour.unwrap_or(self.day_start_hour))
+            .map_err(AppError::invalid)
+    }
+
+    fn ratio_analyzer(&self) -> RatioAnalyzer<'static> {
+        RatioAnalyzer::from_reader(&self.reader)
+            .with_aliases(&self.aliases)
+            .with_display(&self.category_display)
+    }
+
+    /// Append an `AUTOSTOP` for the active session if it ran past its
+    /// limit, returning the line
+    /// Decided under the write lock from a fresh read of the log, so a
+    /// session that ended in the meantime is never auto-stopped
+    fn autostop_stale_session(&self) -> std::io::Result<Option<String>> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.reader.invalidate();
+        let Some(line) = autostop::due(&self.session_projector(), &self.autostop, self.clock.now()) else {
+            return Ok(None);
+        };
+        append_to_log(&self.log_path, &format!("{}\n", line)).inspect_err(|e| self.metrics.append_failed(e))?;
+        self.metrics.appended();
+        self.log_changed();
+        Ok(Some(line))
+    }
+
+    /// Append a STOP for the active session, returning the session and
+    /// the line; None when nothing is running
+    /// Checked and written under the write lock, like
+    /// `autostop_stale_session`, so concurrent closes append one STOP
+    fn close_active_session(&self) -> std::io::Result<Option<(Session, String)>> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.reader.invalidate();
+        let Some(session) = self.session_projector().get_current_session() else {
+            return Ok(None);
+        };
+        let line = events::stamp_line(&format!("STOP {} {}", session.category, session.activity), self.clock.now());
+        append_to_log(&self.log_path, &format!("{}\n", line)).inspect_err(|e| self.metrics.append_failed(e))?;
+        self.metrics.appended();
+        self.log_changed();
+        Ok(Some((session, line)))
+    }
+
+    /// Fail reads over to `fallback`, or stop doing so with None
+    fn set_fallback_log_path(&mut self, fallback: Option<PathBuf>) {
+        self.reader = EventReader::new(&self.log_path).with_fallback(fallback.as_deref());
+        self.fallback_log_path = fallback;
+        // Index what reads will see, without announcing its history as new
+        if let Err(e) = self.index.rebuild(self.read_path()) {
+            tracing::error!(error = %e, "Error indexing log");
+        }
+    }
+
+    /// The file reads that don't go through `reader` open: the log, or
+    /// its fallback while the log can't be opened
+    fn read_path(&self) -> &Path {
+        match &self.fallback_log_path {
+            Some(fallback) if std::fs::File::open(&self.log_path).is_err() => fallback,
+            _ => &self.log_path,
+        }
+    }
+
+    /// Throw away everything derived from the log (cached projections,
+    /// the in-memory lines, the index) and rebuild it from the file
+    /// Held under the write lock so no append lands halfway through
+    fn rebuild(&self) -> std::io::Result<RebuildReport> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let started = std::time::Instant::now();
+        self.cache.invalidate();
+        self.reader.invalidate();
+        self.index.rebuild(self.read_path())?;
+        let lines = match self.reader.lines() {
+            Ok(lines) => lines.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let read = started.elapsed();
+
+        let bundle = self.cache.get_or_compute("bundle", || projection_bundle(self));
+        if self.snapshot.enabled() {
+            self.snapshot.store(&bundle);
+        }
+        let total = started.elapsed();
+        let ms = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0;
+        tracing::info!(lines, total_ms = ms(total), "Rebuilt projections");
+        Ok(RebuildReport {
+            lines,
+            events_indexed: self.index.count(),
+            read_ms: ms(read),
+            project_ms: ms(total - read),
+            total_ms: ms(total),
+        })
+    }
+
+    /// The log grew (through us or externally): drop cached projections
+    /// and push the new lines to live subscribers
+    fn log_changed(&self) {
+        self.cache.invalidate();
+        self.reader.invalidate();
+        if let Err(e) = self.broadcaster.publish_new_lines(self.read_path()) {
+            tracing::error!(error = %e, "Error publishing new events");
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    // Initialize state
-    let state = AppState {
-        log_path: PathBuf::from("log/master.log"),
+    // `project-a-api project < master.log`: print the projection bundle
+    // for a log streamed on stdin and exit, without serving
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let project = args.first().map(String::as_str) == Some("project");
+    if project {
+        args.remove(0);
+    }
+    let loaded = Config::load(&args, |name| std::env::var(name).ok());
+    // Before anything logs; a config that didn't load reports in text
+    logging::init(loaded.as_ref().map(|loaded| loaded.config.log_format).unwrap_or_default());
+    let loaded = match loaded {
+        Ok(loaded) => loaded,
+        Err(errors) => {
+            for error in errors {
+                tracing::error!("Invalid configuration: {}", error);
+            }
+            std::process::exit(2);
+        }
     };
+    for warning in &loaded.warnings {
+        tracing::warn!("Config: {}", warning);
+    }
+    let config = loaded.config;
+
+    // Initialize state
+    let mut state = AppState::new(config.log_path.clone());
+    state.config = Arc::new(loaded.report);
+    state.tokens = Arc::new(config.tokens.clone());
+    state.rate_limiter = RateLimiter::new(config.rate_limit_per_minute, config.rate_limit_burst);
+    if state.tokens.is_empty() && !config.bind.ip().is_loopback() {
+        tracing::warn!("No [[tokens]] configured: anyone who can reach {} can read and append", config.bind);
+    }
+    state.legacy_query_fallback = config.legacy_query_fallback;
+    state.set_fallback_log_path(config.fallback_log_path.clone());
+    state.aliases = config.category_aliases.clone();
+    state.category_display = config.category_display.clone();
+    state.timezone = config.timezone;
+    state.day_start_hour = config.day_start_hour;
+    state.week_start = config.week_start;
+    state.alert_rules = config.alert_rules;
+    state.autostop = config.autostop.clone();
+    state.max_event_len = config.max_event_len;
+    state.max_body_bytes = config.max_body_bytes;
+    state.working_hours = config.working_hours;
+    // A pinned clock is for reproducing answers with `project`; a server
+    // stamping every append with the same time would corrupt the log
+    if let Some(now) = config.fixed_now {
+        if !project {
+            tracing::error!("Invalid configuration: fixed_now is only honored by `project`; unset it to serve");
+            std::process::exit(2);
+        }
+        state.clock = Arc::new(clock::FixedClock::new(now));
+    }
+    state.request_timeout = Duration::from_millis(config.request_timeout_ms);
+    if project {
+        state.reader = EventReader::from_buf_read(std::io::stdin().lock());
+        println!("{}", projection_bundle(&state));
+        return;
+    }
+    // Last, so the refresher's copy of the state has the settings above
+    let secs = config.projection_refresh_secs;
+    state.snapshot = ProjectionSnapshot::new(Duration::from_secs(secs));
+    let bundled = state.clone();
+    if state.snapshot.spawn_refresher(move || projection_bundle(&bundled)).is_some() {
+        tracing::info!(secs, "Refreshing the projection bundle");
+    }
+
+    // Optionally watch for appends made outside this server
+    if config.watch_log {
+        if let Some(parent) = state.log_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let watched = state.clone();
+        match watcher::spawn_log_watcher(state.log_path.clone(), move || watched.log_changed()) {
+            Ok(_) => tracing::info!(path = %state.log_path.display(), "Watching the log for external changes"),
+            Err(e) => tracing::warn!(error = %e, "Could not watch log"),
+        }
+    }
+
+    // The first check runs now, against the log as it is on disk
+    if state.autostop.enabled() {
+        let checked = state.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(autostop::AUTOSTOP_CHECK_INTERVAL);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let state = checked.clone();
+                match tokio::task::spawn_blocking(move || state.autostop_stale_session()).await {
+                    Ok(Ok(Some(line))) => tracing::info!(line = %line, "Auto-stopped session"),
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => tracing::error!(error = %e, "Error auto-stopping session"),
+                    Err(e) => tracing::error!(error = %e, "Error auto-stopping session"),
+                }
+            }
+        });
+        tracing::info!("Auto-stopping sessions past their limit");
+    }
+
+    state.rate_limiter.spawn_pruner();
 
     // Build router
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/health", get(health_check))
-        .route("/events", post(create_event))
-        .route("/events", get(list_events))
-        .route("/query", post(handle_query))
+    let app = build_router(state);
+
+    // Run server
+    let addr = config.bind;
+    tracing::info!("Server running on http://{}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    // Client addresses, which appends are rate limited by without tokens
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap();
+}
+
+fn build_router(state: AppState) -> Router {
+    let timeout = state.request_timeout;
+    let body_limit = DefaultBodyLimit::max(state.max_body_bytes);
+
+    // Projections answer JSON; the layer converts to CSV/NDJSON on request
+    let projections = Router::new()
+        .route("/projections", get(list_projectors))
+        .route("/projections/sessions/:idx", get(get_session))
+        .route("/projections/sessions/:idx/events", get(get_session_events))
+        .route("/projections/ratios/trend", get(get_ratio_trend))
+        .route("/projections/ratios/rolling", get(get_rolling_ratios))
+        .route("/projections/activities", get(get_activities))
+        .route("/projections/gaps", get(get_gaps))
+        .route("/projections/transitions", get(get_transitions))
+        .route("/projections/conflicts", get(get_conflicts))
+        .route("/projections/tags", get(get_tags))
+        .route("/projections/context-switches", get(get_context_switches))
+        .route("/projections/daily", get(get_daily))
+        .route("/projections/day/:date", get(get_day))
+        .route("/projections/busiest-day", get(get_busiest_day))
+        .route("/projections/cadence", get(get_cadence))
+        .route("/projections/cadence/moving-average", get(get_cadence_moving_average))
+        .route("/projections/records", get(get_records))
+        .route("/projections/span", get(get_span))
+        .route("/projections/longest-break", get(get_longest_break))
+        .route_layer(middleware::from_fn(negotiate::convert));
+
+    // Answers that also move with the clock: elapsed time, relative
+    // windows, "today"
+    let clock_projections = Router::new()
         .route("/projections/sessions", get(get_sessions))
+        .route("/projections/sessions/current", get(get_current_session))
+        .route("/projections/sessions/stats", get(get_session_stats))
         .route("/projections/ratios", get(get_ratios))
+        .route("/projections/ratios/target", get(get_ratio_target))
+        .route("/projections/allocation", get(get_allocation))
+        .route("/projections/top", get(get_top))
+        .route("/projections/stale", get(get_stale))
+        .route("/projections/switching", get(get_switching))
+        .route("/projections/streaks", get(get_streaks))
+        .route("/projections/alerts", get(get_alerts))
+        .route("/projections/heatmap", get(get_heatmap))
+        .route("/projections/duration-histogram", get(get_duration_histogram))
+        .route("/projections/bundle", get(get_bundle))
+        .route("/projections/weekly", get(get_weekly))
+        .route("/projections/monthly", get(get_monthly))
+        .route("/projections/forecast", get(get_forecast))
+        .route_layer(middleware::from_fn(negotiate::convert));
+
+    // Read endpoints answer If-None-Match from the log's ETag
+    let reads = Router::new()
+        .route("/events", post(create_event).layer(body_limit))
+        .route("/events", get(list_events).head(count_events))
+        .route("/events/tail", get(tail_events))
+        .route("/events.jsonl", get(export_events_jsonl))
+        .route("/log/raw", get(download_log))
+        .route("/metrics/history", get(metrics_history))
+        .route("/search", get(search_log))
+        .route("/queries", get(list_saved_queries))
+        .route("/projections/sessions.ics", get(export_sessions_ics))
+        .merge(projections)
+        .route_layer(middleware::from_fn_with_state(state.clone(), etag::conditional_get));
+
+    // The same, with the clock's minute in the tag
+    let clock_reads = Router::new()
+        // A saved query may be of any type, clock-dependent ones included
+        .route("/queries/:name/run", get(run_saved_query))
+        .merge(clock_projections)
+        .route_layer(middleware::from_fn_with_state(state.clone(), etag::conditional_get_by_minute));
+
+    let router = Router::new()
+        .route("/", get(root))
+        .route("/dashboard", get(dashboard))
+        .route("/health", get(health_check))
+        .route("/health/live", get(liveness))
+        .route("/health/ready", get(readiness))
+        .route("/status", get(get_status))
+        .route("/config", get(get_config))
+        .route("/metrics", get(get_metrics))
+        .route("/admin/rebuild", post(rebuild))
+        .route("/admin/verify", get(verify_log))
+        .route("/reports/weekly", get(get_weekly_report))
+        .route("/events/stream", get(stream_events))
+        .route("/query", post(handle_query).layer(body_limit))
+        .route("/sessions/close", post(close_session).layer(body_limit))
+        .route("/parse", post(parse_line).layer(body_limit))
+        .route("/ws", get(ws::ws_handler))
+        .route("/openapi.json", get(openapi_spec))
+        .merge(SwaggerUi::new("/docs").config(utoipa_swagger_ui::Config::from("/openapi.json")))
+        .merge(reads)
+        .merge(clock_reads)
+        // Around every route, so every JSON answer can be indented with ?pretty=true
+        .layer(middleware::from_fn(pretty::indent))
+        // Inside auth, so a rejected token never uses up a bucket
+        .layer(middleware::from_fn_with_state(state.clone(), ratelimit::limit_appends))
+        .layer(middleware::from_fn_with_state(state.clone(), auth::require_token))
+        // Outermost, timing the whole answer
+        .layer(middleware::from_fn_with_state(state.clone(), metrics::track_latency))
         .with_state(state);
 
-    // Run server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    println!("🚀 Server running on http://{}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Outside the timeout, so requests it cuts short are logged as 408s
+    with_timeout(router, timeout).layer(
+        TraceLayer::new_for_http()
+            .make_span_with(|request: &axum::extract::Request| {
+                tracing::info_span!("request", method = %request.method(), path = %request.uri().path())
+            })
+            .on_response(DefaultOnResponse::new().level(tracing::Level::INFO).latency_unit(LatencyUnit::Millis)),
+    )
+}
+
+/// Slow requests (e.g. projecting a huge log) return 408 instead of hanging
+fn with_timeout(router: Router, timeout: Duration) -> Router {
+    router.layer(TimeoutLayer::new(timeout))
 }
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "meta",
+    responses((status = 200, description = "API banner", body = String, content_type = "text/plain")),
+)]
 async fn root() -> &'static str {
     "Event-Driven Agent API v0.1.0"
 }
 
-async fn health_check() -> Json<serde_json::Value> {
+#[utoipa::path(
+    get,
+    path = "/dashboard",
+    tag = "meta",
+    responses((status = 200, description = "Self-contained page over /status, /projections/sessions and /projections/ratios", body = String, content_type = "text/html")),
+)]
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+/// Machine-readable API description
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "meta",
+    responses((status = 200, description = "This document", body = Object)),
+)]
+async fn openapi_spec() -> Json<serde_json::Value> {
+    Json(openapi::spec())
+}
+
+/// Re-read the log and recompute projections now, e.g. after editing
+/// master.log by hand with WATCH_LOG off
+#[utoipa::path(
+    post,
+    path = "/admin/rebuild",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Everything derived from the log was rebuilt; how long it took", body = RebuildReport),
+        (status = 500, description = "The log couldn't be read"),
+    ),
+)]
+async fn rebuild(state: axum::extract::State<AppState>) -> Result<Json<RebuildReport>, AppError> {
+    let state = state.0.clone();
+    let report = tokio::task::spawn_blocking(move || state.rebuild())
+        .await
+        .map_err(std::io::Error::other)??;
+    Ok(Json(report))
+}
+
+/// Scan the whole log for lines projections can't use: unparseable,
+/// not UTF-8, or carrying control characters
+/// Reads a line at a time, so any size of log is fine
+#[utoipa::path(
+    get,
+    path = "/admin/verify",
+    tag = "admin",
+    responses(
+        (status = 200, description = "What the scan found; `ok` when nothing", body = verify::VerifyReport),
+        (status = 404, description = "Nothing logged yet"),
+    ),
+)]
+async fn verify_log(state: axum::extract::State<AppState>) -> Result<Json<verify::VerifyReport>, AppError> {
+    let path = state.log_path.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        verify::verify(std::io::BufReader::new(std::fs::File::open(path)?))
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+    Ok(Json(report))
+}
+
+/// Settings the server started with and where each came from
+#[utoipa::path(
+    get,
+    path = "/config",
+    tag = "meta",
+    responses((status = 200, description = "Effective configuration: CLI over env over config file over defaults", body = ConfigReport)),
+)]
+async fn get_config(state: axum::extract::State<AppState>) -> Json<ConfigReport> {
+    Json((*state.config).clone())
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "meta",
+    responses((status = 200, description = "Liveness check", body = openapi::Health)),
+)]
+async fn health_check(state: axum::extract::State<AppState>) -> Json<serde_json::Value> {
+    // From the index, so health checks never rescan the log
+    let events = state.total_events().ok();
+
     Json(serde_json::json!({
         "status": "healthy",
-        "timestamp": Utc::now().to_rfc3339(),
+        "timestamp": state.clock.now().to_rfc3339(),
+        "events": events,
     }))
 }
 
+/// The process is up and serving; says nothing about the log
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "meta",
+    responses((status = 200, description = "Process is up", body = openapi::Liveness)),
+)]
+async fn liveness() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "alive" }))
+}
+
+/// Whether appends can succeed: the log, or the directory it will be
+/// created in, must be writable
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "meta",
+    responses(
+        (status = 200, description = "The log is writable", body = openapi::Readiness),
+        (status = 503, description = "The log can't be written, with the reason", body = openapi::Readiness),
+    ),
+)]
+async fn readiness(state: axum::extract::State<AppState>) -> (StatusCode, Json<serde_json::Value>) {
+    match check_log_writable(&state.log_path) {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({ "status": "ready", "reason": null }))),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "not_ready", "reason": e.to_string() })),
+        ),
+    }
+}
+
 /// Create a new event
 /// Appends to master.log (append-only, never edit)
+/// An `Idempotency-Key` header (or `idempotency_key` body field) seen
+/// recently returns the original response without appending again
+#[utoipa::path(
+    post,
+    path = "/events",
+    tag = "events",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with a recently seen key return the original response without appending")),
+    request_body = EventInput,
+    responses(
+        (status = 200, description = "Event logged", body = ApiResponse),
+        (status = 400, description = "Invalid timestamp or event longer than MAX_EVENT_LEN"),
+        (status = 413, description = "Body larger than MAX_BODY_BYTES"),
+    ),
+)]
 async fn create_event(
     state: axum::extract::State<AppState>,
-    Json(input): Json<EventInput>,
-) -> Result<Json<ApiResponse>, StatusCode> {
-    
-    // Validate event format
-    let event_line = format!("{}\n", input.event.trim());
-    
-    // Append to master.log (the only write operation allowed)
-    match append_to_log(&state.log_path, &event_line) {
-        Ok(_) => {
-            // Derive session info
-            let projector = SessionProjector::new(&state.log_path);
-            let current_session = projector.get_current_session();
-            
-            Ok(Json(ApiResponse {
-                status: "success".to_string(),
-                message: format!("Event logged: {}", input.event),
-                data: Some(serde_json::json!({
-                    "event": input.event,
-                    "timestamp": Utc::now().to_rfc3339(),
-                    "session_info": current_session,
-                })),
-            }))
-        }
-        Err(e) => {
-            eprintln!("Error writing to log: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+    headers: axum::http::HeaderMap,
+    extract::Json(input): extract::Json<EventInput>,
+) -> Result<Json<ApiResponse>, AppError> {
+    let key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| input.idempotency_key.clone());
+
+    let at = match &input.timestamp {
+        Some(ts) => chrono::DateTime::parse_from_rfc3339(ts)
+            .map_err(|e| AppError::invalid(format!("Invalid timestamp {}: {}", ts, e)))?
+            .with_timezone(&Utc),
+        None => state.clock.now(),
+    };
+
+    let Some(key) = key else {
+        return log_event(&state, &input.event, at).await.map(Json);
+    };
+
+    let mut seen = state.idempotency_keys.lock().await;
+    if let Some(original) = seen.get(&key) {
+        return serde_json::from_value(original)
+            .map(Json)
+            .map_err(|e| AppError::Io(std::io::Error::other(e)));
+    }
+
+    let response = log_event(&state, &input.event, at).await?;
+    if let Ok(value) = serde_json::to_value(&response) {
+        seen.insert(key, value);
+    }
+    Ok(Json(response))
+}
+
+/// Show how a line would be parsed, without logging it
+/// Diagnostic only: unparseable lines are a 200 with `parsed: null`
+#[utoipa::path(
+    post,
+    path = "/parse",
+    tag = "events",
+    request_body = ParseInput,
+    responses((status = 200, description = "Parse result", body = ParseResult)),
+)]
+async fn parse_line(extract::Json(input): extract::Json<ParseInput>) -> Json<ParseResult> {
+    // Checked on its own so a bad verb doesn't hide a good timestamp
+    let timestamp_detected = input
+        .line
+        .split_whitespace()
+        .next()
+        .is_some_and(|first| DateTime::parse_from_rfc3339(first).is_ok());
+    Json(ParseResult {
+        parsed: events::parse_event(&input.line),
+        timestamp_detected,
+        line: input.line,
+    })
+}
+
+/// Append to master.log (the only write operation allowed)
+async fn log_event(state: &AppState, event: &str, at: DateTime<Utc>) -> Result<ApiResponse, AppError> {
+    append_event(state, event, at).await?;
+
+    // Derive session info
+    let projector = state.session_projector();
+    let current_session = projector.get_current_session();
+
+    Ok(ApiResponse {
+        status: "success".to_string(),
+        message: format!("Event logged: {}", event),
+        data: Some(serde_json::json!({
+            "event": event,
+            "timestamp": at.to_rfc3339(),
+            "session_info": current_session,
+        })),
+    })
+}
+
+/// Close the active session by appending its STOP event
+/// 409 when nothing is active
+#[utoipa::path(
+    post,
+    path = "/sessions/close",
+    tag = "events",
+    responses(
+        (status = 200, description = "Session closed", body = ApiResponse),
+        (status = 409, description = "No active session"),
+    ),
+)]
+async fn close_session(
+    state: axum::extract::State<AppState>,
+) -> Result<Json<ApiResponse>, AppError> {
+    let closing = state.0.clone();
+    let (session, line) = tokio::task::spawn_blocking(move || closing.close_active_session())
+        .await
+        .map_err(std::io::Error::other)??
+        .ok_or_else(|| AppError::Conflict("No active session".to_string()))?;
+
+    Ok(Json(ApiResponse {
+        status: "success".to_string(),
+        message: format!("Session closed: {} {}", session.category, session.activity),
+        data: Some(serde_json::json!({
+            "event": line,
+            "session": session,
+        })),
+    }))
+}
+
+/// Validate and append one event line, shared by POST /events and /ws
+/// Lines without their own timestamp are stamped with `at`
+/// Events over `max_event_len` fail with `InvalidInput`
+/// Returns the line as written
+/// The write runs on a blocking task under the write lock, so a request
+/// timeout dropping this future can never abort an append mid-write
+async fn append_event(state: &AppState, event: &str, at: DateTime<Utc>) -> std::io::Result<String> {
+    let appended = write_event(state, event, at).await;
+    match &appended {
+        Ok(_) => state.metrics.appended(),
+        Err(e) => state.metrics.append_failed(e),
+    }
+    appended
+}
+
+async fn write_event(state: &AppState, event: &str, at: DateTime<Utc>) -> std::io::Result<String> {
+    let event = event.trim();
+    if event.len() > state.max_event_len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Event longer than {} bytes", state.max_event_len),
+        ));
     }
+    // One request, one line: a second line would skip every check above
+    if event.contains(['\n', '\r']) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Event must be a single line"));
+    }
+
+    // Validate event format, stamping it so durations can be derived
+    let event_line = events::stamp_line(event, at);
+
+    let state = state.clone();
+    let span = tracing::info_span!("append", bytes_written = tracing::field::Empty);
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let _guard = state.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let line = format!("{}\n", event_line);
+        append_to_log(&state.log_path, &line)?;
+        span.record("bytes_written", line.len());
+        tracing::debug!("Appended event");
+        state.log_changed();
+        Ok(event_line)
+    })
+    .await
+    .map_err(std::io::Error::other)?
 }
 
 /// List all events (read-only)
+/// `format=ndjson` (or Accept: application/x-ndjson) streams instead of buffering
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(EventsParams),
+    responses(
+        (
+            status = 200,
+            description = "Event lines; `[IndexedEvent]` with `pattern`, `EventsSince` with `since`. \
+                The representation follows Accept unless `format` (json, csv, ndjson, text) is given",
+            headers(("X-Total-Count" = usize, description = "Number of events matching the filters, ignoring pagination (JSON only)")),
+            content(
+                (Vec<String> = "application/json"),
+                (IndexedEvent = "application/x-ndjson"),
+                (String = "text/csv"),
+                (String = "text/plain"),
+            ),
+        ),
+        (status = 400, description = "Invalid filter; `error.message` carries the regex message"),
+        (status = 406, description = "No acceptable representation"),
+    ),
+)]
 async fn list_events(
     state: axum::extract::State<AppState>,
-) -> Result<Json<Vec<String>>, StatusCode> {
-    match read_log(&state.log_path) {
-        Ok(events) => Ok(Json(events)),
-        Err(e) => {
-            eprintln!("Error reading log: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    headers: axum::http::HeaderMap,
+    extract::Query(params): extract::Query<EventsParams>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let format = negotiate::negotiate(params.format.as_deref(), &headers, EVENT_FORMATS)
+        .map_err(IntoResponse::into_response)?;
+    let filter = event_filter(&state, &params).map_err(IntoResponse::into_response)?;
+
+    match format {
+        Format::Json => {}
+        Format::Ndjson => {
+            return stream_rows(state.read_path().to_path_buf(), filter, &params, format, None, |idx, line| {
+                serde_json::to_string(&IndexedEvent { idx, line })
+            })
+            .map_err(IntoResponse::into_response);
+        }
+        Format::Csv => {
+            let header = negotiate::csv_line(["idx", "timestamp", "verb", "category", "activity", "raw"].into_iter());
+            return stream_rows(state.read_path().to_path_buf(), filter, &params, format, Some(header), |idx, line| {
+                let record = event_record(idx, line);
+                let cells = [
+                    record.idx.to_string(),
+                    record.timestamp.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    record.verb.unwrap_or_default(),
+                    record.category.unwrap_or_default(),
+                    record.activity.unwrap_or_default(),
+                    record.raw.unwrap_or_default(),
+                ];
+                Ok(negotiate::csv_line(cells.iter().map(String::as_str)))
+            })
+            .map_err(IntoResponse::into_response);
+        }
+        Format::Text => {
+            // The whole log may be read, so off the async runtime
+            let (path, params) = (state.read_path().to_path_buf(), params.clone());
+            let body = tokio::task::spawn_blocking(move || raw_lines(&path, &filter, &params))
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|read| read)
+                .map_err(|e| AppError::from(e).into_response())?;
+            return Ok(([(axum::http::header::CONTENT_TYPE, format.content_type())], body).into_response());
         }
     }
+
+    // Unfiltered catch-ups within the index's recent offsets seek
+    // straight to event `since` instead of reading the whole log
+    if let Some(since) = params.since.filter(|_| filter.is_empty()) {
+        let recent = state
+            .total_events()
+            .and_then(|_| index::read_since(&state.index, state.read_path(), since))
+            .map_err(|e| AppError::from(e).into_response())?;
+        if let Some(events) = recent {
+            let total = state.index.count();
+            let page: Vec<IndexedEvent> = events
+                .into_iter()
+                .skip(params.offset.unwrap_or(0))
+                .take(params.limit.unwrap_or(usize::MAX))
+                .collect();
+            let body = Json(serde_json::json!({
+                "events": page,
+                "total": total,
+            }));
+            return Ok(with_total_count(body.into_response(), total));
+        }
+    }
+
+    let events = state.reader.lines().map_err(|e| AppError::from(e).into_response())?;
+
+    let total = events.len();
+    let matching: Vec<IndexedEvent> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| filter.matches(line))
+        .map(|(idx, line)| IndexedEvent { idx, line: line.clone() })
+        .collect();
+    let total_count = if filter.is_empty() {
+        state.total_events().unwrap_or(total)
+    } else {
+        matching.len()
+    };
+
+    let page = matching
+        .into_iter()
+        .filter(|e| e.idx >= params.since.unwrap_or(0))
+        .skip(params.offset.unwrap_or(0))
+        .take(params.limit.unwrap_or(usize::MAX));
+
+    let body = if params.since.is_some() {
+        // Incremental sync: indexed events after `since`, plus the new total
+        Json(serde_json::json!({
+            "events": page.collect::<Vec<_>>(),
+            "total": total,
+        }))
+        .into_response()
+    } else if params.pattern.is_some() {
+        // Pattern hunts want to know where each match sits
+        Json(page.collect::<Vec<_>>()).into_response()
+    } else {
+        Json(page.map(|e| e.line).collect::<Vec<_>>()).into_response()
+    };
+
+    Ok(with_total_count(body, total_count))
 }
 
-/// Handle complex queries
-async fn handle_query(
+/// Event count without a body (HEAD /events)
+/// Unfiltered counts come from the cached line counter, not a file scan
+#[utoipa::path(
+    head,
+    path = "/events",
+    tag = "events",
+    params(EventsParams),
+    responses(
+        (
+            status = 200,
+            description = "No body",
+            headers(("X-Total-Count" = usize, description = "Number of events matching the filters")),
+        ),
+        (status = 400, description = "Invalid filter"),
+    ),
+)]
+async fn count_events(
     state: axum::extract::State<AppState>,
-    Json(query): Json<serde_json::Value>,
-) -> Result<Json<QueryResult>, StatusCode> {
-    
-    let query_str = query.get("query")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    
-    // Route to appropriate projector
-    let result = if query_str.contains("ratio") {
-        let analyzer = RatioAnalyzer::new(&state.log_path);
-        analyzer.analyze()
-    } else if query_str.contains("session") || query_str.contains("timeline") {
-        let projector = SessionProjector::new(&state.log_path);
-        projector.get_timeline()
+    extract::Query(params): extract::Query<EventsParams>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let filter = event_filter(&state, &params).map_err(IntoResponse::into_response)?;
+
+    let count = if filter.is_empty() {
+        state.total_events()
     } else {
-        // Default: return recent events
-        match read_log(&state.log_path) {
-            Ok(events) => QueryResult {
-                query: query_str.to_string(),
-                result_type: "recent".to_string(),
-                data: serde_json::json!({ "events": events }),
-            },
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        state.reader.lines().map(|events| events.iter().filter(|l| filter.matches(l)).count())
+    };
+
+    match count {
+        Ok(count) => Ok(with_total_count(StatusCode::OK.into_response(), count)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(with_total_count(StatusCode::OK.into_response(), 0))
+        }
+        Err(e) => Err(AppError::from(e).into_response()),
+    }
+}
+
+fn with_total_count(mut response: axum::response::Response, count: usize) -> axum::response::Response {
+    response.headers_mut().insert("x-total-count", axum::http::HeaderValue::from(count));
+    response
+}
+
+/// Invalid patterns get a 400 carrying the regex engine's message
+fn event_filter(state: &AppState, params: &EventsParams) -> Result<EventFilter, AppError> {
+    let days = state.days(params.tz.as_deref(), None)?;
+    let bound = |value: &Option<String>, upper| match value {
+        Some(v) => events::parse_bound(v, upper, &days)
+            .map(Some)
+            .ok_or_else(|| AppError::invalid(format!("invalid date: {}", v))),
+        None => Ok(None),
+    };
+    let pattern = params
+        .pattern
+        .as_deref()
+        .map(events::compile_pattern)
+        .transpose()
+        .map_err(AppError::invalid)?;
+
+    Ok(EventFilter {
+        category: params.category.clone(),
+        from: bound(&params.from, false)?,
+        to: bound(&params.to, true)?,
+        pattern,
+        hide_comments: params.comments == Some(false),
+    })
+}
+
+/// Matching lines exactly as stored in master.log (`Accept: text/plain`)
+/// Without filters the body is one contiguous byte range of the file
+/// (the whole file when unpaginated); filtered listings concatenate
+/// each matching line with its own terminator
+fn raw_lines(path: &Path, filter: &EventFilter, params: &EventsParams) -> std::io::Result<Vec<u8>> {
+    let bytes = std::fs::read(path)?;
+    let paginated = params.since.is_some() || params.offset.is_some() || params.limit.is_some();
+    if filter.is_empty() && !paginated {
+        return Ok(bytes);
+    }
+
+    let since = params.since.unwrap_or(0);
+    let mut to_skip = params.offset.unwrap_or(0);
+    let mut to_take = params.limit.unwrap_or(usize::MAX);
+
+    let mut out = Vec::new();
+    let mut range: Option<(usize, usize)> = None;
+    let mut idx = 0;
+    let mut end = 0;
+    for raw in bytes.split_inclusive(|b| *b == b'\n') {
+        let start = end;
+        end += raw.len();
+
+        // Same line and index rules as read_log
+        let text = String::from_utf8_lossy(raw);
+        let line = text.strip_suffix('\n').unwrap_or(&text);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        idx += 1;
+        if idx <= since || !filter.matches(line) {
+            continue;
+        }
+        if to_skip > 0 {
+            to_skip -= 1;
+            continue;
+        }
+        if to_take == 0 {
+            break;
+        }
+        to_take -= 1;
+
+        if filter.is_empty() {
+            range = Some((range.map_or(start, |(first, _)| first), end));
+        } else {
+            out.extend_from_slice(raw);
+        }
+    }
+
+    Ok(match range {
+        Some((first, last)) => bytes[first..last].to_vec(),
+        None => out,
+    })
+}
+
+/// Parsed events, one JSON object per line (/events.jsonl)
+#[utoipa::path(
+    get,
+    path = "/events.jsonl",
+    tag = "events",
+    params(EventsParams),
+    responses(
+        (status = 200, description = "EventRecord per line", body = EventRecord, content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid filter"),
+    ),
+)]
+async fn export_events_jsonl(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<EventsParams>,
+) -> Result<axum::response::Response, axum::response::Response> {
+    let filter = event_filter(&state, &params).map_err(IntoResponse::into_response)?;
+
+    stream_rows(state.read_path().to_path_buf(), filter, &params, Format::Ndjson, None, |idx, line| {
+        serde_json::to_string(&event_record(idx, line))
+    })
+    .map_err(IntoResponse::into_response)
+}
+
+/// Parsed form of one line; unparseable lines keep their text in `raw`
+fn event_record(idx: usize, line: String) -> EventRecord {
+    match events::parse_event(&line) {
+        Some(event) => EventRecord {
+            idx,
+            timestamp: event.timestamp,
+            verb: Some(event.verb.to_string()),
+            category: event.category,
+            activity: event.activity,
+            raw: None,
+        },
+        None => EventRecord {
+            idx,
+            timestamp: None,
+            verb: None,
+            category: None,
+            activity: None,
+            raw: Some(line),
+        },
+    }
+}
+
+/// Stream one row per matching line, rendered by `render`, after an
+/// optional header row
+/// The bounded channel keeps the reader at most STREAM_BUFFER lines ahead
+fn stream_rows(
+    log_path: PathBuf,
+    filter: EventFilter,
+    params: &EventsParams,
+    format: Format,
+    header: Option<String>,
+    render: impl Fn(usize, String) -> serde_json::Result<String> + Send + 'static,
+) -> Result<axum::response::Response, AppError> {
+    let since = params.since.unwrap_or(0);
+    let offset = params.offset.unwrap_or(0);
+    let limit = params.limit.unwrap_or(usize::MAX);
+
+    let file = std::fs::File::open(&log_path)?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<String>>(STREAM_BUFFER);
+
+    tokio::task::spawn_blocking(move || {
+        if let Some(header) = header {
+            if tx.blocking_send(Ok(format!("{}\n", header))).is_err() {
+                return;
+            }
+        }
+        let reader = std::io::BufReader::new(file);
+        let lines = crate::reader::lossy_lines(reader).filter(|line| !line.trim().is_empty());
+
+        let page = lines
+            .enumerate()
+            .skip(since)
+            .filter(|(_, line)| filter.matches(line))
+            .skip(offset)
+            .take(limit);
+
+        for (idx, line) in page {
+            let mut row = render(idx, line).unwrap_or_default();
+            row.push('\n');
+            // Client went away
+            if tx.blocking_send(Ok(row)).is_err() {
+                break;
             }
         }
+    });
+
+    let body = axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    Ok(([(axum::http::header::CONTENT_TYPE, format.content_type())], body).into_response())
+}
+
+/// Counters, gauges and request latencies for Prometheus to scrape
+/// Everything comes from memory; the log isn't read
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "meta",
+    responses((status = 200, description = "Prometheus text exposition", body = String, content_type = "text/plain")),
+)]
+async fn get_metrics(state: axum::extract::State<AppState>) -> impl IntoResponse {
+    // Projected once per log change, like any other cached projection
+    let active = state.cache.get_or_compute("session_active", || {
+        serde_json::json!(state.session_projector().get_current_session().is_some())
+    });
+    let gauges = Gauges {
+        log_bytes: state.index.bytes(),
+        log_lines: state.index.count(),
+        session_active: active == serde_json::Value::Bool(true),
+        cache: state.cache.stats(),
+        rate_limit: state.rate_limiter.stats(),
     };
-    
-    Ok(Json(result))
+    ([(axum::http::header::CONTENT_TYPE, metrics::PROMETHEUS_CONTENT_TYPE)], state.metrics.render(&gauges))
 }
 
-/// Get session projections
-async fn get_sessions(
+/// Per-day, per-category values as timestamped OpenMetrics samples, for
+/// backfilling a time-series database
+#[utoipa::path(
+    get,
+    path = "/metrics/history",
+    tag = "projections",
+    params(DailyParams),
+    responses(
+        (status = 200, description = "One gauge series per category, a sample per day", body = String, content_type = "application/openmetrics-text"),
+        (status = 400, description = "Invalid range, timezone, hour or merge_gap_minutes"),
+    ),
+)]
+async fn metrics_history(
     state: axum::extract::State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let projector = SessionProjector::new(&state.log_path);
-    let sessions = projector.get_all_sessions();
-    
-    Ok(Json(serde_json::json!({
-        "sessions": sessions,
-        "count": sessions.len(),
-    })))
+    extract::Query(params): extract::Query<DailyParams>,
+) -> Result<axum::response::Response, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+
+    let rows = state
+        .session_projector()
+        .with_days(days)
+        .with_merge_gap(merge_gap(params.merge_gap_minutes)?)
+        .daily_rows(params.metric, from, to)
+        .map_err(AppError::invalid)?;
+    let body = metrics::daily_history(&rows, params.metric, &days);
+    Ok(([(axum::http::header::CONTENT_TYPE, metrics::OPENMETRICS_CONTENT_TYPE)], body).into_response())
 }
 
-/// Get ratio projections
-async fn get_ratios(
+/// Download master.log as-is, for backups
+/// Read in chunks on a blocking task, never held in memory whole
+#[utoipa::path(
+    get,
+    path = "/log/raw",
+    tag = "events",
+    responses(
+        (status = 200, description = "The raw log bytes, as an attachment", body = String, content_type = "text/plain"),
+        (status = 404, description = "Nothing logged yet"),
+    ),
+)]
+async fn download_log(
     state: axum::extract::State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let analyzer = RatioAnalyzer::new(&state.log_path);
-    let analysis = analyzer.analyze();
-    
-    Ok(Json(serde_json::json!({
-        "analysis": analysis,
-    })))
+) -> Result<axum::response::Response, AppError> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(state.read_path())?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let mut buf = vec![0; RAW_CHUNK_SIZE];
+        loop {
+            let chunk = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            // Client went away, or the read failed and the body is cut short
+            if tx.blocking_send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let body = axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (axum::http::header::CONTENT_DISPOSITION, "attachment; filename=\"master.log\""),
+        ],
+        body,
+    )
+        .into_response())
 }
 
-// Helper functions
+/// Last n events, read from the end of the log
+#[utoipa::path(
+    get,
+    path = "/events/tail",
+    tag = "events",
+    params(TailParams),
+    responses((status = 200, description = "Indexed events", body = Vec<IndexedEvent>)),
+)]
+async fn tail_events(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<TailParams>,
+) -> Result<Json<Vec<IndexedEvent>>, AppError> {
+    let n = params.n.unwrap_or(DEFAULT_TAIL_SIZE);
+    Ok(Json(state.tail(n)?))
+}
 
-fn append_to_log(path: &PathBuf, line: &str) -> std::io::Result<()> {
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)?;
-    
-    file.write_all(line.as_bytes())?;
-    Ok(())
+/// Live event stream (SSE)
+/// Optionally replays events after the first `since`, then pushes new appends
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    tag = "events",
+    params(StreamParams),
+    responses((status = 200, description = "`data:` frames carrying an IndexedEvent", body = IndexedEvent, content_type = "text/event-stream")),
+)]
+async fn stream_events(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<StreamParams>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, AppError> {
+    // Subscribe before reading history so nothing falls between the two
+    let live = BroadcastStream::new(state.broadcaster.subscribe());
+
+    let replay: Vec<IndexedEvent> = match params.since {
+        Some(since) => state
+            .reader
+            .lines()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .skip(since)
+            .map(|(idx, line)| IndexedEvent { idx, line: line.clone() })
+            .collect(),
+        None => Vec::new(),
+    };
+    let replayed_up_to = replay.last().map(|e| e.idx + 1).unwrap_or(0);
+
+    let live = live.filter_map(move |event| match event {
+        Ok(event) if event.idx >= replayed_up_to => Some(event),
+        // Already replayed, or dropped because this client lagged
+        _ => None,
+    });
+
+    let frames = tokio_stream::iter(replay)
+        .chain(live)
+        .map(|event| {
+            Ok(SseEvent::default()
+                .id(event.idx.to_string())
+                .json_data(&event)
+                .unwrap_or_default())
+        });
+
+    Ok(Sse::new(frames).keep_alive(KeepAlive::new().interval(SSE_HEARTBEAT)))
 }
 
-fn read_log(path: &PathBuf) -> std::io::Result<Vec<String>> {
-    use std::io::BufRead;
-    
-    let file = std::fs::File::open(path)?;
-    let reader = std::io::BufReader::new(file);
-    
-    let mut events = Vec::new();
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            if !line.trim().is_empty() {
-                events.push(line);
-            }
+/// Handle complex queries
+/// Structured queries carry a `type`, legacy ones a free-text `query`
+#[utoipa::path(
+    post,
+    path = "/query",
+    tag = "query",
+    request_body(content = QueryInput, description = "Structured query; a legacy `{\"query\": \"...\"}` body is also accepted"),
+    responses(
+        (status = 200, description = "Query result, with a `plan` when `explain` is true", body = QueryResponse),
+        (status = 400, description = "Unknown query type (lists supported types) or invalid params"),
+        (status = 413, description = "Body larger than MAX_BODY_BYTES"),
+    ),
+)]
+async fn handle_query(
+    state: axum::extract::State<AppState>,
+    extract::Json(mut query): extract::Json<serde_json::Value>,
+) -> Result<Json<QueryResponse>, AppError> {
+    let explain = match query.as_object_mut().and_then(|q| q.remove("explain")) {
+        None => false,
+        Some(serde_json::Value::Bool(explain)) => explain,
+        Some(_) => {
+            let invalid = ProjectionError::Invalid("explain must be true or false".to_string());
+            return Err(query_error(&state, invalid));
+        }
+    };
+
+    let (name, params, legacy_text) = if let Some(query_type) = query.get("type").and_then(|v| v.as_str()) {
+        let name = query_type.to_string();
+        if let Some(fields) = query.as_object_mut() {
+            fields.remove("type");
+        }
+        (name, query, None)
+    } else {
+        let query_str = query.get("query")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        // Legacy: map free text onto the structured types
+        let name = if query_str.contains("ratio") {
+            "ratios"
+        } else if query_str.contains("session") || query_str.contains("timeline") {
+            "timeline"
+        } else if state.legacy_query_fallback {
+            // Default: return recent events
+            "recent"
+        } else {
+            return Err(unknown_query_type(&state, query_str));
+        };
+        (name.to_string(), serde_json::json!({}), Some(query_str.to_string()))
+    };
+    let Some(projector) = state.projectors.get(&name) else {
+        return Err(unknown_query_type(&state, &name));
+    };
+
+    projector.validate(&params).map_err(|e| query_error(&state, e))?;
+    let log = state.query_log(projector.tail(&params))?;
+    let settings = state.query_settings();
+
+    // Planning happens outside the timed section
+    let plan = explain.then(|| projector.plan(&log, &params));
+    let started = std::time::Instant::now();
+
+    let mut result = tracing::info_span!("query", query_type = %name)
+        .in_scope(|| projector.project(&log, &settings, &params))
+        .map_err(|e| query_error(&state, e))?;
+    if let (Some(text), "recent") = (legacy_text, name.as_str()) {
+        result.query = text;
+    }
+
+    let plan = plan.map(|mut plan| {
+        plan.elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        plan
+    });
+    Ok(Json(QueryResponse { result, plan }))
+}
+
+fn unknown_query_type(state: &AppState, query_type: &str) -> AppError {
+    AppError::invalid(format!("Unknown query type: {}", query_type))
+        .with_detail(serde_json::json!({ "supported_types": state.projectors.names() }))
+}
+
+fn query_error(state: &AppState, error: ProjectionError) -> AppError {
+    match error {
+        ProjectionError::Invalid(message) => AppError::invalid(format!("Invalid query: {}", message))
+            .with_detail(serde_json::json!({ "supported_types": state.projectors.names() })),
+    }
+}
+
+/// Query types `/query` accepts and the params each takes
+#[utoipa::path(
+    get,
+    path = "/projections",
+    tag = "query",
+    responses((status = 200, description = "Registered projectors in registration order", body = openapi::ProjectorsEnvelope)),
+)]
+async fn list_projectors(state: axum::extract::State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "projectors": state.projectors.describe(),
+    }))
+}
+
+/// Queries saved in the log with `SAVEQUERY <name> <json>`
+/// Definitions that don't parse are listed with their `error`
+#[utoipa::path(
+    get,
+    path = "/queries",
+    tag = "query",
+    responses((status = 200, description = "Latest definition of each saved query, by name", body = openapi::SavedQueriesEnvelope)),
+)]
+async fn list_saved_queries(state: axum::extract::State<AppState>) -> Json<serde_json::Value> {
+    Json(state.cache.get_or_compute("saved_queries", || {
+        serde_json::json!({
+            "queries": saved::saved_queries(&state.reader.lines().unwrap_or_default()),
+        })
+    }))
+}
+
+/// Run a saved query: exactly what posting its body to `/query` returns
+#[utoipa::path(
+    get,
+    path = "/queries/{name}/run",
+    tag = "query",
+    params(("name" = String, Path, description = "Saved query name")),
+    responses(
+        (status = 200, description = "Query result", body = QueryResponse),
+        (status = 400, description = "The saved body is not a valid query"),
+        (status = 404, description = "No query saved under that name"),
+        (status = 422, description = "The saved definition doesn't parse"),
+    ),
+)]
+async fn run_saved_query(
+    state: axum::extract::State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> Result<Json<QueryResponse>, AppError> {
+    let saved = saved::saved_queries(&state.reader.lines().unwrap_or_default())
+        .into_iter()
+        .find(|q| q.name == name)
+        .ok_or_else(|| AppError::NotFound(format!("No saved query {}", name)))?;
+    match saved.query {
+        Some(query) => handle_query(state, extract::Json(query)).await,
+        None => Err(AppError::Unprocessable {
+            message: saved.error.unwrap_or_default(),
+            detail: Some(serde_json::json!({ "event_index": saved.event_index })),
+        }),
+    }
+}
+
+/// Get session projections
+#[utoipa::path(
+    get,
+    path = "/projections/sessions",
+    tag = "projections",
+    params(SessionsParams),
+    responses(
+        (status = 200, description = "Session timeline", body = openapi::SessionsEnvelope),
+        (status = 400, description = "Unknown sort key or order, a tag filter that isn't key:value, or a negative merge_gap_minutes"),
+    ),
+)]
+async fn get_sessions(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<SessionsParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let tag = tag_filter(params.tag.as_deref())?;
+    let merge_gap = merge_gap(params.merge_gap_minutes)?;
+    let compute = |projector: SessionProjector| {
+        let mut sessions = projector.with_merge_gap(merge_gap).get_all_sessions();
+        if let Some(tag) = &tag {
+            sessions.retain(|session| tag.matches(&session.metadata));
+        }
+        projections::sort_sessions(&mut sessions, params.sort, params.order);
+
+        serde_json::json!({
+            "sessions": sessions,
+            "count": sessions.len(),
+        })
+    };
+
+    // Elapsed time moves with the clock, so those answers aren't cached
+    if params.elapsed {
+        return Ok(Json(compute(state.session_projector().with_clock(&state.clock))));
+    }
+    let key = format!("sessions:{:?}:{:?}:{:?}:{:?}", params.sort, params.order, params.tag, merge_gap);
+    let body = state.cache.get_or_compute(&key, || compute(state.session_projector()));
+
+    Ok(Json(body))
+}
+
+/// Closed sessions as an iCalendar file, filtered like the JSON listing
+#[utoipa::path(
+    get,
+    path = "/projections/sessions.ics",
+    tag = "projections",
+    params(SessionsParams),
+    responses(
+        (status = 200, description = "VCALENDAR with a VEVENT per closed, timestamped session", body = String, content_type = "text/calendar"),
+        (status = 400, description = "A tag filter that isn't key:value, or a negative merge_gap_minutes"),
+    ),
+)]
+async fn export_sessions_ics(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<SessionsParams>,
+) -> Result<axum::response::Response, AppError> {
+    let tag = tag_filter(params.tag.as_deref())?;
+    let mut sessions = state
+        .session_projector()
+        .with_merge_gap(merge_gap(params.merge_gap_minutes)?)
+        .sessions_with_notes();
+    if let Some(tag) = &tag {
+        sessions.retain(|(session, _)| tag.matches(&session.metadata));
+    }
+
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, ical::ICALENDAR_CONTENT_TYPE),
+            (axum::http::header::CONTENT_DISPOSITION, "attachment; filename=\"sessions.ics\""),
+        ],
+        ical::calendar(&sessions),
+    )
+        .into_response())
+}
+
+/// A look-back `window` from now, or a `from`..`to` day range bucketed
+/// like the daily views; asking for both is a 400
+fn time_range(
+    state: &AppState,
+    window: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<days::Bounds, AppError> {
+    match window {
+        Some(_) if from.is_some() || to.is_some() => Err(AppError::invalid("window can't be combined with from or to")),
+        Some(window) => Ok((Some(state.clock.now() - days::parse_window(window).map_err(AppError::invalid)?), None)),
+        None => {
+            let (from, to) = days::parse_date_range(from, to).map_err(AppError::invalid)?;
+            Ok(state.days(None, None)?.bounds(from, to))
+        }
+    }
+}
+
+/// `tag=key:value` as a metadata filter
+fn tag_filter(tag: Option<&str>) -> Result<Option<metadata::MetadataFilter>, AppError> {
+    tag.map(metadata::MetadataFilter::from_tag)
+        .transpose()
+        .map_err(AppError::invalid)
+}
+
+/// `merge_gap_minutes`, which has to be a number of minutes
+fn merge_gap(minutes: Option<f64>) -> Result<Option<f64>, AppError> {
+    match minutes {
+        Some(minutes) if !minutes.is_finite() || minutes < 0.0 => {
+            Err(AppError::invalid("merge_gap_minutes must be a non-negative number"))
+        }
+        minutes => Ok(minutes),
+    }
+}
+
+/// Session duration distribution with a histogram
+/// The active session counts with its minutes so far unless
+/// `exclude_active`; only the excluding answer is cached
+#[utoipa::path(
+    get,
+    path = "/projections/sessions/stats",
+    tag = "projections",
+    params(SessionStatsParams),
+    responses(
+        (status = 200, description = "Duration stats over timed sessions", body = openapi::SessionStatsEnvelope),
+        (status = 400, description = "bucket_minutes below 1 or a negative merge_gap_minutes"),
+    ),
+)]
+async fn get_session_stats(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<SessionStatsParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let bucket_minutes = params.bucket_minutes.unwrap_or(DEFAULT_BUCKET_MINUTES);
+    if !bucket_minutes.is_finite() || bucket_minutes < 1.0 {
+        return Err(AppError::invalid("bucket_minutes must be at least 1"));
+    }
+    let merge_gap = merge_gap(params.merge_gap_minutes)?;
+
+    let compute = |projector: SessionProjector| {
+        serde_json::json!({
+            "stats": projector.with_merge_gap(merge_gap).duration_stats(params.category.as_deref(), bucket_minutes),
+            "category": params.category,
+        })
+    };
+
+    if !params.exclude_active {
+        return Ok(Json(compute(state.session_projector().with_clock(&state.clock))));
+    }
+    let key = format!("session-stats:{:?}:{}:{:?}", params.category, bucket_minutes, merge_gap);
+    let body = state.cache.get_or_compute(&key, || compute(state.session_projector().without_active()));
+
+    Ok(Json(body))
+}
+
+/// Buckets of `GET /projections/duration-histogram` without `buckets`
+const DEFAULT_DURATION_BUCKETS: &str = "5m,15m,30m,1h,2h";
+
+/// How many timed sessions fall into each duration bucket, plus an
+/// overflow bucket past the largest
+/// The active session counts with its minutes so far unless
+/// `exclude_active`; only the excluding answer is cached
+#[utoipa::path(
+    get,
+    path = "/projections/duration-histogram",
+    tag = "projections",
+    params(DurationHistogramParams),
+    responses(
+        (status = 200, description = "Session counts per duration bucket, shortest first", body = openapi::DurationHistogramEnvelope),
+        (status = 400, description = "Malformed bucket, or buckets not in ascending order"),
+    ),
+)]
+async fn get_duration_histogram(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<DurationHistogramParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let buckets = params.buckets.as_deref().unwrap_or(DEFAULT_DURATION_BUCKETS);
+    let bounds = days::parse_duration_buckets(buckets).map_err(AppError::invalid)?;
+
+    let compute = |projector: SessionProjector| {
+        serde_json::json!({
+            "histogram": projector.duration_histogram(params.category.as_deref(), &bounds),
+            "category": params.category,
+        })
+    };
+
+    if !params.exclude_active {
+        return Ok(Json(compute(state.session_projector().with_clock(&state.clock))));
+    }
+    let key = format!("duration-histogram:{:?}:{:?}", params.category, bounds);
+    Ok(Json(state.cache.get_or_compute(&key, || compute(state.session_projector().without_active()))))
+}
+
+/// The active session and how long it has been running
+/// Always 200: `session` is null when nothing is active, and
+/// `elapsed_minutes` is null when the session has no start timestamp
+#[utoipa::path(
+    get,
+    path = "/projections/sessions/current",
+    tag = "projections",
+    responses((status = 200, description = "Active session, or null when nothing is active", body = openapi::CurrentSession)),
+)]
+async fn get_current_session(
+    state: axum::extract::State<AppState>,
+) -> Json<serde_json::Value> {
+    let session = state.session_projector().get_current_session();
+    let elapsed_minutes = session
+        .as_ref()
+        .and_then(|s| s.start_time)
+        .map(|start| (state.clock.now() - start).num_seconds() as f64 / 60.0);
+
+    Json(serde_json::json!({
+        "session": session,
+        "elapsed_minutes": elapsed_minutes,
+    }))
+}
+
+/// What's running now, for status bars polling every few seconds
+/// The active session and today's finished minutes come from the
+/// projection cache; per call there is only the clock math and, when
+/// idle, a tail read of the log's last line
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "projections",
+    params(StatusParams),
+    responses(
+        (
+            status = 200,
+            description = "Active session or idle time, and today's minutes per category",
+            content((Status = "application/json"), (String = "text/plain")),
+        ),
+        (status = 400, description = "Unknown format"),
+    ),
+)]
+async fn get_status(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<StatusParams>,
+) -> Result<axum::response::Response, AppError> {
+    let status = status(&state);
+    Ok(match params.format {
+        StatusFormat::Json => Json(status).into_response(),
+        StatusFormat::Text => (
+            [(axum::http::header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            format!("{}\n", status_line(&status)),
+        )
+            .into_response(),
+    })
+}
+
+/// A week's review as Markdown to paste into notes, or as the JSON it's
+/// rendered from; every figure is what the matching projection says
+/// Not cached: streaks and the active session move with the clock
+#[utoipa::path(
+    get,
+    path = "/reports/weekly",
+    tag = "projections",
+    params(ReportParams),
+    responses(
+        (
+            status = 200,
+            description = "Totals, categories, top activities, streaks, notes and the ratio target for one week",
+            content((String = "text/markdown"), (report::WeeklyReport = "application/json")),
+        ),
+        (status = 400, description = "Invalid week or format"),
+    ),
+)]
+async fn get_weekly_report(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<ReportParams>,
+) -> Result<axum::response::Response, AppError> {
+    let days = state.days(None, None)?;
+    let now = state.clock.now();
+    let period = Period::Week(state.week_start);
+    let start = match &params.week {
+        Some(week) => period.parse_label(week).map_err(AppError::invalid)?,
+        None => period.start_of(days.day_of(now)),
+    };
+
+    let report = report::WeeklyReport::build(&state.session_projector(), &state.ratio_analyzer(), period, start, &days, now);
+    Ok(match params.format {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Markdown => {
+            ([(axum::http::header::CONTENT_TYPE, report::MARKDOWN_CONTENT_TYPE)], report::markdown(&report)).into_response()
+        }
+    })
+}
+
+fn status(state: &AppState) -> Status {
+    let now = state.clock.now();
+    let days = DayBoundary { zone: state.timezone, start_hour: state.day_start_hour };
+    let today = days.day_of(now);
+
+    // Not cached: the active session's minutes run with the clock
+    let projector = state.session_projector().with_clock(&state.clock);
+    let sessions = projector.get_all_sessions();
+    // Net of breaks, split where the day starts, the active session so far
+    let today_minutes = projector
+        .daily_rows(DayMetric::Minutes, Some(today), Some(today))
+        .ok()
+        .and_then(|mut rows| rows.pop())
+        .map(|row| row.categories)
+        .unwrap_or_default();
+
+    let mut status = Status {
+        active: false,
+        category: None,
+        activity: None,
+        elapsed_minutes: None,
+        idle_minutes: None,
+        today_minutes: today_minutes
+            .into_iter()
+            .filter(|(_, minutes)| *minutes > 0.0)
+            .map(|(category, minutes)| (category, minutes.floor() as i64))
+            .collect(),
+    };
+    match sessions.iter().find(|s| s.is_active) {
+        Some(session) => {
+            status.active = true;
+            status.elapsed_minutes = session.duration_minutes.map(|minutes| minutes.floor() as i64);
+            status.category = Some(session.category.clone());
+            status.activity = Some(session.activity.clone());
+        }
+        None => {
+            status.idle_minutes = sessions
+                .iter()
+                .filter_map(|s| s.end_time)
+                .max()
+                .map(|end| (now - end).num_minutes());
+        }
+    }
+    status
+}
+
+/// `THEORY pandas 42m | today GAME 30m THEORY 90m`, or `idle 17m | ...`
+fn status_line(status: &Status) -> String {
+    let mut line = match (&status.category, &status.activity) {
+        (Some(category), Some(activity)) => format!("{} {}", category, activity),
+        _ => "idle".to_string(),
+    };
+    if let Some(minutes) = status.elapsed_minutes.or(status.idle_minutes) {
+        line.push_str(&format!(" {}m", minutes));
+    }
+    if !status.today_minutes.is_empty() {
+        line.push_str(" | today");
+        for (category, minutes) in &status.today_minutes {
+            line.push_str(&format!(" {} {}m", category, minutes));
+        }
+    }
+    line
+}
+
+/// One session with its notes, tags and raw event lines
+#[utoipa::path(
+    get,
+    path = "/projections/sessions/{idx}",
+    tag = "projections",
+    params(("idx" = usize, Path, description = "Position in the session timeline"), SessionDetailParams),
+    responses(
+        (status = 200, description = "Session detail", body = projections::SessionDetail),
+        (status = 400, description = "Negative merge_gap_minutes"),
+        (status = 404, description = "No session at that index"),
+    ),
+)]
+async fn get_session(
+    state: axum::extract::State<AppState>,
+    axum::extract::Path(idx): axum::extract::Path<usize>,
+    extract::Query(params): extract::Query<SessionDetailParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let detail = state
+        .session_projector()
+        .with_merge_gap(merge_gap(params.merge_gap_minutes)?)
+        .session_detail(idx)
+        .ok_or_else(|| AppError::NotFound(format!("No session {}", idx)))?;
+
+    Ok(Json(serde_json::json!(detail)))
+}
+
+/// Raw event lines belonging to one session
+#[utoipa::path(
+    get,
+    path = "/projections/sessions/{idx}/events",
+    tag = "projections",
+    params(("idx" = usize, Path, description = "Position in the session timeline"), SessionDetailParams),
+    responses(
+        (status = 200, description = "Session events", body = openapi::SessionEvents),
+        (status = 400, description = "Negative merge_gap_minutes"),
+        (status = 404, description = "No session at that index"),
+    ),
+)]
+async fn get_session_events(
+    state: axum::extract::State<AppState>,
+    axum::extract::Path(idx): axum::extract::Path<usize>,
+    extract::Query(params): extract::Query<SessionDetailParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let events = state
+        .session_projector()
+        .with_merge_gap(merge_gap(params.merge_gap_minutes)?)
+        .session_events(idx)
+        .ok_or_else(|| AppError::NotFound(format!("No session {}", idx)))?;
+
+    Ok(Json(serde_json::json!({
+        "session": idx,
+        "events": events,
+    })))
+}
+
+/// Get ratio projections, by event count (default) or session minutes
+/// Windowed ratios and the active session's running time aren't
+/// cached: both move with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/ratios",
+    tag = "projections",
+    params(RatiosParams),
+    responses(
+        (status = 200, description = "Category ratios", body = openapi::RatiosEnvelope),
+        (status = 400, description = "Invalid window"),
+    ),
+)]
+async fn get_ratios(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RatiosParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let now = state.clock.now();
+    let analyze = |since| match params.weight {
+        RatioWeight::Count => state.ratio_analyzer().analyze_since(since),
+        RatioWeight::Duration => state
+            .ratio_analyzer()
+            .analyze_by_duration(since, params.include_active.then_some(now)),
+    };
+
+    if let Some(window) = &params.window {
+        let since = now - days::parse_window(window).map_err(AppError::invalid)?;
+        return Ok(Json(serde_json::json!({
+            "analysis": analyze(Some(since)),
+            "since": since,
+        })));
+    }
+    if params.weight == RatioWeight::Duration && params.include_active {
+        return Ok(Json(serde_json::json!({
+            "analysis": analyze(None),
+        })));
+    }
+
+    let key = format!("ratios:{:?}", params.weight);
+    let body = state.cache.get_or_compute(&key, || {
+        let analysis = analyze(None);
+
+        serde_json::json!({
+            "analysis": analysis,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get the theory-to-practice ratio over a sliding window
+#[utoipa::path(
+    get,
+    path = "/projections/ratios/rolling",
+    tag = "projections",
+    params(RollingParams),
+    responses(
+        (status = 200, description = "One point per step, null ratio in windows without practice", body = openapi::RollingEnvelope),
+        (status = 400, description = "Invalid window or step"),
+    ),
+)]
+async fn get_rolling_ratios(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RollingParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let window = params.window.as_deref().unwrap_or(DEFAULT_ROLLING_WINDOW);
+    let step = params.step.as_deref().unwrap_or(DEFAULT_ROLLING_STEP);
+    let (window_len, step_len) = days::parse_window(window)
+        .and_then(|w| Ok((w, days::parse_window(step)?)))
+        .map_err(AppError::invalid)?;
+
+    let key = format!("ratios-rolling:{}:{}", window_len, step_len);
+    let body = state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "rolling": state.ratio_analyzer().rolling(window_len, step_len),
+            "window": window,
+            "step": step,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Compare minute ratios with the `TARGET RATIO` lines in the log
+/// Not cached: the active session's minutes grow with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/ratios/target",
+    tag = "projections",
+    params(TargetParams),
+    responses(
+        (status = 200, description = "Latest target with the current deviation, and every past target's period", body = openapi::TargetEnvelope),
+        (status = 400, description = "Invalid window, range or tolerance, or both a window and a range"),
+    ),
+)]
+async fn get_ratio_target(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<TargetParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let tolerance = params.tolerance.unwrap_or(projections::DEFAULT_TARGET_TOLERANCE);
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Err(AppError::invalid("tolerance must be a non-negative number"));
+    }
+    let now = state.clock.now();
+    let (since, until) = time_range(&state, params.window.as_deref(), params.from.as_deref(), params.to.as_deref())?;
+
+    Ok(Json(serde_json::json!({
+        "report": state.ratio_analyzer().against_targets(since, until, tolerance, now),
+        "since": since,
+        "until": until,
+    })))
+}
+
+/// Get the theory-to-practice ratio per day
+#[utoipa::path(
+    get,
+    path = "/projections/ratios/trend",
+    tag = "projections",
+    params(RangeParams),
+    responses(
+        (status = 200, description = "Date-sorted series, null ratio on days without practice", body = openapi::TrendEnvelope),
+        (status = 400, description = "Invalid range, timezone or hour"),
+    ),
+)]
+async fn get_ratio_trend(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RangeParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+
+    let key = format!("ratio-trend:{:?}:{:?}:{:?}", from, to, days);
+    let body = state.cache.try_get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        Ok::<_, AppError>(serde_json::json!({
+            "trend": projector.ratio_trend(from, to).map_err(AppError::invalid)?,
+        }))
+    })?;
+
+    Ok(Json(body))
+}
+
+/// Get time allocation by duration
+/// The active session counts with its minutes so far unless
+/// `exclude_active`; only the excluding answer is cached
+#[utoipa::path(
+    get,
+    path = "/projections/allocation",
+    tag = "projections",
+    params(AllocationParams),
+    responses((status = 200, description = "Share of tracked time per category", body = openapi::AllocationEnvelope)),
+)]
+async fn get_allocation(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<AllocationParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !params.exclude_active {
+        return Ok(Json(serde_json::json!({
+            "allocation": state.ratio_analyzer().allocation_at(Some(state.clock.now())),
+        })));
+    }
+    let body = state.cache.get_or_compute("allocation", || {
+        let analyzer = state.ratio_analyzer();
+        let allocation = analyzer.allocation();
+
+        serde_json::json!({
+            "allocation": allocation,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Full-text search over event lines
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "query",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matches", body = search::SearchResult),
+        (status = 400, description = "Empty query"),
+    ),
+)]
+async fn search_log(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<SearchParams>,
+) -> Result<Json<search::SearchResult>, AppError> {
+    let terms: Vec<String> = params.q.split_whitespace().map(String::from).collect();
+    if terms.is_empty() {
+        return Err(AppError::invalid("q needs at least one search term"));
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    let searcher = LogSearcher::new(state.read_path());
+
+    Ok(Json(searcher.search(&terms, params.case_sensitive, limit)?))
+}
+
+/// Get per-day context-switch counts
+#[utoipa::path(
+    get,
+    path = "/projections/context-switches",
+    tag = "projections",
+    params(ContextSwitchParams),
+    responses(
+        (status = 200, description = "Per-day counts", body = openapi::ContextSwitchesEnvelope),
+        (status = 400, description = "Invalid threshold or timezone"),
+    ),
+)]
+async fn get_context_switches(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<ContextSwitchParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let threshold = params.threshold_minutes.unwrap_or(DEFAULT_SWITCH_THRESHOLD_MINUTES);
+    if !threshold.is_finite() || threshold < 0.0 {
+        return Err(AppError::invalid("threshold_minutes must be a non-negative number"));
+    }
+
+    let days = state.days(params.tz.as_deref(), None)?;
+
+    let key = format!("context-switches:{}:{:?}", threshold, days);
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+        let switches = projector.context_switches(threshold);
+
+        serde_json::json!({
+            "context_switches": switches,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get per-activity breakdown within a category
+#[utoipa::path(
+    get,
+    path = "/projections/activities",
+    tag = "projections",
+    params(ActivityParams),
+    responses(
+        (status = 200, description = "Activity breakdown", body = openapi::ActivitiesEnvelope),
+        (status = 400, description = "Missing category or unknown sort column"),
+    ),
+)]
+async fn get_activities(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<ActivityParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let key = format!("activities:{}:{:?}:{:?}:{}", params.category, params.sort, params.top, params.normalize);
+    let body = state.cache.get_or_compute(&key, || {
+        let analyzer = state.ratio_analyzer();
+        let activities = analyzer.activities(&params.category, params.sort, params.top, params.normalize);
+
+        serde_json::json!({
+            "activities": activities,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get dead time between sessions
+#[utoipa::path(
+    get,
+    path = "/projections/gaps",
+    tag = "projections",
+    params(GapParams),
+    responses(
+        (status = 200, description = "Gaps, plus session pairs lacking timestamps", body = openapi::GapsEnvelope),
+        (status = 400, description = "Invalid threshold, timezone or hour"),
+    ),
+)]
+async fn get_gaps(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<GapParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let min_minutes = params.min_minutes.unwrap_or(DEFAULT_GAP_MINUTES);
+    if !min_minutes.is_finite() || min_minutes < 0.0 {
+        return Err(AppError::invalid("min_minutes must be a non-negative number"));
+    }
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+
+    let key = format!("gaps:{}:{}:{:?}", min_minutes, params.ignore_overnight, days);
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+        let gaps = projector.gaps(min_minutes, params.ignore_overnight, state.working_hours);
+
+        serde_json::json!({
+            "gaps": gaps,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get how often each category follows each other one
+#[utoipa::path(
+    get,
+    path = "/projections/transitions",
+    tag = "projections",
+    params(TransitionParams),
+    responses(
+        (status = 200, description = "Transition counts and probabilities per ordered category pair", body = openapi::TransitionsEnvelope),
+        (status = 400, description = "Invalid gap"),
+    ),
+)]
+async fn get_transitions(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<TransitionParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let max_gap_hours = params.max_gap_hours.unwrap_or(DEFAULT_TRANSITION_GAP_HOURS);
+    if !max_gap_hours.is_finite() || max_gap_hours < 0.0 {
+        return Err(AppError::invalid("max_gap_hours must be a non-negative number"));
+    }
+    let max_gap = match params.ignore_gaps {
+        true => Some(
+            chrono::Duration::try_seconds((max_gap_hours * 3600.0) as i64)
+                .ok_or_else(|| AppError::invalid("max_gap_hours is too large"))?,
+        ),
+        false => None,
+    };
+
+    let key = format!("transitions:{:?}", max_gap);
+    let body = state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "transitions": state.session_projector().transitions(max_gap),
+            "max_gap_hours": max_gap.map(|_| max_gap_hours),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get pairs of sessions claiming the same time, e.g. after a backfill
+/// landed inside a session that has its own STOP
+#[utoipa::path(
+    get,
+    path = "/projections/conflicts",
+    tag = "projections",
+    responses((status = 200, description = "Overlapping session pairs, by the first one's start", body = openapi::ConflictsEnvelope)),
+)]
+async fn get_conflicts(state: axum::extract::State<AppState>) -> Json<serde_json::Value> {
+    Json(state.cache.get_or_compute("conflicts", || {
+        let conflicts = state.session_projector().conflicts();
+        serde_json::json!({
+            "count": conflicts.len(),
+            "conflicts": conflicts,
+        })
+    }))
+}
+
+/// Get how often each `key=value` tag value appears
+#[utoipa::path(
+    get,
+    path = "/projections/tags",
+    tag = "projections",
+    params(TagParams),
+    responses((status = 200, description = "Tag keys in name order, each with its values by frequency", body = openapi::TagsEnvelope)),
+)]
+async fn get_tags(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<TagParams>,
+) -> Json<serde_json::Value> {
+    let key = format!("tags:{:?}", params.category);
+    Json(state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "tags": state.session_projector().tags(params.category.as_deref()),
+            "category": params.category,
+        })
+    }))
+}
+
+/// Get the most-tracked activities, optionally within a recent window
+/// Not cached: the window moves with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/top",
+    tag = "projections",
+    params(TopParams),
+    responses(
+        (status = 200, description = "Top activities", body = openapi::TopEnvelope),
+        (status = 400, description = "Invalid window or range, or both"),
+    ),
+)]
+async fn get_top(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<TopParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (since, until) = time_range(&state, params.window.as_deref(), params.from.as_deref(), params.to.as_deref())?;
+
+    let top = state.session_projector().top_activities(
+        since,
+        until,
+        params.category.as_deref(),
+        params.by,
+        params.n.unwrap_or(DEFAULT_TOP_N),
+    );
+
+    Ok(Json(serde_json::json!({
+        "top": top,
+        "since": since,
+        "until": until,
+    })))
+}
+
+/// Get activities by how long they've gone untouched
+/// Not cached: days_since moves with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/stale",
+    tag = "projections",
+    params(StaleParams),
+    responses((status = 200, description = "Stale activities, oldest first", body = openapi::StaleEnvelope)),
+)]
+async fn get_stale(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<StaleParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut stale = state
+        .session_projector()
+        .stale_activities(params.category.as_deref(), state.clock.now());
+    if let Some(n) = params.n {
+        stale.truncate(n);
+    }
+
+    Ok(Json(serde_json::json!({
+        "stale": stale,
+    })))
+}
+
+/// Get category switches per day and session length on high- vs
+/// low-switch days
+/// Not cached: the window moves with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/switching",
+    tag = "projections",
+    params(SwitchingParams),
+    responses(
+        (status = 200, description = "Switches per day, high-switch days flagged", body = openapi::SwitchingEnvelope),
+        (status = 400, description = "Invalid window, timezone or hour"),
+    ),
+)]
+async fn get_switching(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<SwitchingParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let since = match &params.window {
+        Some(window) => Some(state.clock.now() - days::parse_window(window).map_err(AppError::invalid)?),
+        None => None,
+    };
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+
+    let switching = state
+        .session_projector()
+        .with_days(days)
+        .switching(since, params.threshold.unwrap_or(DEFAULT_DAILY_SWITCH_THRESHOLD));
+
+    Ok(Json(serde_json::json!({
+        "switching": switching,
+        "since": since,
+    })))
+}
+
+/// Get per-day aggregation (convenience for the by_day query)
+#[utoipa::path(
+    get,
+    path = "/projections/daily",
+    tag = "projections",
+    params(DailyParams),
+    responses(
+        (status = 200, description = "One row per day", body = openapi::DailyEnvelope),
+        (status = 400, description = "Invalid range, timezone, hour or merge_gap_minutes"),
+    ),
+)]
+async fn get_daily(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<DailyParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+    let merge_gap = merge_gap(params.merge_gap_minutes)?;
+
+    let key = format!("daily:{:?}:{:?}:{:?}:{:?}:{:?}", params.metric, from, to, days, merge_gap);
+    let body = state.cache.try_get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days).with_merge_gap(merge_gap);
+        let daily = projector.by_day(params.metric, None, from, to).map_err(AppError::invalid)?;
+
+        Ok::<_, AppError>(serde_json::json!({
+            "daily": daily,
+        }))
+    })?;
+
+    Ok(Json(body))
+}
+
+/// Get the sessions started on one day (convenience for the day query)
+/// Days are bucketed with the server's timezone and day start
+#[utoipa::path(
+    get,
+    path = "/projections/day/{date}",
+    tag = "projections",
+    params(("date" = String, Path, description = "The day, YYYY-MM-DD")),
+    responses(
+        (status = 200, description = "Sessions started that day, in log order", body = openapi::DayEnvelope),
+        (status = 400, description = "Invalid date"),
+    ),
+)]
+async fn get_day(
+    state: axum::extract::State<AppState>,
+    axum::extract::Path(date): axum::extract::Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let date = days::parse_date(&date).map_err(AppError::invalid)?;
+    let days = state.days(None, None)?;
+
+    let key = format!("day:{}:{:?}", date, days);
+    let body = state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "day": state.session_projector().with_days(days).sessions_on(date),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get the day with the most sessions
+#[utoipa::path(
+    get,
+    path = "/projections/busiest-day",
+    tag = "projections",
+    params(RangeParams),
+    responses(
+        (status = 200, description = "Busiest day, null when no session has a timestamp", body = openapi::BusiestDayEnvelope),
+        (status = 400, description = "Invalid range, timezone or hour"),
+    ),
+)]
+async fn get_busiest_day(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RangeParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+
+    let key = format!("busiest-day:{:?}:{:?}:{:?}", from, to, days);
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        serde_json::json!({
+            "busiest_day": projector.busiest_day(from, to),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get average sessions per active and per calendar day
+#[utoipa::path(
+    get,
+    path = "/projections/cadence",
+    tag = "projections",
+    params(RangeParams),
+    responses(
+        (status = 200, description = "Session cadence over the log's span", body = openapi::CadenceEnvelope),
+        (status = 400, description = "Invalid range, timezone or hour"),
+    ),
+)]
+async fn get_cadence(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RangeParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+
+    let key = format!("cadence:{:?}:{:?}:{:?}", from, to, days);
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        serde_json::json!({
+            "cadence": projector.cadence(from, to),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get a trailing moving average of sessions per day
+#[utoipa::path(
+    get,
+    path = "/projections/cadence/moving-average",
+    tag = "projections",
+    params(MovingAverageParams),
+    responses(
+        (status = 200, description = "One averaged point per day", body = openapi::MovingAverageEnvelope),
+        (status = 400, description = "Invalid window, range, timezone or hour"),
+    ),
+)]
+async fn get_cadence_moving_average(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<MovingAverageParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let window = params.window.unwrap_or(DEFAULT_MOVING_AVERAGE_DAYS);
+    if !(1..=MAX_MOVING_AVERAGE_DAYS).contains(&window) {
+        return Err(AppError::invalid(format!("window must be 1 to {} days", MAX_MOVING_AVERAGE_DAYS)));
+    }
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let (from, to) = days::parse_date_range(params.from.as_deref(), params.to.as_deref())
+        .map_err(AppError::invalid)?;
+
+    let key = format!("moving-average:{}:{:?}:{:?}:{:?}", window, from, to, days);
+    let body = state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "moving_average": state.session_projector().with_days(days).session_moving_average(window, from, to),
+            "window": window,
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get current and longest runs of consecutive active days
+#[utoipa::path(
+    get,
+    path = "/projections/streaks",
+    tag = "projections",
+    params(StreakParams),
+    responses(
+        (status = 200, description = "Current and longest streaks", body = openapi::StreaksEnvelope),
+        (status = 400, description = "Invalid threshold, timezone or hour"),
+    ),
+)]
+async fn get_streaks(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<StreakParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if params.min_minutes.is_some_and(|m| !m.is_finite() || m < 0.0) {
+        return Err(AppError::invalid("min_minutes must be a non-negative number"));
+    }
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    // Whether the current streak is alive changes with the date
+    let today = days.day_of(state.clock.now());
+
+    let key = format!(
+        "streaks:{:?}:{:?}:{:?}:{:?}:{}",
+        params.category, params.activity, params.min_minutes, days, today,
+    );
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        serde_json::json!({
+            "streaks": projector.streaks(
+                params.category.as_deref(),
+                params.activity.as_deref(),
+                params.min_minutes,
+                today,
+            ),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Get personal bests: longest sessions, biggest days, longest streak
+/// and earliest/latest starts
+#[utoipa::path(
+    get,
+    path = "/projections/records",
+    tag = "projections",
+    params(RecordsParams),
+    responses(
+        (status = 200, description = "Personal records and the ones nothing qualified for", body = openapi::RecordsEnvelope),
+        (status = 400, description = "Invalid timezone or hour"),
+    ),
+)]
+async fn get_records(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RecordsParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+
+    let key = format!("records:{:?}", days);
+    let body = state.cache.get_or_compute(&key, || {
+        serde_json::json!({
+            "records": state.session_projector().with_days(days).records(),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+/// Evaluate the built-in imbalance and overwork rules on daily minutes
+/// Not cached: today's totals include the active session so far
+#[utoipa::path(
+    get,
+    path = "/projections/alerts",
+    tag = "projections",
+    params(RecordsParams),
+    responses(
+        (status = 200, description = "Every rule's state with its numbers, and the firing ones", body = openapi::AlertsEnvelope),
+        (status = 400, description = "Invalid timezone or hour"),
+    ),
+)]
+async fn get_alerts(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RecordsParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    let now = state.clock.now();
+    // A fresh install has no log yet: every rule lacks data rather than failing
+    let lines = match state.reader.lines() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Arc::default(),
+        lines => lines?,
+    };
+
+    let projector = state.session_projector().with_days(days).with_elapsed_at(now);
+    let alerts = alerts::evaluate(&projector, &lines, state.alert_rules, days.day_of(now));
+
+    Ok(Json(serde_json::json!({
+        "alerts": alerts,
+    })))
+}
+
+/// Get minutes per weekday and hour of day
+/// Not cached: the window moves with the clock
+#[utoipa::path(
+    get,
+    path = "/projections/heatmap",
+    tag = "projections",
+    params(HeatmapParams),
+    responses(
+        (status = 200, description = "7×24 weekday/hour matrix and its peak", body = openapi::HeatmapEnvelope),
+        (status = 400, description = "Invalid window or timezone"),
+    ),
+)]
+async fn get_heatmap(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<HeatmapParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let since = match &params.window {
+        Some(window) => Some(state.clock.now() - days::parse_window(window).map_err(AppError::invalid)?),
+        None => None,
+    };
+    let days = state.days(params.tz.as_deref(), None)?;
+
+    let heatmap = state
+        .session_projector()
+        .with_days(days)
+        .heatmap(params.category.as_deref(), since);
+
+    Ok(Json(serde_json::json!({
+        "heatmap": heatmap,
+        "since": since,
+    })))
+}
+
+/// Get the first and last event timestamps
+#[utoipa::path(
+    get,
+    path = "/projections/span",
+    tag = "projections",
+    responses((status = 200, description = "Time covered by the log, nulls when nothing is timestamped", body = LogSpan)),
+)]
+async fn get_span(
+    state: axum::extract::State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let body = state.cache.get_or_compute("span", || {
+        serde_json::to_value(state.session_projector().span()).unwrap_or_default()
+    });
+
+    Ok(Json(body))
+}
+
+/// The longest stretch with no lines logged, between neighbouring
+/// timestamps of any kind of line, unlike the session gaps
+#[utoipa::path(
+    get,
+    path = "/projections/longest-break",
+    tag = "projections",
+    responses((status = 200, description = "Bounding timestamps and event indices of the widest gap", body = openapi::LongestBreakEnvelope)),
+)]
+async fn get_longest_break(state: axum::extract::State<AppState>) -> Json<serde_json::Value> {
+    Json(state.cache.get_or_compute("longest_break", || {
+        serde_json::json!({
+            "longest_break": state.session_projector().longest_break(),
+        })
+    }))
+}
+
+/// Sessions, ratios, allocation and span in one body
+fn projection_bundle(state: &AppState) -> serde_json::Value {
+    let sessions = state.session_projector().get_all_sessions();
+    let analyzer = state.ratio_analyzer();
+
+    serde_json::json!({
+        "sessions": sessions,
+        "ratios": analyzer.analyze(),
+        "allocation": analyzer.allocation(),
+        "span": state.session_projector().span(),
+        "computed_at": state.clock.now(),
+    })
+}
+
+/// The main projections in one read
+/// With PROJECTION_REFRESH_SECS set this is the background snapshot, up to
+/// one interval stale; otherwise it's computed on demand and cached until
+/// the log changes
+#[utoipa::path(
+    get,
+    path = "/projections/bundle",
+    tag = "projections",
+    responses((status = 200, description = "Projection bundle and when it was computed", body = openapi::BundleEnvelope)),
+)]
+async fn get_bundle(state: axum::extract::State<AppState>) -> axum::response::Response {
+    let body = if state.snapshot.enabled() {
+        state.snapshot.get_or_compute(|| projection_bundle(&state))
+    } else {
+        let bundle = state.cache.get_or_compute("bundle", || projection_bundle(&state));
+        serde_json::to_vec(&bundle).unwrap_or_default().into()
+    };
+
+    ([(axum::http::header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+/// Get weekly rollup
+#[utoipa::path(
+    get,
+    path = "/projections/weekly",
+    tag = "projections",
+    params(RollupParams),
+    responses(
+        (status = 200, description = "One row per week", body = openapi::RollupEnvelope),
+        (status = 400, description = "Invalid timezone, hour or week start"),
+    ),
+)]
+async fn get_weekly(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RollupParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let period = Period::Week(params.week_start.unwrap_or(state.week_start));
+    rollup(&state, period, &params).map(Json)
+}
+
+/// Get monthly rollup
+#[utoipa::path(
+    get,
+    path = "/projections/monthly",
+    tag = "projections",
+    params(RollupParams),
+    responses(
+        (status = 200, description = "One row per month", body = openapi::RollupEnvelope),
+        (status = 400, description = "Invalid timezone or hour"),
+    ),
+)]
+async fn get_monthly(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<RollupParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    rollup(&state, Period::Month, &params).map(Json)
+}
+
+/// Get weekly minutes projected a few weeks ahead
+#[utoipa::path(
+    get,
+    path = "/projections/forecast",
+    tag = "projections",
+    params(ForecastParams),
+    responses(
+        (status = 200, description = "Weekly history and the projection continuing it", body = openapi::ForecastEnvelope),
+        (status = 400, description = "Invalid horizon, timezone, hour or week start"),
+    ),
+)]
+async fn get_forecast(
+    state: axum::extract::State<AppState>,
+    extract::Query(params): extract::Query<ForecastParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let horizon = params.horizon.unwrap_or(DEFAULT_FORECAST_HORIZON);
+    if !(1..=MAX_FORECAST_HORIZON).contains(&horizon) {
+        return Err(AppError::invalid(format!("horizon must be 1 to {} weeks", MAX_FORECAST_HORIZON)));
+    }
+    let week_start = params.week_start.unwrap_or(state.week_start);
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    // The current week, where history ends, moves with the date
+    let today = days.day_of(state.clock.now());
+
+    let key = format!("forecast:{:?}:{}:{:?}:{:?}:{}", params.category, horizon, week_start, days, today);
+    let body = state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        serde_json::json!({
+            "forecast": projector.forecast(week_start, params.category.as_deref(), horizon, today),
+        })
+    });
+
+    Ok(Json(body))
+}
+
+fn rollup(state: &AppState, period: Period, params: &RollupParams) -> Result<serde_json::Value, AppError> {
+    let days = state.days(params.tz.as_deref(), params.day_start_hour)?;
+    // Which period is partial changes with the date, not just the log
+    let today = days.day_of(state.clock.now());
+
+    let key = format!("rollup:{:?}:{:?}:{}", period, days, today);
+    Ok(state.cache.get_or_compute(&key, || {
+        let projector = state.session_projector().with_days(days);
+
+        serde_json::json!({
+            "rollup": projector.rollup(period, today),
+        })
+    }))
+}
+
+// Helper functions
+
+fn append_to_log(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Fail unless `append_to_log` could write to `path`, without writing
+/// to the log: an existing log is opened for append, otherwise a probe
+/// file is created and removed in the nearest existing ancestor directory
+fn check_log_writable(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        return OpenOptions::new().append(true).open(path).map(|_| ());
+    }
+
+    let mut dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    while !dir.exists() {
+        dir = dir.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    }
+    // Concurrent checks in one process each get their own file
+    static PROBES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = PROBES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let probe = dir.join(format!(".ready-probe-{}-{}", std::process::id(), n));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    std::fs::remove_file(&probe)
+}
+
+/// Non-empty lines straight from disk, bypassing the shared reader
+#[cfg(test)]
+fn read_log(path: &Path) -> std::io::Result<Vec<String>> {
+    let file = std::fs::File::open(path)?;
+    let reader = std::io::BufReader::new(file);
+    
+    let mut events = Vec::new();
+    for line in crate::reader::lossy_lines(reader) {
+        if !line.trim().is_empty() {
+            events.push(line);
         }
     }
     
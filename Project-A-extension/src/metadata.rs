@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use serde_json::Value;
+
+/// `where` clause for session queries: every key must match
+/// A key maps either to a plain value (equality) or to an object of
+/// comparisons, e.g. `{"project": "api", "difficulty": {"gte": 3}}`
+/// Sessions lacking a key never match it
+#[derive(Debug, Default, Clone)]
+pub struct MetadataFilter {
+    conditions: Vec<(String, Op, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Op {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "eq" => Some(Op::Eq),
+            "ne" => Some(Op::Ne),
+            "gt" => Some(Op::Gt),
+            "gte" => Some(Op::Gte),
+            "lt" => Some(Op::Lt),
+            "lte" => Some(Op::Lte),
+            _ => None,
+        }
+    }
+}
+
+impl MetadataFilter {
+    /// Build from the JSON `where` object, rejecting unknown operators
+    pub fn from_json(clause: &BTreeMap<String, Value>) -> Result<Self, String> {
+        let mut conditions = Vec::new();
+        for (key, condition) in clause {
+            match condition {
+                Value::Object(ops) => {
+                    for (name, operand) in ops {
+                        let op = Op::parse(name).ok_or_else(|| {
+                            format!("Unknown operator '{}' for '{}' (use eq, ne, gt, gte, lt, lte)", name, key)
+                        })?;
+                        conditions.push((key.clone(), op, scalar(operand, key)?));
+                    }
+                }
+                value => conditions.push((key.clone(), Op::Eq, scalar(value, key)?)),
+            }
+        }
+        Ok(Self { conditions })
+    }
+
+    /// A single `key:value` tag, as in `?tag=focus:high`
+    /// The tag is written `focus=high` in the log; both sides must be non-empty
+    pub fn from_tag(tag: &str) -> Result<Self, String> {
+        match tag.split_once(':') {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() && !key.contains('=') => Ok(Self {
+                conditions: vec![(key.to_string(), Op::Eq, Value::String(value.to_string()))],
+            }),
+            _ => Err(format!("Tag filter '{}' must look like key:value", tag)),
+        }
+    }
+
+    pub fn matches(&self, metadata: &BTreeMap<String, String>) -> bool {
+        self.conditions.iter().all(|(key, op, operand)| {
+            metadata.get(key).is_some_and(|value| compare(value, *op, operand))
+        })
+    }
+}
+
+fn scalar(value: &Value, key: &str) -> Result<Value, String> {
+    match value {
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(value.clone()),
+        _ => Err(format!("Condition for '{}' must be a string, number or boolean", key)),
+    }
+}
+
+/// Numeric comparison when both sides look numeric, string equality otherwise
+/// Ordering operators never match non-numeric values
+fn compare(value: &str, op: Op, operand: &Value) -> bool {
+    let operand_text = match operand {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+
+    if let (Ok(left), Ok(right)) = (value.parse::<f64>(), operand_text.parse::<f64>()) {
+        return match op {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Gt => left > right,
+            Op::Gte => left >= right,
+            Op::Lt => left < right,
+            Op::Lte => left <= right,
+        };
+    }
+
+    match op {
+        Op::Eq => value == operand_text,
+        Op::Ne => value != operand_text,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(clause: Value) -> Result<MetadataFilter, String> {
+        MetadataFilter::from_json(&serde_json::from_value(clause).unwrap())
+    }
+
+    fn meta(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_numeric_and_string_conditions() {
+        let f = filter(json!({ "project": "api", "difficulty": { "gte": 3 } })).unwrap();
+
+        assert!(f.matches(&meta(&[("project", "api"), ("difficulty", "3")])));
+        assert!(f.matches(&meta(&[("project", "api"), ("difficulty", "4.5")])));
+        assert!(!f.matches(&meta(&[("project", "api"), ("difficulty", "2")])));
+        // Non-numeric value can't be ordered
+        assert!(!f.matches(&meta(&[("project", "api"), ("difficulty", "hard")])));
+        assert!(!f.matches(&meta(&[("project", "web"), ("difficulty", "5")])));
+        // Missing keys and no metadata at all never match
+        assert!(!f.matches(&meta(&[("project", "api")])));
+        assert!(!f.matches(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn test_mixed_type_equality() {
+        // "03" and 3 are both numeric, so they compare as numbers
+        assert!(filter(json!({ "level": 3 })).unwrap().matches(&meta(&[("level", "03")])));
+        assert!(filter(json!({ "level": "3" })).unwrap().matches(&meta(&[("level", "3.0")])));
+        assert!(filter(json!({ "done": true })).unwrap().matches(&meta(&[("done", "true")])));
+        assert!(filter(json!({ "mood": { "ne": "tired" } })).unwrap().matches(&meta(&[("mood", "fresh")])));
+        assert!(filter(json!({})).unwrap().matches(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn test_invalid_clauses_rejected() {
+        assert!(filter(json!({ "difficulty": { "between": 3 } })).unwrap_err().contains("between"));
+        assert!(filter(json!({ "project": ["api"] })).is_err());
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let f = MetadataFilter::from_tag("focus:high").unwrap();
+        assert!(f.matches(&meta(&[("focus", "high"), ("project", "api")])));
+        assert!(!f.matches(&meta(&[("focus", "low")])));
+        assert!(!f.matches(&BTreeMap::new()));
+        // Only the first colon splits, so values may contain one
+        assert!(MetadataFilter::from_tag("at:10:30").unwrap().matches(&meta(&[("at", "10:30")])));
+        for bad in ["focus", "focus:", ":high", "focus=high", "focus=x:high"] {
+            assert!(MetadataFilter::from_tag(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::NaiveDate;
+use crate::cache::CacheStats;
+use crate::days::DayBoundary;
+use crate::models::DayMetric;
+use crate::projections::DailyRow;
+use crate::ratelimit::RateLimitStats;
+use crate::AppState;
+
+/// Content type of the history exposition (what `promtool tsdb
+/// create-blocks-from openmetrics` backfills from)
+pub const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Metric name and help text per daily metric
+fn family(metric: DayMetric) -> (&'static str, &'static str) {
+    match metric {
+        DayMetric::Sessions => ("project_a_daily_sessions", "Sessions started per day"),
+        DayMetric::Events => ("project_a_daily_events", "Events logged per day"),
+        DayMetric::Minutes => ("project_a_daily_minutes", "Session minutes per day, by start day"),
+    }
+}
+
+/// Per-day, per-category values as one OpenMetrics gauge family
+/// Each category is one series with a sample per day, stamped with the
+/// second the day starts, oldest first
+pub fn daily_history(rows: &[DailyRow], metric: DayMetric, days: &DayBoundary) -> String {
+    let (name, help) = family(metric);
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP {} {}.", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+
+    let dated: Vec<(i64, &DailyRow)> = rows
+        .iter()
+        .filter_map(|row| {
+            let date: NaiveDate = row.date.parse().ok()?;
+            Some((days.day_start(date).timestamp(), row))
+        })
+        .collect();
+    let categories = rows.first().map(|row| row.categories.keys()).into_iter().flatten();
+    for category in categories {
+        for (ts, row) in &dated {
+            let value = row.categories.get(category).copied().unwrap_or(0.0);
+            let _ = writeln!(out, "{}{{category=\"{}\"}} {} {}", name, escape_label(category), value, ts);
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Content type of GET /metrics
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds, in seconds, of the request latency buckets
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counters and histograms updated by the handlers and the writer as they
+/// go, so GET /metrics never reads the log
+#[derive(Clone, Default)]
+pub struct ServiceMetrics {
+    inner: Arc<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+    appended: AtomicU64,
+    /// Appends rejected before writing (too long, several lines)
+    invalid_appends: AtomicU64,
+    /// Appends that failed writing
+    failed_writes: AtomicU64,
+    /// By method and route template
+    latencies: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Per bucket, not cumulative
+    counts: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// What /metrics reads off the rest of the state when it renders
+pub struct Gauges {
+    /// Size of the log up to its last complete line
+    pub log_bytes: u64,
+    pub log_lines: usize,
+    pub session_active: bool,
+    pub cache: CacheStats,
+    pub rate_limit: RateLimitStats,
+}
+
+impl ServiceMetrics {
+    pub fn appended(&self) {
+        self.inner.appended.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn append_failed(&self, error: &std::io::Error) {
+        let counter = match error.kind() {
+            std::io::ErrorKind::InvalidInput => &self.inner.invalid_appends,
+            _ => &self.inner.failed_writes,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe(&self, method: &str, route: &str, seconds: f64) {
+        let mut latencies = self.inner.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = latencies.entry((method.to_string(), route.to_string())).or_default();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.counts[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Everything in the Prometheus text format
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}.", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (series, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, series, value);
+            }
+        };
+        let plain = |value: String| vec![(String::new(), value)];
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed).to_string();
+
+        family(
+            "project_a_events_appended_total",
+            "counter",
+            "Events appended to the log by this server",
+            &plain(load(&self.inner.appended)),
+        );
+        family(
+            "project_a_append_errors_total",
+            "counter",
+            "Appends that failed, rejected as invalid or failing to write",
+            &[
+                ("{reason=\"invalid\"}".to_string(), load(&self.inner.invalid_appends)),
+                ("{reason=\"io\"}".to_string(), load(&self.inner.failed_writes)),
+            ],
+        );
+        family("project_a_log_bytes", "gauge", "Size of the log in bytes", &plain(gauges.log_bytes.to_string()));
+        family("project_a_log_lines", "gauge", "Events in the log", &plain(gauges.log_lines.to_string()));
+        family(
+            "project_a_session_active",
+            "gauge",
+            "1 while a session is running",
+            &plain(u8::from(gauges.session_active).to_string()),
+        );
+        family(
+            "project_a_projection_cache_hits_total",
+            "counter",
+            "Projections answered from the cache",
+            &plain(gauges.cache.hits.to_string()),
+        );
+        family(
+            "project_a_projection_cache_misses_total",
+            "counter",
+            "Projections computed because the cache didn't have them",
+            &plain(gauges.cache.misses.to_string()),
+        );
+
+        family(
+            "project_a_rate_limited_total",
+            "counter",
+            "Appends refused with 429 for coming too fast",
+            &plain(gauges.rate_limit.limited.to_string()),
+        );
+        family(
+            "project_a_rate_limit_clients",
+            "gauge",
+            "Clients whose rate limit bucket is still refilling",
+            &plain(gauges.rate_limit.clients.to_string()),
+        );
+
+        let latencies = self.inner.latencies.lock().unwrap_or_else(|e| e.into_inner());
+        let mut samples = Vec::new();
+        for ((method, route), histogram) in latencies.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape_label(method), escape_label(route));
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.counts) {
+                cumulative += count;
+                samples.push((format!("_bucket{{{},le=\"{}\"}}", labels, bound), cumulative.to_string()));
+            }
+            samples.push((format!("_bucket{{{},le=\"+Inf\"}}", labels), histogram.count.to_string()));
+            samples.push((format!("_sum{{{}}}", labels), histogram.sum.to_string()));
+            samples.push((format!("_count{{{}}}", labels), histogram.count.to_string()));
+        }
+        family(
+            "project_a_http_request_duration_seconds",
+            "histogram",
+            "Time to answer a request, by method and route",
+            &samples,
+        );
+        out
+    }
+}
+
+/// Time every request under its route template (`/projections/sessions/:idx`),
+/// so ids don't each get a series; unrouted requests share `unmatched`
+pub async fn track_latency(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe(&method, &route, started.elapsed().as_secs_f64());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn row(date: &str, categories: &[(&str, f64)]) -> DailyRow {
+        let categories: BTreeMap<String, f64> = categories.iter().map(|(c, v)| (c.to_string(), *v)).collect();
+        DailyRow {
+            date: date.to_string(),
+            total: categories.values().sum(),
+            categories,
+        }
+    }
+
+    #[test]
+    fn test_series_are_contiguous_and_timestamped() {
+        let rows = [
+            row("2024-01-01", &[("PRACTICE", 0.0), ("THEORY", 2.0)]),
+            row("2024-01-02", &[("PRACTICE", 1.0), ("THEORY", 0.0)]),
+        ];
+        let text = daily_history(&rows, DayMetric::Sessions, &DayBoundary::default());
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[1], "# TYPE project_a_daily_sessions gauge");
+        assert_eq!(&lines[2..6], &[
+            "project_a_daily_sessions{category=\"PRACTICE\"} 0 1704067200",
+            "project_a_daily_sessions{category=\"PRACTICE\"} 1 1704153600",
+            "project_a_daily_sessions{category=\"THEORY\"} 2 1704067200",
+            "project_a_daily_sessions{category=\"THEORY\"} 0 1704153600",
+        ]);
+        assert_eq!(lines.last(), Some(&"# EOF"));
+
+        // A 4am day start moves every stamp
+        let late = DayBoundary::new(Default::default(), 4).unwrap();
+        assert!(daily_history(&rows, DayMetric::Minutes, &late).contains("{category=\"THEORY\"} 2 1704081600"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn test_latency_buckets_are_cumulative() {
+        let metrics = ServiceMetrics::default();
+        metrics.observe("GET", "/events", 0.003);
+        metrics.observe("GET", "/events", 0.2);
+        metrics.observe("GET", "/events", 30.0);
+        metrics.append_failed(&std::io::Error::new(std::io::ErrorKind::InvalidInput, "too long"));
+        let gauges = Gauges { log_bytes: 0, log_lines: 0, session_active: true, cache: CacheStats::default(), rate_limit: RateLimitStats::default() };
+        let text = metrics.render(&gauges);
+        let sample = |series: &str| {
+            text.lines().find_map(|line| line.strip_prefix(series)).map(str::trim).unwrap_or_else(|| panic!("{} in {}", series, text))
+        };
+
+        let labels = "{method=\"GET\",route=\"/events\"";
+        let bucket = |le: &str| sample(&format!("project_a_http_request_duration_seconds_bucket{},le=\"{}\"}}", labels, le));
+        assert_eq!([bucket("0.005"), bucket("0.1"), bucket("0.25"), bucket("10"), bucket("+Inf")], ["1", "1", "2", "2", "3"]);
+        assert_eq!(sample(&format!("project_a_http_request_duration_seconds_count{}}}", labels)), "3");
+        assert_eq!(sample("project_a_append_errors_total{reason=\"invalid\"}"), "1");
+        assert_eq!(sample("project_a_session_active"), "1");
+        assert!(text.contains("# TYPE project_a_http_request_duration_seconds histogram\n"));
+    }
+}
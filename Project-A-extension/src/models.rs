@@ -1,12 +1,195 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 /// Event input from API
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct EventInput {
+    #[schema(example = "START THEORY pandas")]
     pub event: String,
+    /// Retried posts with the same key are only logged once
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// RFC3339 time the event happened, for back-dated imports (default now)
+    #[serde(default)]
+    pub timestamp: Option<String>,
+}
+
+/// POST /parse body
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ParseInput {
+    #[schema(example = "2024-01-01T09:00:00Z START THEORY pandas")]
+    pub line: String,
+}
+
+/// What `parse_event` makes of one line
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ParseResult {
+    pub line: String,
+    /// None when the line isn't an event (blank, or no uppercase verb)
+    pub parsed: Option<crate::events::ParsedEvent>,
+    /// The first token is an RFC3339 timestamp, even if the rest didn't parse
+    pub timestamp_detected: bool,
+}
+
+/// Event line with its position in the log
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct IndexedEvent {
+    pub idx: usize,
+    pub line: String,
+}
+
+/// Parsed event as exported by /events.jsonl
+/// Unparseable lines keep their text in `raw` with null structured fields
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EventRecord {
+    pub idx: usize,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub verb: Option<String>,
+    pub category: Option<String>,
+    pub activity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+/// Event listing parameters
+#[derive(Debug, Clone, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsParams {
+    /// json, csv, ndjson or text (raw lines); overrides the Accept header
+    pub format: Option<String>,
+    pub category: Option<String>,
+    /// RFC3339 or YYYY-MM-DD lower bound
+    pub from: Option<String>,
+    /// RFC3339 or YYYY-MM-DD upper bound
+    pub to: Option<String>,
+    /// Regex matched against each raw line
+    pub pattern: Option<String>,
+    /// Number of events the client already holds; only later ones are returned
+    pub since: Option<usize>,
+    /// Pagination over the matching events
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// Timezone for plain-date bounds, overrides the server default
+    pub tz: Option<String>,
+    /// `false` leaves out `# ...` comment lines (listed by default)
+    pub comments: Option<bool>,
+}
+
+/// Live stream parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamParams {
+    /// Replay events after the first `since` before going live
+    pub since: Option<usize>,
+}
+
+/// Context-switch projection parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ContextSwitchParams {
+    /// Sessions shorter than this count as a switch (minutes)
+    pub threshold_minutes: Option<f64>,
+    pub tz: Option<String>,
+}
+
+/// Tail query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TailParams {
+    /// Number of events, default 20
+    pub n: Option<usize>,
+}
+
+/// GET /status parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatusParams {
+    /// `json` (default) or `text`, one line for status bars
+    #[serde(default)]
+    pub format: StatusFormat,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusFormat {
+    #[default]
+    Json,
+    Text,
+}
+
+/// GET /reports/weekly parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportParams {
+    /// ISO week like `2024-W18`, the current week when absent; with a
+    /// Sunday WEEK_START the week begins the Sunday before its Monday
+    pub week: Option<String>,
+    /// `markdown` (default) or `json`, the same report as data
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+/// What's running now, in whole minutes
+/// Active: `category`, `activity` and `elapsed_minutes`; otherwise
+/// `idle_minutes` since the last session ended
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Status {
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>,
+    /// Left out when the session has no start timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed_minutes: Option<i64>,
+    /// Left out when no session has a timestamped end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_minutes: Option<i64>,
+    /// Net minutes per category logged today, sessions split where the
+    /// day starts and the active one included so far
+    pub today_minutes: BTreeMap<String, i64>,
+}
+
+/// What POST /admin/rebuild redid, with each step's wall time
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RebuildReport {
+    /// Non-empty lines read back from the log
+    pub lines: usize,
+    /// Events in the rebuilt index
+    pub events_indexed: usize,
+    /// Re-reading and re-indexing the log
+    pub read_ms: f64,
+    /// Recomputing the projection bundle
+    pub project_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Search query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// Space-separated terms, all must match
+    pub q: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Default 100
+    pub limit: Option<usize>,
 }
 
 /// Event structure (minimal, as per architecture)
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Event {
     pub line: String,
@@ -14,35 +197,631 @@ pub struct Event {
 }
 
 /// API Response
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ApiResponse {
     pub status: String,
     pub message: String,
     pub data: Option<serde_json::Value>,
 }
 
+/// Structured /query body, discriminated by `type`: the params of the
+/// built-in projector registered under that name
+/// Any query may also set `"explain": true` to get a `QueryPlan` back
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueryInput {
+    Ratios(RatiosQuery),
+    Timeline(TimelineQuery),
+    Allocation(AllocationQuery),
+    Recent(RecentQuery),
+    ContextSwitches(ContextSwitchesQuery),
+    ByDay(ByDayQuery),
+    Sessions(SessionsQuery),
+    Day(DayQuery),
+    Compare(CompareQuery),
+}
+
+/// Params of a built-in query, checked before any log read
+pub trait QueryParams: serde::de::DeserializeOwned + Serialize {
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RatiosQuery {
+    #[serde(default)]
+    pub params: RatioParams,
+}
+
+impl QueryParams for RatiosQuery {}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TimelineQuery {
+    #[serde(default)]
+    pub sort: SessionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Give the active session its elapsed-so-far duration
+    #[serde(default)]
+    pub elapsed: bool,
+}
+
+impl QueryParams for TimelineQuery {}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AllocationQuery {}
+
+impl QueryParams for AllocationQuery {}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecentQuery {
+    pub limit: Option<usize>,
+}
+
+impl QueryParams for RecentQuery {
+    fn validate(&self) -> Result<(), String> {
+        match self.limit {
+            Some(0) => Err("limit must be at least 1".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContextSwitchesQuery {
+    pub threshold_minutes: Option<f64>,
+    pub tz: Option<String>,
+}
+
+impl QueryParams for ContextSwitchesQuery {
+    fn validate(&self) -> Result<(), String> {
+        if self.threshold_minutes.is_some_and(|t| !t.is_finite() || t < 0.0) {
+            return Err("threshold_minutes must be a non-negative number".to_string());
+        }
+        match &self.tz {
+            Some(tz) => tz.parse::<crate::days::DayZone>().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ByDayQuery {
+    #[serde(default)]
+    pub metric: DayMetric,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Days up to today, e.g. `7d` for today and the 6 before; not
+    /// with `from` or `to`
+    pub window: Option<String>,
+    /// Only this (canonical) category in each row
+    pub category: Option<String>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+impl QueryParams for ByDayQuery {
+    fn validate(&self) -> Result<(), String> {
+        match &self.window {
+            Some(_) if self.from.is_some() || self.to.is_some() => {
+                return Err("window can't be combined with from or to".to_string());
+            }
+            Some(window) => crate::days::parse_window(window).map(|_| ())?,
+            None => crate::days::parse_date_range(self.from.as_deref(), self.to.as_deref()).map(|_| ())?,
+        }
+        check_day_boundary(self.tz.as_deref(), self.day_start_hour)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionsQuery {
+    /// Metadata conditions, see `MetadataFilter`
+    #[serde(rename = "where", default)]
+    pub filter: BTreeMap<String, serde_json::Value>,
+}
+
+impl QueryParams for SessionsQuery {
+    fn validate(&self) -> Result<(), String> {
+        crate::metadata::MetadataFilter::from_json(&self.filter).map(|_| ())
+    }
+}
+
+/// Sessions started on one day, bucketed with the server's timezone
+/// and day start
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DayQuery {
+    /// YYYY-MM-DD
+    pub date: String,
+}
+
+impl QueryParams for DayQuery {
+    fn validate(&self) -> Result<(), String> {
+        crate::days::parse_date(&self.date).map(|_| ())
+    }
+}
+
+/// Two date ranges side by side
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CompareQuery {
+    pub a: CompareRange,
+    pub b: CompareRange,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+impl QueryParams for CompareQuery {
+    fn validate(&self) -> Result<(), String> {
+        for range in [&self.a, &self.b] {
+            range.dates()?;
+        }
+        check_day_boundary(self.tz.as_deref(), self.day_start_hour)
+    }
+}
+
+fn check_day_boundary(tz: Option<&str>, day_start_hour: Option<u32>) -> Result<(), String> {
+    let zone = match tz {
+        Some(tz) => tz.parse()?,
+        None => Default::default(),
+    };
+    crate::days::DayBoundary::new(zone, day_start_hour.unwrap_or(0)).map(|_| ())
+}
+
+/// Inclusive date range of a `compare` query; `from` after `to` is a 400
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CompareRange {
+    /// YYYY-MM-DD
+    pub from: String,
+    /// YYYY-MM-DD
+    pub to: String,
+}
+
+impl CompareRange {
+    pub fn dates(&self) -> Result<(chrono::NaiveDate, chrono::NaiveDate), String> {
+        let (from, to) = (crate::days::parse_date(&self.from)?, crate::days::parse_date(&self.to)?);
+        if from > to {
+            return Err(format!("Range {}..{} ends before it starts", self.from, self.to));
+        }
+        Ok((from, to))
+    }
+}
+
+/// Key sessions are ordered by
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionSort {
+    /// Log (timestamp) order
+    #[default]
+    Log,
+    Start,
+    Duration,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Session listing parameters
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SessionsParams {
+    #[serde(default)]
+    pub sort: SessionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Give the active session its elapsed-so-far duration instead of null
+    #[serde(default)]
+    pub elapsed: bool,
+    /// Only sessions tagged `key:value` on their START, e.g. `focus:high`
+    pub tag: Option<String>,
+    /// Fold same-activity sessions less than this many minutes apart
+    pub merge_gap_minutes: Option<f64>,
+}
+
+/// Session detail parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SessionDetailParams {
+    /// Index into the listing merged with the same gap
+    pub merge_gap_minutes: Option<f64>,
+}
+
+/// What the by-day aggregation counts
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DayMetric {
+    #[default]
+    Sessions,
+    Events,
+    Minutes,
+}
+
+/// Daily aggregation parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyParams {
+    #[serde(default)]
+    pub metric: DayMetric,
+    /// First day, YYYY-MM-DD
+    pub from: Option<String>,
+    /// Last day, YYYY-MM-DD
+    pub to: Option<String>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+    /// Fold same-activity sessions less than this many minutes apart
+    pub merge_gap_minutes: Option<f64>,
+}
+
+/// Date range with day bucketing overrides
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RangeParams {
+    /// First day, YYYY-MM-DD
+    pub from: Option<String>,
+    /// Last day, YYYY-MM-DD
+    pub to: Option<String>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Ratio parameters
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RatiosParams {
+    /// Look-back window like `7d`; all history when absent
+    pub window: Option<String>,
+    #[serde(default)]
+    pub weight: RatioWeight,
+    /// With `weight=duration`, count the active session's minutes so far
+    #[serde(default)]
+    pub include_active: bool,
+}
+
+/// What category ratios are computed from
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RatioWeight {
+    /// One per event line
+    #[default]
+    Count,
+    /// Summed session minutes
+    Duration,
+}
+
+/// Rolling ratio parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RollingParams {
+    /// Width of each window, default `7d`
+    pub window: Option<String>,
+    /// Distance between window ends, default `1d`
+    pub step: Option<String>,
+}
+
+/// Ratio target parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TargetParams {
+    /// Look-back window like `7d` for the current ratio; since the latest
+    /// target when absent
+    pub window: Option<String>,
+    /// First day for the current ratio, YYYY-MM-DD; not with `window`
+    pub from: Option<String>,
+    /// Last day for the current ratio, YYYY-MM-DD; not with `window`
+    pub to: Option<String>,
+    /// Fraction of the target the ratio may be off by, default 0.1
+    pub tolerance: Option<f64>,
+}
+
+/// Moving average parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MovingAverageParams {
+    /// Days per average, default 7, at least 1
+    pub window: Option<usize>,
+    /// First day, YYYY-MM-DD
+    pub from: Option<String>,
+    /// Last day, YYYY-MM-DD
+    pub to: Option<String>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Forecast parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ForecastParams {
+    /// All categories together when absent
+    pub category: Option<String>,
+    /// Weeks to project, default 4, 1 to 52
+    pub horizon: Option<usize>,
+    /// Defaults to the server's WEEK_START
+    pub week_start: Option<crate::days::WeekStart>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Session duration stats parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SessionStatsParams {
+    pub category: Option<String>,
+    /// Histogram bucket width, default 15, at least 1
+    pub bucket_minutes: Option<f64>,
+    /// Leave out the active session, counted up to now by default
+    #[serde(default)]
+    pub exclude_active: bool,
+    /// Fold same-activity sessions less than this many minutes apart
+    pub merge_gap_minutes: Option<f64>,
+}
+
+/// Duration histogram parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DurationHistogramParams {
+    /// Ascending bucket bounds like `5m,15m,30m,1h` (units m, h, d);
+    /// default `5m,15m,30m,1h,2h`
+    pub buckets: Option<String>,
+    pub category: Option<String>,
+    /// Leave out the active session, counted up to now by default
+    #[serde(default)]
+    pub exclude_active: bool,
+}
+
+/// Allocation parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AllocationParams {
+    /// Leave out the active session, counted up to now by default
+    #[serde(default)]
+    pub exclude_active: bool,
+}
+
+/// Tag summary parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TagParams {
+    /// Only lines and sessions of this category
+    pub category: Option<String>,
+}
+
+/// Heatmap parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeatmapParams {
+    pub category: Option<String>,
+    /// Look-back window like `90d`; all history when absent
+    pub window: Option<String>,
+    /// Timezone for weekdays and hours, overrides the server default
+    pub tz: Option<String>,
+}
+
+/// Streak parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreakParams {
+    pub category: Option<String>,
+    /// Only sessions of this activity (exact match), combinable with `category`
+    pub activity: Option<String>,
+    /// Shortest session that keeps a streak alive; sessions without a
+    /// known duration count regardless
+    pub min_minutes: Option<f64>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Day bucketing overrides for personal records
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecordsParams {
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Weekly/monthly rollup parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RollupParams {
+    /// Weekly only; defaults to the server's WEEK_START
+    pub week_start: Option<crate::days::WeekStart>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Params accepted by the ratios query (none yet, unknown keys are rejected)
+#[derive(Debug, Deserialize, Serialize, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RatioParams {}
+
 /// Query result
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct QueryResult {
     pub query: String,
     pub result_type: String,
     pub data: serde_json::Value,
 }
 
+/// /query response: the result, plus how it was computed when `explain` was set
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueryResponse {
+    #[serde(flatten)]
+    pub result: QueryResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<QueryPlan>,
+}
+
+/// How a /query request was answered
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueryPlan {
+    /// Resolved type, also for legacy free-text queries
+    pub query_type: String,
+    pub projector: String,
+    pub lines_scanned: usize,
+    /// Scanned lines `parse_event` rejected
+    pub lines_unparseable: usize,
+    /// Params in effect, with defaults filled in
+    pub filters: serde_json::Value,
+    /// Time spent running the query itself
+    pub elapsed_ms: f64,
+}
+
 /// Session projection (derived from events)
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Session {
     pub category: String,
     pub activity: String,
     pub start_event_idx: usize,
     pub end_event_idx: Option<usize>,
     pub is_active: bool,
+    /// Timestamps are None for sessions built from pre-timestamp history
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    /// Net of PAUSE/RESUME breaks
+    pub duration_minutes: Option<f64>,
+    /// Start to end, breaks included
+    #[serde(default)]
+    pub gross_minutes: Option<f64>,
+    /// `key=value` pairs from the START line
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Set when something other than the user ended the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_by: Option<ClosedBy>,
+    /// Runs past the start of the next day (day start hour included);
+    /// daily aggregates split its minutes between the days
+    #[serde(default)]
+    pub spans_days: bool,
+    /// Sessions folded into this one by `merge_gap_minutes`; only set
+    /// when merging
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fragments: Option<usize>,
 }
 
-/// Activity statistics
-#[derive(Debug, Serialize)]
+/// What ended a session on the user's behalf
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClosedBy {
+    /// An `AUTOSTOP` past `max_session_minutes`
+    Auto,
+}
+
+/// Activity statistics within one category
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ActivityStats {
-    pub category: String,
-    pub count: usize,
+    pub activity: String,
+    pub sessions: usize,
+    pub events: usize,
+    pub minutes: f64,
+    /// Share of the category's minutes, or of its sessions when
+    /// nothing in the category has a duration
     pub percentage: f64,
 }
+
+/// Column the activity breakdown is sorted by (descending, activity ascending)
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivitySort {
+    #[default]
+    Minutes,
+    Sessions,
+    Events,
+    Percentage,
+    Activity,
+}
+
+/// Daily switching parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SwitchingParams {
+    /// Look-back window like `30d`; all history when absent
+    pub window: Option<String>,
+    /// Days with more switches than this are flagged, default 5
+    pub threshold: Option<usize>,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Transition matrix parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransitionParams {
+    /// Don't count pairs further apart than `max_gap_hours`
+    #[serde(default)]
+    pub ignore_gaps: bool,
+    /// Default 8
+    pub max_gap_hours: Option<f64>,
+}
+
+/// Gap detection parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GapParams {
+    /// Default 30
+    pub min_minutes: Option<f64>,
+    /// Skip gaps that cross into the next day
+    #[serde(default)]
+    pub ignore_overnight: bool,
+    pub tz: Option<String>,
+    pub day_start_hour: Option<u32>,
+}
+
+/// Top activities parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopParams {
+    /// Default 10
+    pub n: Option<usize>,
+    /// Look-back window like `30d`; all history when absent
+    pub window: Option<String>,
+    /// First day, YYYY-MM-DD; not with `window`
+    pub from: Option<String>,
+    /// Last day, YYYY-MM-DD; not with `window`
+    pub to: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub by: TopBy,
+}
+
+/// What ranks activities in the top-N
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TopBy {
+    #[default]
+    Minutes,
+    Sessions,
+}
+
+/// Stale activities parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StaleParams {
+    pub category: Option<String>,
+    pub n: Option<usize>,
+}
+
+/// Activity breakdown parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ActivityParams {
+    pub category: String,
+    #[serde(default)]
+    pub sort: ActivitySort,
+    pub top: Option<usize>,
+    /// Merge activities differing only in case
+    #[serde(default)]
+    pub normalize: bool,
+}
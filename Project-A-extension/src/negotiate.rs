@@ -0,0 +1,278 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::{Map, Value};
+
+/// Largest JSON body the projection middleware will buffer for conversion
+const MAX_CONVERT_BODY: usize = 64 * 1024 * 1024;
+
+/// Response representation picked from `?format=` or the Accept header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Ndjson,
+    /// Raw log lines, only offered by GET /events
+    Text,
+}
+
+impl Format {
+    fn from_param(format: &str) -> Option<Self> {
+        match format {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "ndjson" => Some(Format::Ndjson),
+            "text" | "txt" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Format::Json),
+            "text/csv" => Some(Format::Csv),
+            "application/x-ndjson" => Some(Format::Ndjson),
+            "text/plain" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Ndjson => "application/x-ndjson",
+            Format::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// Pick a representation among `offered`
+/// A `format` query param wins over Accept (handy in a browser);
+/// otherwise the highest-q acceptable media type is used, JSON when
+/// there's no Accept header. Nothing acceptable is a 406
+pub fn negotiate(format: Option<&str>, headers: &HeaderMap, offered: &[Format]) -> Result<Format, StatusCode> {
+    if let Some(format) = format {
+        return Format::from_param(format)
+            .filter(|f| offered.contains(f))
+            .ok_or(StatusCode::NOT_ACCEPTABLE);
+    }
+
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Ok(Format::Json);
+    };
+
+    let mut ranges: Vec<(f32, &str)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().filter(|m| !m.is_empty())?;
+            let q = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((q, media_type))
+        })
+        .filter(|(q, _)| *q > 0.0)
+        .collect();
+    if ranges.is_empty() {
+        return Ok(Format::Json);
+    }
+    // Stable, so equal q values keep the client's order
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    ranges
+        .into_iter()
+        .filter_map(|(_, media_type)| Format::from_media_type(&media_type.to_ascii_lowercase()))
+        .find(|f| offered.contains(f))
+        .ok_or(StatusCode::NOT_ACCEPTABLE)
+}
+
+/// Projection middleware: handlers always answer JSON, and this turns a
+/// successful JSON body into CSV or NDJSON rows when asked to
+/// Raw log lines don't exist for projections, so text/plain is a 406
+pub async fn convert(request: Request, next: Next) -> Response {
+    let format = request
+        .uri()
+        .query()
+        .and_then(|q| url_param(q, "format"))
+        .map(str::to_string);
+    let format = match negotiate(format.as_deref(), request.headers(), &[Format::Json, Format::Csv, Format::Ndjson]) {
+        Ok(format) => format,
+        Err(status) => return status.into_response(),
+    };
+
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+    if format == Format::Json || response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_CONVERT_BODY).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let Ok(json) = serde_json::from_slice::<Value>(&bytes) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let rows = rows(&json);
+    let body = match format {
+        Format::Csv => to_csv(&rows),
+        _ => rows.iter().map(|row| format!("{}\n", Value::Object(row.clone()))).collect(),
+    };
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Value of `name` in a raw query string (no percent-decoding needed for format names)
+pub fn url_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Rows of a projection body: the first array found walking the envelope
+/// (fields in key order, descending into objects), or the body itself as
+/// a single row when it holds no array
+/// Scalar array items become `{ "value": item }`
+pub fn rows(body: &Value) -> Vec<Map<String, Value>> {
+    let items = match first_array(body) {
+        Some(items) => items.clone(),
+        None => vec![body.clone()],
+    };
+    items
+        .into_iter()
+        .map(|item| match item {
+            Value::Object(row) => row,
+            other => Map::from_iter([("value".to_string(), other)]),
+        })
+        .collect()
+}
+
+fn first_array(value: &Value) -> Option<&Vec<Value>> {
+    match value {
+        Value::Array(items) => Some(items),
+        Value::Object(fields) => fields.values().find_map(first_array),
+        _ => None,
+    }
+}
+
+/// CSV with a header of every column seen, nested objects flattened to
+/// dotted names (`categories.THEORY`) and nested arrays kept as JSON
+pub fn to_csv(rows: &[Map<String, Value>]) -> String {
+    let flat: Vec<Vec<(String, String)>> = rows
+        .iter()
+        .map(|row| {
+            let mut cells = Vec::new();
+            for (key, value) in row {
+                flatten(key, value, &mut cells);
+            }
+            cells
+        })
+        .collect();
+
+    let mut columns: Vec<String> = Vec::new();
+    for cells in &flat {
+        for (column, _) in cells {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+    }
+
+    let mut out = csv_line(columns.iter().map(String::as_str));
+    out.push('\n');
+    for cells in &flat {
+        out.push_str(&csv_line(columns.iter().map(|column| {
+            cells
+                .iter()
+                .find(|(c, _)| c == column)
+                .map(|(_, v)| v.as_str())
+                .unwrap_or("")
+        })));
+        out.push('\n');
+    }
+    out
+}
+
+fn flatten(prefix: &str, value: &Value, cells: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten(&format!("{}.{}", prefix, key), value, cells);
+            }
+        }
+        Value::Null => cells.push((prefix.to_string(), String::new())),
+        Value::String(s) => cells.push((prefix.to_string(), s.clone())),
+        other => cells.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+/// One CSV record, without the line terminator
+pub fn csv_line<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    const ALL: &[Format] = &[Format::Json, Format::Csv, Format::Ndjson, Format::Text];
+
+    #[test]
+    fn test_negotiate_accept_and_param() {
+        assert_eq!(negotiate(None, &HeaderMap::new(), ALL), Ok(Format::Json));
+        assert_eq!(negotiate(None, &accept("text/csv"), ALL), Ok(Format::Csv));
+        assert_eq!(negotiate(None, &accept("text/html, */*;q=0.8"), ALL), Ok(Format::Json));
+        assert_eq!(negotiate(None, &accept("text/plain;q=0.5, application/x-ndjson"), ALL), Ok(Format::Ndjson));
+        assert_eq!(negotiate(None, &accept("text/html"), ALL), Err(StatusCode::NOT_ACCEPTABLE));
+        assert_eq!(negotiate(None, &accept("text/plain"), &ALL[..3]), Err(StatusCode::NOT_ACCEPTABLE));
+
+        // The query param beats the header
+        assert_eq!(negotiate(Some("csv"), &accept("application/json"), ALL), Ok(Format::Csv));
+        assert_eq!(negotiate(Some("xml"), &HeaderMap::new(), ALL), Err(StatusCode::NOT_ACCEPTABLE));
+    }
+
+    #[test]
+    fn test_rows_and_csv() {
+        let body = json!({
+            "count": 2,
+            "sessions": [
+                { "category": "THEORY", "activity": "pandas, numpy", "end_time": null },
+                { "category": "GAME", "metadata": { "mood": "good" } },
+            ],
+        });
+        let rows = rows(&body);
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(
+            to_csv(&rows),
+            "activity,category,end_time,metadata.mood\n\"pandas, numpy\",THEORY,,\n,GAME,,good\n",
+        );
+
+        // No array: the body is one row
+        assert_eq!(super::rows(&json!({ "busiest_day": { "date": "2024-01-02" } })).len(), 1);
+    }
+}
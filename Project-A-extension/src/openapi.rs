@@ -0,0 +1,436 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{OpenApi, ToSchema};
+use crate::days::WeekStart;
+use crate::models::{ActivitySort, IndexedEvent, QueryResult, RatioWeight, Session, TopBy};
+use crate::registry::ProjectorInfo;
+use crate::alerts::AlertReport;
+use crate::saved::SavedQuery;
+use crate::projections::{ActivityTotal, BusiestDay, SessionConflict, TagSummary, Cadence, DailyRatio, Forecast, MovingAverage, Heatmap, DurationHistogram, LogSpan, LoggingBreak, PersonalRecords, SwitchingReport, Transitions, RatioAnalysis, RatioTargetReport, RollingRatio, SessionStats, StaleActivity, Streaks};
+
+/// OpenAPI description generated from the `#[utoipa::path]` annotations
+/// on the handlers; every route in `build_router` should be listed here
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Project-A Event API",
+        description = "Append-only event log with derived projections. \
+            Projection endpoints also answer text/csv and application/x-ndjson, \
+            picked by Accept or a `format` query param. \
+            Failed requests answer an `ErrorBody`. \
+            With `[[tokens]]` configured, everything but /health needs `Authorization: Bearer <token>`",
+    ),
+    paths(
+        crate::root,
+        crate::dashboard,
+        crate::health_check,
+        crate::liveness,
+        crate::readiness,
+        crate::get_config,
+        crate::get_metrics,
+        crate::rebuild,
+        crate::verify_log,
+        crate::openapi_spec,
+        crate::create_event,
+        crate::parse_line,
+        crate::list_events,
+        crate::count_events,
+        crate::tail_events,
+        crate::export_events_jsonl,
+        crate::download_log,
+        crate::metrics_history,
+        crate::stream_events,
+        crate::ws::ws_handler,
+        crate::handle_query,
+        crate::list_projectors,
+        crate::list_saved_queries,
+        crate::run_saved_query,
+        crate::close_session,
+        crate::get_sessions,
+        crate::export_sessions_ics,
+        crate::get_current_session,
+        crate::get_status,
+        crate::get_weekly_report,
+        crate::get_session_stats,
+        crate::get_session,
+        crate::get_session_events,
+        crate::get_ratios,
+        crate::get_ratio_trend,
+        crate::get_rolling_ratios,
+        crate::get_ratio_target,
+        crate::get_allocation,
+        crate::get_activities,
+        crate::get_gaps,
+        crate::get_transitions,
+        crate::get_conflicts,
+        crate::get_tags,
+        crate::get_top,
+        crate::get_stale,
+        crate::get_context_switches,
+        crate::get_switching,
+        crate::get_daily,
+        crate::get_day,
+        crate::get_busiest_day,
+        crate::get_cadence,
+        crate::get_cadence_moving_average,
+        crate::get_streaks,
+        crate::get_records,
+        crate::get_alerts,
+        crate::get_heatmap,
+        crate::get_span,
+        crate::get_longest_break,
+        crate::get_duration_histogram,
+        crate::get_bundle,
+        crate::get_weekly,
+        crate::get_monthly,
+        crate::get_forecast,
+        crate::search_log,
+    ),
+    // Param enums aren't collected from `params(...)` on their own
+    components(schemas(EventsSince, WeekStart, ActivitySort, TopBy, RatioWeight, crate::error::ErrorBody)),
+    tags(
+        (name = "events", description = "Appending and reading the raw log"),
+        (name = "projections", description = "Views derived from the log"),
+        (name = "query", description = "Structured queries and search"),
+        (name = "meta", description = "Service information"),
+        (name = "admin", description = "Operational tools"),
+    ),
+)]
+pub struct ApiDoc;
+
+pub fn spec() -> Value {
+    serde_json::to_value(ApiDoc::openapi()).unwrap_or_default()
+}
+
+// Response envelopes. Handlers build these bodies with `json!`, so the
+// structs only exist to describe (and in tests, check) their shape
+
+/// GET /health
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Health {
+    pub status: String,
+    pub timestamp: DateTime<Utc>,
+    /// Events in the log, null if it couldn't be read
+    pub events: Option<usize>,
+}
+
+/// GET /health/live
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Liveness {
+    /// Always `alive`
+    pub status: String,
+}
+
+/// GET /health/ready
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Readiness {
+    /// `ready` or `not_ready`
+    pub status: String,
+    /// Why the log can't be written, null when ready
+    pub reason: Option<String>,
+}
+
+/// GET /projections
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectorsEnvelope {
+    pub projectors: Vec<ProjectorInfo>,
+}
+
+/// GET /queries
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SavedQueriesEnvelope {
+    pub queries: Vec<SavedQuery>,
+}
+
+/// Session timeline in log order unless sorted
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionsEnvelope {
+    pub sessions: Vec<Session>,
+    pub count: usize,
+}
+
+/// Null `session` when nothing is active; null `elapsed_minutes` when
+/// the session has no start timestamp
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CurrentSession {
+    pub session: Option<Session>,
+    pub elapsed_minutes: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionStatsEnvelope {
+    pub stats: SessionStats,
+    /// Category filter as given, null for all sessions
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SessionEvents {
+    /// Position in the session timeline
+    pub session: usize,
+    pub events: Vec<IndexedEvent>,
+}
+
+/// Event-count ratios wrapped in the query result they came from
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RatiosEnvelope {
+    pub analysis: RatioResult,
+    /// Start of the window, only present with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// `QueryResult` whose data is a `RatioAnalysis`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RatioResult {
+    pub query: String,
+    pub result_type: String,
+    pub data: RatioAnalysis,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TrendEnvelope {
+    /// One row per day, oldest first
+    pub trend: Vec<DailyRatio>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RollingEnvelope {
+    /// Oldest window first
+    pub rolling: Vec<RollingRatio>,
+    /// The window and step used, as given (or defaulted)
+    pub window: String,
+    pub step: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TargetEnvelope {
+    pub report: RatioTargetReport,
+    /// Start of the window or range, null without one
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range (exclusive), null without one
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TopEnvelope {
+    pub top: Vec<ActivityTotal>,
+    /// Start of the window or range, null without one
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range (exclusive), null without one
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StaleEnvelope {
+    pub stale: Vec<StaleActivity>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AllocationEnvelope {
+    pub allocation: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ActivitiesEnvelope {
+    pub activities: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GapsEnvelope {
+    pub gaps: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContextSwitchesEnvelope {
+    pub context_switches: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DailyEnvelope {
+    pub daily: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DayEnvelope {
+    /// `data` holds the `date`, `count` and `sessions`
+    pub day: QueryResult,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BusiestDayEnvelope {
+    pub busiest_day: Option<BusiestDay>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CadenceEnvelope {
+    pub cadence: Cadence,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MovingAverageEnvelope {
+    pub moving_average: Vec<MovingAverage>,
+    /// Days per average
+    pub window: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ForecastEnvelope {
+    pub forecast: Forecast,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct StreaksEnvelope {
+    pub streaks: Streaks,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchingEnvelope {
+    pub switching: SwitchingReport,
+    /// Start of the window, null without one
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConflictsEnvelope {
+    pub conflicts: Vec<SessionConflict>,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TagsEnvelope {
+    pub tags: Vec<TagSummary>,
+    /// Category filter applied, if any
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TransitionsEnvelope {
+    pub transitions: Transitions,
+    /// Gap limit applied, null unless `ignore_gaps`
+    pub max_gap_hours: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsEnvelope {
+    pub alerts: AlertReport,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RecordsEnvelope {
+    pub records: PersonalRecords,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HeatmapEnvelope {
+    pub heatmap: Heatmap,
+    /// Start of the window, null without one
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// GET /projections/duration-histogram
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DurationHistogramEnvelope {
+    pub histogram: DurationHistogram,
+    /// Category filter as given, null for all sessions
+    pub category: Option<String>,
+}
+
+/// GET /projections/longest-break
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LongestBreakEnvelope {
+    /// Null with under two timestamped lines
+    pub longest_break: Option<LoggingBreak>,
+}
+
+/// GET /projections/bundle
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BundleEnvelope {
+    pub sessions: Vec<Session>,
+    pub ratios: RatioResult,
+    pub allocation: QueryResult,
+    pub span: LogSpan,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RollupEnvelope {
+    pub rollup: QueryResult,
+}
+
+/// `{ "events": [...], "total": n }` returned by GET /events with `since`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EventsSince {
+    pub events: Vec<IndexedEvent>,
+    pub total: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_lists_routes() {
+        // Round-trip through text to prove it's valid JSON
+        let text = serde_json::to_string(&spec()).unwrap();
+        let doc: Value = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(doc["openapi"], "3.1.0");
+        for path in [
+            "/events",
+            "/events/tail",
+            "/query",
+            "/projections/sessions",
+            "/projections/sessions/{idx}",
+            "/projections/ratios",
+            "/projections/allocation",
+            "/search",
+            "/openapi.json",
+        ] {
+            assert!(doc["paths"].get(path).is_some(), "missing {}", path);
+        }
+        assert!(doc["paths"]["/events"].get("post").is_some());
+        assert!(doc["paths"]["/events"].get("head").is_some());
+        assert_eq!(doc["components"]["schemas"]["EventInput"]["required"][0], "event");
+    }
+}
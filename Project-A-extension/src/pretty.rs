@@ -0,0 +1,49 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::Value;
+use crate::negotiate::url_param;
+
+/// Largest JSON body the pretty-print layer will buffer for re-indenting
+const MAX_PRETTY_BODY: usize = 64 * 1024 * 1024;
+
+/// `?pretty=true` (or `pretty=1`): indent a JSON body for reading with curl
+fn wanted(request: &Request) -> bool {
+    request
+        .uri()
+        .query()
+        .and_then(|q| url_param(q, "pretty"))
+        .is_some_and(|value| matches!(value, "true" | "1"))
+}
+
+/// Router-wide layer: handlers answer compact JSON and this re-indents
+/// any JSON body when asked to, whatever the status; other content types
+/// (CSV, NDJSON, raw lines, event streams) pass through untouched
+pub async fn indent(request: Request, next: Next) -> Response {
+    let pretty = wanted(&request);
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    if !pretty || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_PRETTY_BODY).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let Ok(json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let Ok(mut body) = serde_json::to_vec_pretty(&json) else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    body.push(b'\n');
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
@@ -1,7 +1,18 @@
-use std::path::PathBuf;
-use std::io::BufRead;
+use std::borrow::Cow;
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
-use crate::models::{Session, QueryResult};
+use utoipa::ToSchema;
+use crate::aliases::CategoryAliases;
+use crate::clock::{FixedClock, SharedClock};
+use crate::display::CategoryDisplayConfig;
+use crate::days::{DayBoundary, Period, WeekStart, WorkingHours};
+use crate::events::{parse_event, parse_log, EventVerb, ParsedEvent};
+use crate::metadata::MetadataFilter;
+use crate::reader::EventReader;
+use crate::models::{Session, ClosedBy, QueryResult, DayMetric, IndexedEvent, ActivityStats, ActivitySort, TopBy, SessionSort, SortOrder, RatioWeight};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::NaiveDate;
 
 #[cfg(test)]
 mod tests {
@@ -16,7 +27,7 @@ mod tests {
         writeln!(temp_file, "START GAME valorant").unwrap();
         writeln!(temp_file, "START PRACTICE rust").unwrap();
         
-        let projector = SessionProjector::new(&temp_file.path().to_path_buf());
+        let projector = SessionProjector::new(temp_file.path());
         let sessions = projector.get_all_sessions();
         
         assert_eq!(sessions.len(), 3);
@@ -32,7 +43,7 @@ mod tests {
         writeln!(temp_file, "START THEORY pandas").unwrap();  // Session 1 start
         writeln!(temp_file, "START GAME valorant").unwrap();   // Session 1 end, Session 2 start
         
-        let projector = SessionProjector::new(&temp_file.path().to_path_buf());
+        let projector = SessionProjector::new(temp_file.path());
         let sessions = projector.get_all_sessions();
         
         assert_eq!(sessions.len(), 2);
@@ -48,7 +59,7 @@ mod tests {
         writeln!(temp_file, "START GAME valorant").unwrap();
         writeln!(temp_file, "START THEORY pandas").unwrap();  // Same activity, new session
         
-        let projector = SessionProjector::new(&temp_file.path().to_path_buf());
+        let projector = SessionProjector::new(temp_file.path());
         let sessions = projector.get_all_sessions();
         
         assert_eq!(sessions.len(), 3);
@@ -67,7 +78,7 @@ mod tests {
         writeln!(temp_file, "START PRACTICE python").unwrap();
         writeln!(temp_file, "START GAME valorant").unwrap();
         
-        let analyzer = RatioAnalyzer::new(&temp_file.path().to_path_buf());
+        let analyzer = RatioAnalyzer::new(temp_file.path());
         let result = analyzer.analyze();
         
         assert_eq!(result.result_type, "analysis");
@@ -85,150 +96,4076 @@ mod tests {
     }
 
     #[test]
-    fn test_no_stop_events_needed() {
-        // Test: sessions derived without explicit STOP
+    fn test_ratio_analyzer_window() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-10T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-10T10:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-11T09:00:00Z START PRACTICE rust").unwrap();
+
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+        let since = "2024-01-09T00:00:00Z".parse().unwrap();
+        let analysis: RatioAnalysis = serde_json::from_value(analyzer.analyze_since(Some(since)).data).unwrap();
+        assert_eq!(analysis.total_events, 3);
+        assert_eq!(analysis.theory_to_practice, Some(2.0));
+    }
+
+    #[test]
+    fn test_ratios_weighted_by_duration() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Three short theory sessions, one long practice block
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:10:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:20:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z START PRACTICE api").unwrap();
+        writeln!(temp_file, "2024-01-01T12:30:00Z START GAME valorant").unwrap();
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+
+        let counted: RatioAnalysis = serde_json::from_value(analyzer.analyze_since(None).data).unwrap();
+        assert_eq!(counted.weight, RatioWeight::Count);
+        assert_eq!(counted.total_minutes, None);
+
+        let since = "2024-01-01T00:00:00Z".parse().unwrap();
+        let counted: RatioAnalysis = serde_json::from_value(analyzer.analyze_since(Some(since)).data).unwrap();
+        assert_eq!(counted.theory_to_practice, Some(3.0));
+
+        let weighted: RatioAnalysis = serde_json::from_value(analyzer.analyze_by_duration(None, None).data).unwrap();
+        assert_eq!(weighted.weight, RatioWeight::Duration);
+        assert_eq!(weighted.total_minutes, Some(210.0));
+        assert_eq!(weighted.total_events, 4);
+        assert_eq!(weighted.theory_to_practice, Some(30.0 / 180.0));
+        assert_eq!(weighted.categories[0].category, "PRACTICE");
+        assert_eq!(weighted.categories[0].minutes, Some(180.0));
+        assert!((weighted.categories[0].percentage - 180.0 / 210.0 * 100.0).abs() < 1e-9);
+
+        // The active game only counts with its elapsed time included
+        let now = "2024-01-01T13:00:00Z".parse().unwrap();
+        let weighted: RatioAnalysis =
+            serde_json::from_value(analyzer.analyze_by_duration(None, Some(now)).data).unwrap();
+        assert_eq!(weighted.total_minutes, Some(240.0));
+        assert!(weighted.categories.iter().any(|c| c.category == "GAME" && c.minutes == Some(30.0)));
+    }
+
+    #[test]
+    fn test_rolling_ratio() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-02T10:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-05T09:00:00Z START GAME valorant").unwrap();
+        // Out of order: sorted before sweeping
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY rust").unwrap();
+
+        let day = chrono::Duration::days(1);
+        let rolling = RatioAnalyzer::new(temp_file.path()).rolling(day * 2, day);
+        let points: Vec<_> = rolling
+            .iter()
+            .map(|p| (p.start.to_rfc3339(), p.events, p.theory, p.practice, p.ratio))
+            .collect();
+        assert_eq!(
+            points,
+            vec![
+                ("2024-01-01T09:00:00+00:00".to_string(), 3, 2, 1, Some(2.0)),
+                ("2024-01-02T09:00:00+00:00".to_string(), 3, 2, 1, Some(2.0)),
+                // No practice: null, not a made-up number
+                ("2024-01-03T09:00:00+00:00".to_string(), 1, 1, 0, None),
+                ("2024-01-04T09:00:00+00:00".to_string(), 1, 0, 0, None),
+            ],
+        );
+
+        let empty = NamedTempFile::new().unwrap();
+        assert!(RatioAnalyzer::new(empty.path()).rolling(day, day).is_empty());
+    }
+
+    #[test]
+    fn test_ratio_edge_cases_are_explicit() {
+        let analyze = |lines: &[&str]| {
+            let mut temp_file = NamedTempFile::new().unwrap();
+            for line in lines {
+                writeln!(temp_file, "{}", line).unwrap();
+            }
+            let result = RatioAnalyzer::new(temp_file.path()).analyze();
+            serde_json::from_value::<RatioAnalysis>(result.data).unwrap()
+        };
+
+        // 10 theory, no practice: no ratio, not 10.0
+        let no_practice = analyze(&["START THEORY pandas"; 10]);
+        assert_eq!(no_practice.version, RATIO_ANALYSIS_VERSION);
+        assert_eq!((no_practice.theory_to_practice, no_practice.ratio_status), (None, RatioStatus::NoPractice));
+
+        let no_theory = analyze(&["START PRACTICE rust", "START GAME valorant"]);
+        assert_eq!((no_theory.theory_to_practice, no_theory.ratio_status), (Some(0.0), RatioStatus::NoTheory));
+
+        let empty = analyze(&[]);
+        assert_eq!((empty.theory_to_practice, empty.ratio_status), (None, RatioStatus::Empty));
+        assert_eq!(empty.total_events, 0);
+
+        let defined = analyze(&["START THEORY pandas", "START PRACTICE rust", "START PRACTICE api"]);
+        assert_eq!((defined.theory_to_practice, defined.ratio_status), (Some(0.5), RatioStatus::Defined));
+
+        // Serialized as an explicit null plus the reason
+        let json = serde_json::to_value(&no_practice).unwrap();
+        assert!(json["theory_to_practice"].is_null());
+        assert_eq!(json["ratio_status"], "no_practice");
+    }
+
+    #[test]
+    fn test_time_allocation_by_duration() {
         let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-02T10:00:00Z START THEORY pandas").unwrap();   // 90 minutes
+        writeln!(temp_file, "2024-01-02T11:30:00Z START PRACTICE rust").unwrap();   // 30 minutes
+        writeln!(temp_file, "2024-01-02T12:00:00Z START GAME valorant").unwrap();   // active, no duration
+
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+        let result = analyzer.allocation();
+        let allocation: TimeAllocation = serde_json::from_value(result.data).unwrap();
+
+        assert_eq!(allocation.total_minutes, 120.0);
+        assert_eq!(allocation.categories.len(), 2);
+        assert_eq!(allocation.categories[0].category, "THEORY");
+        assert!((allocation.categories[0].percentage - 75.0).abs() < 1e-9);
+        assert!((allocation.categories[1].percentage - 25.0).abs() < 1e-9);
+
+        let sum: f64 = allocation.categories.iter().map(|c| c.percentage).sum();
+        assert!((sum - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let values: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert_eq!(percentile(&values, 50.0), 10.0);
+        assert_eq!(percentile(&values, 90.0), 18.0);
+        assert_eq!(percentile(&values, 95.0), 19.0);
+        assert_eq!(percentile(&values, 100.0), 20.0);
+        assert_eq!(percentile(&[7.0], 50.0), 7.0);
+        assert_eq!(percentile(&[], 90.0), 0.0);
+    }
+
+    #[test]
+    fn test_allocation_duration_percentiles() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // THEORY sessions of 10, 20, ..., 100 minutes
+        let mut at = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        for minutes in (1..=10).map(|m| m * 10) {
+            writeln!(temp_file, "{} START THEORY pandas", at.to_rfc3339()).unwrap();
+            at += chrono::Duration::minutes(minutes);
+            writeln!(temp_file, "{} STOP THEORY pandas", at.to_rfc3339()).unwrap();
+        }
+
+        let allocation: TimeAllocation =
+            serde_json::from_value(RatioAnalyzer::new(temp_file.path()).allocation().data).unwrap();
+        let theory = &allocation.categories[0];
+
+        assert_eq!(theory.sessions, 10);
+        assert_eq!(theory.mean_minutes, 55.0);
+        assert_eq!(theory.p50_minutes, 50.0);
+        assert_eq!(theory.p90_minutes, 90.0);
+        assert_eq!(theory.p95_minutes, 100.0);
+    }
+
+    #[test]
+    fn test_context_switches_per_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-02T10:00:00Z START THEORY pandas").unwrap();   // 2 minutes
+        writeln!(temp_file, "2024-01-02T10:02:00Z START GAME valorant").unwrap();   // 3 minutes
+        writeln!(temp_file, "2024-01-02T10:05:00Z START THEORY rust").unwrap();     // 60 minutes
+        writeln!(temp_file, "2024-01-02T11:05:00Z START PRACTICE rust").unwrap();   // next day
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY pandas").unwrap();   // active
+
+        let projector = SessionProjector::new(temp_file.path());
+        let result = projector.context_switches(5.0);
+        let days: Vec<DailyContextSwitches> = serde_json::from_value(result.data["days"].clone()).unwrap();
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, "2024-01-02");
+        assert_eq!(days[0].sessions, 4);
+        assert_eq!(days[0].short_sessions, 2);
+        assert_eq!(days[0].distinct_categories, 3);
+        assert_eq!(days[1].short_sessions, 0);
+    }
+
+    #[test]
+    fn test_context_switches_use_local_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // 23:30 local (UTC-5) on Jan 2
+        writeln!(temp_file, "2024-01-03T04:30:00Z START THEORY pandas").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path())
+            .with_days(DayBoundary::new("-05:00".parse().unwrap(), 0).unwrap());
+        let result = projector.context_switches(5.0);
+
+        assert_eq!(result.data["days"][0]["date"], "2024-01-02");
+    }
+
+    #[test]
+    fn test_session_events_match_boundaries() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "NOTE before anything").unwrap();
         writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "NOTE groupby is neat").unwrap();
         writeln!(temp_file, "START PRACTICE rust").unwrap();
-        // No STOP event, but should still work
-        
-        let projector = SessionProjector::new(&temp_file.path().to_path_buf());
+        writeln!(temp_file, "DONE TASK borrowck").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
         let sessions = projector.get_all_sessions();
-        
-        assert_eq!(sessions.len(), 2);
-        assert!(sessions[0].end_event_idx.is_some());
+
+        for (i, session) in sessions.iter().enumerate() {
+            let events = projector.session_events(i).unwrap();
+            assert_eq!(events.first().unwrap().idx, session.start_event_idx);
+            if let Some(end) = session.end_event_idx {
+                assert_eq!(events.last().unwrap().idx, end);
+            }
+        }
+
+        let first = projector.session_events(0).unwrap();
+        assert_eq!(first.iter().map(|e| e.line.as_str()).collect::<Vec<_>>(),
+                   vec!["START THEORY pandas", "NOTE groupby is neat"]);
+        let active = projector.session_events(1).unwrap();
+        assert_eq!(active.last().unwrap().line, "DONE TASK borrowck");
+        assert!(projector.session_events(2).is_none());
+    }
+
+    #[test]
+    fn test_top_and_stale_activities() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T12:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-01T13:00:00Z STOP THEORY rust").unwrap();
+        writeln!(temp_file, "2024-03-01T09:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-03-01T09:30:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-03-01T11:30:00Z STOP PRACTICE rust").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let now: DateTime<Utc> = "2024-03-11T09:00:00Z".parse().unwrap();
+
+        let top = projector.top_activities(None, None, None, TopBy::Minutes, 10);
+        assert_eq!(top[0].activity, "pandas");
+        assert_eq!(top[0].minutes, 180.0);
+
+        let recent = projector.top_activities(Some(now - chrono::Duration::days(30)), None, None, TopBy::Minutes, 1);
+        assert_eq!(recent.len(), 1);
+        assert_eq!((recent[0].category.as_str(), recent[0].minutes), ("PRACTICE", 120.0));
+        let before = projector.top_activities(None, Some("2024-03-01T00:00:00Z".parse().unwrap()), None, TopBy::Minutes, 10);
+        assert!(before.iter().all(|t| t.category != "PRACTICE"));
+
+        let by_sessions = projector.top_activities(None, None, Some("THEORY"), TopBy::Sessions, 10);
+        assert_eq!((by_sessions[0].activity.as_str(), by_sessions[0].sessions), ("rust", 2));
+
+        let stale = projector.stale_activities(Some("THEORY"), now);
+        assert_eq!(stale.iter().map(|s| s.activity.as_str()).collect::<Vec<_>>(), vec!["pandas", "rust"]);
+        assert_eq!(stale[0].days_since, 70);
+        assert_eq!(stale[1].days_since, 10);
+    }
+
+    #[test]
+    fn test_activity_breakdown() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z START THEORY Rust").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-01T10:10:00Z DONE THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME valorant").unwrap();
+
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+        let stats = |sort, top, normalize| -> Vec<ActivityStats> {
+            let result = analyzer.activities("THEORY", sort, top, normalize);
+            serde_json::from_value(result.data["activities"].clone()).unwrap()
+        };
+
+        let split = stats(ActivitySort::Minutes, None, false);
+        assert_eq!(split.iter().map(|a| a.activity.as_str()).collect::<Vec<_>>(),
+                   vec!["rust", "Rust", "pandas"]);
+        assert_eq!(split[0].minutes, 60.0);
+        assert_eq!(split[0].events, 2);
+
+        let merged = stats(ActivitySort::Sessions, Some(1), true);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].activity, "rust");
+        assert_eq!(merged[0].sessions, 2);
+        assert_eq!(merged[0].minutes, 90.0);
+        assert_eq!(merged[0].percentage, 75.0);
+    }
+
+    #[test]
+    fn test_weekly_and_monthly_rollups() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Monday of 2024-W10
+        writeln!(temp_file, "2024-03-04T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-03-04T10:00:00Z START PRACTICE pandas").unwrap();
+        writeln!(temp_file, "2024-03-04T10:30:00Z START GAME valorant").unwrap();
+        // Sunday of 2024-W11, then nothing in W12
+        writeln!(temp_file, "2024-03-17T09:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-03-25T09:00:00Z START PRACTICE rust").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let today = NaiveDate::from_ymd_opt(2024, 3, 26).unwrap();
+
+        let weeks = projector.rollup(Period::Week(crate::days::WeekStart::Monday), today);
+        let weeks: Vec<PeriodRollup> = serde_json::from_value(weeks.data["periods"].clone()).unwrap();
+        assert_eq!(weeks.iter().map(|w| w.period.as_str()).collect::<Vec<_>>(),
+                   vec!["2024-W10", "2024-W11", "2024-W12", "2024-W13"]);
+        assert_eq!(weeks[0].sessions, 3);
+        assert_eq!(weeks[0].categories["THEORY"].minutes, 60.0);
+        assert_eq!(weeks[0].theory_to_practice, Some(1.0));
+        assert!(weeks[0].delta.is_none());
+        assert_eq!(weeks[1].delta.as_ref().unwrap().sessions, -2);
+        assert_eq!(weeks[2].sessions, 0);
+        assert_eq!(weeks[2].categories["GAME"].sessions, 0);
+        assert!(weeks[3].partial && !weeks[2].partial);
+
+        // With Sunday starts, the 17th opens a new week
+        let weeks = projector.rollup(Period::Week(crate::days::WeekStart::Sunday), today);
+        assert_eq!(weeks.data["periods"][2]["start"], "2024-03-17");
+        assert_eq!(weeks.data["periods"][2]["sessions"], 1);
+
+        let months = projector.rollup(Period::Month, today);
+        let months: Vec<PeriodRollup> = serde_json::from_value(months.data["periods"].clone()).unwrap();
+        assert_eq!(months.len(), 1);
+        assert_eq!(months[0].end, "2024-03-31");
+        assert_eq!(months[0].sessions, 5);
+        assert!(months[0].partial);
+    }
+
+    #[test]
+    fn test_weekly_rollup_straddles_week_in_timezone() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Sunday evening in New York, already Monday in UTC
+        writeln!(temp_file, "2024-01-08T03:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-08T04:00:00Z START PRACTICE pandas").unwrap();
+        // Monday 01:00 in New York
+        writeln!(temp_file, "2024-01-08T06:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-08T07:00:00Z STOP GAME chess").unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let monday = Period::Week(crate::days::WeekStart::Monday);
+
+        let utc = SessionProjector::new(temp_file.path()).period_rollups(monday, today);
+        assert_eq!(utc.len(), 1);
+        assert_eq!((utc[0].period.as_str(), utc[0].sessions), ("2024-W02", 3));
+
+        let days = DayBoundary::new("America/New_York".parse().unwrap(), 0).unwrap();
+        let weeks = SessionProjector::new(temp_file.path()).with_days(days).period_rollups(monday, today);
+        assert_eq!(weeks.iter().map(|w| w.period.as_str()).collect::<Vec<_>>(), vec!["2024-W01", "2024-W02"]);
+        assert_eq!((weeks[0].start.as_str(), weeks[0].end.as_str()), ("2024-01-01", "2024-01-07"));
+        assert_eq!(weeks[0].sessions, 2);
+        assert_eq!(weeks[0].categories["THEORY"].minutes, 60.0);
+        // The practice session runs past local midnight: its minutes split, its count stays
+        assert_eq!((weeks[0].categories["PRACTICE"].sessions, weeks[0].categories["PRACTICE"].minutes), (1, 60.0));
+        assert_eq!((weeks[1].categories["PRACTICE"].sessions, weeks[1].categories["PRACTICE"].minutes), (0, 60.0));
+        assert_eq!((weeks[1].categories["GAME"].sessions, weeks[1].categories["GAME"].minutes), (1, 60.0));
+        assert_eq!(weeks[1].sessions, 1);
+        assert!(weeks[1].partial && !weeks[0].partial);
+    }
+
+    #[test]
+    fn test_gaps_between_sessions() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "START PRACTICE legacy").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T10:00:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T10:10:00Z START PRACTICE pandas").unwrap(); // 10 min gap
+        writeln!(temp_file, "2024-01-02T11:00:00Z STOP PRACTICE pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T12:00:00Z START THEORY rust").unwrap();     // 60 min gap
+        writeln!(temp_file, "2024-01-02T22:00:00Z STOP THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-03T08:00:00Z START GAME valorant").unwrap();   // overnight
+
+        let projector = SessionProjector::new(temp_file.path());
+        let result = projector.gaps(30.0, false, WorkingHours::default());
+        let gaps = result.data["gaps"].as_array().unwrap();
+
+        assert_eq!(gaps.len(), 2);
+        assert_eq!(gaps[0]["minutes"], 60.0);
+        assert_eq!(gaps[0]["in_working_hours"], true);
+        assert_eq!(gaps[0]["previous"]["category"], "PRACTICE");
+        assert_eq!(gaps[1]["overnight"], true);
+        assert_eq!(gaps[1]["in_working_hours"], false);
+        // The untimestamped legacy sessions can't be measured against each other
+        assert_eq!(result.data["uncomputable"][0]["after_session"], 0);
+
+        let result = projector.gaps(5.0, true, WorkingHours::default());
+        let gaps = result.data["gaps"].as_array().unwrap();
+        assert_eq!(gaps.len(), 2);
+        assert_eq!(gaps[0]["minutes"], 10.0);
+    }
+
+    #[test]
+    fn test_session_detail_notes_and_tags() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas #book").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z NOTE groupby is neat #tip").unwrap();
+        writeln!(temp_file, "START GAME valorant").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let detail = projector.session_detail(0).unwrap();
+
+        assert_eq!(detail.session.activity, "pandas");
+        assert_eq!(detail.notes, vec!["groupby is neat #tip"]);
+        assert_eq!(detail.tags, vec!["book", "tip"]);
+        assert_eq!(detail.events.len(), 2);
+        assert!(projector.session_detail(2).is_none());
+    }
+
+    #[test]
+    fn test_ratio_trend_per_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Day 1: 2 theory, 1 practice; day 2: theory only; day 3: nothing; day 4: 1:2
+        for line in [
+            "2024-01-01T09:00:00Z START THEORY pandas",
+            "2024-01-01T10:00:00Z START THEORY rust",
+            "2024-01-01T11:00:00Z START PRACTICE rust",
+            "2024-01-02T09:00:00Z START THEORY pandas",
+            "2024-01-04T09:00:00Z START PRACTICE rust",
+            "2024-01-04T10:00:00Z NOTE not a session",
+            "2024-01-04T11:00:00Z START THEORY rust",
+            "2024-01-04T12:00:00Z START PRACTICE pandas",
+        ] {
+            writeln!(temp_file, "{}", line).unwrap();
+        }
+
+        let trend = SessionProjector::new(temp_file.path()).ratio_trend(None, None).unwrap();
+
+        assert_eq!(trend.iter().map(|d| d.date.as_str()).collect::<Vec<_>>(),
+                   vec!["2024-01-01", "2024-01-02", "2024-01-03", "2024-01-04"]);
+        assert_eq!(trend.iter().map(|d| d.ratio).collect::<Vec<_>>(),
+                   vec![Some(2.0), None, None, Some(0.5)]);
+        assert_eq!(trend[1].theory, 1);
+    }
+
+    #[test]
+    fn test_busiest_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-02T11:00:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "START PRACTICE untimed").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let busiest = projector.busiest_day(None, None).unwrap();
+        assert_eq!(busiest.date, "2024-01-02");
+        assert_eq!(busiest.sessions, 3);
+
+        // Sessions without a timestamp can't be placed on a day
+        let busiest = projector.busiest_day(Some("2024-01-03".parse().unwrap()), None).unwrap();
+        assert_eq!((busiest.date.as_str(), busiest.sessions), ("2024-01-03", 1));
+        assert!(projector.busiest_day(Some("2025-01-01".parse().unwrap()), None).is_none());
+    }
+
+    #[test]
+    fn test_cadence_over_span() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-04T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-10T09:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-10T10:00:00Z START THEORY rust").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let cadence = projector.cadence(None, None);
+        assert_eq!(cadence.first_day.as_deref(), Some("2024-01-01"));
+        assert_eq!(cadence.last_day.as_deref(), Some("2024-01-10"));
+        assert_eq!((cadence.sessions, cadence.active_days, cadence.span_days), (6, 3, 10));
+        assert_eq!(cadence.per_active_day, Some(2.0));
+        assert_eq!(cadence.per_calendar_day, Some(0.6));
+
+        // An explicit range widens (or narrows) the span
+        let cadence = projector.cadence(Some("2024-01-04".parse().unwrap()), Some("2024-01-13".parse().unwrap()));
+        assert_eq!((cadence.sessions, cadence.active_days, cadence.span_days), (3, 2, 10));
+        assert_eq!(cadence.per_calendar_day, Some(0.3));
+    }
+
+    #[test]
+    fn test_cadence_empty_log() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let cadence = SessionProjector::new(temp_file.path()).cadence(None, None);
+        assert_eq!((cadence.sessions, cadence.span_days), (0, 0));
+        assert_eq!((cadence.per_active_day, cadence.per_calendar_day), (None, None));
+    }
+
+    #[test]
+    fn test_span() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let span = SessionProjector::new(temp_file.path()).span();
+        assert_eq!((span.first, span.last, span.duration_days), (None, None, None));
+
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-02T12:00:00Z START THEORY pandas").unwrap();
+        let span = SessionProjector::new(temp_file.path()).span();
+        assert_eq!(span.first, span.last);
+        assert_eq!(span.duration_days, Some(0.0));
+
+        writeln!(temp_file, "2024-01-05T00:00:00Z START PRACTICE rust").unwrap();
+        // Back-dated import at the end of the file
+        writeln!(temp_file, "2024-01-01T00:00:00Z START GAME valorant").unwrap();
+        let span = SessionProjector::new(temp_file.path()).span();
+        assert_eq!(span.first.unwrap().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(span.last.unwrap().to_rfc3339(), "2024-01-05T00:00:00+00:00");
+        assert_eq!(span.duration_days, Some(4.0));
+    }
+
+    #[test]
+    fn test_longest_break_between_any_lines() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        assert_eq!(SessionProjector::new(temp_file.path()).longest_break(), None);
+
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        assert_eq!(SessionProjector::new(temp_file.path()).longest_break(), None);
+
+        writeln!(temp_file, "2024-01-01T10:00:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        // A note is still logging: the gap between sessions is split here
+        writeln!(temp_file, "2024-01-01T18:00:00Z NOTE reading").unwrap();
+        writeln!(temp_file, "2024-01-04T18:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-04T19:00:00Z STOP PRACTICE rust").unwrap();
+        // Back-dated into the middle of the quiet stretch
+        writeln!(temp_file, "2024-01-02T18:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-02T18:30:00Z STOP GAME chess").unwrap();
+
+        let gap = SessionProjector::new(temp_file.path()).longest_break().unwrap();
+        assert_eq!(gap.from.to_rfc3339(), "2024-01-02T18:30:00+00:00");
+        assert_eq!(gap.from_event_idx, 7);
+        assert_eq!(gap.to.to_rfc3339(), "2024-01-04T18:00:00+00:00");
+        assert_eq!(gap.to_event_idx, 4);
+        assert_eq!(gap.minutes, 47.5 * 60.0);
+    }
+
+    #[test]
+    fn test_duration_histogram_buckets() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        for (start, stop) in [("09:00", "09:03"), ("10:00", "10:05"), ("11:00", "11:20"), ("12:00", "12:45"), ("13:00", "14:00")] {
+            writeln!(temp_file, "2024-01-01T{}:00Z START THEORY pandas", start).unwrap();
+            writeln!(temp_file, "2024-01-01T{}:00Z STOP THEORY pandas", stop).unwrap();
+        }
+        writeln!(temp_file, "2024-01-01T15:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T16:30:00Z STOP PRACTICE rust").unwrap();
+        // No timestamp, no duration
+        writeln!(temp_file, "START GAME chess").unwrap();
+        writeln!(temp_file, "STOP GAME chess").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let histogram = projector.duration_histogram(None, &[5.0, 15.0, 30.0, 60.0]);
+        let buckets: Vec<(&str, usize)> = histogram.buckets.iter().map(|b| (b.label.as_str(), b.sessions)).collect();
+        // Bounds are exclusive: the 5 minute session is 5m-15m, the hour 1h+
+        assert_eq!(buckets, [("<5m", 1), ("5m-15m", 1), ("15m-30m", 1), ("30m-1h", 1), ("1h+", 2)]);
+        assert_eq!((histogram.count, histogram.excluded), (6, 1));
+        assert_eq!(histogram.buckets[4].from_minutes, 60.0);
+        assert_eq!(histogram.buckets[4].to_minutes, None);
+
+        let theory = projector.duration_histogram(Some("THEORY"), &[30.0]);
+        let counts: Vec<usize> = theory.buckets.iter().map(|b| b.sessions).collect();
+        assert_eq!(counts, [3, 2]);
+    }
+
+    #[test]
+    fn test_busiest_day_tie_goes_to_earliest() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-05T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-05T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-03T10:00:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-04T10:00:00Z START GAME valorant").unwrap();
+
+        let busiest = SessionProjector::new(temp_file.path()).busiest_day(None, None).unwrap();
+        assert_eq!(busiest.date, "2024-01-03");
+        assert_eq!(busiest.sessions, 2);
+    }
+
+    #[test]
+    fn test_streaks() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-03T09:10:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-05T09:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-06T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-07T09:00:00Z START THEORY numpy").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+
+        let streaks = projector.streaks(None, None, None, "2024-01-08".parse().unwrap());
+        assert_eq!((streaks.current, streaks.current_start.as_deref()), (3, Some("2024-01-05")));
+        assert_eq!(streaks.longest, 3);
+        // Ties go to the earliest run
+        assert_eq!(streaks.longest_start.as_deref(), Some("2024-01-01"));
+        assert_eq!(streaks.longest_end.as_deref(), Some("2024-01-03"));
+        assert_eq!(streaks.broken_on, vec!["2024-01-04"]);
+
+        // Two idle days end the current streak
+        let streaks = projector.streaks(Some("THEORY"), None, None, "2024-01-09".parse().unwrap());
+        assert_eq!((streaks.current, streaks.longest), (0, 3));
+        assert_eq!(streaks.broken_on, vec!["2024-01-04", "2024-01-08"]);
+
+        // The 10-minute pandas session on the 3rd no longer qualifies;
+        // the last session has no end, so it counts on presence alone
+        let streaks = projector.streaks(Some("THEORY"), None, Some(30.0), "2024-01-07".parse().unwrap());
+        assert_eq!((streaks.current, streaks.longest), (2, 2));
+        assert_eq!(streaks.broken_on, vec!["2024-01-03"]);
+    }
+
+    #[test]
+    fn test_streaks_for_one_activity() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY pandas").unwrap();
+        // numpy fills the gap for THEORY, but not for pandas
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-04T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-05T09:00:00Z START PRACTICE pandas").unwrap();
+        writeln!(temp_file, "2024-01-06T09:00:00Z START GAME valorant").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+        let today = "2024-01-05".parse().unwrap();
+
+        let category = projector.streaks(Some("THEORY"), None, None, today);
+        assert_eq!((category.current, category.longest), (4, 4));
+
+        let activity = projector.streaks(None, Some("pandas"), None, today);
+        assert_eq!((activity.current, activity.current_start.as_deref()), (2, Some("2024-01-04")));
+        assert_eq!((activity.longest, activity.longest_start.as_deref()), (2, Some("2024-01-01")));
+        assert_eq!(activity.broken_on, vec!["2024-01-03"]);
+
+        // Both filters together: PRACTICE pandas on the 5th no longer counts
+        let both = projector.streaks(Some("THEORY"), Some("pandas"), None, today);
+        assert_eq!((both.current, both.longest), (1, 2));
+        assert_eq!(both.broken_on, vec!["2024-01-03"]);
+    }
+
+    #[test]
+    fn test_heatmap_splits_sessions_across_hours() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Monday 2024-01-01, 09:40 to 11:10
+        writeln!(temp_file, "2024-01-01T09:40:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T11:10:00Z START GAME valorant").unwrap();
+        // Sunday 23:30 into Monday 00:30
+        writeln!(temp_file, "2024-01-07T23:30:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-08T00:30:00Z STOP THEORY rust").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+
+        let heatmap = projector.heatmap(Some("THEORY"), None);
+        assert_eq!(heatmap.unit, "minutes");
+        assert_eq!((heatmap.cells.len(), heatmap.cells[0].len()), (7, 24));
+        assert_eq!(&heatmap.cells[0][9..12], &[20.0, 60.0, 10.0]);
+        assert_eq!((heatmap.cells[6][23], heatmap.cells[0][0]), (30.0, 30.0));
+        let peak = heatmap.peak.unwrap();
+        assert_eq!((peak.weekday.as_str(), peak.hour, peak.value), ("monday", 10, 60.0));
+
+        // The window clips sessions that started before it
+        let since = "2024-01-08T00:00:00Z".parse().unwrap();
+        let heatmap = projector.heatmap(Some("THEORY"), Some(since));
+        assert_eq!(heatmap.cells.iter().flatten().sum::<f64>(), 30.0);
+
+        // Hours are local: at +05:30 the first session runs 15:10 to 16:40
+        let zone = DayBoundary::new("+05:30".parse().unwrap(), 0).unwrap();
+        let heatmap = projector.with_days(zone).heatmap(Some("THEORY"), None);
+        assert_eq!(&heatmap.cells[0][15..17], &[50.0, 40.0]);
+    }
+
+    #[test]
+    fn test_heatmap_counts_events_without_durations() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z NOTE THEORY reading").unwrap();
+        writeln!(temp_file, "2024-01-02T09:30:00Z START THEORY pandas").unwrap();
+
+        let heatmap = SessionProjector::new(temp_file.path()).heatmap(None, None);
+        assert_eq!(heatmap.unit, "events");
+        assert_eq!(heatmap.cells[1][9], 2.0);
+        assert_eq!(heatmap.peak.unwrap().weekday, "tuesday");
+
+        let empty = NamedTempFile::new().unwrap();
+        assert!(SessionProjector::new(empty.path()).heatmap(None, None).peak.is_none());
+    }
+
+    #[test]
+    fn test_unknown_verbs_are_ignored() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z LEARN PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T09:40:00Z NOTE THEORY reading").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z STOP").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z DONE PRACTICE exercise").unwrap();
+
+        // Neither an unknown verb nor a NOTE ends or opens a session
+        let sessions = SessionProjector::new(temp_file.path()).get_all_sessions();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].duration_minutes, Some(60.0));
+
+        // Nor does it count toward ratios
+        let analysis: RatioAnalysis = serde_json::from_value(RatioAnalyzer::new(temp_file.path()).analyze().data).unwrap();
+        assert_eq!(analysis.total_events, 1);
+    }
+
+    #[test]
+    fn test_ratios_count_only_starts() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "PAUSE THEORY pandas").unwrap();
+        writeln!(temp_file, "RESUME THEORY pandas").unwrap();
+        writeln!(temp_file, "STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "NOTE hello").unwrap();
+        writeln!(temp_file, "DONE TASK refactor").unwrap();
+        writeln!(temp_file, "START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START PRACTICE api").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z STOP THEORY pandas").unwrap();
+
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+        let analysis: RatioAnalysis = serde_json::from_value(analyzer.analyze().data).unwrap();
+        let counts: Vec<_> = analysis.categories.iter().map(|c| (c.category.as_str(), c.count)).collect();
+        assert_eq!(counts, vec![("PRACTICE", 2), ("THEORY", 1)]);
+        assert_eq!(analysis.total_events, 3);
+        assert_eq!(analysis.theory_to_practice, Some(0.5));
+
+        // Same filter inside a window
+        let since = "2024-01-01T00:00:00Z".parse().unwrap();
+        let windowed: RatioAnalysis = serde_json::from_value(analyzer.analyze_since(Some(since)).data).unwrap();
+        assert_eq!(windowed.total_events, 1);
+    }
+
+    #[test]
+    fn test_targets_apply_from_their_index_forward() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T08:00:00Z TARGET RATIO THEORY PRACTICE 2.0").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z STOP").unwrap();
+        writeln!(temp_file, "2024-01-02T08:00:00Z TARGET RATIO THEORY").unwrap();
+        writeln!(temp_file, "2024-01-02T08:00:00Z TARGET RATIO THEORY PRACTICE 1.0").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-02T09:50:00Z START PRACTICE api").unwrap();
+        writeln!(temp_file, "2024-01-02T10:35:00Z STOP").unwrap();
+
+        let now = "2024-01-03T00:00:00Z".parse().unwrap();
+        let analyzer = RatioAnalyzer::new(temp_file.path());
+        let report = analyzer.against_targets(None, None, DEFAULT_TARGET_TOLERANCE, now);
+
+        // The malformed line isn't a target
+        assert_eq!(report.history.len(), 2);
+        assert_eq!(report.target.as_ref().unwrap().idx, 5);
+
+        // Day one is judged against 2.0: an hour each is short an hour of theory
+        let first = &report.history[0];
+        assert_eq!(first.end_idx, Some(5));
+        assert_eq!(first.deviation.ratio, Some(1.0));
+        assert_eq!(first.deviation.delta, Some(-1.0));
+        assert_eq!(first.deviation.on_target, Some(false));
+        let correction = first.deviation.correction.as_ref().unwrap();
+        assert_eq!((correction.category.as_str(), correction.minutes), ("THEORY", 60.0));
+
+        // Day two against 1.0: 50 theory to 45 practice is just outside 10%
+        let current = report.current.unwrap();
+        assert_eq!((current.numerator_minutes, current.denominator_minutes), (50.0, 45.0));
+        assert_eq!(current.on_target, Some(false));
+        let correction = current.correction.unwrap();
+        assert_eq!((correction.category.as_str(), correction.minutes), ("PRACTICE", 5.0));
+
+        let loose = analyzer.against_targets(None, None, 0.2, now).current.unwrap();
+        assert_eq!(loose.on_target, Some(true));
+        assert!(loose.correction.is_none());
+
+        // A window reaching back over both days is still held to the latest target
+        let since = "2024-01-01T00:00:00Z".parse().unwrap();
+        let windowed = analyzer.against_targets(Some(since), None, DEFAULT_TARGET_TOLERANCE, now).current.unwrap();
+        assert_eq!(windowed.numerator_minutes, 110.0);
+        let until = "2024-01-02T00:00:00Z".parse().unwrap();
+        let first_day = analyzer.against_targets(Some(since), Some(until), DEFAULT_TARGET_TOLERANCE, now).current.unwrap();
+        assert_eq!((first_day.numerator_minutes, first_day.denominator_minutes), (60.0, 60.0));
+
+        let empty = NamedTempFile::new().unwrap();
+        let report = RatioAnalyzer::new(empty.path()).against_targets(None, None, DEFAULT_TARGET_TOLERANCE, now);
+        assert!(report.target.is_none() && report.current.is_none() && report.history.is_empty());
+    }
+
+    #[test]
+    fn test_pauses_are_taken_off_net_duration() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:20:00Z PAUSE").unwrap();
+        writeln!(temp_file, "2024-01-01T09:35:00Z RESUME").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+        // Unbalanced: a RESUME with nothing paused, then a PAUSE never resumed
+        writeln!(temp_file, "2024-01-01T10:10:00Z RESUME").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z PAUSE").unwrap();
+        writeln!(temp_file, "2024-01-01T10:40:00Z PAUSE").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-01T11:30:00Z PAUSE").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions.len(), 3);
+
+        let theory = &sessions[0];
+        assert_eq!((theory.gross_minutes, theory.duration_minutes), (Some(60.0), Some(45.0)));
+        assert_eq!(theory.end_event_idx, Some(2));
+
+        // The open pause runs until the next START
+        assert_eq!((sessions[1].gross_minutes, sessions[1].duration_minutes), (Some(60.0), Some(30.0)));
+
+        // The active session is paused up to now
+        let now = "2024-01-01T12:00:00Z".parse().unwrap();
+        let active = projector.with_elapsed_at(now).get_all_sessions().pop().unwrap();
+        assert_eq!((active.gross_minutes, active.duration_minutes), (Some(60.0), Some(30.0)));
+    }
+
+    #[test]
+    fn test_duration_stats_and_histogram() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:05:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:45:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START THEORY polars").unwrap();
+        writeln!(temp_file, "2024-01-01T10:20:00Z START THEORY active").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let stats = projector.duration_stats(Some("THEORY"), 15.0);
+        // The untimestamped and the active session are set aside
+        assert_eq!((stats.count, stats.excluded), (3, 2));
+        assert_eq!(stats.total_minutes, 65.0);
+        assert_eq!((stats.median_minutes, stats.p90_minutes, stats.max_minutes), (Some(20.0), Some(40.0), Some(40.0)));
+        let buckets: Vec<_> = stats.histogram.iter().map(|b| (b.from_minutes, b.sessions)).collect();
+        assert_eq!(buckets, vec![(0.0, 1), (15.0, 1), (30.0, 1)]);
+
+        let all = projector.duration_stats(None, 60.0);
+        assert_eq!((all.count, all.histogram.len()), (4, 1));
+
+        let none = projector.duration_stats(Some("GAME"), 15.0);
+        assert_eq!((none.count, none.mean_minutes), (0, None));
+        assert!(none.histogram.is_empty());
+
+        // A session forgotten for years
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2020-01-01T09:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z STOP GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START GAME go").unwrap();
+        writeln!(temp_file, "2024-01-01T09:10:00Z STOP GAME go").unwrap();
+        let stats = SessionProjector::new(temp_file.path()).duration_stats(None, 1.0);
+        assert_eq!(stats.histogram.len(), MAX_DURATION_BUCKETS);
+        let last = stats.histogram.last().unwrap();
+        assert_eq!((last.from_minutes, last.to_minutes, last.sessions), (999.0, stats.max_minutes.unwrap() + 1.0, 1));
+        assert_eq!(stats.histogram[10].sessions, 1);
+    }
+
+    #[test]
+    fn test_personal_records() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T06:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-02T06:20:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-02T07:00:00Z STOP GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-02T23:30:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-03T00:30:00Z STOP THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-05T12:00:00Z START THEORY active").unwrap();
+
+        let records = SessionProjector::new(temp_file.path()).records();
+        let theory = &records.longest_session["THEORY"];
+        assert_eq!((theory.value, theory.date.as_deref(), theory.activity.as_deref()), (90.0, Some("2024-01-01"), Some("pandas")));
+        assert_eq!(theory.event_indices, vec![1, 2]);
+        // Ended by the next START: only its own line backs it
+        assert_eq!(records.longest_session["PRACTICE"].event_indices, vec![3]);
+        assert_eq!(records.longest_session["GAME"].value, 40.0);
+
+        let most_sessions = records.most_sessions_in_a_day.unwrap();
+        assert_eq!((most_sessions.value, most_sessions.date.as_deref()), (3.0, Some("2024-01-02")));
+        assert_eq!(most_sessions.event_indices, vec![3, 4, 6]);
+        // numpy's half hour past midnight counts toward 01-03
+        assert_eq!(records.most_minutes_in_a_day.unwrap().value, 90.0);
+
+        let streak = records.longest_streak.unwrap();
+        assert_eq!((streak.value, streak.date.as_deref(), streak.end_date.as_deref()), (2.0, Some("2024-01-01"), Some("2024-01-02")));
+        assert_eq!(streak.event_indices, vec![1, 3, 4, 6]);
+
+        let (earliest, latest) = (records.earliest_start.unwrap(), records.latest_start.unwrap());
+        assert_eq!((earliest.time.as_deref(), earliest.event_indices), (Some("06:00:00"), vec![3]));
+        assert_eq!((latest.value, latest.event_indices), (1410.0, vec![6]));
+        assert!(records.omitted.is_empty());
+
+        // Minutes into the day count from the day start
+        let days = DayBoundary::new(Default::default(), 4).unwrap();
+        let shifted = SessionProjector::new(temp_file.path()).with_days(days).records();
+        assert_eq!(shifted.earliest_start.unwrap().value, 120.0);
+    }
+
+    #[test]
+    fn test_records_without_time_say_why() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let reasons = |path: &std::path::Path| -> Vec<(String, String)> {
+            let records = SessionProjector::new(path).records();
+            records.omitted.into_iter().map(|o| (o.record, o.reason)).collect()
+        };
+
+        let empty = reasons(temp_file.path());
+        assert_eq!(empty.len(), 6);
+        assert!(empty.iter().all(|(_, reason)| reason == "no sessions"));
+
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "START GAME valorant").unwrap();
+        let untimed = reasons(temp_file.path());
+        assert_eq!(untimed[0], ("longest_session".to_string(), "no session has a duration".to_string()));
+        assert_eq!(untimed[5], ("latest_start".to_string(), "no session has a start timestamp".to_string()));
+    }
+
+    #[test]
+    fn test_transitions_skip_long_gaps() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z STOP GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-01T12:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T13:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T14:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T15:00:00Z STOP PRACTICE rust").unwrap();
+        // Overnight
+        writeln!(temp_file, "2024-01-02T09:00:00Z START THEORY polars").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let all = projector.transitions(None);
+        assert_eq!(all.categories, vec!["GAME", "PRACTICE", "THEORY"]);
+        assert_eq!(all.matrix.len(), 9);
+        assert_eq!((all.total, all.excluded), (5, 0));
+        let cell = |t: &Transitions, from: &str, to: &str| {
+            t.matrix.iter().find(|c| c.from == from && c.to == to).map(|c| (c.count, c.probability)).unwrap()
+        };
+        assert_eq!(cell(&all, "THEORY", "GAME"), (2, Some(1.0)));
+        assert_eq!(cell(&all, "GAME", "THEORY"), (1, Some(0.5)));
+        assert_eq!(cell(&all, "PRACTICE", "THEORY"), (1, Some(1.0)));
+        assert_eq!(all.most_likely_next["THEORY"].to, "GAME");
+        // A tie goes to the alphabetically first
+        assert_eq!(all.most_likely_next["GAME"].to, "PRACTICE");
+
+        let same_day = projector.transitions(Some(chrono::Duration::hours(8)));
+        assert_eq!((same_day.total, same_day.excluded), (4, 1));
+        assert_eq!(cell(&same_day, "PRACTICE", "THEORY"), (0, None));
+        assert!(!same_day.most_likely_next.contains_key("PRACTICE"));
+    }
+
+    #[test]
+    fn test_transitions_count_self_transitions() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // THEORY, THEORY, PRACTICE, THEORY, GAME, GAME, THEORY
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z START THEORY polars").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T11:10:00Z STOP GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T11:15:00Z START GAME valorant").unwrap();
+        writeln!(temp_file, "2024-01-01T12:00:00Z START THEORY pandas").unwrap();
+
+        let transitions = SessionProjector::new(temp_file.path()).transitions(None);
+        let counts: Vec<(&str, &str, usize)> = transitions
+            .matrix
+            .iter()
+            .filter(|t| t.count > 0)
+            .map(|t| (t.from.as_str(), t.to.as_str(), t.count))
+            .collect();
+        assert_eq!(counts, vec![
+            ("GAME", "GAME", 1),
+            ("GAME", "THEORY", 1),
+            ("PRACTICE", "THEORY", 1),
+            ("THEORY", "GAME", 1),
+            ("THEORY", "PRACTICE", 1),
+            ("THEORY", "THEORY", 1),
+        ]);
+        assert_eq!(transitions.total, 6);
+        let theory: f64 = transitions.matrix.iter().filter(|t| t.from == "THEORY").filter_map(|t| t.probability).sum();
+        assert!((theory - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_switching_per_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:10:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T09:20:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z START THEORY polars").unwrap();
+        writeln!(temp_file, "2024-01-01T09:40:00Z STOP THEORY polars").unwrap();
+        writeln!(temp_file, "2024-01-01T23:00:00Z START PRACTICE rust").unwrap();
+        // Runs past midnight: switching away from it still counts on Jan 2,
+        // the session itself doesn't
+        writeln!(temp_file, "2024-01-02T01:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-02T03:00:00Z STOP GAME chess").unwrap();
+        // After an overnight break: not a switch
+        writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-03T10:00:00Z STOP THEORY pandas").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let report = projector.switching(None, 2);
+        let switches: Vec<(&str, usize, usize)> =
+            report.days.iter().map(|d| (d.date.as_str(), d.sessions, d.switches)).collect();
+        assert_eq!(switches, vec![("2024-01-01", 5, 3), ("2024-01-02", 1, 1), ("2024-01-03", 1, 0)]);
+
+        // Jan 1 is the only day over 2: four 10-minute sessions and a 2-hour one
+        assert_eq!(report.high_switch_days, 1);
+        assert!(report.days[0].high_switch);
+        assert_eq!(report.mean_session_minutes_high, Some(32.0));
+        assert_eq!(report.mean_session_minutes_low, Some(90.0));
+
+        let since = "2024-01-02T00:00:00Z".parse().unwrap();
+        let recent = projector.switching(Some(since), 2);
+        assert_eq!(recent.days.len(), 2);
+        assert_eq!(recent.days[0].switches, 1);
+        assert_eq!((recent.high_switch_days, recent.mean_session_minutes_high), (0, None));
+    }
+
+    #[test]
+    fn test_session_moving_average() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // 1, 3, 0, 2, 0, 6 sessions on Jan 1-6
+        for (day, sessions) in [(1, 1), (2, 3), (4, 2), (6, 6)] {
+            for i in 0..sessions {
+                writeln!(temp_file, "2024-01-0{}T1{}:00:00Z START THEORY t{}", day, i, i).unwrap();
+            }
+        }
+        let projector = SessionProjector::new(temp_file.path());
+
+        let points = projector.session_moving_average(3, None, None);
+        let series: Vec<(usize, usize, f64)> = points.iter().map(|p| (p.sessions, p.days, p.average)).collect();
+        // Partial windows at the start, then full ones
+        assert_eq!(series[0], (1, 1, 1.0));
+        assert_eq!(series[1], (3, 2, 2.0));
+        assert_eq!(series[2], (0, 3, 4.0 / 3.0));
+        assert_eq!(series[3], (2, 3, 5.0 / 3.0));
+        assert_eq!(series[5], (6, 3, 8.0 / 3.0));
+        assert_eq!(points.len(), 6);
+
+        // A later `from` still averages over the days before it; `to` pads with zeros
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        let points = projector.session_moving_average(3, Some(date("2024-01-04")), Some(date("2024-01-08")));
+        let series: Vec<(&str, f64)> = points.iter().map(|p| (p.date.as_str(), p.average)).collect();
+        assert_eq!(series[0], ("2024-01-04", 5.0 / 3.0));
+        assert_eq!(series[4], ("2024-01-08", 2.0));
+
+        let single = projector.session_moving_average(1, None, None);
+        assert!(single.iter().all(|p| p.average == p.sessions as f64 && p.days == 1));
+    }
+
+    #[test]
+    fn test_forecast_fits_a_line_through_zero_weeks() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Mondays of Jan 1, 8 and 22, 2024: 60, 30 and 90 minutes of THEORY;
+        // the week of Jan 15 is empty
+        for (day, stop) in [(1, "10:00"), (8, "09:30"), (22, "10:30")] {
+            writeln!(temp_file, "2024-01-{:02}T09:00:00Z START THEORY pandas", day).unwrap();
+            writeln!(temp_file, "2024-01-{:02}T{}:00Z START GAME chess", day, stop).unwrap();
+            writeln!(temp_file, "2024-01-{:02}T12:00:00Z STOP GAME chess", day).unwrap();
+        }
+        let projector = SessionProjector::new(temp_file.path());
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+
+        let forecast = projector.forecast(WeekStart::Monday, Some("THEORY"), 2, date("2024-01-31"));
+        let history: Vec<f64> = forecast.history.iter().map(|w| w.minutes).collect();
+        assert_eq!(history, vec![60.0, 30.0, 0.0, 90.0]);
+        // Best line through (0, 60), (1, 30), (2, 0), (3, 90): 36 + 6x
+        assert_eq!(forecast.slope, Some(6.0));
+        assert_eq!(forecast.forecast[0].minutes, 60.0);
+        assert_eq!(forecast.method, "linear_trend");
+        assert!(!forecast.low_confidence);
+        assert_eq!(forecast.forecast[0].start, "2024-01-29");
+        assert_eq!(forecast.forecast[1].week, "2024-W06");
+        // Weeks after the last session count as zeros: the trend turns down
+        let later = projector.forecast(WeekStart::Monday, Some("THEORY"), 1, date("2024-03-01"));
+        assert!(later.history.len() > 4);
+        assert!(later.slope.unwrap() < 0.0);
+        assert!(later.forecast[0].minutes >= 0.0);
+
+        let early = projector.forecast(WeekStart::Monday, None, 3, date("2024-01-17"));
+        assert_eq!((early.method.as_str(), early.low_confidence), ("mean", true));
+        assert_eq!(early.history.len(), 2);
+        assert!(early.forecast.iter().all(|w| w.minutes == early.forecast[0].minutes));
+    }
+
+    #[test]
+    fn test_compare_normalizes_per_day() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // February: 2 × 60 min of THEORY, 1 × 30 min of PRACTICE
+        writeln!(temp_file, "2023-02-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2023-02-01T10:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2023-02-01T10:30:00Z STOP PRACTICE rust").unwrap();
+        writeln!(temp_file, "2023-02-20T09:00:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2023-02-20T10:00:00Z STOP THEORY numpy").unwrap();
+        // March: 1 × 62 min of GAME
+        writeln!(temp_file, "2023-03-05T09:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2023-03-05T10:02:00Z STOP GAME chess").unwrap();
+
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+        let feb = (date("2023-02-01"), date("2023-02-28"));
+        let mar = (date("2023-03-01"), date("2023-03-31"));
+        let result = projector.compare(feb, mar);
+        let comparison: RangeComparison = serde_json::from_value(result.data).unwrap();
+
+        assert_eq!((comparison.a.days, comparison.a.sessions, comparison.a.minutes), (28, 3, 150.0));
+        assert_eq!(comparison.a.theory_to_practice, Some(2.0));
+        assert_eq!((comparison.b.days, comparison.b.minutes), (31, 62.0));
+        assert_eq!(comparison.deltas.minutes.delta, -88.0);
+        // Per day: 150/28 against 62/31
+        let per_day = comparison.deltas.minutes_per_day.unwrap();
+        assert!((per_day.delta - (2.0 - 150.0 / 28.0)).abs() < 1e-9);
+        assert_eq!(comparison.deltas.categories["GAME"].minutes.percent, None);
+        assert_eq!(comparison.deltas.categories["THEORY"].minutes.percent, Some(-100.0));
+        assert!(comparison.deltas.theory_to_practice.is_none());
+        assert!(comparison.flags.is_empty());
+
+        let result = projector.compare(feb, (date("2023-02-15"), date("2023-03-15")));
+        let comparison: RangeComparison = serde_json::from_value(result.data).unwrap();
+        assert_eq!(comparison.flags, vec!["ranges overlap"]);
+        assert_eq!(comparison.b.sessions, 2);
+    }
+
+    #[test]
+    fn test_tag_value_frequencies() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas focus=high mood=tired").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START THEORY numpy focus=low").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z NOTE THEORY numpy focus=high").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME chess focus=high").unwrap();
+        writeln!(temp_file, "2024-01-01T11:30:00Z STOP").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+
+        let tags = projector.tags(None);
+        assert_eq!(tags.iter().map(|t| t.key.as_str()).collect::<Vec<_>>(), vec!["focus", "mood"]);
+        assert_eq!(tags[0].events, 4);
+        let high = &tags[0].values[0];
+        assert_eq!((high.value.as_str(), high.events, high.sessions, high.minutes), ("high", 3, 2, 90.0));
+        assert_eq!((tags[0].values[1].value.as_str(), tags[0].values[1].minutes), ("low", 60.0));
+
+        let theory = projector.tags(Some("THEORY"));
+        assert_eq!((theory[0].values[0].events, theory[0].values[0].sessions), (2, 1));
+        assert!(projector.tags(Some("PRACTICE")).is_empty());
+    }
+
+    #[test]
+    fn test_autostop_ends_a_session() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-02T08:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z STOP PRACTICE rust").unwrap();
+        // Appended late, stamped when the limit was reached
+        writeln!(temp_file, "2024-01-01T13:00:00Z AUTOSTOP THEORY pandas reason=stale").unwrap();
+        let sessions = SessionProjector::new(temp_file.path()).get_all_sessions();
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].end_event_idx, sessions[0].duration_minutes), (Some(3), Some(240.0)));
+        assert_eq!(sessions[0].closed_by, Some(ClosedBy::Auto));
+        assert_eq!(serde_json::to_value(&sessions[0]).unwrap()["closed_by"], "auto");
+        assert_eq!(sessions[1].closed_by, None);
+        assert!(serde_json::to_value(&sessions[1]).unwrap().get("closed_by").is_none());
+    }
+
+    #[test]
+    fn test_conflicts_from_backfilled_sessions() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T12:00:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T13:00:00Z STOP").unwrap();
+        // Backfilled into the middle of THEORY
+        writeln!(temp_file, "2024-01-01T10:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z STOP GAME chess").unwrap();
+        // Backfilled into gaps
+        writeln!(temp_file, "2024-01-01T11:15:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T11:45:00Z STOP GAME").unwrap();
+        writeln!(temp_file, "2024-01-01T14:00:00Z START GAME go").unwrap();
+        writeln!(temp_file, "2024-01-01T14:30:00Z STOP GAME go").unwrap();
+        let projector = SessionProjector::new(temp_file.path());
+
+        let conflicts = projector.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!((conflict.a.activity.as_str(), conflict.a.end_event_idx), ("pandas", 1));
+        assert_eq!((conflict.b.activity.as_str(), conflict.b.start_event_idx), ("chess", 4));
+        assert_eq!(conflict.overlap_minutes, 30.0);
+
+        // In order, nothing collides
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T10:30:00Z STOP GAME chess").unwrap();
+        writeln!(temp_file, "START THEORY untimed").unwrap();
+        assert!(SessionProjector::new(temp_file.path()).conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_conflicts_on_a_long_log_without_stops() {
+        // Every session runs into the next; none is closed by a STOP, so
+        // nothing claims time twice. Scanning ahead per session made this
+        // quadratic
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let start = "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        for i in 0..20_000 {
+            let ts = start + chrono::Duration::minutes(i);
+            writeln!(temp_file, "{} START THEORY topic{}", ts.to_rfc3339(), i).unwrap();
+        }
+        // A STOP naming no activity still closes the backfilled GAME session
+        writeln!(temp_file, "2023-12-31T23:00:00Z START GAME chess").unwrap();
+        writeln!(temp_file, "2024-01-01T00:30:00Z STOP GAME").unwrap();
+        let conflicts = SessionProjector::new(temp_file.path()).conflicts();
+
+        assert_eq!(conflicts.len(), 30);
+        assert!(conflicts.iter().all(|c| c.a.activity == "chess" && c.a.end_event_idx == 20_001));
+    }
+
+    #[test]
+    fn test_merge_gap_folds_interruptions() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas focus=high").unwrap();
+        writeln!(temp_file, "2024-01-01T09:20:00Z NOTE groupby first").unwrap();
+        writeln!(temp_file, "2024-01-01T09:30:00Z STOP THEORY pandas").unwrap();
+        // Three minutes at the door
+        writeln!(temp_file, "2024-01-01T09:33:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:40:00Z NOTE then merge").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:04:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:10:00Z STOP THEORY pandas").unwrap();
+        // Another activity never merges, however close
+        writeln!(temp_file, "2024-01-01T10:11:00Z START THEORY numpy").unwrap();
+        writeln!(temp_file, "2024-01-01T10:20:00Z STOP THEORY numpy").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let raw = projector.get_all_sessions();
+        assert_eq!(raw.len(), 4);
+        assert!(raw.iter().all(|s| s.fragments.is_none()));
+
+        let merged = SessionProjector::new(temp_file.path()).with_merge_gap(Some(5.0));
+        let sessions = merged.get_all_sessions();
+        assert_eq!(sessions.len(), 2);
+        let pandas = &sessions[0];
+        assert_eq!((pandas.fragments, pandas.duration_minutes, pandas.gross_minutes), (Some(3), Some(63.0), Some(63.0)));
+        assert_eq!((pandas.start_event_idx, pandas.end_event_idx), (0, Some(7)));
+        assert_eq!(pandas.metadata["focus"], "high");
+        assert_eq!((sessions[1].activity.as_str(), sessions[1].fragments), ("numpy", Some(1)));
+        // Notes from both fragments stay with the merged session
+        assert_eq!(merged.session_detail(0).unwrap().notes, vec!["groupby first", "then merge"]);
+
+        // The gap must be shorter than the threshold
+        let sessions = SessionProjector::new(temp_file.path()).with_merge_gap(Some(4.0)).get_all_sessions();
+        assert_eq!(sessions.iter().map(|s| s.fragments).collect::<Vec<_>>(), vec![Some(2), Some(1), Some(1)]);
+        let days = SessionProjector::new(temp_file.path()).with_merge_gap(Some(5.0)).daily_rows(DayMetric::Sessions, None, None).unwrap();
+        assert_eq!(days[0].categories["THEORY"], 2.0);
+    }
+
+    #[test]
+    fn test_overnight_sessions_split_at_day_start() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Crosses two midnights
+        writeln!(temp_file, "2024-01-01T23:30:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-03T01:15:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-05T23:30:00Z START PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-06T01:15:00Z STOP PRACTICE rust").unwrap();
+
+        let minutes = |projector: &SessionProjector, category: &str| -> Vec<(String, f64)> {
+            projector
+                .daily_rows(DayMetric::Minutes, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|row| (row.date, row.categories.get(category).copied().unwrap_or(0.0)))
+                .filter(|(_, minutes)| *minutes > 0.0)
+                .collect()
+        };
+        let day = |date: &str, minutes: f64| (date.to_string(), minutes);
+
+        let projector = SessionProjector::new(temp_file.path());
+        assert_eq!(minutes(&projector, "THEORY"), vec![day("2024-01-01", 30.0), day("2024-01-02", 1440.0), day("2024-01-03", 75.0)]);
+        assert_eq!(minutes(&projector, "PRACTICE"), vec![day("2024-01-05", 30.0), day("2024-01-06", 75.0)]);
+        // Still one session each, counted on the day it started
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions.iter().map(|s| s.spans_days).collect::<Vec<_>>(), vec![true, true]);
+        let counts = projector.daily_rows(DayMetric::Sessions, None, None).unwrap();
+        assert_eq!((counts[0].total, counts[1].total, counts[2].total), (1.0, 0.0, 0.0));
+
+        // With a 4am day start the first splits at 04:00 and the second
+        // finishes inside the day it started
+        let days = DayBoundary::new(Default::default(), 4).unwrap();
+        let projector = SessionProjector::new(temp_file.path()).with_days(days);
+        assert_eq!(minutes(&projector, "THEORY"), vec![day("2024-01-01", 270.0), day("2024-01-02", 1275.0)]);
+        assert_eq!(minutes(&projector, "PRACTICE"), vec![day("2024-01-05", 105.0)]);
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions.iter().map(|s| s.spans_days).collect::<Vec<_>>(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_by_day_fills_gaps_and_respects_day_start() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME valorant").unwrap();
+        // 02:00 on Jan 4 still belongs to Jan 3 with a 4am day start
+        writeln!(temp_file, "2024-01-04T02:00:00Z START THEORY rust").unwrap();
+        writeln!(temp_file, "2024-01-04T02:30:00Z START PRACTICE rust").unwrap();
+
+        let days = DayBoundary::new(Default::default(), 4).unwrap();
+        let projector = SessionProjector::new(temp_file.path()).with_days(days);
+
+        let result = projector.by_day(DayMetric::Sessions, None, None, None).unwrap();
+        let rows: Vec<DailyRow> = serde_json::from_value(result.data["days"].clone()).unwrap();
+        assert_eq!(rows.iter().map(|r| r.date.as_str()).collect::<Vec<_>>(),
+                   vec!["2024-01-01", "2024-01-02", "2024-01-03"]);
+        assert_eq!(rows[0].total, 2.0);
+        assert_eq!(rows[1].total, 0.0);
+        assert_eq!(rows[1].categories["THEORY"], 0.0);
+        assert_eq!(rows[2].categories["THEORY"], 1.0);
+
+        let result = projector.by_day(DayMetric::Minutes, None, None, None).unwrap();
+        let rows: Vec<DailyRow> = serde_json::from_value(result.data["days"].clone()).unwrap();
+        assert_eq!(rows[0].categories["THEORY"], 60.0);
+        assert_eq!(rows[2].categories["THEORY"], 30.0);
+
+        let from = NaiveDate::from_ymd_opt(2023, 12, 31);
+        let result = projector.by_day(DayMetric::Events, None, from, from).unwrap();
+        let rows: Vec<DailyRow> = serde_json::from_value(result.data["days"].clone()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].total, 0.0);
+
+        // Open ends are filled in from the log before the span is checked
+        assert!(projector.by_day(DayMetric::Sessions, None, NaiveDate::from_ymd_opt(2000, 1, 1), None).is_err());
+        let after = projector.daily_rows(DayMetric::Sessions, NaiveDate::from_ymd_opt(2030, 1, 1), None).unwrap();
+        assert!(after.is_empty());
+        let recent = projector.recent_daily_rows(DayMetric::Sessions, NaiveDate::from_ymd_opt(2040, 1, 1));
+        assert_eq!(recent.len() as i64, crate::days::MAX_RANGE_DAYS + 1);
+    }
+
+    #[test]
+    fn test_category_aliases_merge() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START PRAC rust").unwrap();
+        writeln!(temp_file, "START PRACTICE python").unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+
+        let aliases = CategoryAliases::from_json(r#"{"PRAC": "PRACTICE"}"#).unwrap();
+
+        let analyzer = RatioAnalyzer::new(temp_file.path()).with_aliases(&aliases);
+        let analysis: RatioAnalysis = serde_json::from_value(analyzer.analyze().data).unwrap();
+        assert_eq!(analysis.categories.len(), 2);
+        assert_eq!(analysis.categories[0].category, "PRACTICE");
+        assert_eq!(analysis.categories[0].count, 2);
+        assert_eq!(analysis.theory_to_practice, Some(0.5));
+
+        let projector = SessionProjector::new(temp_file.path()).with_aliases(&aliases);
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions[0].category, "PRACTICE");
+        assert_eq!(sessions[0].activity, "rust");
+    }
+
+    #[test]
+    fn test_no_stop_events_needed() {
+        // Test: sessions derived without explicit STOP
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "START PRACTICE rust").unwrap();
+        // No STOP event, but should still work
+        
+        let projector = SessionProjector::new(temp_file.path());
+        let sessions = projector.get_all_sessions();
+        
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions[0].end_event_idx.is_some());
+    }
+
+    #[test]
+    fn test_back_dated_events_are_ordered() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-02T09:00:00Z START PRACTICE rust").unwrap();
+        // Imported later, but happened the day before
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z STOP THEORY pandas").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let sessions = projector.get_all_sessions();
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].activity.as_str(), sessions[0].start_event_idx), ("pandas", 1));
+        assert_eq!(sessions[0].duration_minutes, Some(60.0));
+        assert!(sessions[1].is_active);
+        assert_eq!(projector.session_events(0).unwrap().iter().map(|e| e.idx).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_sessions_filtered_by_metadata() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START PRACTICE api project=api difficulty=4").unwrap();
+        writeln!(temp_file, "START PRACTICE api project=api difficulty=easy").unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "START PRACTICE web project=web difficulty=5").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let clause = serde_json::from_value(serde_json::json!({
+            "project": "api",
+            "difficulty": { "gte": 3 },
+        }))
+        .unwrap();
+        let result = projector.filtered_sessions(&MetadataFilter::from_json(&clause).unwrap());
+
+        assert_eq!(result.data["count"], 1);
+        assert_eq!(result.data["sessions"][0]["start_event_idx"], 0);
+        assert_eq!(result.data["sessions"][0]["metadata"]["difficulty"], "4");
+
+        // No conditions: every session, with or without metadata
+        let all = projector.filtered_sessions(&MetadataFilter::default());
+        assert_eq!(all.data["count"], 4);
+        assert!(all.data["sessions"][2].get("metadata").is_none());
+    }
+
+    #[test]
+    fn test_timeline_sorting() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();   // 30 min
+        writeln!(temp_file, "2024-01-01T09:30:00Z START PRACTICE rust").unwrap();   // 90 min
+        writeln!(temp_file, "2024-01-01T11:00:00Z START GAME valorant").unwrap();   // 10 min
+        writeln!(temp_file, "2024-01-01T11:10:00Z START THEORY rust").unwrap();     // active
+
+        let projector = SessionProjector::new(temp_file.path());
+        let activities = |sort, order| -> Vec<String> {
+            let result = projector.get_timeline(sort, order);
+            result.data["sessions"].as_array().unwrap().iter()
+                .map(|s| format!("{} {}", s["category"].as_str().unwrap(), s["activity"].as_str().unwrap()))
+                .collect()
+        };
+
+        assert_eq!(activities(SessionSort::Duration, SortOrder::Desc),
+                   vec!["PRACTICE rust", "THEORY pandas", "GAME valorant", "THEORY rust"]);
+        assert_eq!(activities(SessionSort::Start, SortOrder::Asc),
+                   vec!["THEORY pandas", "PRACTICE rust", "GAME valorant", "THEORY rust"]);
+        assert_eq!(activities(SessionSort::Start, SortOrder::Desc)[0], "THEORY rust");
+        assert_eq!(activities(SessionSort::Log, SortOrder::Desc)[0], "THEORY rust");
+    }
+
+    #[test]
+    fn test_stop_ends_active_session() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T09:45:00Z STOP THEORY pandas").unwrap();
+        writeln!(temp_file, "2024-01-01T10:00:00Z NOTE coffee").unwrap();
+
+        let projector = SessionProjector::new(temp_file.path());
+        let sessions = projector.get_all_sessions();
+
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].end_event_idx, Some(1));
+        assert_eq!(sessions[0].duration_minutes, Some(45.0));
+        assert!(projector.get_current_session().is_none());
+    }
+
+    #[test]
+    fn test_exact_durations_from_timestamps() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY legacy").unwrap();                         // no timestamps
+        writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();    // 25.5 min
+        writeln!(temp_file, "2024-01-01T09:25:30Z NOTE groupby").unwrap();
+        writeln!(temp_file, "2024-01-01T09:25:30Z START PRACTICE rust").unwrap();    // 94.5 min
+        writeln!(temp_file, "2024-01-01T11:00:00Z STOP PRACTICE rust").unwrap();
+        writeln!(temp_file, "2024-01-01T13:00:00Z START GAME valorant").unwrap();    // active
+
+        let projector = SessionProjector::new(temp_file.path());
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions.len(), 4);
+
+        // Pre-timestamp history gets nulls, not a made-up duration
+        assert!(sessions[0].start_time.is_none());
+        assert_eq!(sessions[0].duration_minutes, None);
+
+        assert_eq!(sessions[1].start_time.unwrap().to_rfc3339(), "2024-01-01T09:00:00+00:00");
+        assert_eq!(sessions[1].end_time.unwrap().to_rfc3339(), "2024-01-01T09:25:30+00:00");
+        assert_eq!(sessions[1].duration_minutes, Some(25.5));
+        assert_eq!(sessions[2].duration_minutes, Some(94.5));
+        assert!(sessions[3].is_active);
+        assert_eq!((sessions[3].end_time, sessions[3].duration_minutes), (None, None));
+
+        // Elapsed-so-far only on request, and only for the active session
+        let now = "2024-01-01T13:20:00Z".parse().unwrap();
+        let sessions = SessionProjector::new(temp_file.path()).with_elapsed_at(now).get_all_sessions();
+        assert_eq!(sessions[3].duration_minutes, Some(20.0));
+        assert_eq!(sessions[3].end_time, None);
+        assert_eq!(sessions[2].duration_minutes, Some(94.5));
+    }
+}
+
+/// Where a projector's events come from
+#[derive(Clone)]
+enum EventSource<'a> {
+    /// Read and parsed on every projection
+    Log(EventReader),
+    /// Parsed once by the caller, e.g. for one `/query`
+    Parsed(&'a [ParsedEvent]),
+}
+
+impl EventSource<'_> {
+    /// Events in log order; missing or unreadable logs project as empty
+    fn events(&self) -> Cow<'_, [ParsedEvent]> {
+        match self {
+            EventSource::Log(reader) => Cow::Owned(parse_log(&reader.lines().unwrap_or_default())),
+            EventSource::Parsed(events) => Cow::Borrowed(events),
+        }
+    }
+
+    /// Every non-empty line with its index; only the event lines when
+    /// the events were parsed elsewhere
+    fn lines(&self) -> Vec<IndexedEvent> {
+        match self {
+            EventSource::Log(reader) => {
+                let lines = reader.lines().unwrap_or_default();
+                lines.iter().enumerate().map(|(idx, line)| IndexedEvent { idx, line: line.clone() }).collect()
+            }
+            EventSource::Parsed(events) => {
+                events.iter().map(|e| IndexedEvent { idx: e.index, line: e.line.clone() }).collect()
+            }
+        }
+    }
+}
+
+/// Projects sessions from event log
+/// Session = period from a START to the next START or STOP
+pub struct SessionProjector<'a> {
+    source: EventSource<'a>,
+    aliases: CategoryAliases,
+    days: DayBoundary,
+    /// When set, the active session's duration runs up to its "now"
+    clock: Option<SharedClock>,
+    /// Leave the active session out altogether
+    exclude_active: bool,
+    /// Fold same-activity sessions less than this many minutes apart
+    merge_gap: Option<f64>,
+}
+
+impl<'a> SessionProjector<'a> {
+    /// Own uncached reader over `log_path`; the server shares one via
+    /// `from_reader`
+    #[cfg(test)]
+    pub fn new(log_path: &std::path::Path) -> Self {
+        Self::from_reader(&EventReader::new(log_path))
+    }
+
+    pub fn from_reader(reader: &EventReader) -> Self {
+        Self::from_source(EventSource::Log(reader.clone()))
+    }
+
+    /// Over events already parsed from the log, in log order
+    pub fn from_events(events: &'a [ParsedEvent]) -> Self {
+        Self::from_source(EventSource::Parsed(events))
+    }
+
+    fn from_source(source: EventSource<'a>) -> Self {
+        Self {
+            source,
+            aliases: CategoryAliases::default(),
+            days: DayBoundary::default(),
+            clock: None,
+            exclude_active: false,
+            merge_gap: None,
+        }
+    }
+
+    /// Fold aliased categories into their canonical name
+    pub fn with_aliases(mut self, aliases: &CategoryAliases) -> Self {
+        self.aliases = aliases.clone();
+        self
+    }
+
+    /// Timezone and day start hour used to bucket sessions into days
+    pub fn with_days(mut self, days: DayBoundary) -> Self {
+        self.days = days;
+        self
+    }
+
+    /// Report the active session's elapsed-so-far minutes, up to the
+    /// clock's now, as its duration instead of None (it stays None without
+    /// a start timestamp)
+    pub fn with_clock(mut self, clock: &SharedClock) -> Self {
+        self.clock = Some(clock.clone());
+        self
+    }
+
+    /// Leave the active session out, e.g. of aggregates its running
+    /// duration would skew
+    pub fn without_active(mut self) -> Self {
+        self.exclude_active = true;
+        self
+    }
+
+    /// Treat consecutive sessions of one category and activity less than
+    /// `minutes` apart (a STOP to answer the door) as one session
+    pub fn with_merge_gap(mut self, minutes: Option<f64>) -> Self {
+        self.merge_gap = minutes;
+        self
+    }
+
+    /// `with_clock` stopped at `now`
+    pub fn with_elapsed_at(self, now: DateTime<Utc>) -> Self {
+        self.with_clock(&(Arc::new(FixedClock::new(now)) as SharedClock))
+    }
+
+    /// Raw lines in timestamp order, keeping their log indices
+    /// Back-dated appends land at the end of the log but belong earlier;
+    /// untimestamped lines stay right after the line before them
+    fn ordered_lines(&self) -> Vec<IndexedEvent> {
+        let mut last_seen = None;
+        let keyed = self
+            .source
+            .lines()
+            .into_iter()
+            .map(|line| {
+                if let Some(ts) = parse_event(&line.line).and_then(|e| e.timestamp) {
+                    last_seen = Some(ts);
+                }
+                (last_seen, line)
+            })
+            .collect();
+        in_timestamp_order(keyed)
+    }
+
+    pub fn get_all_sessions(&self) -> Vec<Session> {
+        let events = self.source.events();
+        let events = ordered_events(&events);
+        let mut sessions = Vec::new();
+        let mut current_session: Option<Session> = None;
+        let mut pauses = Pauses::default();
+
+        for (pos, event) in events.iter().enumerate() {
+            let idx = event.index;
+
+            match event.verb {
+                EventVerb::Stop | EventVerb::AutoStop => {
+                    // Explicit close: the STOP line belongs to the session it ends
+                    if let Some(mut session) = current_session.take() {
+                        session.end_event_idx = Some(idx);
+                        session.is_active = false;
+                        session.end_time = event.timestamp;
+                        session.closed_by = (event.verb == EventVerb::AutoStop).then_some(ClosedBy::Auto);
+                        pauses.finish(&mut session);
+                        sessions.push(session);
+                    }
+                }
+                EventVerb::Start => {
+                    let (Some(category), Some(activity)) = (&event.category, &event.activity) else {
+                        continue;
+                    };
+
+                    // End previous session
+                    if let Some(mut session) = current_session.take() {
+                        session.end_event_idx = Some(events[pos - 1].index);
+                        session.is_active = false;
+                        session.end_time = event.timestamp;
+                        pauses.finish(&mut session);
+                        sessions.push(session);
+                    }
+
+                    // Start new session
+                    current_session = Some(Session {
+                        category: self.aliases.resolve(category),
+                        activity: activity.clone(),
+                        start_event_idx: idx,
+                        end_event_idx: None,
+                        is_active: true,
+                        start_time: event.timestamp,
+                        end_time: None,
+                        duration_minutes: None,
+                        gross_minutes: None,
+                        metadata: event.metadata.clone(),
+                        closed_by: None,
+                        spans_days: false,
+                        fragments: None,
+                    });
+                    pauses = Pauses::default();
+                }
+                // Outside a session there's nothing to pause
+                EventVerb::Pause if current_session.is_some() => pauses.pause(event.timestamp),
+                EventVerb::Resume if current_session.is_some() => pauses.resume(event.timestamp),
+                // Don't move session boundaries
+                EventVerb::Pause
+                | EventVerb::Resume
+                | EventVerb::Note
+                | EventVerb::Done
+                | EventVerb::Target
+                | EventVerb::AlertRule
+                | EventVerb::SaveQuery
+                | EventVerb::Other(_) => {}
+            }
+        }
+
+        // Don't forget the last session
+        if let Some(mut session) = current_session.filter(|_| !self.exclude_active) {
+            if let Some(clock) = &self.clock {
+                session.end_time = Some(clock.now());
+                pauses.finish(&mut session);
+                session.end_time = None;
+            }
+            sessions.push(session);
+        }
+
+        if let Some(gap) = self.merge_gap {
+            sessions = merge_fragments(sessions, gap);
+        }
+        for session in &mut sessions {
+            session.spans_days = self.minutes_by_day(session).len() > 1;
+        }
+        sessions
+    }
+
+    /// A session's net minutes per day, split where each day starts,
+    /// shared in proportion to the wall-clock time on each side
+    /// Empty without a start timestamp or a duration
+    fn minutes_by_day(&self, session: &Session) -> Vec<(NaiveDate, f64)> {
+        let (Some(start), Some(minutes)) = (session.start_time, session.duration_minutes) else {
+            return Vec::new();
+        };
+        // An active session's end is where its elapsed time runs to
+        let gross = session.gross_minutes.unwrap_or(minutes);
+        let end = session
+            .end_time
+            .unwrap_or(start + chrono::Duration::milliseconds((gross * 60_000.0) as i64));
+        let wall = (end - start).num_milliseconds() as f64;
+        let mut day = self.days.day_of(start);
+        if wall <= 0.0 {
+            return vec![(day, minutes)];
+        }
+
+        let mut pieces = Vec::new();
+        let mut from = start;
+        while from < end {
+            let Some(next) = day.succ_opt() else { break };
+            let until = self.days.day_start(next).min(end);
+            if until <= from {
+                break;
+            }
+            pieces.push((day, minutes * (until - from).num_milliseconds() as f64 / wall));
+            (day, from) = (next, until);
+        }
+        pieces
+    }
+
+    /// Raw lines from a session's START up to its end (or the log end if active),
+    /// in timestamp order; None when `session_idx` is out of range
+    pub fn session_events(&self, session_idx: usize) -> Option<Vec<IndexedEvent>> {
+        let session = self.get_all_sessions().into_iter().nth(session_idx)?;
+        let events = self.ordered_lines();
+        let position = |idx| events.iter().position(|e| e.idx == idx);
+        let start = position(session.start_event_idx)?;
+        let end = match session.end_event_idx {
+            Some(idx) => position(idx)?,
+            None => events.len() - 1,
+        };
+
+        Some(events[start..=end].to_vec())
+    }
+
+    /// Every session with the NOTE texts it spans, in one pass over the log
+    pub fn sessions_with_notes(&self) -> Vec<(Session, Vec<String>)> {
+        let events = self.source.events();
+        let events = ordered_events(&events);
+        let positions: std::collections::HashMap<usize, usize> = events.iter().enumerate().map(|(pos, e)| (e.index, pos)).collect();
+        self.get_all_sessions()
+            .into_iter()
+            .map(|session| {
+                let start = positions.get(&session.start_event_idx).copied().unwrap_or(events.len());
+                let end = session
+                    .end_event_idx
+                    .and_then(|idx| positions.get(&idx).copied())
+                    .unwrap_or(events.len().saturating_sub(1));
+                let notes = events
+                    .iter()
+                    .take(end + 1)
+                    .skip(start)
+                    .filter(|e| e.verb == EventVerb::Note)
+                    .filter_map(|e| note_text(&e.line))
+                    .collect();
+                (session, notes)
+            })
+            .collect()
+    }
+
+    /// Timestamped NOTE lines from `since` up to (excluding) `until`, in
+    /// timestamp order
+    pub fn notes(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Vec<LoggedNote> {
+        let events = self.source.events();
+        ordered_events(&events)
+            .into_iter()
+            .filter(|event| event.verb == EventVerb::Note)
+            .filter_map(|event| {
+                let timestamp = event.timestamp?;
+                let text = note_text(&event.line).filter(|_| since <= timestamp && timestamp < until)?;
+                Some(LoggedNote { event_index: event.index, timestamp, text })
+            })
+            .collect()
+    }
+
+    /// Activities with the most minutes (or sessions) among sessions
+    /// starting at or after `since`; untimed sessions only count without it
+    pub fn top_activities(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        category: Option<&str>,
+        by: TopBy,
+        n: usize,
+    ) -> Vec<ActivityTotal> {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let mut totals: BTreeMap<(String, String), ActivityTotal> = BTreeMap::new();
+
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category) {
+                continue;
+            }
+            if since.is_some() || until.is_some() {
+                let Some(start) = session.start_time else { continue };
+                if since.is_some_and(|since| start < since) || until.is_some_and(|until| start >= until) {
+                    continue;
+                }
+            }
+            let total = totals
+                .entry((session.category.clone(), session.activity.clone()))
+                .or_insert_with(|| ActivityTotal {
+                    category: session.category.clone(),
+                    activity: session.activity.clone(),
+                    sessions: 0,
+                    minutes: 0.0,
+                });
+            total.sessions += 1;
+            total.minutes += session.duration_minutes.unwrap_or(0.0);
+        }
+
+        let mut top: Vec<ActivityTotal> = totals.into_values().collect();
+        top.sort_by(|a, b| match by {
+            TopBy::Minutes => b.minutes.total_cmp(&a.minutes).then(b.sessions.cmp(&a.sessions)),
+            TopBy::Sessions => b.sessions.cmp(&a.sessions).then(b.minutes.total_cmp(&a.minutes)),
+        });
+        top.truncate(n);
+        top
+    }
+
+    /// Activities ordered by how long ago their last session started,
+    /// most neglected first; sessions without a timestamp are ignored
+    pub fn stale_activities(&self, category: Option<&str>, now: DateTime<Utc>) -> Vec<StaleActivity> {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let mut last: BTreeMap<(String, String), DateTime<Utc>> = BTreeMap::new();
+
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category) {
+                continue;
+            }
+            let Some(start) = session.start_time else { continue };
+            let seen = last.entry((session.category, session.activity)).or_insert(start);
+            *seen = (*seen).max(start);
+        }
+
+        let mut stale: Vec<StaleActivity> = last
+            .into_iter()
+            .map(|((category, activity), last_session)| StaleActivity {
+                category,
+                activity,
+                last_session,
+                days_since: (now - last_session).num_days(),
+            })
+            .collect();
+        stale.sort_by_key(|s| s.last_session);
+        stale
+    }
+
+    /// A session with the notes, `#tags` and raw lines it spans
+    pub fn session_detail(&self, session_idx: usize) -> Option<SessionDetail> {
+        let session = self.get_all_sessions().into_iter().nth(session_idx)?;
+        let events = self.session_events(session_idx)?;
+
+        let notes = events.iter().filter_map(|e| note_text(&e.line)).collect();
+        let tags: BTreeSet<String> = events
+            .iter()
+            .flat_map(|e| e.line.split_whitespace())
+            .filter_map(|word| word.strip_prefix('#'))
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Some(SessionDetail {
+            session,
+            notes,
+            tags: tags.into_iter().collect(),
+            events,
+        })
+    }
+
+    /// Dead time between one session ending and the next starting
+    /// Pairs missing a timestamp can't be measured and are listed separately
+    pub fn gaps(&self, min_minutes: f64, ignore_overnight: bool, working_hours: WorkingHours) -> QueryResult {
+        let sessions = self.get_all_sessions();
+        let mut gaps = Vec::new();
+        let mut unknown = Vec::new();
+
+        for (idx, pair) in sessions.windows(2).enumerate() {
+            let (before, after) = (&pair[0], &pair[1]);
+            let (Some(start), Some(end)) = (before.end_time, after.start_time) else {
+                unknown.push(serde_json::json!({
+                    "after_session": idx,
+                    "before_session": idx + 1,
+                    "reason": "missing timestamps",
+                }));
+                continue;
+            };
+
+            let minutes = (end - start).num_seconds() as f64 / 60.0;
+            if minutes < min_minutes {
+                continue;
+            }
+            let overnight = self.days.day_of(start) != self.days.day_of(end);
+            if ignore_overnight && overnight {
+                continue;
+            }
+
+            gaps.push(Gap {
+                after_session: idx,
+                before_session: idx + 1,
+                start,
+                end,
+                minutes,
+                overnight,
+                in_working_hours: working_hours.contains(&self.days.zone, start, end),
+                previous: before.clone(),
+                next: after.clone(),
+            });
+        }
+
+        QueryResult {
+            query: "gaps".to_string(),
+            result_type: "gaps".to_string(),
+            data: serde_json::json!({
+                "gaps": gaps,
+                "uncomputable": unknown,
+            }),
+        }
+    }
+
+    /// How often a session of one category is directly followed by one
+    /// of another, repeats of the same category included
+    /// With `max_gap`, pairs further apart than it (end to next start)
+    /// aren't counted; pairs lacking the timestamps to tell always are
+    pub fn transitions(&self, max_gap: Option<chrono::Duration>) -> Transitions {
+        let sessions = self.get_all_sessions();
+        let categories: BTreeSet<&str> = sessions.iter().map(|s| s.category.as_str()).collect();
+        let mut counts: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+        let mut excluded = 0;
+
+        for pair in sessions.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            if let (Some(max_gap), Some(end), Some(start)) = (max_gap, before.end_time, after.start_time) {
+                if start - end > max_gap {
+                    excluded += 1;
+                    continue;
+                }
+            }
+            *counts.entry((before.category.as_str(), after.category.as_str())).or_insert(0) += 1;
+        }
+
+        let mut matrix = Vec::new();
+        let mut most_likely_next = BTreeMap::new();
+        for from in &categories {
+            let row_total: usize = categories.iter().map(|to| counts.get(&(*from, *to)).copied().unwrap_or(0)).sum();
+            let row: Vec<Transition> = categories
+                .iter()
+                .map(|to| {
+                    let count = counts.get(&(*from, *to)).copied().unwrap_or(0);
+                    Transition {
+                        from: from.to_string(),
+                        to: to.to_string(),
+                        count,
+                        probability: (row_total > 0).then(|| count as f64 / row_total as f64),
+                    }
+                })
+                .collect();
+            // max_by_key keeps the last maximum, so walk the row backwards
+            if let Some(next) = row.iter().rev().filter(|t| t.count > 0).max_by_key(|t| t.count) {
+                most_likely_next.insert(from.to_string(), next.clone());
+            }
+            matrix.extend(row);
+        }
+
+        Transitions {
+            categories: categories.into_iter().map(String::from).collect(),
+            total: counts.values().sum(),
+            matrix,
+            most_likely_next,
+            excluded,
+        }
+    }
+
+    /// Pairs of sessions whose written time ranges overlap
+    /// The timeline never overlaps itself: a back-dated START cuts the
+    /// session around it short. As written, though, that session runs on
+    /// to the STOP naming its category (and activity, when the STOP has
+    /// one), so the two collide. Sessions without timestamps are skipped
+    pub fn conflicts(&self) -> Vec<SessionConflict> {
+        let sessions = self.get_all_sessions();
+        let stops = self.written_stops(&sessions);
+        let mut written: Vec<WrittenSession> = sessions
+            .into_iter()
+            .filter_map(|session| {
+                let (Some(start_time), Some(end_time), Some(end_event_idx)) =
+                    (session.start_time, session.end_time, session.end_event_idx)
+                else {
+                    return None;
+                };
+                let (end_time, end_event_idx) =
+                    stops.get(&session.start_event_idx).copied().unwrap_or((end_time, end_event_idx));
+                Some(WrittenSession {
+                    category: session.category,
+                    activity: session.activity,
+                    start_event_idx: session.start_event_idx,
+                    end_event_idx,
+                    start_time,
+                    end_time,
+                })
+            })
+            .collect();
+        written.sort_by_key(|s| (s.start_time, s.start_event_idx));
+
+        let mut conflicts = Vec::new();
+        for (i, a) in written.iter().enumerate() {
+            for b in written[i + 1..].iter().take_while(|b| b.start_time < a.end_time) {
+                let overlap = a.end_time.min(b.end_time) - b.start_time;
+                if overlap > chrono::Duration::zero() {
+                    conflicts.push(SessionConflict {
+                        a: a.clone(),
+                        b: b.clone(),
+                        overlap_minutes: overlap.num_seconds() as f64 / 60.0,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// The first timestamped STOP naming each session, by its START's
+    /// index, unless it was started again first; one pass over the log
+    fn written_stops(&self, sessions: &[Session]) -> HashMap<usize, (DateTime<Utc>, usize)> {
+        let starts: HashMap<usize, &Session> = sessions.iter().map(|s| (s.start_event_idx, s)).collect();
+        // Sessions still waiting for their STOP: category -> activity -> START index
+        let mut open: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        let mut stops = HashMap::new();
+        let events = self.source.events();
+        for event in ordered_events(&events) {
+            let category = event.category.as_deref().map(|c| self.aliases.resolve(c));
+            match (&event.verb, category.and_then(|c| open.get_mut(&c))) {
+                (EventVerb::Start, Some(activities)) => {
+                    if let Some(activity) = &event.activity {
+                        activities.remove(activity);
+                    }
+                }
+                (EventVerb::Stop | EventVerb::AutoStop, Some(activities)) => {
+                    let closed: Vec<usize> = match &event.activity {
+                        Some(activity) => activities.remove(activity).into_iter().collect(),
+                        None => activities.drain().map(|(_, start)| start).collect(),
+                    };
+                    if let Some(ts) = event.timestamp {
+                        stops.extend(closed.into_iter().map(|start| (start, (ts, event.index))));
+                    }
+                }
+                _ => {}
+            }
+            if let Some(session) = starts.get(&event.index) {
+                open.entry(session.category.clone()).or_default().insert(session.activity.clone(), event.index);
+            }
+        }
+        stops
+    }
+
+    pub fn get_current_session(&self) -> Option<Session> {
+        let sessions = self.get_all_sessions();
+        sessions.into_iter().find(|s| s.is_active)
+    }
+
+    /// Per-day count of sessions shorter than `threshold_minutes`
+    /// Sessions without a start timestamp can't be placed on a day and are skipped
+    pub fn context_switches(&self, threshold_minutes: f64) -> QueryResult {
+        let sessions = self.get_all_sessions();
+        let mut days: std::collections::BTreeMap<chrono::NaiveDate, (usize, usize, std::collections::BTreeSet<String>)> =
+            std::collections::BTreeMap::new();
+
+        for session in &sessions {
+            let Some(start) = session.start_time else { continue };
+            let (total, short, categories) = days.entry(self.days.day_of(start)).or_default();
+            *total += 1;
+            if session.duration_minutes.is_some_and(|d| d < threshold_minutes) {
+                *short += 1;
+            }
+            categories.insert(session.category.clone());
+        }
+
+        let days: Vec<DailyContextSwitches> = days
+            .into_iter()
+            .map(|(date, (sessions, short_sessions, categories))| DailyContextSwitches {
+                date: date.to_string(),
+                sessions,
+                short_sessions,
+                distinct_categories: categories.len(),
+            })
+            .collect();
+
+        QueryResult {
+            query: "context-switches".to_string(),
+            result_type: "context_switches".to_string(),
+            data: serde_json::json!({
+                "threshold_minutes": threshold_minutes,
+                "days": days,
+            }),
+        }
+    }
+
+    /// Category switches per day, in one pass over the timeline
+    /// A switch is a session whose category differs from the previous
+    /// session's, counted on the day it starts; the previous session must
+    /// have reached that day (ended, or started, on it), so the first
+    /// session after an overnight break isn't a switch and a session
+    /// running past the day boundary is never one by itself
+    /// Days with more than `threshold` switches are flagged; `since`
+    /// limits the days reported
+    pub fn switching(&self, since: Option<DateTime<Utc>>, threshold: usize) -> SwitchingReport {
+        let mut days: BTreeMap<NaiveDate, DailySwitching> = BTreeMap::new();
+        let mut previous: Option<Session> = None;
+
+        for session in self.get_all_sessions() {
+            let Some(start) = session.start_time else {
+                previous = None;
+                continue;
+            };
+            let date = self.days.day_of(start);
+            let switched = previous.as_ref().is_some_and(|prev| {
+                let reached = prev.end_time.or(prev.start_time).map(|ts| self.days.day_of(ts));
+                prev.category != session.category && reached == Some(date)
+            });
+
+            if since.is_none_or(|since| start >= since) {
+                let day = days.entry(date).or_insert_with(|| DailySwitching {
+                    date: date.to_string(),
+                    ..Default::default()
+                });
+                day.sessions += 1;
+                day.switches += usize::from(switched);
+                if let Some(minutes) = session.duration_minutes {
+                    day.timed_sessions += 1;
+                    day.total_minutes += minutes;
+                }
+            }
+            previous = Some(session);
+        }
+
+        let mut high = (0, 0.0);
+        let mut low = (0, 0.0);
+        let days: Vec<DailySwitching> = days
+            .into_values()
+            .map(|mut day| {
+                day.high_switch = day.switches > threshold;
+                let totals = if day.high_switch { &mut high } else { &mut low };
+                totals.0 += day.timed_sessions;
+                totals.1 += day.total_minutes;
+                day
+            })
+            .collect();
+        let mean = |(sessions, minutes): (usize, f64)| (sessions > 0).then(|| minutes / sessions as f64);
+
+        SwitchingReport {
+            threshold,
+            high_switch_days: days.iter().filter(|d| d.high_switch).count(),
+            mean_session_minutes_high: mean(high),
+            mean_session_minutes_low: mean(low),
+            days,
+        }
+    }
+
+    /// One row per day with per-category values of `metric`, or just
+    /// `category`'s
+    /// Days inside the range with nothing logged appear as zero rows
+    pub fn by_day(
+        &self,
+        metric: DayMetric,
+        category: Option<&str>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<QueryResult, String> {
+        let mut rows = self.daily_rows(metric, from, to)?;
+        if let Some(category) = category.map(|category| self.aliases.resolve(category)) {
+            for row in &mut rows {
+                row.total = row.categories.get(&category).copied().unwrap_or(0.0);
+                row.categories = BTreeMap::from([(category.clone(), row.total)]);
+            }
+        }
+        Ok(QueryResult {
+            query: "by_day".to_string(),
+            result_type: "daily".to_string(),
+            data: serde_json::json!({
+                "metric": metric,
+                "days": rows,
+            }),
+        })
+    }
+
+    /// Rows from `from` (or the first day logged) to `to` (or the last);
+    /// a span over MAX_RANGE_DAYS once those are filled in is an error
+    pub fn daily_rows(&self, metric: DayMetric, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyRow>, String> {
+        let days = self.daily_values(metric);
+        let first = from.or_else(|| days.keys().next().copied());
+        let last = to.or_else(|| days.keys().next_back().copied());
+        if let (Some(first), Some(last)) = (first, last) {
+            crate::days::check_range_len(first, last)?;
+        }
+        Ok(Self::rows_between(&days, first, last))
+    }
+
+    /// Rows up to `to` (or the last day logged) from the first day logged,
+    /// but no more than MAX_RANGE_DAYS back, for views looking back from a day
+    pub fn recent_daily_rows(&self, metric: DayMetric, to: Option<NaiveDate>) -> Vec<DailyRow> {
+        let days = self.daily_values(metric);
+        let last = to.or_else(|| days.keys().next_back().copied());
+        let first = match (days.keys().next(), last) {
+            (Some(&first), Some(last)) => {
+                let earliest = last.checked_sub_signed(chrono::Duration::days(crate::days::MAX_RANGE_DAYS));
+                Some(earliest.map_or(first, |earliest| first.max(earliest)))
+            }
+            (first, _) => first.copied(),
+        };
+        Self::rows_between(&days, first, last)
+    }
+
+    /// Per-day, per-category values of `metric` on the days something was logged
+    fn daily_values(&self, metric: DayMetric) -> BTreeMap<NaiveDate, BTreeMap<String, f64>> {
+        let mut days: BTreeMap<NaiveDate, BTreeMap<String, f64>> = BTreeMap::new();
+
+        match metric {
+            DayMetric::Events => {
+                for event in self.source.events().iter() {
+                    let (Some(ts), Some(category)) = (event.timestamp, &event.category) else { continue };
+                    *days
+                        .entry(self.days.day_of(ts))
+                        .or_default()
+                        .entry(self.aliases.resolve(category))
+                        .or_insert(0.0) += 1.0;
+                }
+            }
+            DayMetric::Sessions => {
+                for session in self.get_all_sessions() {
+                    let Some(start) = session.start_time else { continue };
+                    *days
+                        .entry(self.days.day_of(start))
+                        .or_default()
+                        .entry(session.category)
+                        .or_insert(0.0) += 1.0;
+                }
+            }
+            // Split at day starts, so an overnight session adds to both days
+            DayMetric::Minutes => {
+                for session in self.get_all_sessions() {
+                    for (day, minutes) in self.minutes_by_day(&session) {
+                        *days.entry(day).or_default().entry(session.category.clone()).or_insert(0.0) += minutes;
+                    }
+                }
+            }
+        }
+
+        days
+    }
+
+    /// A row for every day from `first` to `last`, zeros where nothing was logged
+    fn rows_between(days: &BTreeMap<NaiveDate, BTreeMap<String, f64>>, first: Option<NaiveDate>, last: Option<NaiveDate>) -> Vec<DailyRow> {
+        let (Some(first), Some(last)) = (first, last) else { return Vec::new() };
+        if first > last {
+            return Vec::new();
+        }
+        let categories: BTreeSet<String> = days.range(first..=last).flat_map(|(_, cats)| cats.keys().cloned()).collect();
+
+        first
+            .iter_days()
+            .take_while(|d| *d <= last)
+            .map(|date| {
+                let logged = days.get(&date);
+                let values: BTreeMap<String, f64> = categories
+                    .iter()
+                    .map(|c| (c.clone(), logged.and_then(|l| l.get(c)).copied().unwrap_or(0.0)))
+                    .collect();
+                DailyRow {
+                    date: date.to_string(),
+                    total: values.values().sum(),
+                    categories: values,
+                }
+            })
+            .collect()
+    }
+
+    /// Sessions and minutes per week or month, from the first session's
+    /// period to the last, with deltas against the period before
+    /// The period containing `today` is marked partial
+    pub fn rollup(&self, period: Period, today: NaiveDate) -> QueryResult {
+        let (query, result_type) = match period {
+            Period::Week(_) => ("weekly", "weeks"),
+            Period::Month => ("monthly", "months"),
+        };
+        QueryResult {
+            query: query.to_string(),
+            result_type: result_type.to_string(),
+            data: serde_json::json!({ "periods": self.period_rollups(period, today) }),
+        }
+    }
+
+    /// The rows of `rollup`
+    pub fn period_rollups(&self, period: Period, today: NaiveDate) -> Vec<PeriodRollup> {
+        let mut periods: BTreeMap<NaiveDate, BTreeMap<String, CategoryRollup>> = BTreeMap::new();
+        let mut categories = BTreeSet::new();
+
+        for session in self.get_all_sessions() {
+            let Some(start) = session.start_time else { continue };
+            periods
+                .entry(period.start_of(self.days.day_of(start)))
+                .or_default()
+                .entry(session.category.clone())
+                .or_default()
+                .sessions += 1;
+            // Minutes go to the period each day's share falls in
+            for (day, minutes) in self.minutes_by_day(&session) {
+                periods
+                    .entry(period.start_of(day))
+                    .or_default()
+                    .entry(session.category.clone())
+                    .or_default()
+                    .minutes += minutes;
+            }
+            categories.insert(session.category);
+        }
+
+        let mut rows: Vec<PeriodRollup> = Vec::new();
+        if let (Some(&first), Some(&last)) = (periods.keys().next(), periods.keys().next_back()) {
+            let mut start = first;
+            while start <= last {
+                let next = period.next(start);
+                let logged = periods.remove(&start).unwrap_or_default();
+                let by_category: BTreeMap<String, CategoryRollup> = categories
+                    .iter()
+                    .map(|c| (c.clone(), logged.get(c).cloned().unwrap_or_default()))
+                    .collect();
+
+                let sessions = by_category.values().map(|c| c.sessions).sum();
+                let minutes = by_category.values().map(|c| c.minutes).sum();
+                let count = |name: &str| by_category.get(name).map(|c| c.sessions).unwrap_or(0);
+                let theory_to_practice = match count("PRACTICE") {
+                    0 => None,
+                    practice => Some(count("THEORY") as f64 / practice as f64),
+                };
+                let delta = rows.last().map(|prev| RollupDelta {
+                    sessions: sessions as i64 - prev.sessions as i64,
+                    minutes: minutes - prev.minutes,
+                    theory_to_practice: theory_to_practice
+                        .zip(prev.theory_to_practice)
+                        .map(|(now, before)| now - before),
+                });
+
+                rows.push(PeriodRollup {
+                    period: period.label(start),
+                    start: start.to_string(),
+                    end: next.pred_opt().unwrap_or(next).to_string(),
+                    sessions,
+                    minutes,
+                    categories: by_category,
+                    theory_to_practice,
+                    delta,
+                    partial: start <= today && today < next,
+                });
+                start = next;
+            }
+        }
+        rows
+    }
+
+    /// Weekly minutes (of one category, or all) projected `horizon` weeks
+    /// ahead with a least-squares line through the complete weeks so far
+    /// History runs from the first session's week, at most MAX_RANGE_DAYS
+    /// back, to the week before `today`'s, empty weeks as zeros; with
+    /// under 3 weeks of it the forecast is the mean instead, flagged
+    /// `low_confidence`
+    pub fn forecast(&self, week_start: WeekStart, category: Option<&str>, horizon: usize, today: NaiveDate) -> Forecast {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let period = Period::Week(week_start);
+        let current = period.start_of(today);
+        let earliest = today
+            .checked_sub_signed(chrono::Duration::days(crate::days::MAX_RANGE_DAYS))
+            .map_or(NaiveDate::MIN, |day| period.start_of(day));
+
+        let mut history: Vec<WeekMinutes> = self
+            .period_rollups(period, today)
+            .into_iter()
+            .filter(|row| row.start < current.to_string() && row.start >= earliest.to_string())
+            .map(|row| WeekMinutes {
+                week: row.period,
+                start: row.start,
+                minutes: match &category {
+                    Some(c) => row.categories.get(c).map(|c| c.minutes).unwrap_or(0.0),
+                    None => row.minutes,
+                },
+            })
+            .collect();
+        // Quiet weeks since the last session count too
+        let mut next = match history.last() {
+            Some(last) => last.start.parse().map(|start| period.next(start)).unwrap_or(current),
+            None => current,
+        }
+        .max(earliest);
+        while next < current {
+            history.push(WeekMinutes { week: period.label(next), start: next.to_string(), minutes: 0.0 });
+            next = period.next(next);
+        }
+
+        let n = history.len() as f64;
+        let mean = if history.is_empty() { 0.0 } else { history.iter().map(|w| w.minutes).sum::<f64>() / n };
+        let low_confidence = history.len() < MIN_FORECAST_WEEKS;
+        let (slope, intercept) = if low_confidence {
+            (None, mean)
+        } else {
+            // x is the week's position in the history
+            let x_mean = (n - 1.0) / 2.0;
+            let (mut covariance, mut variance) = (0.0, 0.0);
+            for (x, week) in history.iter().enumerate() {
+                covariance += (x as f64 - x_mean) * (week.minutes - mean);
+                variance += (x as f64 - x_mean).powi(2);
+            }
+            let slope = covariance / variance;
+            (Some(slope), mean - slope * x_mean)
+        };
+
+        let mut start = current;
+        let forecast = (0..horizon)
+            .map(|i| {
+                let x = history.len() + i;
+                let week = WeekMinutes {
+                    week: period.label(start),
+                    start: start.to_string(),
+                    minutes: (intercept + slope.unwrap_or(0.0) * x as f64).max(0.0),
+                };
+                start = period.next(start);
+                week
+            })
+            .collect();
+
+        Forecast {
+            category,
+            method: if low_confidence { "mean" } else { "linear_trend" }.to_string(),
+            slope,
+            low_confidence,
+            history,
+            forecast,
+        }
+    }
+
+    /// Totals of two date ranges (inclusive) and how `b` differs from `a`,
+    /// raw and per day so ranges of different lengths compare fairly
+    /// Ranges that overlap are still compared, but flagged; callers
+    /// reject ranges that run backwards
+    pub fn compare(&self, a: (NaiveDate, NaiveDate), b: (NaiveDate, NaiveDate)) -> QueryResult {
+        let mut summaries = [range_summary(a), range_summary(b)];
+        for session in self.get_all_sessions() {
+            let Some(start) = session.start_time else { continue };
+            let day = self.days.day_of(start);
+            let split = self.minutes_by_day(&session);
+            for (summary, (from, to)) in summaries.iter_mut().zip([a, b]) {
+                let within = |day: &NaiveDate| from <= *day && *day <= to;
+                // Counted on its start day, its minutes on the days they fell in
+                let minutes: f64 = split.iter().filter(|(day, _)| within(day)).map(|(_, m)| m).sum();
+                let started = within(&day);
+                if !started && minutes == 0.0 {
+                    continue;
+                }
+                summary.sessions += usize::from(started);
+                summary.minutes += minutes;
+                let category = summary.categories.entry(session.category.clone()).or_default();
+                category.sessions += usize::from(started);
+                category.minutes += minutes;
+            }
+        }
+        for summary in &mut summaries {
+            let count = |name: &str| summary.categories.get(name).map(|c| c.sessions).unwrap_or(0);
+            summary.theory_to_practice = theory_to_practice(Some(count("THEORY") as f64), Some(count("PRACTICE") as f64)).0;
+            let per_day = |total: f64| (summary.days > 0).then(|| total / summary.days as f64);
+            summary.sessions_per_day = per_day(summary.sessions as f64);
+            summary.minutes_per_day = per_day(summary.minutes);
+        }
+        let [a_summary, b_summary] = summaries;
+
+        let mut flags = Vec::new();
+        if a.0 <= b.1 && b.0 <= a.1 {
+            flags.push("ranges overlap".to_string());
+        }
+
+        let categories: BTreeSet<&String> = a_summary.categories.keys().chain(b_summary.categories.keys()).collect();
+        let category_changes = categories
+            .into_iter()
+            .map(|name| {
+                let minutes = |s: &RangeSummary| s.categories.get(name).map(|c| c.minutes).unwrap_or(0.0);
+                let per_day = |s: &RangeSummary| (s.days > 0).then(|| minutes(s) / s.days as f64);
+                let change = CategoryChange {
+                    minutes: change(minutes(&a_summary), minutes(&b_summary)),
+                    minutes_per_day: per_day(&a_summary).zip(per_day(&b_summary)).map(|(a, b)| change(a, b)),
+                };
+                (name.clone(), change)
+            })
+            .collect();
+        let deltas = RangeDeltas {
+            sessions: change(a_summary.sessions as f64, b_summary.sessions as f64),
+            minutes: change(a_summary.minutes, b_summary.minutes),
+            sessions_per_day: a_summary.sessions_per_day.zip(b_summary.sessions_per_day).map(|(a, b)| change(a, b)),
+            minutes_per_day: a_summary.minutes_per_day.zip(b_summary.minutes_per_day).map(|(a, b)| change(a, b)),
+            theory_to_practice: a_summary.theory_to_practice.zip(b_summary.theory_to_practice).map(|(a, b)| change(a, b)),
+            categories: category_changes,
+        };
+
+        QueryResult {
+            query: "compare".to_string(),
+            result_type: "comparison".to_string(),
+            data: serde_json::json!(RangeComparison { a: a_summary, b: b_summary, deltas, flags }),
+        }
+    }
+
+    /// Theory-to-practice ratio per day from that day's START events
+    /// The ratio is None on days with no practice
+    pub fn ratio_trend(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<DailyRatio>, String> {
+        Ok(self
+            .daily_rows(DayMetric::Sessions, from, to)?
+            .into_iter()
+            .map(|row| {
+                let count = |name: &str| row.categories.get(name).copied().unwrap_or(0.0) as usize;
+                let (theory, practice) = (count("THEORY"), count("PRACTICE"));
+                DailyRatio {
+                    date: row.date,
+                    theory,
+                    practice,
+                    ratio: (practice > 0).then(|| theory as f64 / practice as f64),
+                }
+            })
+            .collect())
+    }
+
+    /// Sessions per active day and per calendar day
+    /// The span runs from `from` (or the first session's day) to `to`
+    /// (or the last session's day); untimestamped sessions are ignored
+    pub fn cadence(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Cadence {
+        let mut days: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for session in self.get_all_sessions() {
+            let Some(start) = session.start_time else { continue };
+            let day = self.days.day_of(start);
+            if from.is_some_and(|f| day < f) || to.is_some_and(|t| day > t) {
+                continue;
+            }
+            *days.entry(day).or_insert(0) += 1;
+        }
+
+        let first = from.or_else(|| days.keys().next().copied());
+        let last = to.or_else(|| days.keys().next_back().copied());
+        let span_days = match (first, last) {
+            (Some(first), Some(last)) if first <= last => (last - first).num_days() as usize + 1,
+            _ => 0,
+        };
+        let sessions: usize = days.values().sum();
+        let per = |count: usize| (count > 0).then(|| sessions as f64 / count as f64);
+
+        Cadence {
+            first_day: first.map(|d| d.to_string()),
+            last_day: last.map(|d| d.to_string()),
+            sessions,
+            active_days: days.len(),
+            span_days,
+            per_active_day: per(days.len()),
+            per_calendar_day: per(span_days),
+        }
+    }
+
+    /// Trailing `window`-day mean of sessions started per day, one point
+    /// per day from `from` (or the first session's day) to `to` (or the
+    /// last session's); empty days count as zero
+    /// Averages look back past `from` when there is history there; only
+    /// days near the start of the log average over fewer than `window` days
+    pub fn session_moving_average(&self, window: usize, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Vec<MovingAverage> {
+        let rows = self.recent_daily_rows(DayMetric::Sessions, to);
+        let from = from.map(|d| d.to_string());
+        let mut points = Vec::new();
+        let mut sum = 0.0;
+        for (i, row) in rows.iter().enumerate() {
+            sum += row.total;
+            if i >= window {
+                sum -= rows[i - window].total;
+            }
+            if from.as_ref().is_some_and(|from| row.date < *from) {
+                continue;
+            }
+            let days = window.min(i + 1);
+            points.push(MovingAverage {
+                date: row.date.clone(),
+                sessions: row.total as usize,
+                days,
+                average: sum / days as f64,
+            });
+        }
+        points
+    }
+
+    /// Distribution of session durations, optionally for one category
+    /// Sessions without a duration (untimestamped, or still active) are
+    /// only counted in `excluded`
+    pub fn duration_stats(&self, category: Option<&str>, bucket_minutes: f64) -> SessionStats {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let mut durations = Vec::new();
+        let mut excluded = 0;
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category) {
+                continue;
+            }
+            match session.duration_minutes {
+                Some(minutes) => durations.push(minutes),
+                None => excluded += 1,
+            }
+        }
+        durations.sort_by(f64::total_cmp);
+
+        let total_minutes: f64 = durations.iter().sum();
+        let max_minutes = durations.last().copied();
+        // A session left running for years mustn't mean millions of
+        // buckets: past the cap, the last one stretches to the longest
+        let bucket = |minutes: f64| ((minutes / bucket_minutes) as usize).min(MAX_DURATION_BUCKETS - 1);
+        let mut histogram: Vec<DurationBucket> = match max_minutes {
+            Some(max) => (0..=bucket(max))
+                .map(|i| DurationBucket {
+                    from_minutes: i as f64 * bucket_minutes,
+                    to_minutes: (i + 1) as f64 * bucket_minutes,
+                    sessions: 0,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if let (Some(last), Some(max)) = (histogram.last_mut(), max_minutes) {
+            last.to_minutes = last.to_minutes.max(((max / bucket_minutes).floor() + 1.0) * bucket_minutes);
+        }
+        for minutes in &durations {
+            histogram[bucket(*minutes)].sessions += 1;
+        }
+
+        let stat = |value: f64| (!durations.is_empty()).then_some(value);
+        SessionStats {
+            count: durations.len(),
+            excluded,
+            total_minutes,
+            mean_minutes: stat(total_minutes / durations.len() as f64),
+            median_minutes: stat(percentile(&durations, 50.0)),
+            p90_minutes: stat(percentile(&durations, 90.0)),
+            max_minutes,
+            bucket_minutes,
+            histogram,
+        }
+    }
+
+    /// Timed sessions counted into `[0, b0)`, `[b0, b1)`, … and an overflow
+    /// bucket from the last bound up; `bounds` are ascending minutes
+    pub fn duration_histogram(&self, category: Option<&str>, bounds: &[f64]) -> DurationHistogram {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let mut buckets: Vec<HistogramBucket> = std::iter::once(0.0)
+            .chain(bounds.iter().copied())
+            .zip(bounds.iter().copied().map(Some).chain(std::iter::once(None)))
+            .map(|(from, to)| HistogramBucket {
+                label: match to {
+                    Some(to) if from == 0.0 => format!("<{}", crate::days::minutes_label(to)),
+                    Some(to) => format!("{}-{}", crate::days::minutes_label(from), crate::days::minutes_label(to)),
+                    None => format!("{}+", crate::days::minutes_label(from)),
+                },
+                from_minutes: from,
+                to_minutes: to,
+                sessions: 0,
+            })
+            .collect();
+
+        let (mut count, mut excluded) = (0, 0);
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category) {
+                continue;
+            }
+            let Some(minutes) = session.duration_minutes else {
+                excluded += 1;
+                continue;
+            };
+            count += 1;
+            let bucket = bounds.iter().position(|bound| minutes < *bound).unwrap_or(bounds.len());
+            buckets[bucket].sessions += 1;
+        }
+
+        DurationHistogram { count, excluded, buckets }
+    }
+
+    /// Earliest and latest event timestamps, whatever their order in the
+    /// log; untimestamped lines are ignored
+    pub fn span(&self) -> LogSpan {
+        let mut bounds: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+        for event in self.source.events().iter() {
+            let Some(ts) = event.timestamp else { continue };
+            bounds = Some(match bounds {
+                Some((first, last)) => (first.min(ts), last.max(ts)),
+                None => (ts, ts),
+            });
+        }
+
+        LogSpan {
+            first: bounds.map(|(first, _)| first),
+            last: bounds.map(|(_, last)| last),
+            duration_days: bounds.map(|(first, last)| (last - first).num_seconds() as f64 / 86_400.0),
+        }
+    }
+
+    /// Widest gap between neighbouring event timestamps, in time order:
+    /// the longest stretch with nothing logged at all, whatever the lines
+    /// Ties go to the earliest; None with under two timestamped lines
+    pub fn longest_break(&self) -> Option<LoggingBreak> {
+        let mut stamped: Vec<(DateTime<Utc>, usize)> = self
+            .source
+            .events()
+            .iter()
+            .filter_map(|event| Some((event.timestamp?, event.index)))
+            .collect();
+        stamped.sort();
+
+        let gap = |pair: &[(DateTime<Utc>, usize)]| pair[1].0 - pair[0].0;
+        let mut longest = None;
+        for pair in stamped.windows(2) {
+            if longest.is_none_or(|widest| gap(pair) > gap(widest)) {
+                longest = Some(pair);
+            }
+        }
+        longest.map(|pair| (pair[0], pair[1])).map(|((from, from_event_idx), (to, to_event_idx))| LoggingBreak {
+            from,
+            from_event_idx,
+            to,
+            to_event_idx,
+            minutes: (to - from).num_seconds() as f64 / 60.0,
+        })
+    }
+
+    /// Day with the most sessions started, by timestamp
+    /// Ties go to the earliest day; None when no session has a timestamp
+    pub fn busiest_day(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Option<BusiestDay> {
+        let mut days: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for session in self.get_all_sessions() {
+            let Some(start) = session.start_time else { continue };
+            *days.entry(self.days.day_of(start)).or_insert(0) += 1;
+        }
+
+        days.range(from.unwrap_or(NaiveDate::MIN)..=to.unwrap_or(NaiveDate::MAX))
+            // max_by_key keeps the last maximum, so walk the days backwards
+            .rev()
+            .max_by_key(|(_, sessions)| **sessions)
+            .map(|(date, sessions)| BusiestDay {
+                date: date.to_string(),
+                sessions: *sessions,
+            })
+    }
+
+    /// Runs of consecutive days with at least one qualifying session
+    /// A session qualifies when it matches `category` and `activity` (both
+    /// optional) and lasts `min_minutes` or more, or on presence alone
+    /// when its duration isn't known; days are bucketed like `by_day`
+    /// A run ending yesterday is still current, since today isn't over
+    pub fn streaks(
+        &self,
+        category: Option<&str>,
+        activity: Option<&str>,
+        min_minutes: Option<f64>,
+        today: NaiveDate,
+    ) -> Streaks {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let mut active: BTreeSet<NaiveDate> = BTreeSet::new();
+
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category)
+                || activity.is_some_and(|a| a != session.activity)
+            {
+                continue;
+            }
+            let Some(start) = session.start_time else { continue };
+            if let (Some(min), Some(minutes)) = (min_minutes, session.duration_minutes) {
+                if minutes < min {
+                    continue;
+                }
+            }
+            active.insert(self.days.day_of(start));
+        }
+
+        let mut runs: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+        for day in active {
+            match runs.last_mut() {
+                Some((_, end)) if end.succ_opt() == Some(day) => *end = day,
+                _ => runs.push((day, day)),
+            }
+        }
+
+        let length = |(start, end): &(NaiveDate, NaiveDate)| (*end - *start).num_days() as usize + 1;
+        let yesterday = today.pred_opt().unwrap_or(today);
+        let current = runs.last().filter(|(_, end)| *end >= yesterday);
+        // max_by_key keeps the last maximum, so walk the runs backwards
+        let longest = runs.iter().rev().max_by_key(|run| length(run));
+
+        Streaks {
+            current: current.map(length).unwrap_or(0),
+            current_start: current.map(|(start, _)| start.to_string()),
+            longest: longest.map(length).unwrap_or(0),
+            longest_start: longest.map(|(start, _)| start.to_string()),
+            longest_end: longest.map(|(_, end)| end.to_string()),
+            broken_on: runs
+                .iter()
+                .filter_map(|(_, end)| end.succ_opt())
+                .filter(|day| *day < today)
+                .map(|day| day.to_string())
+                .collect(),
+        }
+    }
+
+    /// Personal bests, from one walk over the sessions
+    /// Day-based records need start timestamps and the minute-based ones
+    /// need durations; a record nothing qualifies for is listed in
+    /// `omitted` with the reason. Ties go to the earliest
+    pub fn records(&self) -> PersonalRecords {
+        let mut longest: BTreeMap<String, PersonalRecord> = BTreeMap::new();
+        let mut days: BTreeMap<NaiveDate, RecordDay> = BTreeMap::new();
+        let mut earliest: Option<(chrono::Duration, PersonalRecord)> = None;
+        let mut latest: Option<(chrono::Duration, PersonalRecord)> = None;
+        let (mut sessions, mut timed) = (0, 0);
+
+        for session in self.get_all_sessions() {
+            sessions += 1;
+            let date = session.start_time.map(|start| self.days.day_of(start));
+            let indices: Vec<usize> = std::iter::once(session.start_event_idx)
+                .chain(session.end_event_idx.filter(|end| *end != session.start_event_idx))
+                .collect();
+
+            if let Some(minutes) = session.duration_minutes {
+                timed += 1;
+                let best = longest.get(&session.category).map(|r| r.value);
+                if best.is_none_or(|best| minutes > best) {
+                    longest.insert(session.category.clone(), PersonalRecord {
+                        value: minutes,
+                        date: date.map(|d| d.to_string()),
+                        activity: Some(session.activity.clone()),
+                        event_indices: indices.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+
+            let (Some(start), Some(date)) = (session.start_time, date) else { continue };
+            for (day, minutes) in self.minutes_by_day(&session) {
+                days.entry(day).or_default().minutes += minutes;
+            }
+            days.entry(date).or_default().starts.push(session.start_event_idx);
+
+            // Time since the day began, so a 1am start is late with a 4am day start
+            let offset = start - self.days.day_start(date);
+            let record = || PersonalRecord {
+                value: offset.num_seconds() as f64 / 60.0,
+                date: Some(date.to_string()),
+                time: Some(self.days.zone.local(start).time().format("%H:%M:%S").to_string()),
+                activity: Some(session.activity.clone()),
+                event_indices: vec![session.start_event_idx],
+                ..Default::default()
+            };
+            if earliest.as_ref().is_none_or(|(best, _)| offset < *best) {
+                earliest = Some((offset, record()));
+            }
+            if latest.as_ref().is_none_or(|(best, _)| offset > *best) {
+                latest = Some((offset, record()));
+            }
+        }
+
+        let day_record = |value: f64, date: &NaiveDate, day: &RecordDay| PersonalRecord {
+            value,
+            date: Some(date.to_string()),
+            event_indices: day.starts.clone(),
+            ..Default::default()
+        };
+        let mut most_sessions: Option<PersonalRecord> = None;
+        let mut most_minutes: Option<PersonalRecord> = None;
+        // Runs of consecutive days, as (first, last) positions in `days`
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        let mut previous: Option<NaiveDate> = None;
+        for (pos, (date, day)) in days.iter().enumerate() {
+            if most_sessions.as_ref().is_none_or(|r| day.starts.len() as f64 > r.value) {
+                most_sessions = Some(day_record(day.starts.len() as f64, date, day));
+            }
+            if timed > 0 && most_minutes.as_ref().is_none_or(|r| day.minutes > r.value) {
+                most_minutes = Some(day_record(day.minutes, date, day));
+            }
+            // Only the tail of an overnight session doesn't extend a streak
+            if day.starts.is_empty() {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((_, last)) if previous.and_then(|d| d.succ_opt()) == Some(*date) => *last = pos,
+                _ => runs.push((pos, pos)),
+            }
+            previous = Some(*date);
+        }
+
+        // max_by_key keeps the last maximum, so walk the runs backwards
+        let longest_streak = runs.iter().rev().max_by_key(|(first, last)| last - first).map(|&(first, last)| {
+            let run: Vec<(&NaiveDate, &RecordDay)> = days.iter().skip(first).take(last - first + 1).collect();
+            PersonalRecord {
+                value: run.len() as f64,
+                date: Some(run[0].0.to_string()),
+                end_date: Some(run[run.len() - 1].0.to_string()),
+                event_indices: run.iter().flat_map(|(_, day)| day.starts.iter().copied()).collect(),
+                ..Default::default()
+            }
+        });
+
+        let earliest_start = earliest.map(|(_, r)| r);
+        let latest_start = latest.map(|(_, r)| r);
+        let mut omitted = Vec::new();
+        let mut omit = |record: &str, reason: &str| {
+            omitted.push(OmittedRecord { record: record.to_string(), reason: reason.to_string() })
+        };
+        let (no_time, no_duration) = match sessions {
+            0 => ("no sessions", "no sessions"),
+            _ => ("no session has a start timestamp", "no session has a duration"),
+        };
+        if longest.is_empty() {
+            omit("longest_session", no_duration);
+        }
+        for (name, record) in [
+            ("most_sessions_in_a_day", &most_sessions),
+            ("most_minutes_in_a_day", &most_minutes),
+            ("longest_streak", &longest_streak),
+            ("earliest_start", &earliest_start),
+            ("latest_start", &latest_start),
+        ] {
+            if record.is_none() {
+                let reason = if name == "most_minutes_in_a_day" && !days.is_empty() { no_duration } else { no_time };
+                omit(name, reason);
+            }
+        }
+
+        PersonalRecords {
+            longest_session: longest,
+            most_sessions_in_a_day: most_sessions,
+            most_minutes_in_a_day: most_minutes,
+            longest_streak,
+            earliest_start,
+            latest_start,
+            omitted,
+        }
+    }
+
+    /// Minutes per local weekday (Monday first) and hour, sessions
+    /// clipped to `since` and split across every hour they overlap
+    /// When no session has a duration, timestamped events are counted
+    /// per bucket instead
+    pub fn heatmap(&self, category: Option<&str>, since: Option<DateTime<Utc>>) -> Heatmap {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let zone = self.days.zone;
+        let bucket = |ts: DateTime<Utc>| {
+            let local = zone.local(ts);
+            (local.weekday().num_days_from_monday() as usize, local.hour() as usize)
+        };
+        let mut cells = vec![vec![0.0; 24]; 7];
+        let mut unit = "minutes";
+
+        for session in self.get_all_sessions() {
+            if category.as_ref().is_some_and(|c| *c != session.category) {
+                continue;
+            }
+            let (Some(start), Some(end)) = (session.start_time, session.end_time) else { continue };
+            let mut at = since.map_or(start, |since| start.max(since));
+            while at < end {
+                // Next local hour boundary, which isn't a UTC one for
+                // half-hour offsets
+                let local = zone.local(at);
+                let into_hour = chrono::Duration::minutes(local.minute() as i64)
+                    + chrono::Duration::seconds(local.second() as i64)
+                    + chrono::Duration::nanoseconds(local.nanosecond() as i64);
+                let next = (at - into_hour + chrono::Duration::hours(1)).min(end);
+                let (day, hour) = bucket(at);
+                cells[day][hour] += (next - at).num_milliseconds() as f64 / 60_000.0;
+                at = next;
+            }
+        }
+
+        if cells.iter().flatten().all(|v| *v == 0.0) {
+            unit = "events";
+            for event in self.source.events().iter() {
+                let (Some(ts), Some(event_category)) = (event.timestamp, &event.category) else { continue };
+                if since.is_some_and(|since| ts < since)
+                    || category.as_ref().is_some_and(|c| *c != self.aliases.resolve(event_category))
+                {
+                    continue;
+                }
+                let (day, hour) = bucket(ts);
+                cells[day][hour] += 1.0;
+            }
+        }
+
+        // Ties go to the earliest cell, Monday 00:00 first
+        let peak = (0..7)
+            .flat_map(|day| (0..24).map(move |hour| (day, hour)))
+            .filter(|&(day, hour)| cells[day][hour] > 0.0)
+            .rev()
+            .max_by(|a, b| cells[a.0][a.1].total_cmp(&cells[b.0][b.1]))
+            .map(|(day, hour)| HeatmapPeak {
+                weekday: WEEKDAYS[day].to_string(),
+                hour: hour as u32,
+                value: cells[day][hour],
+            });
+
+        Heatmap {
+            unit: unit.to_string(),
+            cells,
+            peak,
+        }
+    }
+
+    /// Sessions whose metadata satisfies `filter`
+    pub fn filtered_sessions(&self, filter: &MetadataFilter) -> QueryResult {
+        let sessions: Vec<Session> = self
+            .get_all_sessions()
+            .into_iter()
+            .filter(|s| filter.matches(&s.metadata))
+            .collect();
+
+        QueryResult {
+            query: "sessions".to_string(),
+            result_type: "sessions".to_string(),
+            data: serde_json::json!({
+                "count": sessions.len(),
+                "sessions": sessions,
+            }),
+        }
+    }
+
+    /// How often each tag value appears, by key
+    /// `events` counts every line carrying it; `sessions` and `minutes`
+    /// the sessions whose START carries it
+    pub fn tags(&self, category: Option<&str>) -> Vec<TagSummary> {
+        let category = category.map(|c| self.aliases.resolve(c));
+        let in_category = |c: Option<&str>| match (&category, c) {
+            (None, _) => true,
+            (Some(wanted), Some(c)) => self.aliases.resolve(c) == *wanted,
+            (Some(_), None) => false,
+        };
+        let mut keys: BTreeMap<String, BTreeMap<String, TagValue>> = BTreeMap::new();
+        fn value<'a>(keys: &'a mut BTreeMap<String, BTreeMap<String, TagValue>>, key: &str, value: &str) -> &'a mut TagValue {
+            keys.entry(key.to_string())
+                .or_default()
+                .entry(value.to_string())
+                .or_insert_with(|| TagValue { value: value.to_string(), events: 0, sessions: 0, minutes: 0.0 })
+        }
+
+        for event in self.source.events().iter() {
+            if !in_category(event.category.as_deref()) {
+                continue;
+            }
+            for (k, v) in &event.metadata {
+                value(&mut keys, k, v).events += 1;
+            }
+        }
+        for session in self.get_all_sessions() {
+            if !in_category(Some(&session.category)) {
+                continue;
+            }
+            for (k, v) in &session.metadata {
+                let tag = value(&mut keys, k, v);
+                tag.sessions += 1;
+                tag.minutes += session.duration_minutes.unwrap_or(0.0);
+            }
+        }
+
+        keys.into_iter()
+            .map(|(key, values)| {
+                let mut values: Vec<TagValue> = values.into_values().collect();
+                values.sort_by(|a, b| b.events.cmp(&a.events).then_with(|| a.value.cmp(&b.value)));
+                TagSummary { key, events: values.iter().map(|v| v.events).sum(), values }
+            })
+            .collect()
+    }
+
+    /// Sessions that started on `date`, in log order
+    /// Sessions without a start timestamp belong to no day
+    pub fn sessions_on(&self, date: NaiveDate) -> QueryResult {
+        let sessions: Vec<Session> = self
+            .get_all_sessions()
+            .into_iter()
+            .filter(|s| s.start_time.is_some_and(|start| self.days.day_of(start) == date))
+            .collect();
+
+        QueryResult {
+            query: "day".to_string(),
+            result_type: "sessions".to_string(),
+            data: serde_json::json!({
+                "date": date.to_string(),
+                "count": sessions.len(),
+                "sessions": sessions,
+            }),
+        }
+    }
+
+    pub fn get_timeline(&self, sort: SessionSort, order: SortOrder) -> QueryResult {
+        let mut sessions = self.get_all_sessions();
+        sort_sessions(&mut sessions, sort, order);
+
+        QueryResult {
+            query: "timeline".to_string(),
+            result_type: "sessions".to_string(),
+            data: serde_json::json!({
+                "sessions": sessions,
+                "total": sessions.len(),
+                "active": sessions.iter().filter(|s| s.is_active).count(),
+            }),
+        }
+    }
+}
+
+/// Thrashing indicators for one calendar day
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DailyContextSwitches {
+    pub date: String,
+    pub sessions: usize,
+    pub short_sessions: usize,
+    pub distinct_categories: usize,
+}
+
+/// Switching per day, and how long sessions last on busy vs calm days
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SwitchingReport {
+    /// Days with more switches than this are high-switch days
+    pub threshold: usize,
+    /// Days with a timestamped session, oldest first
+    pub days: Vec<DailySwitching>,
+    pub high_switch_days: usize,
+    /// Mean timed session minutes over all high-switch days, null without any
+    pub mean_session_minutes_high: Option<f64>,
+    pub mean_session_minutes_low: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, ToSchema)]
+pub struct DailySwitching {
+    pub date: String,
+    pub sessions: usize,
+    pub switches: usize,
+    /// Sessions with a duration, the ones `total_minutes` sums
+    pub timed_sessions: usize,
+    pub total_minutes: f64,
+    pub high_switch: bool,
+}
+
+/// Time between two consecutive sessions
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Gap {
+    /// Indices of the bounding sessions in the timeline
+    pub after_session: usize,
+    pub before_session: usize,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub minutes: f64,
+    pub overnight: bool,
+    pub in_working_hours: bool,
+    pub previous: Session,
+    pub next: Session,
+}
+
+/// One session with everything logged while it was active
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionDetail {
+    pub session: Session,
+    pub notes: Vec<String>,
+    pub tags: Vec<String>,
+    pub events: Vec<IndexedEvent>,
+}
+
+/// A NOTE line's text and when it was written
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct LoggedNote {
+    pub event_index: usize,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+}
+
+/// One tag key and the values it took, most frequent first
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TagSummary {
+    pub key: String,
+    /// Lines carrying the key
+    pub events: usize,
+    pub values: Vec<TagValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TagValue {
+    pub value: String,
+    pub events: usize,
+    /// Sessions started with this value
+    pub sessions: usize,
+    /// Their timed minutes
+    pub minutes: f64,
+}
+
+/// Sessions and minutes of one activity, for the top-N
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ActivityTotal {
+    pub category: String,
+    pub activity: String,
+    pub sessions: usize,
+    pub minutes: f64,
+}
+
+/// An activity and how long it has gone untouched
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct StaleActivity {
+    pub category: String,
+    pub activity: String,
+    pub last_session: DateTime<Utc>,
+    pub days_since: i64,
+}
+
+/// Two ranges side by side, see `compare`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RangeComparison {
+    pub a: RangeSummary,
+    pub b: RangeSummary,
+    /// `b` relative to `a`
+    pub deltas: RangeDeltas,
+    /// Overlapping ranges; compared anyway
+    pub flags: Vec<String>,
+}
+
+/// Sessions started within one inclusive date range
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RangeSummary {
+    pub from: String,
+    pub to: String,
+    /// Calendar days covered, 0 for a backwards range
+    pub days: usize,
+    pub sessions: usize,
+    pub minutes: f64,
+    /// THEORY to PRACTICE sessions, null without practice
+    pub theory_to_practice: Option<f64>,
+    pub categories: BTreeMap<String, CategoryRollup>,
+    /// Null for an empty range
+    pub sessions_per_day: Option<f64>,
+    pub minutes_per_day: Option<f64>,
+}
+
+fn range_summary((from, to): (NaiveDate, NaiveDate)) -> RangeSummary {
+    RangeSummary {
+        from: from.to_string(),
+        to: to.to_string(),
+        days: if from <= to { (to - from).num_days() as usize + 1 } else { 0 },
+        sessions: 0,
+        minutes: 0.0,
+        theory_to_practice: None,
+        categories: BTreeMap::new(),
+        sessions_per_day: None,
+        minutes_per_day: None,
     }
 }
 
-/// Projects sessions from event log
-/// Session = period between START events
-pub struct SessionProjector {
-    log_path: PathBuf,
+/// Raw and per-day changes; per-day ones are null when either range is
+/// empty, the ratio's when either lacks practice
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RangeDeltas {
+    pub sessions: Change,
+    pub minutes: Change,
+    pub sessions_per_day: Option<Change>,
+    pub minutes_per_day: Option<Change>,
+    pub theory_to_practice: Option<Change>,
+    pub categories: BTreeMap<String, CategoryChange>,
 }
 
-impl SessionProjector {
-    pub fn new(log_path: &PathBuf) -> Self {
-        Self {
-            log_path: log_path.clone(),
-        }
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CategoryChange {
+    pub minutes: Change,
+    pub minutes_per_day: Option<Change>,
+}
+
+/// From one value to another
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Change {
+    pub delta: f64,
+    /// Null when starting from zero
+    pub percent: Option<f64>,
+}
+
+fn change(before: f64, after: f64) -> Change {
+    Change {
+        delta: after - before,
+        percent: (before != 0.0).then(|| (after - before) / before * 100.0),
     }
+}
 
-    fn read_events(&self) -> Vec<String> {
-        match std::fs::File::open(&self.log_path) {
-            Ok(file) => {
-                let reader = std::io::BufReader::new(file);
-                reader.lines()
-                    .filter_map(|line| line.ok())
-                    .filter(|line| !line.trim().is_empty())
-                    .collect()
-            }
-            Err(_) => Vec::new(),
-        }
+/// Sessions and minutes of one category within a rollup period
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct CategoryRollup {
+    pub sessions: usize,
+    pub minutes: f64,
+}
+
+/// Change against the previous period
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RollupDelta {
+    pub sessions: i64,
+    pub minutes: f64,
+    pub theory_to_practice: Option<f64>,
+}
+
+/// Fewest complete weeks a trend is fitted to
+pub const MIN_FORECAST_WEEKS: usize = 3;
+
+/// Past weekly minutes and the projection that continues them
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Forecast {
+    /// Null for all categories together
+    pub category: Option<String>,
+    /// `linear_trend`: least-squares line over the history, extended;
+    /// `mean`: the history's mean, used with fewer than 3 weeks
+    pub method: String,
+    /// Minutes gained per week, null for `mean`
+    pub slope: Option<f64>,
+    pub low_confidence: bool,
+    /// Complete weeks, oldest first, zeros included
+    pub history: Vec<WeekMinutes>,
+    /// From the current week on; never below zero
+    pub forecast: Vec<WeekMinutes>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WeekMinutes {
+    /// `2024-W09`
+    pub week: String,
+    /// First day
+    pub start: String,
+    pub minutes: f64,
+}
+
+/// One week or month of the rollup report
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PeriodRollup {
+    pub period: String,
+    pub start: String,
+    pub end: String,
+    pub sessions: usize,
+    pub minutes: f64,
+    pub categories: BTreeMap<String, CategoryRollup>,
+    pub theory_to_practice: Option<f64>,
+    /// None for the first period
+    pub delta: Option<RollupDelta>,
+    pub partial: bool,
+}
+
+/// One point of the ratio trend
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DailyRatio {
+    pub date: String,
+    pub theory: usize,
+    pub practice: usize,
+    pub ratio: Option<f64>,
+}
+
+/// One window of the rolling ratio
+/// `ratio` is null when the window holds no practice
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RollingRatio {
+    /// Inclusive
+    pub start: DateTime<Utc>,
+    /// Exclusive
+    pub end: DateTime<Utc>,
+    /// Timestamped events of any category in the window
+    pub events: usize,
+    pub theory: usize,
+    pub practice: usize,
+    pub ratio: Option<f64>,
+}
+
+/// Logging habit over a span of days
+/// The averages are None when there's nothing to divide by
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Cadence {
+    pub first_day: Option<String>,
+    pub last_day: Option<String>,
+    pub sessions: usize,
+    pub active_days: usize,
+    pub span_days: usize,
+    pub per_active_day: Option<f64>,
+    pub per_calendar_day: Option<f64>,
+}
+
+/// One day of a moving average
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MovingAverage {
+    pub date: String,
+    /// Sessions started that day
+    pub sessions: usize,
+    /// Days averaged: the window, or fewer at the start of the log
+    pub days: usize,
+    pub average: f64,
+}
+
+/// Most buckets a duration histogram has
+pub const MAX_DURATION_BUCKETS: usize = 1000;
+
+/// Session duration distribution; the summary stats are None without
+/// any timed sessions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SessionStats {
+    /// Sessions with a duration, the ones the stats describe
+    pub count: usize,
+    /// Sessions left out for lacking a duration
+    pub excluded: usize,
+    pub total_minutes: f64,
+    pub mean_minutes: Option<f64>,
+    pub median_minutes: Option<f64>,
+    pub p90_minutes: Option<f64>,
+    pub max_minutes: Option<f64>,
+    pub bucket_minutes: f64,
+    /// From zero up to the bucket holding the longest session, at most
+    /// MAX_DURATION_BUCKETS; the last one is widened to reach it
+    pub histogram: Vec<DurationBucket>,
+}
+
+/// Sessions lasting from `from_minutes` (inclusive) to `to_minutes`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DurationBucket {
+    pub from_minutes: f64,
+    pub to_minutes: f64,
+    pub sessions: usize,
+}
+
+/// Session counts per duration bucket, for GET /projections/duration-histogram
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DurationHistogram {
+    /// Sessions with a duration, the ones counted
+    pub count: usize,
+    /// Sessions left out for lacking a duration
+    pub excluded: usize,
+    /// Shortest first; the last has no `to_minutes` and holds everything
+    /// at least as long as the largest bound
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// Sessions lasting from `from_minutes` (inclusive) to `to_minutes`
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistogramBucket {
+    /// `<5m`, `5m-15m`, …, `1h+`
+    pub label: String,
+    pub from_minutes: f64,
+    pub to_minutes: Option<f64>,
+    pub sessions: usize,
+}
+
+/// Consecutive-day streaks; ties for longest go to the earliest run
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Streaks {
+    /// Days in the run ending today or yesterday, 0 when broken
+    pub current: usize,
+    pub current_start: Option<String>,
+    pub longest: usize,
+    pub longest_start: Option<String>,
+    pub longest_end: Option<String>,
+    /// First missed day after each run, oldest first
+    pub broken_on: Vec<String>,
+}
+
+/// A session's range as its own lines give it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct WrittenSession {
+    pub category: String,
+    pub activity: String,
+    pub start_event_idx: usize,
+    /// The STOP naming it, or the line that ended it in the timeline
+    pub end_event_idx: usize,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+/// Two sessions claiming the same time, `a` starting first
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SessionConflict {
+    pub a: WrittenSession,
+    pub b: WrittenSession,
+    pub overlap_minutes: f64,
+}
+
+/// Category-to-category transition counts
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Transitions {
+    /// Every category seen, alphabetically: the matrix's rows and columns
+    pub categories: Vec<String>,
+    /// Every ordered pair, zeros included, row by row
+    pub matrix: Vec<Transition>,
+    /// The commonest follower of each category (ties go alphabetically);
+    /// categories never followed by anything are left out
+    pub most_likely_next: BTreeMap<String, Transition>,
+    /// Pairs counted
+    pub total: usize,
+    /// Pairs left out for being further apart than the gap limit
+    pub excluded: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    pub count: usize,
+    /// Share of the sessions after `from` that were `to`; null when
+    /// nothing followed `from`
+    pub probability: Option<f64>,
+}
+
+/// Personal bests; each one carries the lines that set it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PersonalRecords {
+    /// Longest session minutes per category; categories without a timed
+    /// session are left out
+    pub longest_session: BTreeMap<String, PersonalRecord>,
+    pub most_sessions_in_a_day: Option<PersonalRecord>,
+    pub most_minutes_in_a_day: Option<PersonalRecord>,
+    /// Consecutive days with a session
+    pub longest_streak: Option<PersonalRecord>,
+    /// Start closest to the beginning of its day; `value` is minutes
+    /// into the day
+    pub earliest_start: Option<PersonalRecord>,
+    pub latest_start: Option<PersonalRecord>,
+    /// Records nothing qualified for, and why
+    pub omitted: Vec<OmittedRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct PersonalRecord {
+    /// Minutes, sessions or days, depending on the record
+    pub value: f64,
+    /// Day the record was set (a streak's first day); null for an
+    /// untimestamped session
+    pub date: Option<String>,
+    /// Last day of a streak
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_date: Option<String>,
+    /// Local wall-clock start, HH:MM:SS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>,
+    /// START (and STOP) lines backing the record
+    pub event_indices: Vec<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct OmittedRecord {
+    pub record: String,
+    pub reason: String,
+}
+
+/// A day's totals while finding records
+#[derive(Default)]
+struct RecordDay {
+    minutes: f64,
+    /// START lines of the sessions begun that day
+    starts: Vec<usize>,
+}
+
+/// Heatmap row names, in row order
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+/// Weekday × hour totals as plain nested arrays, ready for charting
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Heatmap {
+    /// `minutes`, or `events` when no session has a duration
+    pub unit: String,
+    /// 7 rows, Monday first, of 24 local hours
+    pub cells: Vec<Vec<f64>>,
+    /// Fullest cell, null when everything is empty
+    pub peak: Option<HeatmapPeak>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HeatmapPeak {
+    /// `monday` to `sunday`
+    pub weekday: String,
+    pub hour: u32,
+    pub value: f64,
+}
+
+/// Time covered by the log, all null when nothing has a timestamp
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LogSpan {
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// Fractional days from first to last
+    pub duration_days: Option<f64>,
+}
+
+/// The longest stretch with nothing logged
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingBreak {
+    /// Timestamp of the last line before the break
+    pub from: DateTime<Utc>,
+    pub from_event_idx: usize,
+    /// Timestamp of the first line after it
+    pub to: DateTime<Utc>,
+    pub to_event_idx: usize,
+    pub minutes: f64,
+}
+
+/// Day with the most sessions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BusiestDay {
+    pub date: String,
+    pub sessions: usize,
+}
+
+/// One day of the by-day aggregation
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct DailyRow {
+    pub date: String,
+    pub total: f64,
+    pub categories: BTreeMap<String, f64>,
+}
+
+fn stats_entry(
+    stats: &mut std::collections::HashMap<String, ActivityStats>,
+    activity: String,
+) -> &mut ActivityStats {
+    stats.entry(activity.clone()).or_insert_with(|| ActivityStats {
+        activity,
+        sessions: 0,
+        events: 0,
+        minutes: 0.0,
+        percentage: 0.0,
+    })
+}
+
+/// Order sessions by `sort`; sessions missing the key (no timestamp,
+/// or still active for duration) go last in either order
+pub fn sort_sessions(sessions: &mut [Session], sort: SessionSort, order: SortOrder) {
+    let key = |s: &Session| match sort {
+        SessionSort::Log => Some(0.0),
+        SessionSort::Start => s.start_time.map(|t| t.timestamp_millis() as f64),
+        SessionSort::Duration => s.duration_minutes,
+    };
+    sessions.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(x), Some(y)) => match order {
+            SortOrder::Asc => x.total_cmp(&y),
+            SortOrder::Desc => y.total_cmp(&x),
+        },
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    if sort == SessionSort::Log && order == SortOrder::Desc {
+        sessions.reverse();
     }
+}
 
-    pub fn get_all_sessions(&self) -> Vec<Session> {
-        let events = self.read_events();
-        let mut sessions = Vec::new();
-        let mut current_session: Option<Session> = None;
+/// Text of a NOTE line, without its timestamp and verb
+/// Stable sort by each item's last seen timestamp, so in-order logs come
+/// back unchanged
+fn in_timestamp_order<T>(mut keyed: Vec<(Option<DateTime<Utc>>, T)>) -> Vec<T> {
+    if !keyed.is_sorted_by_key(|(ts, _)| *ts) {
+        keyed.sort_by_key(|(ts, _)| *ts);
+    }
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
 
-        for (idx, line) in events.iter().enumerate() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            
-            if parts.len() >= 3 && parts[0] == "START" {
-                // End previous session
-                if let Some(mut session) = current_session.take() {
-                    session.end_event_idx = Some(idx - 1);
-                    session.is_active = false;
-                    sessions.push(session);
-                }
+/// Events in timestamp order, as `SessionProjector::ordered_lines`
+/// orders their lines
+fn ordered_events(events: &[ParsedEvent]) -> Vec<&ParsedEvent> {
+    let mut last_seen = None;
+    let keyed = events
+        .iter()
+        .map(|event| {
+            last_seen = event.timestamp.or(last_seen);
+            (last_seen, event)
+        })
+        .collect();
+    in_timestamp_order(keyed)
+}
 
-                // Start new session
-                current_session = Some(Session {
-                    category: parts[1].to_string(),
-                    activity: parts[2].to_string(),
-                    start_event_idx: idx,
-                    end_event_idx: None,
-                    is_active: true,
-                });
+fn note_text(line: &str) -> Option<String> {
+    let event = parse_event(line)?;
+    if event.verb != EventVerb::Note {
+        return None;
+    }
+    let skip = if event.timestamp.is_some() { 2 } else { 1 };
+    Some(line.split_whitespace().skip(skip).collect::<Vec<_>>().join(" "))
+}
+
+/// Theory over practice, whichever weight the amounts are in
+/// Without practice there's no ratio, rather than pretending one existed
+fn theory_to_practice(theory: Option<f64>, practice: Option<f64>) -> (Option<f64>, RatioStatus) {
+    let (theory, practice) = (theory.unwrap_or(0.0), practice.unwrap_or(0.0));
+    let status = match (theory > 0.0, practice > 0.0) {
+        (true, true) => RatioStatus::Defined,
+        (true, false) => RatioStatus::NoPractice,
+        (false, true) => RatioStatus::NoTheory,
+        (false, false) => RatioStatus::Empty,
+    };
+    ((practice > 0.0).then(|| theory / practice), status)
+}
+
+/// Nearest-rank percentile of an ascending slice (0.0 when empty)
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Folds each session into the one before it when both are the same
+/// category and activity and it starts less than `gap` minutes after
+/// that one ended. The merged session runs from the first START to the
+/// last end, so its events (and notes) cover every fragment; durations
+/// add up and the gaps between fragments don't count
+fn merge_fragments(sessions: Vec<Session>, gap: f64) -> Vec<Session> {
+    let mut merged: Vec<Session> = Vec::new();
+    for session in sessions {
+        if let Some(last) = merged.last_mut() {
+            let apart = last
+                .end_time
+                .zip(session.start_time)
+                .map(|(end, start)| (start - end).num_seconds() as f64 / 60.0);
+            if last.category == session.category
+                && last.activity == session.activity
+                && apart.is_some_and(|minutes| (0.0..gap).contains(&minutes))
+            {
+                let add = |a: Option<f64>, b: Option<f64>| a.zip(b).map(|(a, b)| a + b);
+                last.duration_minutes = add(last.duration_minutes, session.duration_minutes);
+                last.gross_minutes = add(last.gross_minutes, session.gross_minutes);
+                last.end_event_idx = session.end_event_idx;
+                last.end_time = session.end_time;
+                last.is_active = session.is_active;
+                last.closed_by = session.closed_by;
+                for (key, value) in session.metadata {
+                    last.metadata.entry(key).or_insert(value);
+                }
+                last.fragments = last.fragments.map(|n| n + 1);
+                continue;
             }
         }
+        merged.push(Session { fragments: Some(1), ..session });
+    }
+    merged
+}
 
-        // Don't forget the last session
-        if let Some(session) = current_session {
-            sessions.push(session);
-        }
+/// Minutes between start and end, only when both timestamps exist
+fn duration_minutes(session: &Session) -> Option<f64> {
+    let (start, end) = (session.start_time?, session.end_time?);
+    Some((end - start).num_seconds() as f64 / 60.0)
+}
 
-        sessions
-    }
+/// PAUSE/RESUME bookkeeping for the session being built
+/// A repeated PAUSE or a RESUME without one is ignored, and a pause still
+/// open when the session ends lasts until the end; untimestamped lines
+/// can't be measured and are skipped
+#[derive(Debug, Default)]
+struct Pauses {
+    since: Option<DateTime<Utc>>,
+    minutes: f64,
+}
 
-    pub fn get_current_session(&self) -> Option<Session> {
-        let sessions = self.get_all_sessions();
-        sessions.into_iter().find(|s| s.is_active)
+impl Pauses {
+    fn pause(&mut self, at: Option<DateTime<Utc>>) {
+        if self.since.is_none() {
+            self.since = at;
+        }
     }
 
-    pub fn get_timeline(&self) -> QueryResult {
-        let sessions = self.get_all_sessions();
-        
-        QueryResult {
-            query: "timeline".to_string(),
-            result_type: "sessions".to_string(),
-            data: serde_json::json!({
-                "sessions": sessions,
-                "total": sessions.len(),
-                "active": sessions.iter().filter(|s| s.is_active).count(),
-            }),
+    fn resume(&mut self, at: Option<DateTime<Utc>>) {
+        if let (Some(since), Some(at)) = (self.since, at) {
+            self.minutes += ((at - since).num_seconds() as f64 / 60.0).max(0.0);
+            self.since = None;
         }
     }
+
+    /// Set the session's gross duration, and its net one with pauses
+    /// up to its end taken off
+    fn finish(&self, session: &mut Session) {
+        let gross = duration_minutes(session);
+        let open = match (self.since, session.end_time) {
+            (Some(since), Some(end)) => ((end - since).num_seconds() as f64 / 60.0).max(0.0),
+            _ => 0.0,
+        };
+        session.gross_minutes = gross;
+        session.duration_minutes = gross.map(|gross| (gross - self.minutes - open).max(0.0));
+    }
 }
 
+/// Longest rolling ratio series returned; a tiny step over a long log
+/// is cut off here rather than producing millions of points
+pub const MAX_ROLLING_POINTS: usize = 5000;
+
 /// Analyzes ratios between activity types
-pub struct RatioAnalyzer {
-    log_path: PathBuf,
+pub struct RatioAnalyzer<'a> {
+    source: EventSource<'a>,
+    aliases: CategoryAliases,
+    display: CategoryDisplayConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Shape version of `RatioAnalysis`; 2 made `theory_to_practice` nullable
+pub const RATIO_ANALYSIS_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RatioAnalysis {
+    /// `RATIO_ANALYSIS_VERSION`; absent before the ratio became nullable
+    pub version: u32,
+    /// What the percentages and ratio are computed from
+    #[serde(default)]
+    pub weight: RatioWeight,
     pub categories: Vec<CategoryCount>,
+    /// Events counted, or timed sessions when weighted by duration
     pub total_events: usize,
-    pub theory_to_practice: f64,
+    /// Only when weighted by duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_minutes: Option<f64>,
+    /// Null when there's no practice to divide by
+    pub theory_to_practice: Option<f64>,
+    /// Why the ratio is what it is
+    pub ratio_status: RatioStatus,
+}
+
+/// Which side of theory:practice has data
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RatioStatus {
+    /// Both present, the ratio is meaningful
+    Defined,
+    /// Theory but no practice: no ratio
+    NoPractice,
+    /// Practice but no theory: the ratio is 0
+    NoTheory,
+    /// Neither: no ratio
+    Empty,
+}
+
+/// Fraction of the target a ratio may be off by and still be on target
+pub const DEFAULT_TARGET_TOLERANCE: f64 = 0.1;
+
+/// A `TARGET RATIO <numerator> <denominator> <value>` line, in force from
+/// its event index until the next one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct RatioTarget {
+    pub idx: usize,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub numerator: String,
+    pub denominator: String,
+    pub value: f64,
+}
+
+/// Minute ratio of a target's two categories compared against it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TargetDeviation {
+    pub numerator_minutes: f64,
+    pub denominator_minutes: f64,
+    /// Null without denominator minutes
+    pub ratio: Option<f64>,
+    /// `ratio - value`, null when the ratio is
+    pub delta: Option<f64>,
+    /// Null when neither category has any minutes
+    pub on_target: Option<bool>,
+    /// Roughly what would bring the ratio back to the target, null when on it
+    pub correction: Option<TargetCorrection>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TargetCorrection {
+    pub category: String,
+    /// Rounded up to whole minutes
+    pub minutes: f64,
+}
+
+/// The stretch of log one target was in force for
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TargetPeriod {
+    pub target: RatioTarget,
+    /// Index of the next target (exclusive), null for the current one
+    pub end_idx: Option<usize>,
+    pub deviation: TargetDeviation,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RatioTargetReport {
+    /// Most recent target, null when none has been logged
+    pub target: Option<RatioTarget>,
+    pub tolerance: f64,
+    /// The window (or, without one, the current target's period) against
+    /// the most recent target
+    pub current: Option<TargetDeviation>,
+    /// Every target judged on the sessions started while it was in force,
+    /// oldest first
+    pub history: Vec<TargetPeriod>,
+}
+
+/// Parse a `TARGET RATIO` line; anything malformed isn't a target
+fn ratio_target(idx: usize, line: &str, aliases: &CategoryAliases) -> Option<RatioTarget> {
+    let event = parse_event(line)?;
+    if event.verb != EventVerb::Target || event.category.as_deref() != Some("RATIO") {
+        return None;
+    }
+    // VERB RATIO NUMERATOR DENOMINATOR VALUE, after the timestamp if any
+    let mut parts = line.split_whitespace().skip(usize::from(event.timestamp.is_some()) + 2);
+    let numerator = parts.next()?;
+    let denominator = parts.next()?;
+    let value: f64 = parts.next()?.parse().ok()?;
+    if !value.is_finite() || value <= 0.0 {
+        return None;
+    }
+    Some(RatioTarget {
+        idx,
+        timestamp: event.timestamp,
+        numerator: aliases.resolve(numerator),
+        denominator: aliases.resolve(denominator),
+        value,
+    })
+}
+
+/// Compare the minutes of `sessions` against `target`
+fn target_deviation<'a>(
+    target: &RatioTarget,
+    tolerance: f64,
+    sessions: impl Iterator<Item = &'a Session>,
+) -> TargetDeviation {
+    let (mut numerator, mut denominator) = (0.0, 0.0);
+    for session in sessions {
+        let Some(minutes) = session.duration_minutes else { continue };
+        if session.category == target.numerator {
+            numerator += minutes;
+        } else if session.category == target.denominator {
+            denominator += minutes;
+        }
+    }
+
+    let ratio = (denominator > 0.0).then(|| numerator / denominator);
+    let delta = ratio.map(|ratio| ratio - target.value);
+    let on_target = (numerator > 0.0 || denominator > 0.0)
+        .then(|| delta.is_some_and(|delta| delta.abs() <= target.value * tolerance));
+    // Top up whichever side is short until the ratio is exactly on target
+    let correction = match on_target {
+        Some(false) if ratio.is_some_and(|ratio| ratio < target.value) => Some(TargetCorrection {
+            category: target.numerator.clone(),
+            minutes: (target.value * denominator - numerator).ceil(),
+        }),
+        Some(false) => Some(TargetCorrection {
+            category: target.denominator.clone(),
+            minutes: (numerator / target.value - denominator).ceil(),
+        }),
+        _ => None,
+    };
+
+    TargetDeviation {
+        numerator_minutes: numerator,
+        denominator_minutes: denominator,
+        ratio,
+        delta,
+        on_target,
+        correction,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CategoryCount {
     pub category: String,
+    /// Events, or timed sessions when weighted by duration
     pub count: usize,
     pub percentage: f64,
+    /// Only when weighted by duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minutes: Option<f64>,
+    /// From the category display config, null when unconfigured
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// Share of tracked time per category (duration analogue of ratios)
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TimeAllocation {
+    pub categories: Vec<CategoryDuration>,
+    pub total_minutes: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CategoryDuration {
+    pub category: String,
+    pub minutes: f64,
+    pub percentage: f64,
+    /// Per-session duration distribution (timed sessions only)
+    pub sessions: usize,
+    pub mean_minutes: f64,
+    pub p50_minutes: f64,
+    pub p90_minutes: f64,
+    pub p95_minutes: f64,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
 }
 
-impl RatioAnalyzer {
-    pub fn new(log_path: &PathBuf) -> Self {
+impl<'a> RatioAnalyzer<'a> {
+    /// Own uncached reader over `log_path`; the server shares one via
+    /// `from_reader`
+    #[cfg(test)]
+    pub fn new(log_path: &std::path::Path) -> Self {
+        Self::from_reader(&EventReader::new(log_path))
+    }
+
+    pub fn from_reader(reader: &EventReader) -> Self {
+        Self::from_source(EventSource::Log(reader.clone()))
+    }
+
+    /// Over events already parsed from the log, in log order
+    pub fn from_events(events: &'a [ParsedEvent]) -> Self {
+        Self::from_source(EventSource::Parsed(events))
+    }
+
+    fn from_source(source: EventSource<'a>) -> Self {
         Self {
-            log_path: log_path.clone(),
+            source,
+            aliases: CategoryAliases::default(),
+            display: CategoryDisplayConfig::default(),
         }
     }
 
-    fn read_events(&self) -> Vec<String> {
-        match std::fs::File::open(&self.log_path) {
-            Ok(file) => {
-                let reader = std::io::BufReader::new(file);
-                reader.lines()
-                    .filter_map(|line| line.ok())
-                    .filter(|line| !line.trim().is_empty())
-                    .collect()
-            }
-            Err(_) => Vec::new(),
-        }
+    /// Fold aliased categories into their canonical name
+    pub fn with_aliases(mut self, aliases: &CategoryAliases) -> Self {
+        self.aliases = aliases.clone();
+        self
+    }
+
+    /// Display names and colors to echo next to each category
+    pub fn with_display(mut self, display: &CategoryDisplayConfig) -> Self {
+        self.display = display.clone();
+        self
+    }
+
+    /// Missing or unreadable logs project as empty
+    fn sessions(&self) -> SessionProjector<'a> {
+        SessionProjector::from_source(self.source.clone()).with_aliases(&self.aliases)
     }
 
     pub fn analyze(&self) -> QueryResult {
-        let events = self.read_events();
+        self.analyze_since(None)
+    }
+
+    /// Ratios over events timestamped at or after `since` only, when given
+    /// Untimestamped lines can't be placed in a window and are left out
+    pub fn analyze_since(&self, since: Option<DateTime<Utc>>) -> QueryResult {
+        let events = self.source.events();
         let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
 
-        for line in &events {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let category = parts[1].to_string();
-                *counts.entry(category).or_insert(0) += 1;
+        for event in events.iter() {
+            if !event.verb.counts_toward_ratios() {
+                continue;
+            }
+            if since.is_some_and(|since| event.timestamp.is_none_or(|ts| ts < since)) {
+                continue;
+            }
+            if let Some(category) = &event.category {
+                *counts.entry(self.aliases.resolve(category)).or_insert(0) += 1;
             }
         }
 
@@ -236,22 +4173,91 @@ impl RatioAnalyzer {
         
         let mut categories: Vec<CategoryCount> = counts
             .into_iter()
-            .map(|(cat, count)| CategoryCount {
-                category: cat.clone(),
-                count,
-                percentage: if total > 0 { (count as f64 / total as f64) * 100.0 } else { 0.0 },
+            .map(|(cat, count)| {
+                let display = self.display.get(&cat);
+                CategoryCount {
+                    category: cat,
+                    count,
+                    percentage: if total > 0 { (count as f64 / total as f64) * 100.0 } else { 0.0 },
+                    minutes: None,
+                    display_name: display.display_name,
+                    color: display.color,
+                }
             })
             .collect();
         
-        categories.sort_by(|a, b| b.count.cmp(&a.count));
+        categories.sort_by_key(|c| std::cmp::Reverse(c.count));
 
-        let theory_count = categories.iter().find(|c| c.category == "THEORY").map(|c| c.count).unwrap_or(0);
-        let practice_count = categories.iter().find(|c| c.category == "PRACTICE").map(|c| c.count).unwrap_or(1);
+        let count = |name: &str| categories.iter().find(|c| c.category == name).map(|c| c.count as f64);
+        let (theory_to_practice, ratio_status) = theory_to_practice(count("THEORY"), count("PRACTICE"));
 
         let analysis = RatioAnalysis {
+            version: RATIO_ANALYSIS_VERSION,
+            weight: RatioWeight::Count,
             categories,
             total_events: total,
-            theory_to_practice: theory_count as f64 / practice_count as f64,
+            total_minutes: None,
+            theory_to_practice,
+            ratio_status,
+        };
+
+        QueryResult {
+            query: "ratios".to_string(),
+            result_type: "analysis".to_string(),
+            data: serde_json::to_value(analysis).unwrap_or_default(),
+        }
+    }
+
+    /// Ratios from summed session minutes instead of event counts, so a
+    /// short break weighs less than a long study block
+    /// Sessions without a duration are left out, except the active one
+    /// when `elapsed_at` gives it a running time; `since` keeps sessions
+    /// starting at or after it
+    pub fn analyze_by_duration(&self, since: Option<DateTime<Utc>>, elapsed_at: Option<DateTime<Utc>>) -> QueryResult {
+        let mut projector = self.sessions();
+        if let Some(now) = elapsed_at {
+            projector = projector.with_elapsed_at(now);
+        }
+
+        let mut totals: std::collections::HashMap<String, (usize, f64)> = std::collections::HashMap::new();
+        for session in projector.get_all_sessions() {
+            let Some(minutes) = session.duration_minutes else { continue };
+            if since.is_some_and(|since| session.start_time.is_none_or(|start| start < since)) {
+                continue;
+            }
+            let (sessions, total) = totals.entry(session.category).or_default();
+            *sessions += 1;
+            *total += minutes;
+        }
+
+        let total_minutes: f64 = totals.values().map(|(_, minutes)| minutes).sum();
+        let mut categories: Vec<CategoryCount> = totals
+            .into_iter()
+            .map(|(category, (sessions, minutes))| {
+                let display = self.display.get(&category);
+                CategoryCount {
+                    category,
+                    count: sessions,
+                    percentage: if total_minutes > 0.0 { (minutes / total_minutes) * 100.0 } else { 0.0 },
+                    minutes: Some(minutes),
+                    display_name: display.display_name,
+                    color: display.color,
+                }
+            })
+            .collect();
+        categories.sort_by(|a, b| b.minutes.unwrap_or(0.0).total_cmp(&a.minutes.unwrap_or(0.0)));
+
+        let minutes = |name: &str| categories.iter().find(|c| c.category == name).and_then(|c| c.minutes);
+        let (theory_to_practice, ratio_status) = theory_to_practice(minutes("THEORY"), minutes("PRACTICE"));
+
+        let analysis = RatioAnalysis {
+            version: RATIO_ANALYSIS_VERSION,
+            weight: RatioWeight::Duration,
+            total_events: categories.iter().map(|c| c.count).sum(),
+            categories,
+            total_minutes: Some(total_minutes),
+            theory_to_practice,
+            ratio_status,
         };
 
         QueryResult {
@@ -260,4 +4266,249 @@ impl RatioAnalyzer {
             data: serde_json::to_value(analysis).unwrap_or_default(),
         }
     }
+
+    /// Theory-to-practice ratio over a window sliding by `step`, from the
+    /// window opening at the first timestamped event to the one covering
+    /// the last
+    /// Events are read and sorted once, then swept with two pointers, so
+    /// the cost doesn't grow with the number of points
+    pub fn rolling(&self, window: chrono::Duration, step: chrono::Duration) -> Vec<RollingRatio> {
+        let mut events: Vec<(DateTime<Utc>, String)> = self
+            .source
+            .events()
+            .iter()
+            .filter(|e| e.verb.counts_toward_ratios())
+            .filter_map(|e| Some((e.timestamp?, self.aliases.resolve(e.category.as_deref()?))))
+            .collect();
+        events.sort_by_key(|(ts, _)| *ts);
+
+        let (Some((first, _)), Some((last, _))) = (events.first(), events.last()) else {
+            return Vec::new();
+        };
+        let (first, last) = (*first, *last);
+
+        // Running THEORY and PRACTICE counts inside the window
+        let mut counts = [0usize; 2];
+        let slot = |category: &str| match category {
+            "THEORY" => Some(0),
+            "PRACTICE" => Some(1),
+            _ => None,
+        };
+
+        let (mut entered, mut left) = (0, 0);
+        let mut points = Vec::new();
+        let mut start = first;
+        loop {
+            let end = start + window;
+            while entered < events.len() && events[entered].0 < end {
+                if let Some(i) = slot(&events[entered].1) {
+                    counts[i] += 1;
+                }
+                entered += 1;
+            }
+            while left < entered && events[left].0 < start {
+                if let Some(i) = slot(&events[left].1) {
+                    counts[i] -= 1;
+                }
+                left += 1;
+            }
+
+            let [theory, practice] = counts;
+            points.push(RollingRatio {
+                start,
+                end,
+                events: entered - left,
+                theory,
+                practice,
+                ratio: (practice > 0).then(|| theory as f64 / practice as f64),
+            });
+            if end > last || points.len() >= MAX_ROLLING_POINTS {
+                break;
+            }
+            start += step;
+        }
+        points
+    }
+
+    /// Minute ratios against the logged `TARGET RATIO` lines
+    /// Each past target is judged on the sessions started while it was in
+    /// force; `current` compares sessions started between `since` and
+    /// `until` (or since the latest target) against the latest one
+    /// The active session counts with its minutes up to `now`
+    pub fn against_targets(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tolerance: f64,
+        now: DateTime<Utc>,
+    ) -> RatioTargetReport {
+        let targets: Vec<RatioTarget> = self
+            .source
+            .events()
+            .iter()
+            .filter(|e| e.verb == EventVerb::Target)
+            .filter_map(|e| ratio_target(e.index, &e.line, &self.aliases))
+            .collect();
+        let sessions = self
+            .sessions()
+            .with_elapsed_at(now)
+            .get_all_sessions();
+
+        let history: Vec<TargetPeriod> = targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| {
+                let end_idx = targets.get(i + 1).map(|next| next.idx);
+                let in_force = sessions.iter().filter(|s| {
+                    s.start_event_idx > target.idx && end_idx.is_none_or(|end| s.start_event_idx < end)
+                });
+                TargetPeriod {
+                    target: target.clone(),
+                    end_idx,
+                    deviation: target_deviation(target, tolerance, in_force),
+                }
+            })
+            .collect();
+
+        let target = targets.last().cloned();
+        let current = target.as_ref().map(|target| match (since, until) {
+            (None, None) => target_deviation(target, tolerance, sessions.iter().filter(|s| s.start_event_idx > target.idx)),
+            (since, until) => target_deviation(
+                target,
+                tolerance,
+                sessions.iter().filter(|s| {
+                    s.start_time.is_some_and(|start| {
+                        since.is_none_or(|since| start >= since) && until.is_none_or(|until| start < until)
+                    })
+                }),
+            ),
+        });
+
+        RatioTargetReport {
+            target,
+            tolerance,
+            current,
+            history,
+        }
+    }
+
+    /// Per-activity sessions, events and minutes within one category
+    /// With `normalize`, activities differing only in case are merged
+    /// under their lowercase name
+    pub fn activities(&self, category: &str, sort: ActivitySort, top: Option<usize>, normalize: bool) -> QueryResult {
+        let category = self.aliases.resolve(category);
+        let key = |activity: &str| if normalize { activity.to_lowercase() } else { activity.to_string() };
+        let mut stats: std::collections::HashMap<String, ActivityStats> = std::collections::HashMap::new();
+
+        for event in self.source.events().iter() {
+            let (Some(cat), Some(activity)) = (&event.category, &event.activity) else { continue };
+            if self.aliases.resolve(cat) == category {
+                stats_entry(&mut stats, key(activity)).events += 1;
+            }
+        }
+
+        let sessions = self
+            .sessions()
+            .get_all_sessions();
+        for session in sessions.iter().filter(|s| s.category == category) {
+            let row = stats_entry(&mut stats, key(&session.activity));
+            row.sessions += 1;
+            row.minutes += session.duration_minutes.unwrap_or(0.0);
+        }
+
+        let mut activities: Vec<ActivityStats> = stats.into_values().collect();
+        let total_minutes: f64 = activities.iter().map(|a| a.minutes).sum();
+        let total_sessions: usize = activities.iter().map(|a| a.sessions).sum();
+        for a in &mut activities {
+            a.percentage = if total_minutes > 0.0 {
+                a.minutes / total_minutes * 100.0
+            } else if total_sessions > 0 {
+                a.sessions as f64 / total_sessions as f64 * 100.0
+            } else {
+                0.0
+            };
+        }
+
+        activities.sort_by(|a, b| {
+            let by_column = match sort {
+                ActivitySort::Minutes => b.minutes.total_cmp(&a.minutes),
+                ActivitySort::Sessions => b.sessions.cmp(&a.sessions),
+                ActivitySort::Events => b.events.cmp(&a.events),
+                ActivitySort::Percentage => b.percentage.total_cmp(&a.percentage),
+                ActivitySort::Activity => std::cmp::Ordering::Equal,
+            };
+            by_column.then_with(|| a.activity.cmp(&b.activity))
+        });
+        if let Some(top) = top {
+            activities.truncate(top);
+        }
+
+        QueryResult {
+            query: "activities".to_string(),
+            result_type: "activities".to_string(),
+            data: serde_json::json!({
+                "category": category,
+                "activities": activities,
+            }),
+        }
+    }
+
+    /// Time allocation by category, from sessions with a known duration
+    pub fn allocation(&self) -> QueryResult {
+        self.allocation_at(None)
+    }
+
+    /// Allocation counting the active session's minutes up to
+    /// `elapsed_at`; without it the active session has no duration and
+    /// is left out
+    pub fn allocation_at(&self, elapsed_at: Option<DateTime<Utc>>) -> QueryResult {
+        let mut projector = self.sessions();
+        if let Some(now) = elapsed_at {
+            projector = projector.with_elapsed_at(now);
+        }
+        let sessions = projector.get_all_sessions();
+        let mut durations: std::collections::HashMap<String, Vec<f64>> = std::collections::HashMap::new();
+
+        for session in &sessions {
+            if let Some(duration) = session.duration_minutes {
+                durations.entry(session.category.clone()).or_default().push(duration);
+            }
+        }
+
+        let total: f64 = durations.values().flatten().sum();
+
+        let mut categories: Vec<CategoryDuration> = durations
+            .into_iter()
+            .map(|(cat, mut values)| {
+                values.sort_by(f64::total_cmp);
+                let mins: f64 = values.iter().sum();
+                let display = self.display.get(&cat);
+                CategoryDuration {
+                    category: cat,
+                    minutes: mins,
+                    percentage: if total > 0.0 { (mins / total) * 100.0 } else { 0.0 },
+                    sessions: values.len(),
+                    mean_minutes: mins / values.len() as f64,
+                    p50_minutes: percentile(&values, 50.0),
+                    p90_minutes: percentile(&values, 90.0),
+                    p95_minutes: percentile(&values, 95.0),
+                    display_name: display.display_name,
+                    color: display.color,
+                }
+            })
+            .collect();
+
+        categories.sort_by(|a, b| b.minutes.total_cmp(&a.minutes));
+
+        let allocation = TimeAllocation {
+            categories,
+            total_minutes: total,
+        };
+
+        QueryResult {
+            query: "allocation".to_string(),
+            result_type: "allocation".to_string(),
+            data: serde_json::to_value(allocation).unwrap_or_default(),
+        }
+    }
 }
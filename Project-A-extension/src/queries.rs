@@ -0,0 +1,393 @@
+use serde_json::Value;
+use crate::days::{self, DayBoundary};
+use crate::metadata::MetadataFilter;
+use crate::models::{
+    AllocationQuery, ByDayQuery, CompareQuery, ContextSwitchesQuery, DayQuery, QueryParams, QueryPlan, QueryResult,
+    RatiosQuery, RecentQuery, SessionsQuery, TimelineQuery,
+};
+use crate::projections::{RatioAnalyzer, SessionProjector};
+use crate::registry::{query_plan, strip_unset, ProjectionError, Projector, ProjectorParam, QueryLog, QuerySettings};
+
+fn session_projector<'a>(log: &'a QueryLog, settings: &QuerySettings) -> SessionProjector<'a> {
+    SessionProjector::from_events(&log.events)
+        .with_aliases(&settings.aliases)
+        .with_days(settings.days)
+}
+
+fn ratio_analyzer<'a>(log: &'a QueryLog, settings: &QuerySettings) -> RatioAnalyzer<'a> {
+    RatioAnalyzer::from_events(&log.events)
+        .with_aliases(&settings.aliases)
+        .with_display(&settings.display)
+}
+
+/// Per-query day boundary overrides, falling back to the server's
+fn days(settings: &QuerySettings, tz: Option<&str>, start_hour: Option<u32>) -> Result<DayBoundary, ProjectionError> {
+    let zone = match tz {
+        Some(tz) => tz.parse().map_err(ProjectionError::Invalid)?,
+        None => settings.days.zone,
+    };
+    DayBoundary::new(zone, start_hour.unwrap_or(settings.days.start_hour)).map_err(ProjectionError::Invalid)
+}
+
+/// `params` as a built-in query's typed, checked params
+fn typed<T: QueryParams>(params: &Value) -> Result<T, ProjectionError> {
+    let params = match params {
+        Value::Object(_) => params.clone(),
+        Value::Null => Value::Object(Default::default()),
+        _ => return Err(ProjectionError::Invalid("params must be an object".to_string())),
+    };
+    let params: T = serde_json::from_value(params).map_err(|e| ProjectionError::Invalid(e.to_string()))?;
+    params.validate().map_err(ProjectionError::Invalid)?;
+    Ok(params)
+}
+
+/// The default plan, reporting params with their defaults filled in
+fn typed_plan<T: QueryParams>(projector: &dyn Projector, log: &QueryLog, params: &Value) -> QueryPlan {
+    let filters = match typed::<T>(params) {
+        Ok(params) => serde_json::to_value(&params).unwrap_or_default(),
+        Err(_) => params.clone(),
+    };
+    query_plan(projector.name(), projector.projector(), log, strip_unset(filters))
+}
+
+fn tz_param() -> ProjectorParam {
+    ProjectorParam::new("tz", "string", "Timezone, overrides the server default")
+}
+
+fn day_start_hour_param() -> ProjectorParam {
+    ProjectorParam::new("day_start_hour", "integer", "Hour local days start at")
+}
+
+/// Share of events per category
+pub struct Ratios;
+
+impl Projector for Ratios {
+    fn name(&self) -> &str {
+        "ratios"
+    }
+
+    fn projector(&self) -> &str {
+        "RatioAnalyzer"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![ProjectorParam::new("params", "object", "Reserved, must be empty")]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<RatiosQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        typed::<RatiosQuery>(params)?;
+        Ok(ratio_analyzer(log, settings).analyze())
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<RatiosQuery>(self, log, params)
+    }
+}
+
+/// Every session, in log order unless sorted
+pub struct Timeline;
+
+impl Projector for Timeline {
+    fn name(&self) -> &str {
+        "timeline"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![
+            ProjectorParam::new("sort", "string", "log, start or duration"),
+            ProjectorParam::new("order", "string", "asc or desc"),
+            ProjectorParam::new("elapsed", "boolean", "Give the active session its elapsed-so-far duration"),
+        ]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<TimelineQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: TimelineQuery = typed(params)?;
+        let projector = session_projector(log, settings);
+        let projector = if query.elapsed { projector.with_clock(&settings.clock) } else { projector };
+        Ok(projector.get_timeline(query.sort, query.order))
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<TimelineQuery>(self, log, params)
+    }
+}
+
+/// Where the time went, per category
+pub struct Allocation;
+
+impl Projector for Allocation {
+    fn name(&self) -> &str {
+        "allocation"
+    }
+
+    fn projector(&self) -> &str {
+        "RatioAnalyzer"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        Vec::new()
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<AllocationQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        typed::<AllocationQuery>(params)?;
+        Ok(ratio_analyzer(log, settings).allocation())
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<AllocationQuery>(self, log, params)
+    }
+}
+
+/// Every non-empty log line as logged, comments and lines that don't
+/// parse included, oldest first
+pub struct Recent;
+
+impl Projector for Recent {
+    fn name(&self) -> &str {
+        "recent"
+    }
+
+    fn projector(&self) -> &str {
+        "read_log"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![ProjectorParam::new("limit", "integer", "Last n events only")]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<RecentQuery>(params).map(|_| ())
+    }
+
+    /// With a limit only the end of the log is read
+    fn tail(&self, params: &Value) -> Option<usize> {
+        typed::<RecentQuery>(params).ok()?.limit
+    }
+
+    fn project(&self, log: &QueryLog, _settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: RecentQuery = typed(params)?;
+        let skip = query.limit.map_or(0, |n| log.lines.len().saturating_sub(n));
+        Ok(QueryResult {
+            query: "recent".to_string(),
+            result_type: "recent".to_string(),
+            data: serde_json::json!({ "events": log.lines[skip..] }),
+        })
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        let mut plan = typed_plan::<RecentQuery>(self, log, params);
+        if log.tail {
+            plan.projector = "tail".to_string();
+        }
+        plan
+    }
+}
+
+/// Sessions too short to count as focus
+pub struct ContextSwitches;
+
+impl Projector for ContextSwitches {
+    fn name(&self) -> &str {
+        "context_switches"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![
+            ProjectorParam::new("threshold_minutes", "number", "Sessions shorter than this count as a switch"),
+            tz_param(),
+        ]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<ContextSwitchesQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: ContextSwitchesQuery = typed(params)?;
+        let days = days(settings, query.tz.as_deref(), None)?;
+        Ok(session_projector(log, settings)
+            .with_days(days)
+            .context_switches(query.threshold_minutes.unwrap_or(crate::DEFAULT_SWITCH_THRESHOLD_MINUTES)))
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        let mut plan = typed_plan::<ContextSwitchesQuery>(self, log, params);
+        if let Some(fields) = plan.filters.as_object_mut() {
+            fields
+                .entry("threshold_minutes")
+                .or_insert(crate::DEFAULT_SWITCH_THRESHOLD_MINUTES.into());
+        }
+        plan
+    }
+}
+
+/// One row per local day
+pub struct ByDay;
+
+impl Projector for ByDay {
+    fn name(&self) -> &str {
+        "by_day"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![
+            ProjectorParam::new("metric", "string", "sessions, events or minutes"),
+            ProjectorParam::new("from", "string", "First day, YYYY-MM-DD"),
+            ProjectorParam::new("to", "string", "Last day, YYYY-MM-DD"),
+            ProjectorParam::new("window", "string", "Days up to today like 7d, instead of from and to"),
+            ProjectorParam::new("category", "string", "Only this category"),
+            tz_param(),
+            day_start_hour_param(),
+        ]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<ByDayQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: ByDayQuery = typed(params)?;
+        let days = days(settings, query.tz.as_deref(), query.day_start_hour)?;
+        let (from, to) = match query.window {
+            Some(window) => {
+                let window = days::parse_window(&window).map_err(ProjectionError::Invalid)?;
+                let today = days.day_of(settings.clock.now());
+                let earlier = chrono::Duration::days((window.num_seconds() - 1).div_euclid(86_400));
+                (today.checked_sub_signed(earlier), Some(today))
+            }
+            None => days::parse_date_range(query.from.as_deref(), query.to.as_deref()).map_err(ProjectionError::Invalid)?,
+        };
+        session_projector(log, settings)
+            .with_days(days)
+            .by_day(query.metric, query.category.as_deref(), from, to)
+            .map_err(ProjectionError::Invalid)
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<ByDayQuery>(self, log, params)
+    }
+}
+
+/// Sessions whose metadata matches `where`
+pub struct Sessions;
+
+impl Projector for Sessions {
+    fn name(&self) -> &str {
+        "sessions"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![ProjectorParam::new("where", "object", "Metadata conditions")]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<SessionsQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: SessionsQuery = typed(params)?;
+        let filter = MetadataFilter::from_json(&query.filter).map_err(ProjectionError::Invalid)?;
+        Ok(session_projector(log, settings).filtered_sessions(&filter))
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<SessionsQuery>(self, log, params)
+    }
+}
+
+/// Sessions started on one local day
+pub struct Day;
+
+impl Projector for Day {
+    fn name(&self) -> &str {
+        "day"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![ProjectorParam::new("date", "string", "The day, YYYY-MM-DD")]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<DayQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: DayQuery = typed(params)?;
+        let date = days::parse_date(&query.date).map_err(ProjectionError::Invalid)?;
+        Ok(session_projector(log, settings).sessions_on(date))
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<DayQuery>(self, log, params)
+    }
+}
+
+/// Two date ranges side by side
+pub struct Compare;
+
+impl Projector for Compare {
+    fn name(&self) -> &str {
+        "compare"
+    }
+
+    fn projector(&self) -> &str {
+        "SessionProjector"
+    }
+
+    fn params(&self) -> Vec<ProjectorParam> {
+        vec![
+            ProjectorParam::new("a", "object", "First range, {from, to} as YYYY-MM-DD"),
+            ProjectorParam::new("b", "object", "Range compared against it"),
+            tz_param(),
+            day_start_hour_param(),
+        ]
+    }
+
+    fn validate(&self, params: &Value) -> Result<(), ProjectionError> {
+        typed::<CompareQuery>(params).map(|_| ())
+    }
+
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError> {
+        let query: CompareQuery = typed(params)?;
+        let days = days(settings, query.tz.as_deref(), query.day_start_hour)?;
+        let a = query.a.dates().map_err(ProjectionError::Invalid)?;
+        let b = query.b.dates().map_err(ProjectionError::Invalid)?;
+        Ok(session_projector(log, settings).with_days(days).compare(a, b))
+    }
+
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        typed_plan::<CompareQuery>(self, log, params)
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use crate::error::AppError;
+use crate::AppState;
+
+/// How often clients whose buckets have refilled are forgotten
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket per client over the appending routes: `burst` appends
+/// at once, refilled at `per_minute`
+/// A bucket that has refilled is the same as no bucket, so those are
+/// pruned and long-gone clients cost nothing
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// None: unlimited
+    limit: Option<Limit>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    limited: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    per_second: f64,
+    burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Counts for GET /metrics
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimitStats {
+    /// Requests answered 429 since startup
+    pub limited: u64,
+    /// Clients with a bucket still refilling
+    pub clients: usize,
+}
+
+impl RateLimiter {
+    /// Unlimited
+    pub fn disabled() -> Self {
+        Self::with_limit(None)
+    }
+
+    /// `per_minute` of 0 disables the limit; a `burst` of 0 is taken as 1
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self::with_limit((per_minute > 0).then(|| Limit {
+            per_second: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+        }))
+    }
+
+    fn with_limit(limit: Option<Limit>) -> Self {
+        Self {
+            limit,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            limited: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Take one token from `client`'s bucket, or say how long until
+    /// there is one
+    pub fn check(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.limit else { return Ok(()) };
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets
+            .entry(client.to_string())
+            .or_insert(Bucket { tokens: limit.burst, updated: now });
+        bucket.tokens = limit.refill(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        self.limited.fetch_add(1, Ordering::Relaxed);
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second))
+    }
+
+    /// `check` now, as the error an append answers with
+    pub fn check_append(&self, client: &str) -> Result<(), AppError> {
+        self.check(client, Instant::now())
+            .map_err(|wait| AppError::RateLimited { retry_after_secs: wait.as_secs_f64().ceil() as u64 })
+    }
+
+    /// Forget every client whose bucket is full again
+    pub fn prune(&self, now: Instant) {
+        let Some(limit) = self.limit else { return };
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|_, bucket| limit.refill(bucket, now) < limit.burst);
+    }
+
+    /// Prune every PRUNE_INTERVAL; does nothing when disabled
+    pub fn spawn_pruner(&self) -> Option<tokio::task::JoinHandle<()>> {
+        if !self.enabled() {
+            return None;
+        }
+        let limiter = self.clone();
+        Some(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                ticks.tick().await;
+                limiter.prune(Instant::now());
+            }
+        }))
+    }
+
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            limited: self.limited.load(Ordering::Relaxed),
+            clients: self.buckets.lock().unwrap_or_else(|e| e.into_inner()).len(),
+        }
+    }
+}
+
+impl Limit {
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+}
+
+/// Whether a request appends to the log: only those are limited
+pub fn limited_route(method: &Method, path: &str) -> bool {
+    *method == Method::POST && matches!(path, "/events" | "/sessions/close")
+}
+
+/// Who a request counts against: its bearer token when tokens are
+/// configured (auth has already checked it), otherwise its address
+pub fn client(state: &AppState, headers: &HeaderMap, addr: Option<SocketAddr>) -> String {
+    match crate::auth::bearer_token(headers) {
+        Some(token) if !state.tokens.is_empty() => format!("token:{}", token),
+        _ => match addr {
+            Some(addr) => format!("ip:{}", addr.ip()),
+            None => "ip:unknown".to_string(),
+        },
+    }
+}
+
+/// Answer 429 with `Retry-After` once a client's bucket is empty
+pub async fn limit_appends(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.rate_limiter.enabled() || !limited_route(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    let addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+    match state.rate_limiter.check_append(&client(&state, request.headers(), addr)) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_refills_at_the_rate() {
+        // 1 a second, 3 at once
+        let limiter = RateLimiter::new(60, 3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.check("a", start), Ok(()));
+        }
+        assert_eq!(limiter.check("a", start), Err(Duration::from_secs(1)));
+        // Another client has its own bucket
+        assert_eq!(limiter.check("b", start), Ok(()));
+
+        let later = start + Duration::from_millis(1500);
+        assert_eq!(limiter.check("a", later), Ok(()));
+        let wait = limiter.check("a", later).unwrap_err();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500), "{:?}", wait);
+        assert_eq!(limiter.stats(), RateLimitStats { limited: 2, clients: 2 });
+
+        assert!(RateLimiter::new(0, 3).check("a", start).is_ok());
+        assert!(!RateLimiter::disabled().enabled());
+    }
+
+    #[test]
+    fn test_prune_forgets_refilled_clients() {
+        let limiter = RateLimiter::new(60, 3);
+        let start = Instant::now();
+        limiter.check("gone", start).unwrap();
+        for _ in 0..3 {
+            limiter.check("busy", start + Duration::from_secs(2)).unwrap();
+        }
+
+        limiter.prune(start + Duration::from_millis(500));
+        assert_eq!(limiter.stats().clients, 2);
+        // "gone" refilled a second after its request, "busy" hasn't yet
+        limiter.prune(start + Duration::from_secs(3));
+        assert_eq!(limiter.stats().clients, 1);
+        limiter.prune(start + Duration::from_secs(60));
+        assert_eq!(limiter.stats().clients, 0);
+    }
+
+    #[test]
+    fn test_only_appends_are_limited() {
+        assert!(limited_route(&Method::POST, "/events"));
+        assert!(limited_route(&Method::POST, "/sessions/close"));
+        assert!(!limited_route(&Method::GET, "/events"));
+        assert!(!limited_route(&Method::POST, "/query"));
+    }
+}
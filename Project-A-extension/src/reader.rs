@@ -0,0 +1,260 @@
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Non-empty log lines, read once and shared between projections until
+/// the file changes
+/// Every call checks the file's length and mtime, so external appends are
+/// picked up without the watcher; our own appends also `invalidate`
+/// Streams (stdin, a named pipe, any `BufRead`) can only be consumed once,
+/// so they are read to the end on first use and never re-read
+#[derive(Clone)]
+pub struct EventReader {
+    source: Source,
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
+    /// Full reads of the file so far
+    reads: Arc<AtomicUsize>,
+}
+
+#[derive(Clone)]
+enum Source {
+    /// Read from `fallback` (a synced copy) while `path` can't be read
+    File { path: PathBuf, fallback: Option<PathBuf> },
+    Stream(Arc<Vec<String>>),
+}
+
+struct Snapshot {
+    /// Which file the lines came from
+    path: PathBuf,
+    /// A named pipe, which can't be read again
+    stream: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+    lines: Arc<Vec<String>>,
+}
+
+impl EventReader {
+    pub fn new(path: &Path) -> Self {
+        Self::with_source(Source::File { path: path.to_path_buf(), fallback: None })
+    }
+
+    /// Read `fallback` instead whenever `path` is missing or unreadable
+    pub fn with_fallback(self, fallback: Option<&Path>) -> Self {
+        let source = match self.source {
+            Source::File { path, .. } => Source::File { path, fallback: fallback.map(Path::to_path_buf) },
+            stream => stream,
+        };
+        Self { source, ..self }
+    }
+
+    /// Lines read to the end of `source` now, e.g. `stdin().lock()`
+    pub fn from_buf_read(source: impl BufRead) -> Self {
+        let reader = Self::with_source(Source::Stream(Arc::new(read_lines(source))));
+        reader.reads.fetch_add(1, Ordering::Relaxed);
+        reader
+    }
+
+    fn with_source(source: Source) -> Self {
+        Self {
+            source,
+            snapshot: Arc::new(RwLock::new(None)),
+            reads: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The log's non-empty lines, from memory unless the file changed
+    /// If neither the log nor its fallback can be read, the log's error
+    pub fn lines(&self) -> std::io::Result<Arc<Vec<String>>> {
+        let (path, fallback) = match &self.source {
+            Source::File { path, fallback } => (path, fallback),
+            Source::Stream(lines) => return Ok(lines.clone()),
+        };
+        let e = match self.read_file(path) {
+            Ok((lines, _)) => return Ok(lines),
+            Err(e) => e,
+        };
+        let Some(fallback) = fallback else { return Err(e) };
+        let (lines, fresh) = self.read_file(fallback).map_err(|_| e)?;
+        if fresh {
+            tracing::warn!(path = %path.display(), fallback = %fallback.display(), "Log unreadable, reading its fallback");
+        }
+        Ok(lines)
+    }
+
+    /// `path`'s lines, and whether they were just read rather than cached
+    fn read_file(&self, path: &Path) -> std::io::Result<(Arc<Vec<String>>, bool)> {
+        let metadata = std::fs::metadata(path)?;
+        // A named pipe has no length or mtime to compare; the first read
+        // drains it and stands
+        let stream = !metadata.is_file();
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let current = |snapshot: &Option<Snapshot>| {
+            snapshot
+                .as_ref()
+                .filter(|s| s.path == path && (stream || (s.len == len && s.modified == modified)))
+                .map(|s| s.lines.clone())
+        };
+
+        if let Some(lines) = current(&self.snapshot.read().unwrap()) {
+            return Ok((lines, false));
+        }
+        let mut snapshot = self.snapshot.write().unwrap();
+        // Another request may have refreshed it while we waited
+        if let Some(lines) = current(&snapshot) {
+            return Ok((lines, false));
+        }
+
+        let file = std::fs::File::open(path)?;
+        let lines = read_lines(std::io::BufReader::new(file));
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(lines_scanned = lines.len(), bytes = len, "Read log");
+
+        // Stat from before the read: a write racing with it only forces
+        // one more read next time
+        let lines = Arc::new(lines);
+        *snapshot = Some(Snapshot { path: path.to_path_buf(), stream, len, modified, lines: lines.clone() });
+        Ok((lines, true))
+    }
+
+    /// Forget the cached lines; the next call reads the file
+    /// No-op for streams, which can't be read again
+    pub fn invalidate(&self) {
+        let mut snapshot = self.snapshot.write().unwrap();
+        if snapshot.as_ref().is_some_and(|s| !s.stream) {
+            *snapshot = None;
+        }
+    }
+
+    /// How many times the file has actually been read
+    #[cfg(test)]
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+/// Non-empty lines up to the end of `source` or a read error
+pub fn read_lines(source: impl BufRead) -> Vec<String> {
+    lossy_lines(source).filter(|line| !line.trim().is_empty()).collect()
+}
+
+/// Lines of `source`, invalid UTF-8 replaced with U+FFFD so one bad byte
+/// doesn't hide the rest of the log; stops at a read error
+pub fn lossy_lines(source: impl BufRead) -> impl Iterator<Item = String> {
+    source.split(b'\n').map_while(Result::ok).map(|bytes| {
+        let line = String::from_utf8_lossy(&bytes);
+        line.trim_end_matches('\r').to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_second_read_comes_from_memory() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        {
+            let mut out = std::io::BufWriter::new(temp_file.as_file_mut());
+            for i in 0..100_000 {
+                writeln!(out, "2024-01-01T09:00:00Z START THEORY topic{}", i).unwrap();
+            }
+        }
+        let reader = EventReader::new(temp_file.path());
+        let projector = crate::projections::SessionProjector::from_reader(&reader);
+
+        assert_eq!(projector.get_all_sessions().len(), 100_000);
+        let analysis = crate::projections::RatioAnalyzer::from_reader(&reader).analyze();
+
+        // The ratio analyzer got the lines the session projector loaded
+        assert_eq!(analysis.data["total_events"], 100_000);
+        assert_eq!(reader.reads(), 1);
+        assert!(Arc::ptr_eq(&reader.lines().unwrap(), &reader.lines().unwrap()));
+
+        // An external append changes the length and is picked up
+        writeln!(temp_file, "START PRACTICE rust").unwrap();
+        assert_eq!(reader.lines().unwrap().len(), 100_001);
+        assert_eq!(reader.reads(), 2);
+
+        reader.invalidate();
+        reader.lines().unwrap();
+        assert_eq!(reader.reads(), 3);
+    }
+
+    #[test]
+    fn test_projects_from_an_in_memory_stream() {
+        let log = "2024-01-01T09:00:00Z START THEORY pandas\n\n2024-01-01T10:00:00Z START PRACTICE rust\n";
+        let reader = EventReader::from_buf_read(std::io::Cursor::new(log));
+        let projector = crate::projections::SessionProjector::from_reader(&reader);
+
+        let sessions = projector.get_all_sessions();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].duration_minutes, Some(60.0));
+        let analyzer = crate::projections::RatioAnalyzer::from_reader(&EventReader::from_buf_read(log.as_bytes()));
+        assert_eq!(analyzer.analyze().data["total_events"], 2);
+
+        // Nothing to go back to
+        reader.invalidate();
+        assert_eq!(reader.lines().unwrap().len(), 2);
+        assert_eq!(reader.reads(), 1);
+    }
+
+    #[test]
+    fn test_invalid_utf8_doesnt_hide_later_lines() {
+        let log = b"START THEORY pandas\r\nNOTE bad \xff bytes\n\nSTART PRACTICE rust";
+        assert_eq!(read_lines(&log[..]), ["START THEORY pandas", "NOTE bad \u{FFFD} bytes", "START PRACTICE rust"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_named_pipe_is_drained_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("master.log");
+        assert!(std::process::Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+        let writer = {
+            let fifo = fifo.clone();
+            std::thread::spawn(move || {
+                let mut pipe = std::fs::OpenOptions::new().write(true).open(fifo).unwrap();
+                writeln!(pipe, "START THEORY pandas").unwrap();
+                writeln!(pipe, "START GAME chess").unwrap();
+            })
+        };
+
+        let reader = EventReader::new(&fifo);
+        assert_eq!(reader.lines().unwrap().len(), 2);
+        writer.join().unwrap();
+        // No writer left; a second open would block
+        reader.invalidate();
+        assert_eq!(reader.lines().unwrap().len(), 2);
+        assert_eq!(reader.reads(), 1);
+    }
+
+    #[test]
+    fn test_missing_log_is_an_error() {
+        let reader = EventReader::new(Path::new("/nonexistent/master.log"));
+        assert_eq!(reader.lines().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        // Even with a fallback, if that's missing too
+        let reader = reader.with_fallback(Some(Path::new("/nonexistent/backup.log")));
+        assert_eq!(reader.lines().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_reads_fall_back_while_the_log_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (primary, backup) = (dir.path().join("master.log"), dir.path().join("backup.log"));
+        std::fs::write(&backup, "START THEORY pandas\n").unwrap();
+        let reader = EventReader::new(&primary).with_fallback(Some(&backup));
+
+        assert_eq!(*reader.lines().unwrap(), ["START THEORY pandas"]);
+        assert_eq!(reader.lines().unwrap().len(), 1);
+        assert_eq!(reader.reads(), 1);
+
+        // Back to the log as soon as it's there again
+        std::fs::write(&primary, "START THEORY pandas\nSTART PRACTICE rust\n").unwrap();
+        assert_eq!(reader.lines().unwrap().len(), 2);
+        assert_eq!(reader.reads(), 2);
+    }
+}
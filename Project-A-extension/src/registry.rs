@@ -0,0 +1,282 @@
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use crate::aliases::CategoryAliases;
+use crate::clock::SharedClock;
+use crate::days::DayBoundary;
+use crate::display::CategoryDisplayConfig;
+use crate::events::ParsedEvent;
+use crate::models::{QueryPlan, QueryResult};
+use crate::queries;
+
+/// Why a projector couldn't answer
+#[derive(Debug)]
+pub enum ProjectionError {
+    /// Bad params: an `invalid_input` 400 naming the problem
+    Invalid(String),
+}
+
+/// The server's current settings, as every other route sees them
+#[derive(Clone)]
+pub struct QuerySettings {
+    pub aliases: CategoryAliases,
+    pub display: CategoryDisplayConfig,
+    /// Unless a query brings its own `tz` or `day_start_hour`
+    pub days: DayBoundary,
+    /// "Now" for elapsed times and relative windows
+    pub clock: SharedClock,
+}
+
+/// The part of the log a query is answered from
+pub struct QueryLog {
+    /// Non-empty lines as logged, the last ones only when `tail`
+    pub lines: Arc<Vec<String>>,
+    /// The lines that parse, numbered by their position in the whole log
+    pub events: Vec<ParsedEvent>,
+    /// Read from the end of the log, see `Projector::tail`
+    pub tail: bool,
+}
+
+impl QueryLog {
+    /// `lines` starting at log position `first_index`
+    pub fn new(lines: Arc<Vec<String>>, first_index: usize, tail: bool) -> Self {
+        let mut events = crate::events::parse_log(&lines);
+        for event in &mut events {
+            event.index += first_index;
+        }
+        Self { lines, events, tail }
+    }
+}
+
+/// One param a projector accepts next to `type`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct ProjectorParam {
+    pub name: String,
+    /// JSON type, e.g. `string` or `number`
+    pub kind: String,
+    pub description: String,
+}
+
+impl ProjectorParam {
+    pub fn new(name: &str, kind: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            description: description.to_string(),
+        }
+    }
+}
+
+/// What GET /projections lists for each registered projector
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProjectorInfo {
+    /// Query `type` it answers
+    pub name: String,
+    pub projector: String,
+    pub params: Vec<ProjectorParam>,
+}
+
+/// A named query over the log that `/query` dispatches to by `type`
+/// Implement it and `register` an instance to add a query type without
+/// touching the handlers
+pub trait Projector: Send + Sync {
+    /// The query `type` it answers
+    fn name(&self) -> &str;
+
+    /// What does the work, as reported by `explain`
+    fn projector(&self) -> &str;
+
+    fn params(&self) -> Vec<ProjectorParam>;
+
+    /// Reject bad params before the log is read
+    fn validate(&self, _params: &Value) -> Result<(), ProjectionError> {
+        Ok(())
+    }
+
+    /// When the last `n` lines are enough, only those are read
+    fn tail(&self, _params: &Value) -> Option<usize> {
+        None
+    }
+
+    /// `params` is the query body without `type` and `explain`
+    fn project(&self, log: &QueryLog, settings: &QuerySettings, params: &Value) -> Result<QueryResult, ProjectionError>;
+
+    /// How the query is answered, minus the timing
+    /// By default the params are reported as given
+    fn plan(&self, log: &QueryLog, params: &Value) -> QueryPlan {
+        query_plan(self.name(), self.projector(), log, strip_unset(params.clone()))
+    }
+}
+
+/// Projectors `/query` can dispatch to, in registration order
+#[derive(Clone, Default)]
+pub struct ProjectorRegistry {
+    projectors: Vec<Arc<dyn Projector>>,
+}
+
+impl ProjectorRegistry {
+    /// Every built-in query type
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register(queries::Ratios);
+        registry.register(queries::Timeline);
+        registry.register(queries::Allocation);
+        registry.register(queries::Recent);
+        registry.register(queries::ContextSwitches);
+        registry.register(queries::ByDay);
+        registry.register(queries::Sessions);
+        registry.register(queries::Day);
+        registry.register(queries::Compare);
+        registry
+    }
+
+    /// Add a projector, replacing any registered under the same name
+    pub fn register(&mut self, projector: impl Projector + 'static) {
+        self.projectors.retain(|p| p.name() != projector.name());
+        self.projectors.push(Arc::new(projector));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Projector> {
+        self.projectors.iter().find(|p| p.name() == name).map(|p| p.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.projectors.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn describe(&self) -> Vec<ProjectorInfo> {
+        self.projectors
+            .iter()
+            .map(|p| ProjectorInfo {
+                name: p.name().to_string(),
+                projector: p.projector().to_string(),
+                params: p.params(),
+            })
+            .collect()
+    }
+}
+
+/// A plan over every line read; the ones that aren't events are
+/// reported as unparseable
+pub fn query_plan(query_type: &str, projector: &str, log: &QueryLog, filters: Value) -> QueryPlan {
+    QueryPlan {
+        query_type: query_type.to_string(),
+        projector: projector.to_string(),
+        lines_scanned: log.lines.len(),
+        lines_unparseable: log.lines.len() - log.events.len(),
+        filters,
+        elapsed_ms: 0.0,
+    }
+}
+
+/// Drop `type`, nulls and empty objects from reported params
+pub fn strip_unset(mut params: Value) -> Value {
+    if let Some(fields) = params.as_object_mut() {
+        fields.remove("type");
+        fields.retain(|_, v| !v.is_null() && v.as_object().is_none_or(|o| !o.is_empty()));
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EventCount;
+
+    impl Projector for EventCount {
+        fn name(&self) -> &str {
+            "event_count"
+        }
+
+        fn projector(&self) -> &str {
+            "EventCount"
+        }
+
+        fn params(&self) -> Vec<ProjectorParam> {
+            Vec::new()
+        }
+
+        fn project(&self, log: &QueryLog, _settings: &QuerySettings, _params: &Value) -> Result<QueryResult, ProjectionError> {
+            Ok(QueryResult {
+                query: "event_count".to_string(),
+                result_type: "count".to_string(),
+                data: serde_json::json!({ "count": log.events.len() }),
+            })
+        }
+    }
+
+    #[test]
+    fn test_register_replaces_by_name() {
+        let mut registry = ProjectorRegistry::builtin();
+        let types = ["ratios", "timeline", "allocation", "recent", "context_switches", "by_day", "sessions", "day", "compare"];
+        assert_eq!(registry.names(), types);
+
+        registry.register(EventCount);
+        assert_eq!(registry.get("event_count").unwrap().projector(), "EventCount");
+        registry.register(EventCount);
+        assert_eq!(registry.names().len(), types.len() + 1);
+        assert!(registry.get("nope").is_none());
+    }
+
+    fn lines(lines: &[&str]) -> Arc<Vec<String>> {
+        Arc::new(lines.iter().map(|l| l.to_string()).collect())
+    }
+
+    #[test]
+    fn test_plan_counts_every_line_read() {
+        let log = QueryLog::new(
+            lines(&["# header", "START THEORY pandas", "oops", "STOP THEORY pandas", "# footer"]),
+            0,
+            false,
+        );
+        let plan = EventCount.plan(&log, &serde_json::json!({ "unused": null }));
+        assert_eq!((plan.lines_scanned, plan.lines_unparseable), (5, 3));
+        assert_eq!(plan.filters, serde_json::json!({}));
+
+        let settings = QuerySettings {
+            aliases: Default::default(),
+            display: Default::default(),
+            days: Default::default(),
+            clock: Arc::new(crate::clock::SystemClock),
+        };
+        let result = EventCount.project(&log, &settings, &Value::Null).unwrap();
+        assert_eq!(result.data["count"], 2);
+
+        let garbage = QueryLog::new(lines(&["oops", "# note"]), 0, false);
+        let plan = EventCount.plan(&garbage, &Value::Null);
+        assert_eq!((plan.lines_scanned, plan.lines_unparseable), (2, 2));
+    }
+
+    #[test]
+    fn test_tail_events_keep_their_log_positions() {
+        let log = QueryLog::new(lines(&["oops", "STOP THEORY pandas"]), 7, true);
+        assert_eq!(log.events.iter().map(|e| e.index).collect::<Vec<_>>(), [8]);
+    }
+
+    #[test]
+    fn test_builtin_params_match_query_schema() {
+        // Every documented param is a field of the typed query, and vice versa
+        let spec = crate::openapi::spec();
+        let schemas = &spec["components"]["schemas"];
+        let variants = schemas["QueryInput"]["oneOf"].as_array().unwrap();
+        for info in ProjectorRegistry::builtin().describe() {
+            let variant = variants
+                .iter()
+                .find(|v| v["allOf"][1]["properties"]["type"]["enum"][0] == info.name.as_str())
+                .unwrap();
+            let reference = variant["allOf"][0]["$ref"].as_str().unwrap();
+            let schema = &schemas[reference.trim_start_matches("#/components/schemas/")];
+            let mut fields: Vec<&str> = schema["properties"]
+                .as_object()
+                .map(|properties| properties.keys().map(String::as_str).collect())
+                .unwrap_or_default();
+            let mut params: Vec<&str> = info.params.iter().map(|p| p.name.as_str()).collect();
+            fields.sort();
+            params.sort();
+            assert_eq!(params, fields, "{}", info.name);
+        }
+        assert_eq!(variants.len(), ProjectorRegistry::builtin().names().len());
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::days::{DayBoundary, Period};
+use crate::models::TopBy;
+use crate::projections::{
+    ActivityTotal, CategoryRollup, LoggedNote, RatioAnalyzer, RatioTarget, RollupDelta, SessionProjector, Streaks,
+    TargetDeviation, DEFAULT_TARGET_TOLERANCE,
+};
+
+/// Content type of the rendered report
+pub const MARKDOWN_CONTENT_TYPE: &str = "text/markdown; charset=utf-8";
+
+/// Activities listed under "Top activities"
+pub const REPORT_TOP_N: usize = 5;
+
+/// Notes listed before the rest are only counted
+pub const REPORT_NOTES: usize = 10;
+
+/// One week's review, every figure taken from an existing projection:
+/// totals and categories are the `/projections/weekly` row, top
+/// activities `/projections/top`, streaks `/projections/streaks` and the
+/// target `/projections/ratios/target`, the last two ranged to the week
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WeeklyReport {
+    /// `2024-W18`
+    pub week: String,
+    pub start: String,
+    /// Last day of the week
+    pub end: String,
+    /// The week isn't over yet
+    pub partial: bool,
+    pub sessions: usize,
+    pub minutes: f64,
+    pub theory_to_practice: Option<f64>,
+    /// Against the week before, null for the first logged week
+    pub delta: Option<RollupDelta>,
+    pub categories: BTreeMap<String, CategoryRollup>,
+    /// By minutes, sessions started in the week
+    pub top_activities: Vec<ActivityTotal>,
+    /// As of today, not the week's end
+    pub streaks: Streaks,
+    /// The first notes of the week
+    pub notes: Vec<LoggedNote>,
+    /// Notes in the week past the ones listed
+    pub notes_omitted: usize,
+    /// Most recent target, null when none has been logged
+    pub target: Option<RatioTarget>,
+    /// The week's sessions against `target`
+    pub target_deviation: Option<TargetDeviation>,
+}
+
+impl WeeklyReport {
+    /// The week of `period` starting on `start`
+    pub fn build(
+        projector: &SessionProjector,
+        analyzer: &RatioAnalyzer,
+        period: Period,
+        start: NaiveDate,
+        days: &DayBoundary,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let today = days.day_of(now);
+        let next = period.next(start);
+        let end = next.pred_opt().unwrap_or(start);
+        let (since, until) = days.bounds(Some(start), Some(end));
+        let (since, until) = (since.unwrap_or(now), until.unwrap_or(now));
+
+        let row = projector.period_rollups(period, today).into_iter().find(|row| row.start == start.to_string());
+        let mut notes = projector.notes(since, until);
+        let notes_omitted = notes.len().saturating_sub(REPORT_NOTES);
+        notes.truncate(REPORT_NOTES);
+        let targets = analyzer.against_targets(Some(since), Some(until), DEFAULT_TARGET_TOLERANCE, now);
+
+        Self {
+            week: period.label(start),
+            start: start.to_string(),
+            end: end.to_string(),
+            partial: row.as_ref().map(|row| row.partial).unwrap_or(start <= today && today < next),
+            sessions: row.as_ref().map(|row| row.sessions).unwrap_or(0),
+            minutes: row.as_ref().map(|row| row.minutes).unwrap_or(0.0),
+            theory_to_practice: row.as_ref().and_then(|row| row.theory_to_practice),
+            categories: row.as_ref().map(|row| row.categories.clone()).unwrap_or_default(),
+            delta: row.and_then(|row| row.delta),
+            top_activities: projector.top_activities(Some(since), Some(until), None, TopBy::Minutes, REPORT_TOP_N),
+            streaks: projector.streaks(None, None, None, today),
+            notes,
+            notes_omitted,
+            target: targets.target,
+            target_deviation: targets.current,
+        }
+    }
+}
+
+/// The report as Markdown, a view of exactly the JSON fields
+pub fn markdown(report: &WeeklyReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Weekly review {} ({} to {})", report.week, report.start, report.end);
+    if report.partial {
+        let _ = writeln!(out, "\n_Week in progress._");
+    }
+
+    let _ = write!(out, "\n**{} sessions, {} minutes**", report.sessions, number(report.minutes));
+    if let Some(ratio) = report.theory_to_practice {
+        let _ = write!(out, ", theory:practice {}", number(ratio));
+    }
+    if let Some(delta) = &report.delta {
+        let _ = write!(out, " ({:+} sessions, {} minutes on the week before)", delta.sessions, signed(delta.minutes));
+    }
+    out.push('\n');
+
+    let _ = writeln!(out, "\n## Categories\n");
+    if report.categories.is_empty() {
+        let _ = writeln!(out, "No sessions.");
+    } else {
+        let _ = writeln!(out, "| Category | Sessions | Minutes |\n| --- | ---: | ---: |");
+        for (category, rollup) in &report.categories {
+            let _ = writeln!(out, "| {} | {} | {} |", cell(category), rollup.sessions, number(rollup.minutes));
+        }
+    }
+
+    let _ = writeln!(out, "\n## Top activities\n");
+    if report.top_activities.is_empty() {
+        let _ = writeln!(out, "No sessions.");
+    }
+    for (rank, top) in report.top_activities.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}. {} {}: {} minutes in {} sessions",
+            rank + 1,
+            top.category,
+            top.activity,
+            number(top.minutes),
+            top.sessions,
+        );
+    }
+
+    let streaks = &report.streaks;
+    let _ = writeln!(out, "\n## Streak\n");
+    match &streaks.current_start {
+        Some(since) if streaks.current > 0 => {
+            let _ = write!(out, "Current streak: {} days, since {}.", streaks.current, since);
+        }
+        _ => {
+            let _ = write!(out, "No current streak.");
+        }
+    }
+    if let (Some(from), Some(to)) = (&streaks.longest_start, &streaks.longest_end) {
+        let _ = write!(out, " Longest: {} days, {} to {}.", streaks.longest, from, to);
+    }
+    out.push('\n');
+
+    let _ = writeln!(out, "\n## Notes\n");
+    if report.notes.is_empty() {
+        let _ = writeln!(out, "No notes.");
+    }
+    for note in &report.notes {
+        let _ = writeln!(out, "- {}: {}", note.timestamp.format("%Y-%m-%d %H:%M"), note.text);
+    }
+    if report.notes_omitted > 0 {
+        let _ = writeln!(out, "- and {} more", report.notes_omitted);
+    }
+
+    let _ = writeln!(out, "\n## Ratio vs target\n");
+    match (&report.target, &report.target_deviation) {
+        (Some(target), Some(deviation)) => {
+            let _ = write!(
+                out,
+                "Target {}:{} {}; this week {} to {} minutes",
+                target.numerator,
+                target.denominator,
+                number(target.value),
+                number(deviation.numerator_minutes),
+                number(deviation.denominator_minutes),
+            );
+            if let (Some(ratio), Some(delta)) = (deviation.ratio, deviation.delta) {
+                let _ = write!(out, ", a ratio of {} ({})", number(ratio), signed(delta));
+            }
+            let _ = match (deviation.on_target, &deviation.correction) {
+                (Some(true), _) => write!(out, ". On target."),
+                (Some(false), Some(correction)) => write!(
+                    out,
+                    ". Off target: about {} more {} minutes would bring it back.",
+                    number(correction.minutes),
+                    correction.category,
+                ),
+                _ => write!(out, "."),
+            };
+            out.push('\n');
+        }
+        _ => {
+            let _ = writeln!(out, "No target logged.");
+        }
+    }
+    out
+}
+
+/// Whole numbers as they are, anything else to two decimals
+fn number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn signed(value: f64) -> String {
+    if value >= 0.0 {
+        format!("+{}", number(value))
+    } else {
+        number(value)
+    }
+}
+
+/// Pipes would end a table cell early
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projections::TargetCorrection;
+
+    #[test]
+    fn test_markdown_sections() {
+        let report = WeeklyReport {
+            week: "2024-W18".to_string(),
+            start: "2024-04-29".to_string(),
+            end: "2024-05-05".to_string(),
+            partial: false,
+            sessions: 3,
+            minutes: 150.5,
+            theory_to_practice: Some(2.0),
+            delta: Some(RollupDelta { sessions: -1, minutes: 30.0, theory_to_practice: None }),
+            categories: BTreeMap::from([
+                ("A|B".to_string(), CategoryRollup { sessions: 1, minutes: 0.5 }),
+                ("THEORY".to_string(), CategoryRollup { sessions: 2, minutes: 150.0 }),
+            ]),
+            top_activities: vec![ActivityTotal {
+                category: "THEORY".to_string(),
+                activity: "pandas".to_string(),
+                sessions: 2,
+                minutes: 150.0,
+            }],
+            streaks: Streaks {
+                current: 0,
+                current_start: None,
+                longest: 4,
+                longest_start: Some("2024-04-01".to_string()),
+                longest_end: Some("2024-04-04".to_string()),
+                broken_on: vec![],
+            },
+            notes: vec![],
+            notes_omitted: 0,
+            target: Some(RatioTarget {
+                idx: 0,
+                timestamp: None,
+                numerator: "THEORY".to_string(),
+                denominator: "PRACTICE".to_string(),
+                value: 1.0,
+            }),
+            target_deviation: Some(TargetDeviation {
+                numerator_minutes: 150.0,
+                denominator_minutes: 0.0,
+                ratio: None,
+                delta: None,
+                on_target: Some(false),
+                correction: Some(TargetCorrection { category: "PRACTICE".to_string(), minutes: 150.0 }),
+            }),
+        };
+        let md = markdown(&report);
+
+        assert!(md.starts_with("# Weekly review 2024-W18 (2024-04-29 to 2024-05-05)\n"));
+        assert!(md.contains("**3 sessions, 150.50 minutes**, theory:practice 2 (-1 sessions, +30 minutes on the week before)"));
+        assert!(md.contains("| A\\|B | 1 | 0.50 |\n| THEORY | 2 | 150 |"));
+        assert!(md.contains("1. THEORY pandas: 150 minutes in 2 sessions"));
+        assert!(md.contains("No current streak. Longest: 4 days, 2024-04-01 to 2024-04-04."));
+        assert!(md.contains("## Notes\n\nNo notes."));
+        assert!(md.contains("Target THEORY:PRACTICE 1; this week 150 to 0 minutes. Off target: about 150 more PRACTICE minutes"));
+        assert!(!md.contains("in progress"));
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::events::{parse_event, EventVerb};
+
+/// A `SAVEQUERY <name> <json>` line: a `/query` body kept in the log
+/// under a name, the latest line for a name winning
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SavedQuery {
+    pub name: String,
+    /// Index of the defining line
+    pub event_index: usize,
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The body `GET /queries/{name}/run` posts to `/query`; null when
+    /// it doesn't parse
+    #[schema(value_type = Option<Object>)]
+    pub query: Option<serde_json::Value>,
+    /// Why `query` is null
+    pub error: Option<String>,
+}
+
+/// Current definition of every saved query, by name
+pub fn saved_queries(lines: &[String]) -> Vec<SavedQuery> {
+    let mut saved = BTreeMap::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let Some(event) = parse_event(line) else { continue };
+        if event.verb != EventVerb::SaveQuery {
+            continue;
+        }
+        let Some(name) = event.category else { continue };
+        // The body keeps its spaces, so take the raw rest of the line
+        let skip = if event.timestamp.is_some() { 3 } else { 2 };
+        let (query, error) = match parse_body(after_words(line, skip)) {
+            Ok(query) => (Some(query), None),
+            Err(e) => (None, Some(e)),
+        };
+        saved.insert(name.clone(), SavedQuery { name, event_index: idx, timestamp: event.timestamp, query, error });
+    }
+    saved.into_values().collect()
+}
+
+/// `line` without its first `n` whitespace-separated words
+fn after_words(line: &str, n: usize) -> &str {
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        rest = rest.split_once(char::is_whitespace).map_or("", |(_, tail)| tail).trim_start();
+    }
+    rest.trim_end()
+}
+
+fn parse_body(body: &str) -> Result<serde_json::Value, String> {
+    if body.is_empty() {
+        return Err("Missing query body".to_string());
+    }
+    match serde_json::from_str(body) {
+        Ok(query @ serde_json::Value::Object(_)) => Ok(query),
+        Ok(_) => Err("Query body must be a JSON object".to_string()),
+        Err(e) => Err(format!("Invalid JSON: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_definition_wins_and_bad_ones_are_listed() {
+        let lines: Vec<String> = [
+            r#"SAVEQUERY weekly {"type":"ratios"}"#,
+            "START THEORY pandas",
+            r#"2024-01-01T09:00:00Z SAVEQUERY weekly  {"type": "by_day", "metric": "minutes"} "#,
+            r#"SAVEQUERY broken {"type": "#,
+            "SAVEQUERY empty",
+            "SAVEQUERY",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        let saved = saved_queries(&lines);
+
+        assert_eq!(saved.iter().map(|q| q.name.as_str()).collect::<Vec<_>>(), vec!["broken", "empty", "weekly"]);
+        assert_eq!(saved[2].event_index, 2);
+        assert_eq!(saved[2].query, Some(serde_json::json!({ "type": "by_day", "metric": "minutes" })));
+        assert!(saved[0].query.is_none());
+        assert!(saved[0].error.as_deref().unwrap().starts_with("Invalid JSON"));
+        assert_eq!(saved[1].error.as_deref(), Some("Missing query body"));
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::events::{parse_event, EventVerb};
+
+/// Full-text search over the event log
+/// Streams line by line, never loads the whole log
+pub struct LogSearcher {
+    log_path: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SearchResult {
+    pub terms: Vec<String>,
+    pub matches: Vec<SearchMatch>,
+    pub count: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SearchMatch {
+    pub idx: usize,
+    pub line: String,
+    pub session: Option<SessionSummary>,
+}
+
+/// Session owning a matched line
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct SessionSummary {
+    pub category: String,
+    pub activity: String,
+    pub start_event_idx: usize,
+}
+
+impl LogSearcher {
+    pub fn new(log_path: &Path) -> Self {
+        Self {
+            log_path: log_path.to_path_buf(),
+        }
+    }
+
+    /// All terms must appear in a line for it to match (simple AND)
+    pub fn search(&self, terms: &[String], case_sensitive: bool, limit: usize) -> std::io::Result<SearchResult> {
+        let file = std::fs::File::open(&self.log_path)?;
+        let reader = std::io::BufReader::new(file);
+
+        let needles: Vec<String> = terms
+            .iter()
+            .map(|t| if case_sensitive { t.clone() } else { t.to_lowercase() })
+            .collect();
+
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        let mut current_session: Option<SessionSummary> = None;
+
+        let lines = crate::reader::lossy_lines(reader).filter(|line| !line.trim().is_empty());
+
+        for (idx, line) in lines.enumerate() {
+            // Track the owning session as we stream
+            if let Some(event) = parse_event(&line) {
+                if let (EventVerb::Start, Some(category), Some(activity)) =
+                    (event.verb, event.category, event.activity)
+                {
+                    current_session = Some(SessionSummary {
+                        category,
+                        activity,
+                        start_event_idx: idx,
+                    });
+                }
+            }
+
+            let haystack = if case_sensitive { line.clone() } else { line.to_lowercase() };
+            if !needles.iter().all(|n| haystack.contains(n.as_str())) {
+                continue;
+            }
+
+            if matches.len() == limit {
+                truncated = true;
+                break;
+            }
+
+            matches.push(SearchMatch {
+                idx,
+                line,
+                session: current_session.clone(),
+            });
+        }
+
+        Ok(SearchResult {
+            terms: terms.to_vec(),
+            count: matches.len(),
+            matches,
+            truncated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn terms(q: &str) -> Vec<String> {
+        q.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_search_and_terms_with_session() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START PRACTICE rust").unwrap();
+        writeln!(temp_file, "NOTE fighting the borrow checker").unwrap();
+        writeln!(temp_file, "NOTE borrow a book").unwrap();
+
+        let searcher = LogSearcher::new(temp_file.path());
+        let result = searcher.search(&terms("Borrow checker"), false, 100).unwrap();
+
+        assert_eq!(result.count, 1);
+        assert_eq!(result.matches[0].idx, 1);
+        let session = result.matches[0].session.as_ref().unwrap();
+        assert_eq!(session.activity, "rust");
+        assert_eq!(session.start_event_idx, 0);
+        assert!(!result.truncated);
+
+        let result = searcher.search(&terms("Borrow"), true, 100).unwrap();
+        assert_eq!(result.count, 0);
+    }
+
+    #[test]
+    fn test_search_limit_truncates() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "NOTE one").unwrap();
+        writeln!(temp_file, "NOTE two").unwrap();
+        writeln!(temp_file, "NOTE three").unwrap();
+
+        let searcher = LogSearcher::new(temp_file.path());
+        let result = searcher.search(&terms("note"), false, 2).unwrap();
+
+        assert_eq!(result.count, 2);
+        assert!(result.truncated);
+        assert!(result.matches[0].session.is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use axum::body::Bytes;
+
+/// A serialized projection bundle, recomputed in the background on a
+/// timer so reads never wait on a projection
+/// Reads see results up to one interval old; an interval of zero turns
+/// the background refresh off
+#[derive(Clone)]
+pub struct ProjectionSnapshot {
+    interval: Duration,
+    body: Arc<RwLock<Option<Bytes>>>,
+}
+
+impl ProjectionSnapshot {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            body: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Whether reads come from the snapshot rather than being computed
+    pub fn enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+
+    /// The last bundle, computing the first one if the refresher hasn't
+    /// run yet
+    pub fn get_or_compute(&self, compute: impl FnOnce() -> serde_json::Value) -> Bytes {
+        if let Some(body) = self.body.read().unwrap().as_ref() {
+            return body.clone();
+        }
+        let body = serialize(&compute());
+        self.body.write().unwrap().get_or_insert(body).clone()
+    }
+
+    /// Replace the bundle now rather than at the next refresh
+    pub fn store(&self, value: &serde_json::Value) {
+        *self.body.write().unwrap() = Some(serialize(value));
+    }
+
+    /// Recompute the bundle every interval, off the async runtime
+    /// Does nothing when disabled
+    pub fn spawn_refresher<F>(&self, compute: F) -> Option<tokio::task::JoinHandle<()>>
+    where
+        F: Fn() -> serde_json::Value + Send + Sync + 'static,
+    {
+        if !self.enabled() {
+            return None;
+        }
+        let snapshot = self.clone();
+        let compute = Arc::new(compute);
+        Some(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(snapshot.interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let compute = compute.clone();
+                match tokio::task::spawn_blocking(move || serialize(&compute())).await {
+                    Ok(body) => *snapshot.body.write().unwrap() = Some(body),
+                    Err(e) => tracing::error!(error = %e, "Error refreshing projection snapshot"),
+                }
+            }
+        }))
+    }
+}
+
+fn serialize(value: &serde_json::Value) -> Bytes {
+    Bytes::from(serde_json::to_vec(value).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The snapshot once it differs from `previous`, waiting out the
+    /// blocking compute without moving the paused clock
+    async fn refreshed(snapshot: &ProjectionSnapshot, previous: Option<&Bytes>) -> Bytes {
+        loop {
+            if let Some(body) = snapshot.body.read().unwrap().as_ref().filter(|body| Some(*body) != previous) {
+                return body.clone();
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresher_replaces_the_snapshot() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let snapshot = ProjectionSnapshot::new(Duration::from_secs(60));
+        let counted = runs.clone();
+        let refresher = snapshot
+            .spawn_refresher(move || serde_json::json!({ "run": counted.fetch_add(1, Ordering::SeqCst) }))
+            .unwrap();
+
+        // The first refresh runs straight away
+        let first = refreshed(&snapshot, None).await;
+        assert_eq!(&first[..], br#"{"run":0}"#);
+        assert_eq!(snapshot.get_or_compute(|| unreachable!()), first);
+
+        // Nothing new until the interval is up
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(&refreshed(&snapshot, Some(&first)).await[..], br#"{"run":1}"#);
+        refresher.abort();
+
+        assert!(ProjectionSnapshot::new(Duration::ZERO).spawn_refresher(serde_json::Value::default).is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+use tokio::sync::broadcast;
+use crate::index::EventIndex;
+use crate::models::IndexedEvent;
+
+/// Live events kept for slow subscribers before they start lagging
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Fan-out of newly appended events to live subscribers (SSE, etc.)
+/// Publishes whatever the event index picks up, so our own appends and
+/// external appends seen by the watcher are each published exactly once
+#[derive(Clone)]
+pub struct EventBroadcaster {
+    tx: broadcast::Sender<IndexedEvent>,
+    index: EventIndex,
+}
+
+impl EventBroadcaster {
+    /// Publish from wherever `index` has already got to
+    /// Existing history is replayed on request, never pushed
+    pub fn new(index: EventIndex) -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx, index }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<IndexedEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Publish every complete line appended since the last call
+    pub fn publish_new_lines(&self, log_path: &Path) -> std::io::Result<usize> {
+        let events = self.index.update(log_path)?;
+        let published = events.len();
+        for event in events {
+            // No subscribers is fine
+            let _ = self.tx.send(event);
+        }
+        Ok(published)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_publishes_only_new_lines_once() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+
+        let index = EventIndex::build(temp_file.path(), crate::index::DEFAULT_RECENT_LINES).unwrap();
+        let broadcaster = EventBroadcaster::new(index);
+        let mut rx = broadcaster.subscribe();
+
+        writeln!(temp_file, "START GAME valorant").unwrap();
+        write!(temp_file, "START PRAC").unwrap();
+        assert_eq!(broadcaster.publish_new_lines(temp_file.path()).unwrap(), 1);
+        assert_eq!(broadcaster.publish_new_lines(temp_file.path()).unwrap(), 0);
+
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.idx, 1);
+        assert_eq!(event.line, "START GAME valorant");
+
+        writeln!(temp_file, "TICE rust").unwrap();
+        assert_eq!(broadcaster.publish_new_lines(temp_file.path()).unwrap(), 1);
+        assert_eq!(rx.try_recv().unwrap().line, "START PRACTICE rust");
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::index::EventIndex;
+use crate::models::IndexedEvent;
+
+/// Bytes read per backwards step
+const CHUNK_SIZE: u64 = 8 * 1024;
+
+/// Last `n` events, read by seeking from the end of the log
+/// Indices match the forward numbering used by the rest of the API: with
+/// `index` kept up to date for this file, only lines past what it has
+/// counted are read; without, everything before the window is counted
+pub fn tail_events(path: &Path, n: usize, index: Option<&EventIndex>) -> std::io::Result<Vec<IndexedEvent>> {
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut pos = len;
+    let mut buf: Vec<u8> = Vec::new();
+
+    // Scan backwards until the window holds n complete lines (or the whole file)
+    let (window_start, lines) = loop {
+        let step = CHUNK_SIZE.min(pos);
+        pos -= step;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut chunk = vec![0u8; step as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+
+        // Unless we reached the start, the first segment may be a partial line
+        let skip = if pos == 0 {
+            0
+        } else {
+            match buf.iter().position(|&b| b == b'\n') {
+                Some(i) => i + 1,
+                None => continue,
+            }
+        };
+
+        let lines = non_empty_lines(&buf[skip..]);
+        if lines.len() >= n || pos == 0 {
+            let keep_from = lines.len().saturating_sub(n);
+            let offset = lines.get(keep_from).map(|(o, _)| skip + o).unwrap_or(buf.len());
+            let kept: Vec<(u64, String)> = lines
+                .into_iter()
+                .skip(keep_from)
+                .map(|(o, l)| (pos + (skip + o) as u64, l))
+                .collect();
+            break (pos + offset as u64, kept);
+        }
+    };
+
+    let first_idx = match index.map(EventIndex::position) {
+        Some(indexed) => match from_index(&mut file, len, indexed, window_start, &lines)? {
+            Some(idx) => idx,
+            None => count_lines_between(&mut file, 0, window_start)?,
+        },
+        None => count_lines_between(&mut file, 0, window_start)?,
+    };
+
+    Ok(lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, line))| IndexedEvent { idx: first_idx + i, line })
+        .collect())
+}
+
+/// Index of the window's first line from the `(bytes, count)` an index
+/// has for the file: window lines it already covers come off its count,
+/// lines between it and the window go on
+/// None when the index can't be for the file as it is now
+fn from_index(
+    file: &mut File,
+    len: u64,
+    (bytes, count): (u64, usize),
+    window_start: u64,
+    lines: &[(u64, String)],
+) -> std::io::Result<Option<usize>> {
+    if bytes > len {
+        return Ok(None);
+    }
+    if window_start <= bytes {
+        let counted = lines.iter().filter(|(offset, _)| *offset < bytes).count();
+        return Ok(count.checked_sub(counted));
+    }
+    Ok(Some(count + count_lines_between(file, bytes, window_start)?))
+}
+
+/// Non-empty lines with their byte offsets inside `bytes`
+fn non_empty_lines(bytes: &[u8]) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for segment in bytes.split(|&b| b == b'\n') {
+        let line = String::from_utf8_lossy(segment);
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            lines.push((offset, line.to_string()));
+        }
+        offset += segment.len() + 1;
+    }
+    lines
+}
+
+/// Count non-empty lines between two byte offsets, blank meaning what it
+/// does to the reader
+fn count_lines_between(file: &mut File, start: u64, end: u64) -> std::io::Result<usize> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file.take(end.saturating_sub(start)));
+    let mut segment = Vec::new();
+    let mut count = 0;
+    loop {
+        segment.clear();
+        if reader.read_until(b'\n', &mut segment)? == 0 {
+            break;
+        }
+        if !String::from_utf8_lossy(&segment).trim().is_empty() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_tail_returns_last_n_with_indices() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        for i in 0..5000 {
+            writeln!(temp_file, "START THEORY topic{}", i).unwrap();
+            if i % 100 == 0 {
+                writeln!(temp_file).unwrap();
+            }
+        }
+
+        let tail = tail_events(temp_file.path(), 3, None).unwrap();
+        assert_eq!(tail.len(), 3);
+        assert_eq!(tail[0].idx, 4997);
+        assert_eq!(tail[0].line, "START THEORY topic4997");
+        assert_eq!(tail[2].idx, 4999);
+    }
+
+    #[test]
+    fn test_tail_bounds() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "START THEORY pandas").unwrap();
+        writeln!(temp_file, "START GAME valorant").unwrap();
+
+        assert!(tail_events(temp_file.path(), 0, None).unwrap().is_empty());
+
+        let all = tail_events(temp_file.path(), 50, None).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].idx, 0);
+        assert_eq!(all[1].line, "START GAME valorant");
+    }
+
+    #[test]
+    fn test_indices_from_the_index() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        for i in 0..3000 {
+            writeln!(temp_file, "START THEORY topic{}", i).unwrap();
+        }
+        // Blank to the reader, though not ASCII whitespace
+        writeln!(temp_file, "\u{3000}").unwrap();
+        let index = EventIndex::build(temp_file.path(), 16).unwrap();
+        let expected = |events: Vec<IndexedEvent>| events.into_iter().map(|e| (e.idx, e.line)).collect::<Vec<_>>();
+
+        let tail = expected(tail_events(temp_file.path(), 2, Some(&index)).unwrap());
+        assert_eq!(tail, [(2998, "START THEORY topic2998".into()), (2999, "START THEORY topic2999".into())]);
+        assert_eq!(expected(tail_events(temp_file.path(), 2, None).unwrap()), tail);
+
+        // Lines the index hasn't seen yet, and a partial one it never counts
+        writeln!(temp_file, "START GAME chess").unwrap();
+        writeln!(temp_file, "START GAME go").unwrap();
+        write!(temp_file, "START PRAC").unwrap();
+        let tail = tail_events(temp_file.path(), 1, Some(&index)).unwrap();
+        assert_eq!(expected(tail), [(3002, "START PRAC".into())]);
+        let tail = expected(tail_events(temp_file.path(), 3, Some(&index)).unwrap());
+        assert_eq!(tail[0], (3000, "START GAME chess".into()));
+        index.update(temp_file.path()).unwrap();
+        assert_eq!(expected(tail_events(temp_file.path(), 3, Some(&index)).unwrap()), tail);
+        assert_eq!(expected(tail_events(temp_file.path(), 3, None).unwrap()), tail);
+
+        // An index for a longer file is ignored
+        std::fs::write(temp_file.path(), "START THEORY pandas\n").unwrap();
+        assert_eq!(tail_events(temp_file.path(), 1, Some(&index)).unwrap()[0].idx, 0);
+    }
+}
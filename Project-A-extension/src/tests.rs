@@ -54,3 +54,2485 @@ mod tests {
         assert_eq!(lines[1], "START PRACTICE rust");
     }
 }
+
+use crate::{append_to_log, read_log, handle_query, parse_line, get_ratios, list_events, create_event, close_session, stream_events, build_router, with_timeout, AppState};
+use crate::models::{EventsParams, EventInput, ParseInput, QueryResponse, RatiosParams, StreamParams};
+use axum::response::IntoResponse;
+use tokio_stream::StreamExt;
+use axum::{extract::State, http::StatusCode, Json};
+use crate::extract;
+use std::io::Write;
+use tempfile::NamedTempFile;
+use crate::events::EventVerb;
+use crate::clock::FixedClock;
+use crate::error::AppError;
+use std::sync::Arc;
+
+/// An error's status and JSON body, as a client gets them
+fn error_parts(error: AppError) -> (StatusCode, serde_json::Value) {
+    (error.status(), serde_json::to_value(error.body()).unwrap())
+}
+
+#[tokio::test]
+async fn test_unknown_query_type_lists_supported_types() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "type": "ratio" });
+    let (status, body) = error_parts(handle_query(State(state), extract::Json(query)).await.unwrap_err());
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let supported = body["error"]["detail"]["supported_types"].as_array().unwrap();
+    assert!(supported.iter().any(|t| t == "ratios"));
+}
+
+#[tokio::test]
+async fn test_registered_projector_is_queryable() {
+    use crate::registry::{ProjectionError, Projector, ProjectorParam};
+    use crate::models::QueryResult;
+
+    struct Echo;
+    impl Projector for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+        fn projector(&self) -> &str {
+            "Echo"
+        }
+        fn params(&self) -> Vec<ProjectorParam> {
+            vec![ProjectorParam::new("word", "string", "Echoed back")]
+        }
+        fn project(
+            &self,
+            _log: &crate::registry::QueryLog,
+            _settings: &crate::registry::QuerySettings,
+            params: &serde_json::Value,
+        ) -> Result<QueryResult, ProjectionError> {
+            let word = params["word"].as_str().ok_or(ProjectionError::Invalid("word is required".to_string()))?;
+            Ok(QueryResult {
+                query: "echo".to_string(),
+                result_type: "echo".to_string(),
+                data: serde_json::json!({ "word": word }),
+            })
+        }
+    }
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let mut state = AppState::new(temp_file.path().to_path_buf());
+    std::sync::Arc::make_mut(&mut state.projectors).register(Echo);
+
+    let query = serde_json::json!({ "type": "echo", "word": "hi", "explain": true });
+    let Json(response) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(response.result.data["word"], "hi");
+    assert_eq!(response.plan.unwrap().filters, serde_json::json!({ "word": "hi" }));
+
+    let (status, body) = error_parts(handle_query(State(state.clone()), extract::Json(serde_json::json!({ "type": "echo" })))
+        .await
+        .unwrap_err());
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["detail"]["supported_types"].as_array().unwrap().iter().any(|t| t == "echo"));
+
+    let Json(listed) = crate::list_projectors(State(state)).await;
+    let echo = listed["projectors"].as_array().unwrap().iter().find(|p| p["name"] == "echo").unwrap();
+    assert_eq!(echo["params"][0]["name"], "word");
+}
+
+#[tokio::test]
+async fn test_bundle_served_from_refreshed_snapshot() {
+    use crate::snapshot::ProjectionSnapshot;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n").unwrap();
+    let mut state = AppState::new(path.clone());
+    state.snapshot = ProjectionSnapshot::new(std::time::Duration::from_millis(100));
+    let bundled = state.clone();
+    let refresher = state.snapshot.spawn_refresher(move || crate::projection_bundle(&bundled)).unwrap();
+
+    let bundle = |state: AppState| async move {
+        let response = crate::get_bundle(State(state)).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<crate::openapi::BundleEnvelope>(&body).unwrap()
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let first = bundle(state.clone()).await;
+    assert_eq!(first.sessions.len(), 1);
+
+    // Until the next refresh, reads get the snapshot, not the log
+    append_to_log(&path, "2024-01-01T10:00:00Z START PRACTICE rust\n").unwrap();
+    let stale = bundle(state.clone()).await;
+    assert_eq!((stale.sessions.len(), stale.computed_at), (1, first.computed_at));
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let refreshed = bundle(state.clone()).await;
+    assert_eq!(refreshed.sessions.len(), 2);
+    assert!(refreshed.computed_at > first.computed_at);
+    refresher.abort();
+}
+
+#[tokio::test]
+async fn test_structured_query_params_validated() {
+    // Path that doesn't exist: validation must fail before any log read
+    let state = AppState::new(std::path::PathBuf::from("/nonexistent/master.log"));
+
+    let query = serde_json::json!({ "type": "recent", "limit": 0 });
+    let (status, body) = error_parts(handle_query(State(state.clone()), extract::Json(query)).await.unwrap_err());
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("limit"));
+
+    let query = serde_json::json!({ "type": "ratios", "params": { "bogus": 1 } });
+    let status = handle_query(State(state), extract::Json(query)).await.unwrap_err().status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_structured_recent_limit() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    writeln!(temp_file, "START GAME valorant").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "type": "recent", "limit": 1 });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["events"], serde_json::json!(["START GAME valorant"]));
+
+    // "sessions ratio by day" used to go wherever the first substring matched
+    let query = serde_json::json!({ "type": "timeline" });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state), extract::Json(query)).await.unwrap();
+    assert_eq!(result.result_type, "sessions");
+}
+
+#[tokio::test]
+async fn test_recent_returns_every_logged_line() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_path_buf();
+    append_to_log(&path, "# imported from the old tracker\nSTART THEORY pandas\nstarted theory\nSTOP THEORY pandas\n").unwrap();
+    let state = AppState::new(path);
+
+    let recent = |query: serde_json::Value| {
+        let state = state.clone();
+        async move {
+            let Json(QueryResponse { result, .. }) = handle_query(State(state), extract::Json(query)).await.unwrap();
+            result.data["events"].clone()
+        }
+    };
+    assert_eq!(
+        recent(serde_json::json!({ "type": "recent" })).await,
+        serde_json::json!(["# imported from the old tracker", "START THEORY pandas", "started theory", "STOP THEORY pandas"])
+    );
+    assert_eq!(
+        recent(serde_json::json!({ "type": "recent", "limit": 2 })).await,
+        serde_json::json!(["started theory", "STOP THEORY pandas"])
+    );
+    // The free-text fallback answers the same way
+    assert_eq!(recent(serde_json::json!({ "query": "what did I do" })).await.as_array().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_query_on_unreadable_log_is_an_error() {
+    // A path under a regular file can't be read, and isn't merely missing
+    let temp_file = NamedTempFile::new().unwrap();
+    let state = AppState::new(temp_file.path().join("master.log"));
+    for query in [serde_json::json!({ "type": "ratios" }), serde_json::json!({ "type": "recent" })] {
+        let status = handle_query(State(state.clone()), extract::Json(query)).await.unwrap_err().status();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // A log that doesn't exist yet answers empty
+    let dir = tempfile::tempdir().unwrap();
+    let state = AppState::new(dir.path().join("master.log"));
+    let query = serde_json::json!({ "type": "recent", "limit": 5 });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["events"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn test_by_day_query() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "2024-03-01T09:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-03-03T09:00:00Z START PRACTICE rust").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "type": "by_day", "metric": "events", "to": "2024-03-04" });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    let days = result.data["days"].as_array().unwrap();
+    assert_eq!(days.len(), 4);
+    assert_eq!(days[1]["total"], 0.0);
+    assert_eq!(days[2]["categories"]["PRACTICE"], 1.0);
+
+    let query = serde_json::json!({ "type": "by_day", "from": "2024-03-04", "to": "2024-03-01" });
+    let status = handle_query(State(state), extract::Json(query)).await.unwrap_err().status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_day_query() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY legacy").unwrap();
+    writeln!(temp_file, "2024-01-01T23:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-01-02T09:00:00Z START PRACTICE rust").unwrap();
+    writeln!(temp_file, "2024-01-02T10:00:00Z START GAME chess").unwrap();
+    writeln!(temp_file, "2024-01-04T09:00:00Z START THEORY numpy").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "type": "day", "date": "2024-01-02" });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["count"], 2);
+    let sessions = result.data["sessions"].as_array().unwrap();
+    assert_eq!(sessions[0]["activity"], "rust");
+    assert_eq!(sessions[1]["activity"], "chess");
+
+    let query = serde_json::json!({ "type": "day", "date": "2024-01-03" });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["count"], 0);
+    assert!(result.data["sessions"].as_array().unwrap().is_empty());
+
+    for date in ["2024-1-2x", "02/01/2024", "2024-02-30"] {
+        let query = serde_json::json!({ "type": "day", "date": date });
+        let status = handle_query(State(state.clone()), extract::Json(query)).await.unwrap_err().status();
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", date);
+    }
+}
+
+#[tokio::test]
+async fn test_compare_query() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-01-08T09:00:00Z START THEORY numpy").unwrap();
+    writeln!(temp_file, "2024-01-09T09:00:00Z START PRACTICE rust").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({
+        "type": "compare",
+        "a": { "from": "2024-01-01", "to": "2024-01-07" },
+        "b": { "from": "2024-01-08", "to": "2024-01-14" },
+    });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.result_type, "comparison");
+    assert_eq!(result.data["deltas"]["sessions"]["delta"], 1.0);
+    assert_eq!(result.data["deltas"]["sessions"]["percent"], 100.0);
+    assert_eq!(result.data["b"]["theory_to_practice"], 1.0);
+
+    let query = serde_json::json!({ "type": "compare", "a": { "from": "2024-01-01", "to": "soon" }, "b": { "from": "2024-01-08", "to": "2024-01-14" } });
+    let status = handle_query(State(state.clone()), extract::Json(query)).await.unwrap_err().status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Backwards, so empty: rejected rather than flagged as overlapping
+    let query = serde_json::json!({ "type": "compare", "a": { "from": "2024-01-01", "to": "2024-01-07" }, "b": { "from": "2024-01-06", "to": "2024-01-02" } });
+    let (status, body) = error_parts(handle_query(State(state), extract::Json(query)).await.unwrap_err());
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["message"], "Invalid query: Range 2024-01-06..2024-01-02 ends before it starts");
+}
+
+#[tokio::test]
+async fn test_day_endpoint_uses_server_timezone() {
+    use tower::ServiceExt;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "2024-01-01T23:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-01-02T09:00:00Z START PRACTICE rust").unwrap();
+    let mut state = AppState::new(temp_file.path().to_path_buf());
+    // 23:00 UTC is already Jan 2 in Berlin
+    state.timezone = "Europe/Berlin".parse().unwrap();
+    let app = build_router(state);
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/projections/day/2024-01-02")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["day"]["data"]["count"], 2);
+    assert_eq!(body["day"]["data"]["date"], "2024-01-02");
+
+    let response = app.clone().oneshot(get("/projections/day/2024-01-01")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["day"]["data"]["count"], 0);
+
+    let response = app.oneshot(get("/projections/day/yesterday")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sessions_query_where_clause() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START PRACTICE api project=api difficulty=3").unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "type": "sessions", "where": { "project": "api", "difficulty": { "gte": 3 } } });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["count"], 1);
+
+    let query = serde_json::json!({ "type": "sessions", "where": { "difficulty": { "about": 3 } } });
+    let (status, body) = error_parts(handle_query(State(state), extract::Json(query)).await.unwrap_err());
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("about"));
+}
+
+#[tokio::test]
+async fn test_legacy_fallback_flag() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    let mut state = AppState::new(temp_file.path().to_path_buf());
+
+    let query = serde_json::json!({ "query": "what did I do" });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query.clone())).await.unwrap();
+    assert_eq!(result.result_type, "recent");
+
+    state.legacy_query_fallback = false;
+    let status = handle_query(State(state), extract::Json(query)).await.unwrap_err().status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_watcher_invalidates_on_external_append() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\n").unwrap();
+
+    let state = AppState::new(path.clone());
+    let _watcher = {
+        let watched = state.clone();
+        crate::watcher::spawn_log_watcher(path.clone(), move || watched.log_changed()).unwrap()
+    };
+
+    let Json(before) = get_ratios(State(state.clone()), extract::Query(RatiosParams::default())).await.unwrap();
+    assert_eq!(before["analysis"]["data"]["total_events"], 1);
+
+    // Another process appends directly to the file
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"START GAME valorant\n")
+        .unwrap();
+
+    let mut total = 1;
+    for _ in 0..50 {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let Json(after) = get_ratios(State(state.clone()), extract::Query(RatiosParams::default())).await.unwrap();
+        total = after["analysis"]["data"]["total_events"].as_u64().unwrap();
+        if total == 2 {
+            break;
+        }
+    }
+    assert_eq!(total, 2);
+}
+
+#[tokio::test]
+async fn test_category_display_in_ratios() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    writeln!(temp_file, "START PRAC rust").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let Json(plain) = get_ratios(State(state), extract::Query(RatiosParams::default())).await.unwrap();
+    let categories = plain["analysis"]["data"]["categories"].as_array().unwrap();
+    assert!(categories.iter().all(|c| c["display_name"].is_null() && c["color"].is_null()));
+
+    // Keyed by canonical category, after aliasing
+    let mut state = AppState::new(temp_file.path().to_path_buf());
+    state.aliases = crate::aliases::CategoryAliases::from_json(r#"{"PRAC": "PRACTICE"}"#).unwrap();
+    state.category_display = crate::display::CategoryDisplayConfig::from_json(
+        r##"{"PRACTICE": {"display_name": "Hands-on", "color": "#59a14f"}, "THEORY": {"color": "#4e79a7"}}"##,
+    )
+    .unwrap();
+    let Json(body) = get_ratios(State(state), extract::Query(RatiosParams::default())).await.unwrap();
+    let categories = body["analysis"]["data"]["categories"].as_array().unwrap();
+    let category = |name: &str| categories.iter().find(|c| c["category"] == name).unwrap();
+    assert_eq!(category("PRACTICE")["display_name"], "Hands-on");
+    assert_eq!(category("PRACTICE")["color"], "#59a14f");
+    assert!(category("THEORY")["display_name"].is_null());
+    assert_eq!(category("THEORY")["color"], "#4e79a7");
+
+    assert!(crate::display::CategoryDisplayConfig::from_json(r#"{"THEORY": {"colour": "red"}}"#).is_err());
+}
+
+#[tokio::test]
+async fn test_list_events_ndjson_with_filter() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    writeln!(temp_file, "START GAME valorant").unwrap();
+    writeln!(temp_file, "START THEORY rust").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let params = EventsParams {
+        format: Some("ndjson".to_string()),
+        category: Some("THEORY".to_string()),
+        ..Default::default()
+    };
+    let response = list_events(State(state), axum::http::HeaderMap::new(), extract::Query(params))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let rows: Vec<serde_json::Value> = String::from_utf8(body.to_vec())
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["idx"], 0);
+    assert_eq!(rows[1]["idx"], 2);
+    assert_eq!(rows[1]["line"], "START THEORY rust");
+}
+
+#[tokio::test]
+async fn test_list_events_since() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "START THEORY pandas").unwrap();
+    writeln!(temp_file, "START GAME valorant").unwrap();
+    let state = AppState::new(temp_file.path().to_path_buf());
+
+    let fetch = |since| {
+        let state = state.clone();
+        async move {
+            let params = EventsParams { since: Some(since), ..Default::default() };
+            let response = list_events(State(state), axum::http::HeaderMap::new(), extract::Query(params))
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let all = fetch(0).await;
+    assert_eq!(all["total"], 2);
+    assert_eq!(all["events"].as_array().unwrap().len(), 2);
+
+    let newer = fetch(1).await;
+    assert_eq!(newer["events"][0]["idx"], 1);
+    assert_eq!(newer["events"][0]["line"], "START GAME valorant");
+
+    let past_end = fetch(10).await;
+    assert_eq!(past_end["total"], 2);
+    assert!(past_end["events"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_event_index_counts_across_appends() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let mut existing = String::new();
+    for i in 0..crate::index::DEFAULT_RECENT_LINES + 10 {
+        existing.push_str(&format!("START THEORY topic{}\n", i));
+    }
+    existing.push('\n');
+    append_to_log(&path, &existing).unwrap();
+    let total = crate::index::DEFAULT_RECENT_LINES + 10;
+
+    // Built from the existing log at startup
+    let app = build_router(AppState::new(path.clone()));
+    let get = |uri: String| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+    assert_eq!(get("/health".into()).await["events"], total);
+
+    // Appends through the API and behind its back are both counted
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"event": "START GAME valorant"}"#))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    append_to_log(&path, "\nSTART PRACTICE rust\n").unwrap();
+    assert_eq!(get("/health".into()).await["events"], total + 2);
+
+    // Recent catch-ups come from the index; older ones scan the log, and both agree
+    let recent = get(format!("/events?since={}", total)).await;
+    assert_eq!(recent["total"], total + 2);
+    assert_eq!(recent["events"][0]["idx"], total);
+    assert_eq!(recent["events"][1]["line"], "START PRACTICE rust");
+
+    let old = get("/events?since=1&offset=3&limit=2".into()).await;
+    assert_eq!(old["total"], total + 2);
+    assert_eq!(old["events"][0]["idx"], 4);
+    assert_eq!(old["events"][1]["line"], "START THEORY topic5");
+}
+
+#[tokio::test]
+async fn test_comment_lines_are_ignored_by_projections() {
+    use tower::ServiceExt;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "# START THEORY commented-out").unwrap();
+    writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-01-01T09:30:00Z # PRACTICE would go here").unwrap();
+    writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+    let app = build_router(AppState::new(temp_file.path().to_path_buf()));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let body = axum::body::to_bytes(app.oneshot(request).await.unwrap().into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let sessions = get("/projections/sessions").await;
+    assert_eq!(sessions["count"], 2);
+    assert_eq!(sessions["sessions"][0]["duration_minutes"], 60.0);
+
+    let ratios = get("/projections/ratios").await;
+    assert_eq!(ratios["analysis"]["data"]["total_events"], 2);
+
+    let daily = get("/projections/daily?metric=events").await;
+    assert_eq!(daily["daily"]["data"]["days"][0]["total"], 2.0);
+
+    // Comments stay in the log and in listings unless asked otherwise
+    assert_eq!(get("/events").await.as_array().unwrap().len(), 4);
+    let events = get("/events?comments=false").await;
+    assert_eq!(
+        events,
+        serde_json::json!(["2024-01-01T09:00:00Z START THEORY pandas", "2024-01-01T10:00:00Z START PRACTICE rust"]),
+    );
+}
+
+#[tokio::test]
+async fn test_event_stream_replays_then_pushes_appends() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\nSTART GAME valorant\n").unwrap();
+    let state = AppState::new(path);
+
+    let sse = stream_events(State(state.clone()), extract::Query(StreamParams { since: Some(1) }))
+        .await
+        .unwrap();
+    let mut frames = sse.into_response().into_body().into_data_stream();
+
+    let replayed = frames.next().await.unwrap().unwrap();
+    let replayed = String::from_utf8(replayed.to_vec()).unwrap();
+    assert!(replayed.contains("\"idx\":1"));
+    assert!(replayed.contains("START GAME valorant"));
+
+    let input = EventInput { event: "START PRACTICE rust".to_string(), idempotency_key: None, timestamp: None };
+    let Json(response) = create_event(State(state), Default::default(), extract::Json(input)).await.unwrap();
+    assert_eq!(response.status, "success");
+
+    let pushed = frames.next().await.unwrap().unwrap();
+    let pushed = String::from_utf8(pushed.to_vec()).unwrap();
+    assert!(pushed.contains("\"idx\":2"));
+    assert!(pushed.contains("START PRACTICE rust"));
+}
+
+/// Next text frame from a WebSocket client, parsed as JSON
+async fn next_json<S>(socket: &mut S) -> serde_json::Value
+where
+    S: futures_util::Stream<Item = Result<tokio_tungstenite::tungstenite::Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        if let tokio_tungstenite::tungstenite::Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_append_and_errors() {
+    use futures_util::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let state = AppState::new(path.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, build_router(state)).await.unwrap() });
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+
+    // Malformed message yields an error frame, connection stays open
+    socket.send(Message::Text("not json".into())).await.unwrap();
+    let frame = next_json(&mut socket).await;
+    assert_eq!(frame["type"], "error");
+
+    socket
+        .send(Message::Text(r#"{"type":"append","event":"START THEORY pandas"}"#.into()))
+        .await
+        .unwrap();
+    let frame = next_json(&mut socket).await;
+    assert_eq!(frame["type"], "event");
+    assert_eq!(frame["idx"], 0);
+    assert!(frame["line"].as_str().unwrap().ends_with("START THEORY pandas"));
+
+    let frame = next_json(&mut socket).await;
+    assert_eq!(frame["type"], "session_update");
+    assert_eq!(frame["session"]["activity"], "pandas");
+
+    assert_eq!(read_log(&path).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_websocket_appends_are_rate_limited() {
+    use futures_util::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let mut state = AppState::new(path.clone());
+    // One at once, then one a minute
+    state.rate_limiter = crate::ratelimit::RateLimiter::new(1, 1);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, build_router(state)).await.unwrap() });
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+
+    let append = |activity: &str| Message::Text(format!(r#"{{"type":"append","event":"START THEORY {}"}}"#, activity));
+    socket.send(append("pandas")).await.unwrap();
+    assert_eq!(next_json(&mut socket).await["type"], "event");
+    assert_eq!(next_json(&mut socket).await["type"], "session_update");
+
+    // The next one waits its turn, and the socket stays open
+    socket.send(append("numpy")).await.unwrap();
+    let frame = next_json(&mut socket).await;
+    assert_eq!((frame["type"].as_str(), frame["message"].as_str()), (Some("error"), Some("Too many appends, retry in 60s")));
+    socket.send(Message::Text("not json".into())).await.unwrap();
+    assert_eq!(next_json(&mut socket).await["type"], "error");
+    assert_eq!(read_log(&path).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_request_timeout_fires() {
+    use tower::ServiceExt;
+
+    let slow = axum::Router::new().route(
+        "/slow",
+        axum::routing::get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            "done"
+        }),
+    );
+    let app = with_timeout(slow, std::time::Duration::from_millis(20));
+
+    let request = axum::http::Request::builder().uri("/slow").body(axum::body::Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+}
+
+#[tokio::test]
+async fn test_etag_not_modified_until_append() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |etag: Option<String>| {
+        let mut request = axum::http::Request::builder().uri("/projections/ratios");
+        if let Some(etag) = etag {
+            request = request.header("if-none-match", etag);
+        }
+        request.body(axum::body::Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(get(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    let response = app.clone().oneshot(get(Some(etag.clone()))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(body.is_empty());
+
+    let post = axum::http::Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"event":"START GAME valorant"}"#))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(post).await.unwrap().status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(get(Some(etag.clone()))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()["etag"].to_str().unwrap(), etag);
+}
+
+#[tokio::test]
+async fn test_clock_dependent_etags_expire_with_the_minute() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n").unwrap();
+    let clock = Arc::new(FixedClock::new("2024-01-01T09:10:00Z".parse().unwrap()));
+    let mut state = AppState::new(path);
+    state.clock = clock.clone();
+    let app = build_router(state);
+    let get = |uri: &str, etag: Option<&str>| {
+        let mut request = axum::http::Request::builder().uri(uri);
+        if let Some(etag) = etag {
+            request = request.header("if-none-match", etag);
+        }
+        app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+    };
+    let etag = |response: &axum::response::Response| response.headers()["etag"].to_str().unwrap().to_string();
+
+    let current = etag(&get("/projections/sessions/current", None).await.unwrap());
+    let span = etag(&get("/projections/span", None).await.unwrap());
+    clock.advance(chrono::Duration::seconds(30));
+    assert_eq!(get("/projections/sessions/current", Some(&current)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+
+    // A minute on, the elapsed time has moved; the log-only answer hasn't
+    clock.advance(chrono::Duration::seconds(30));
+    let response = get("/projections/sessions/current", Some(&current)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(etag(&response), current);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["elapsed_minutes"], 11.0);
+    assert_eq!(get("/projections/span", Some(&span)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn test_etag_per_negotiated_representation() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\n").unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |accept: Option<&str>, etag: Option<&str>| {
+        let mut request = axum::http::Request::builder().uri("/events");
+        if let Some(accept) = accept {
+            request = request.header("accept", accept);
+        }
+        if let Some(etag) = etag {
+            request = request.header("if-none-match", etag);
+        }
+        app.clone().oneshot(request.body(axum::body::Body::empty()).unwrap())
+    };
+
+    let text = get(Some("text/plain"), None).await.unwrap();
+    assert_eq!(text.headers()["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(text.headers()["vary"], "accept");
+    let text_etag = text.headers()["etag"].to_str().unwrap().to_string();
+    let json = get(None, None).await.unwrap();
+    assert_eq!(json.headers()["vary"], "accept");
+    let json_etag = json.headers()["etag"].to_str().unwrap().to_string();
+    assert_ne!(text_etag, json_etag);
+
+    // A tag only stands for the representation it came with
+    assert_eq!(get(None, Some(&text_etag)).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(get(Some("text/plain"), Some(&text_etag)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(get(None, Some(&json_etag)).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+    let csv = get(Some("text/csv"), Some(&json_etag)).await.unwrap();
+    assert_eq!((csv.status(), csv.headers()["content-type"].to_str().unwrap()), (StatusCode::OK, "text/csv; charset=utf-8"));
+}
+
+#[tokio::test]
+async fn test_head_events_total_count() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\nSTART GAME valorant\nSTART THEORY rust\n").unwrap();
+    let app = build_router(AppState::new(path.clone()));
+
+    let request = |method: &str, uri: &str| {
+        axum::http::Request::builder().method(method).uri(uri).body(axum::body::Body::empty()).unwrap()
+    };
+
+    let response = app.clone().oneshot(request("HEAD", "/events")).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "3");
+    assert!(response.headers().contains_key("etag"));
+
+    // External append is picked up by the counter
+    append_to_log(&path, "START PRACTICE rust\n").unwrap();
+    let response = app.clone().oneshot(request("HEAD", "/events")).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "4");
+
+    let response = app.clone().oneshot(request("HEAD", "/events?category=THEORY")).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "2");
+
+    let response = app.clone().oneshot(request("GET", "/events?category=THEORY&limit=1&offset=1")).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "2");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page: Vec<String> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page, vec!["START THEORY rust"]);
+}
+
+#[tokio::test]
+async fn test_events_jsonl_export() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-02T10:00:00Z START THEORY pandas\nthis line is garbage\nNOTE tricky\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let request = axum::http::Request::builder().uri("/events.jsonl").body(axum::body::Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let rows: Vec<crate::models::EventRecord> = String::from_utf8(body.to_vec())
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0].verb.as_deref(), Some("START"));
+    assert_eq!(rows[0].activity.as_deref(), Some("pandas"));
+    assert!(rows[0].timestamp.is_some() && rows[0].raw.is_none());
+
+    assert_eq!(rows[1].idx, 1);
+    assert_eq!(rows[1].raw.as_deref(), Some("this line is garbage"));
+    assert!(rows[1].verb.is_none() && rows[1].category.is_none());
+
+    assert_eq!(rows[2].verb.as_deref(), Some("NOTE"));
+}
+
+#[tokio::test]
+async fn test_session_events_route() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY pandas\nNOTE groupby\nSTART GAME valorant\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/projections/sessions/0/events")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["events"][1], serde_json::json!({ "idx": 1, "line": "NOTE groupby" }));
+
+    let response = app.clone().oneshot(get("/projections/sessions/5/events")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_idempotency_key_appends_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let state = AppState::new(path.clone());
+
+    // Body field
+    let post = |key: &str| EventInput { event: "START THEORY pandas".to_string(), idempotency_key: Some(key.to_string()), timestamp: None };
+    let Json(first) = create_event(State(state.clone()), Default::default(), extract::Json(post("abc"))).await.unwrap();
+    let Json(retry) = create_event(State(state.clone()), Default::default(), extract::Json(post("abc"))).await.unwrap();
+    assert_eq!(first.data, retry.data);
+    assert_eq!(read_log(&path).unwrap().len(), 1);
+
+    // Header, which wins over the body field
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert("idempotency-key", "xyz".parse().unwrap());
+    for _ in 0..2 {
+        let _ = create_event(State(state.clone()), headers.clone(), extract::Json(post("ignored"))).await.unwrap();
+    }
+    assert_eq!(read_log(&path).unwrap().len(), 2);
+
+    // No key: every post is logged
+    let plain = || EventInput { event: "START GAME valorant".to_string(), idempotency_key: None, timestamp: None };
+    let _ = create_event(State(state.clone()), Default::default(), extract::Json(plain())).await.unwrap();
+    let _ = create_event(State(state), Default::default(), extract::Json(plain())).await.unwrap();
+    assert_eq!(read_log(&path).unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_close_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let state = AppState::new(path.clone());
+
+    // Nothing logged yet
+    assert_eq!(close_session(State(state.clone())).await.unwrap_err().status(), StatusCode::CONFLICT);
+
+    append_to_log(&path, "START THEORY pandas\n").unwrap();
+    let Json(response) = close_session(State(state.clone())).await.unwrap();
+    assert_eq!(response.status, "success");
+    let lines = read_log(&path).unwrap();
+    assert!(lines[1].ends_with("STOP THEORY pandas"));
+
+    // Already closed
+    assert_eq!(close_session(State(state.clone())).await.unwrap_err().status(), StatusCode::CONFLICT);
+
+    // Racing closes append one STOP between them
+    append_to_log(&path, "START GAME chess\n").unwrap();
+    let closes: Vec<_> = (0..8).map(|_| tokio::spawn(close_session(State(state.clone())))).collect();
+    let mut closed = 0;
+    for close in closes {
+        closed += usize::from(close.await.unwrap().is_ok());
+    }
+    assert_eq!(closed, 1);
+    assert_eq!(read_log(&path).unwrap().iter().filter(|l| l.ends_with("STOP GAME chess")).count(), 1);
+}
+
+#[tokio::test]
+async fn test_top_and_stale_on_empty_log() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let app = build_router(AppState::new(dir.path().join("master.log")));
+
+    for (uri, key) in [("/projections/top?n=10&window=30d", "top"), ("/projections/stale?category=THEORY", "stale")] {
+        let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body[key], serde_json::json!([]));
+    }
+
+    let request = axum::http::Request::builder().uri("/projections/top?window=soon").body(axum::body::Body::empty()).unwrap();
+    assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_current_and_nth_session_routes() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let app = build_router(AppState::new(path.clone()));
+
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+    let body = |response: axum::response::Response| async move {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+    };
+
+    // Nothing active yet
+    let response = app.clone().oneshot(get("/projections/sessions/current")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body(response).await["session"].is_null());
+
+    let started = (chrono::Utc::now() - chrono::Duration::minutes(10)).to_rfc3339();
+    append_to_log(&path, &format!("START THEORY pandas\n{} START PRACTICE rust\n", started)).unwrap();
+
+    let current = body(app.clone().oneshot(get("/projections/sessions/current")).await.unwrap()).await;
+    assert_eq!(current["session"]["activity"], "rust");
+    let elapsed = current["elapsed_minutes"].as_f64().unwrap();
+    assert!((10.0..11.0).contains(&elapsed));
+
+    let first = body(app.clone().oneshot(get("/projections/sessions/0")).await.unwrap()).await;
+    assert_eq!(first["session"]["activity"], "pandas");
+    assert_eq!(first["events"][0]["line"], "START THEORY pandas");
+
+    let response = app.oneshot(get("/projections/sessions/9")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_create_event_with_client_timestamp() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let state = AppState::new(path.clone());
+
+    let input = |timestamp: &str| EventInput {
+        event: "START THEORY pandas".to_string(),
+        idempotency_key: None,
+        timestamp: Some(timestamp.to_string()),
+    };
+
+    let Json(response) = create_event(State(state.clone()), Default::default(), extract::Json(input("2023-05-01T09:00:00+02:00")))
+        .await
+        .unwrap();
+    assert_eq!(response.data.unwrap()["timestamp"], "2023-05-01T07:00:00+00:00");
+    assert_eq!(read_log(&path).unwrap(), vec!["2023-05-01T07:00:00+00:00 START THEORY pandas"]);
+
+    let err = create_event(State(state), Default::default(), extract::Json(input("yesterday"))).await.unwrap_err();
+    assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(read_log(&path).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_events_pattern_filter_pages_matches() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "START THEORY rustbook\nSTART GAME valorant\nSTART THEORY pandas-book\nNOTE book club\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/events?pattern=%5ESTART%20%5CS%2B%20%5CS*book%24&offset=1&limit=5")).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "2");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page, vec![serde_json::json!({ "idx": 2, "line": "START THEORY pandas-book" })]);
+
+    let response = app.oneshot(get("/events?pattern=(unclosed")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(body["error"]["message"].as_str().unwrap().contains("unclosed"));
+}
+
+#[tokio::test]
+async fn test_max_event_len_boundary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let mut state = AppState::new(path.clone());
+    state.max_event_len = 20;
+
+    let input = |event: String| EventInput { event, idempotency_key: None, timestamp: None };
+    let exact = format!("START THEORY {}", "x".repeat(7));
+    assert_eq!(exact.len(), 20);
+
+    // Surrounding whitespace doesn't count
+    let Json(response) = create_event(State(state.clone()), Default::default(), extract::Json(input(format!("  {}\n", exact))))
+        .await
+        .unwrap();
+    assert_eq!(response.status, "success");
+
+    let err = create_event(State(state), Default::default(), extract::Json(input(format!("{}x", exact)))).await.unwrap_err();
+    assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(read_log(&path).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_embedded_newlines_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let state = AppState::new(path.clone());
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n").unwrap();
+
+    let input = |event: &str| EventInput { event: event.to_string(), idempotency_key: None, timestamp: None };
+    for event in ["START THEORY a\nSTART GAME b", "START THEORY a\rSTART GAME b", "START THEORY a\r\nNOTE sneaky"] {
+        let err = create_event(State(state.clone()), Default::default(), extract::Json(input(event))).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST, "{:?}", event);
+    }
+    assert_eq!(read_log(&path).unwrap(), vec!["2024-01-01T09:00:00Z START THEORY pandas"]);
+
+    // A trailing newline is only surrounding whitespace
+    let Json(response) = create_event(State(state), Default::default(), extract::Json(input("STOP THEORY pandas\r\n"))).await.unwrap();
+    assert_eq!(response.status, "success");
+    assert_eq!(read_log(&path).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_sessions_sort_params() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T09:10:00Z START PRACTICE rust\n2024-01-01T11:00:00Z STOP PRACTICE rust\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/projections/sessions?sort=duration&order=desc")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["sessions"][0]["activity"], "rust");
+
+    let response = app.oneshot(get("/projections/sessions?sort=mood")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_sessions_filtered_by_tag() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-01-01T09:00:00Z START THEORY pandas focus=high\n\
+         2024-01-01T10:00:00Z START THEORY numpy focus=low\n\
+         2024-01-01T11:00:00Z START PRACTICE rust\n\
+         2024-01-01T12:00:00Z START PRACTICE rust focus=high project=api\n",
+    )
+    .unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+        }
+    };
+    let starts = |body: &serde_json::Value| -> Vec<u64> {
+        body["sessions"].as_array().unwrap().iter().map(|s| s["start_event_idx"].as_u64().unwrap()).collect()
+    };
+
+    let (status, body) = get("/projections/sessions?tag=focus:high").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((starts(&body), body["count"].as_u64()), (vec![0, 3], Some(2)));
+    // Untagged sessions and other values of the key are left out
+    let (_, body) = get("/projections/sessions?tag=focus:medium").await;
+    assert_eq!((starts(&body), body["count"].as_u64()), (vec![], Some(0)));
+    let (_, body) = get("/projections/sessions?tag=project:api&sort=start&order=desc").await;
+    assert_eq!(starts(&body), vec![3]);
+
+    for uri in ["/projections/sessions?tag=focus", "/projections/sessions?tag=focus=high", "/projections/sessions?tag=:high"] {
+        assert_eq!(get(uri).await.0, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_merge_gap_minutes_param() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-01-01T09:00:00Z START THEORY pandas\n\
+         2024-01-01T09:30:00Z STOP THEORY pandas\n\
+         2024-01-01T09:33:00Z START THEORY pandas\n\
+         2024-01-01T10:00:00Z STOP THEORY pandas\n",
+    )
+    .unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // Unmerged by default
+    let (_, body) = get("/projections/sessions").await;
+    assert_eq!(body["count"], 2);
+    assert!(body["sessions"][0].get("fragments").is_none());
+
+    let (_, body) = get("/projections/sessions?merge_gap_minutes=5").await;
+    assert_eq!(body["count"], 1);
+    assert_eq!((&body["sessions"][0]["fragments"], &body["sessions"][0]["duration_minutes"]), (&2.into(), &57.0.into()));
+    let (_, body) = get("/projections/sessions/stats?merge_gap_minutes=5").await;
+    assert_eq!((&body["stats"]["count"], &body["stats"]["max_minutes"]), (&1.into(), &57.0.into()));
+    let (_, body) = get("/projections/daily?metric=sessions&merge_gap_minutes=5").await;
+    assert_eq!(body["daily"]["data"]["days"][0]["categories"]["THEORY"], 1.0);
+    let (_, body) = get("/projections/sessions/0/events?merge_gap_minutes=5").await;
+    assert_eq!(body["events"].as_array().unwrap().len(), 4);
+
+    for uri in ["/projections/sessions?merge_gap_minutes=-1", "/projections/daily?merge_gap_minutes=-1"] {
+        assert_eq!(get(uri).await.0, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_pretty_param_indents_json() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T10:00:00Z STOP THEORY pandas\n").unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let (_, compact) = get("/projections/sessions").await;
+    let (status, pretty) = get("/projections/sessions?pretty=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!compact.contains('\n'));
+    assert!(pretty.contains("\n  \"count\": 1,\n"));
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&pretty).unwrap(), serde_json::from_str::<serde_json::Value>(&compact).unwrap());
+
+    // Any JSON endpoint, error bodies included; other formats are left alone
+    assert!(get("/health?pretty=1").await.1.contains('\n'));
+    let (status, error) = get("/queries/missing/run?pretty=true").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(error.contains('\n'));
+    assert_eq!(get("/projections/sessions?format=csv&pretty=true").await.1, get("/projections/sessions?format=csv").await.1);
+    assert!(!get("/projections/sessions?pretty=false").await.1.contains('\n'));
+}
+
+#[tokio::test]
+async fn test_sessions_ics_export() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "START GAME chess\n\
+         2024-01-01T09:00:00Z START THEORY pandas focus=high\n\
+         2024-01-01T09:20:00Z NOTE groupby; then merge, maybe\n\
+         2024-01-01T10:00:00Z START PRACTICE rust\n\
+         2024-01-01T10:30:00Z STOP PRACTICE rust\n\
+         2024-01-01T11:00:00Z START THEORY numpy\n",
+    )
+    .unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let content_type = response.headers().get("content-type").map(|v| v.to_str().unwrap().to_string());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let (status, content_type, ics) = get("/projections/sessions.ics").await;
+    assert_eq!((status, content_type.as_deref()), (StatusCode::OK, Some("text/calendar; charset=utf-8")));
+    // pandas and rust; chess has no timestamps, numpy is still running
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert!(ics.contains("\r\nX-PROJECT-A-UNTIMED-SESSIONS:1\r\n"));
+    assert!(ics.contains("\r\nSUMMARY:THEORY: pandas\r\nCATEGORIES:THEORY\r\nDESCRIPTION:groupby\\; then merge\\, maybe\r\n"));
+    assert!(ics.contains("\r\nDTSTART:20240101T100000Z\r\nDTEND:20240101T103000Z\r\n"));
+
+    // Same filters as the JSON listing
+    let (_, _, tagged) = get("/projections/sessions.ics?tag=focus:high").await;
+    assert_eq!(tagged.matches("BEGIN:VEVENT").count(), 1);
+    assert!(tagged.contains("SUMMARY:THEORY: pandas"));
+    assert_eq!(get("/projections/sessions.ics?tag=focus").await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_weekly_report_matches_projections() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-04-22T09:00:00Z START THEORY pandas\n\
+         2024-04-22T10:00:00Z STOP THEORY pandas\n\
+         2024-04-28T08:00:00Z TARGET RATIO THEORY PRACTICE 1.5\n\
+         2024-04-29T09:00:00Z START THEORY pandas\n\
+         2024-04-29T10:30:00Z NOTE groupby | merge is the trick\n\
+         2024-04-29T11:00:00Z STOP THEORY pandas\n\
+         2024-04-30T18:00:00Z START PRACTICE rust\n\
+         2024-04-30T19:00:00Z STOP PRACTICE rust\n\
+         2024-05-01T09:00:00Z START THEORY numpy\n\
+         2024-05-01T09:45:00Z STOP THEORY numpy\n\
+         2024-05-06T09:00:00Z START GAME chess\n\
+         2024-05-06T10:00:00Z STOP GAME chess\n",
+    )
+    .unwrap();
+    let mut state = AppState::new(path);
+    state.clock = Arc::new(FixedClock::new("2024-05-07T12:00:00Z".parse().unwrap()));
+    let app = build_router(state);
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let content_type = response.headers().get("content-type").map(|v| v.to_str().unwrap().to_string());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+    let json = |body: &str| serde_json::from_str::<serde_json::Value>(body).unwrap();
+
+    let (status, _, body) = get("/reports/weekly?week=2024-W18&format=json").await;
+    assert_eq!(status, StatusCode::OK);
+    let report = json(&body);
+    assert_eq!((&report["start"], &report["end"], &report["partial"]), (&"2024-04-29".into(), &"2024-05-05".into(), &false.into()));
+
+    // Every figure is what the projection it comes from says
+    let weekly = json(&get("/projections/weekly").await.2);
+    let row = weekly["rollup"]["data"]["periods"].as_array().unwrap().iter().find(|p| p["start"] == "2024-04-29").unwrap().clone();
+    for field in ["sessions", "minutes", "categories", "theory_to_practice", "delta"] {
+        assert_eq!(report[field], row[field], "{}", field);
+    }
+    assert_eq!((&report["sessions"], &report["minutes"]), (&3.into(), &225.0.into()));
+    let top = json(&get("/projections/top?from=2024-04-29&to=2024-05-05&n=5").await.2);
+    assert_eq!(report["top_activities"], top["top"]);
+    assert_eq!(report["top_activities"][0]["activity"], "pandas");
+    assert_eq!(report["streaks"], json(&get("/projections/streaks").await.2)["streaks"]);
+    let target = json(&get("/projections/ratios/target?from=2024-04-29&to=2024-05-05").await.2);
+    assert_eq!(report["target"], target["report"]["target"]);
+    assert_eq!(report["target_deviation"], target["report"]["current"]);
+    assert_eq!(report["target_deviation"]["numerator_minutes"], 165.0);
+    assert_eq!(report["notes"][0]["text"], "groupby | merge is the trick");
+
+    let (status, content_type, markdown) = get("/reports/weekly?week=2024-W18&format=markdown").await;
+    assert_eq!((status, content_type.as_deref()), (StatusCode::OK, Some("text/markdown; charset=utf-8")));
+    assert!(markdown.starts_with("# Weekly review 2024-W18 (2024-04-29 to 2024-05-05)\n"));
+    assert!(markdown.contains("| THEORY | 2 | 165 |"));
+    assert!(markdown.contains("- 2024-04-29 10:30: groupby | merge is the trick"));
+    // Markdown is the default, and the current week is
+    let (_, _, current) = get("/reports/weekly").await;
+    assert!(current.starts_with("# Weekly review 2024-W19 (2024-05-06 to 2024-05-12)\n\n_Week in progress._"));
+
+    for uri in ["/reports/weekly?week=2024-18", "/reports/weekly?week=2024-W60", "/reports/weekly?format=html"] {
+        assert_eq!(get(uri).await.0, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+    assert_eq!(get("/projections/top?window=7d&from=2024-04-29").await.0, StatusCode::BAD_REQUEST);
+}
+
+/// Minimal JSON Schema check for the subset utoipa emits: `$ref`,
+/// `oneOf`, `type` (single or list), `enum`, `properties`, `required`,
+/// `additionalProperties` and `items`
+fn check_schema(doc: &serde_json::Value, schema: &serde_json::Value, value: &serde_json::Value, at: &str) {
+    use serde_json::Value;
+
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        let target = &doc["components"]["schemas"][name];
+        assert!(!target.is_null(), "{}: dangling $ref {}", at, reference);
+        return check_schema(doc, target, value, at);
+    }
+    if let Some(options) = schema["oneOf"].as_array() {
+        let matching = options
+            .iter()
+            .filter(|option| std::panic::catch_unwind(|| check_schema(doc, option, value, at)).is_ok())
+            .count();
+        assert_eq!(matching, 1, "{}: {} matches {} oneOf options", at, value, matching);
+        return;
+    }
+
+    let kind = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    let allowed: Vec<&str> = match &schema["type"] {
+        Value::String(t) => vec![t.as_str()],
+        Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    assert!(
+        allowed.is_empty() || allowed.contains(&kind) || (kind == "integer" && allowed.contains(&"number")),
+        "{}: {} is {}, documented as {:?}", at, value, kind, allowed,
+    );
+    if let Some(options) = schema["enum"].as_array() {
+        assert!(options.contains(value), "{}: {} not in {:?}", at, value, options);
+    }
+
+    match value {
+        Value::Object(fields) => {
+            for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                assert!(fields.contains_key(name), "{}: missing required field {}", at, name);
+            }
+            for (name, field) in fields {
+                let path = format!("{}.{}", at, name);
+                match schema["properties"].get(name) {
+                    Some(property) => check_schema(doc, property, field, &path),
+                    None => match &schema["additionalProperties"] {
+                        Value::Bool(false) => panic!("{}: undocumented field", path),
+                        Value::Object(_) => check_schema(doc, &schema["additionalProperties"], field, &path),
+                        _ => assert!(schema["properties"].is_null(), "{}: undocumented field", path),
+                    },
+                }
+            }
+        }
+        Value::Array(items) if schema.get("items").is_some() => {
+            for (i, item) in items.iter().enumerate() {
+                check_schema(doc, &schema["items"], item, &format!("{}[{}]", at, i));
+            }
+        }
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn test_openapi_schemas_match_responses() {
+    use crate::openapi::{RatiosEnvelope, SessionsEnvelope};
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-01-01T09:00:00Z START THEORY pandas project=api\n\
+         2024-01-01T09:30:00Z STOP THEORY pandas\n\
+         START PRACTICE rust\n\
+         2024-01-01T11:00:00Z START GAME valorant\n\
+         2024-01-01T11:05:00Z TARGET RATIO THEORY PRACTICE 2.0\n\
+         SAVEQUERY weekly {\"type\": \"ratios\"}\n",
+    )
+    .unwrap();
+    let app = build_router(AppState::new(path));
+    let doc = crate::openapi::spec();
+
+    for (path, uri) in [
+        ("/projections/sessions", "/projections/sessions"),
+        ("/projections/ratios", "/projections/ratios"),
+        ("/projections/sessions/current", "/projections/sessions/current"),
+        ("/projections/sessions/stats", "/projections/sessions/stats?category=THEORY"),
+        ("/projections/sessions/{idx}", "/projections/sessions/0"),
+        ("/projections/allocation", "/projections/allocation"),
+        ("/projections/top", "/projections/top?window=30d"),
+        ("/projections/ratios/trend", "/projections/ratios/trend"),
+        ("/projections/ratios/rolling", "/projections/ratios/rolling?window=12h&step=6h"),
+        ("/projections/ratios/target", "/projections/ratios/target?tolerance=0.2"),
+        ("/projections/busiest-day", "/projections/busiest-day"),
+        ("/projections/cadence", "/projections/cadence"),
+        ("/projections/cadence/moving-average", "/projections/cadence/moving-average?window=3"),
+        ("/projections/forecast", "/projections/forecast?category=THEORY&horizon=2"),
+        ("/projections/streaks", "/projections/streaks?min_minutes=10"),
+        ("/projections/records", "/projections/records"),
+        ("/projections/alerts", "/projections/alerts"),
+        ("/projections/switching", "/projections/switching?window=30d&threshold=1"),
+        ("/projections/transitions", "/projections/transitions?ignore_gaps=true"),
+        ("/projections/day/{date}", "/projections/day/2024-01-01"),
+        ("/projections/heatmap", "/projections/heatmap?tz=Europe/Dublin"),
+        ("/projections/span", "/projections/span"),
+        ("/projections/longest-break", "/projections/longest-break"),
+        ("/projections/duration-histogram", "/projections/duration-histogram?buckets=15m,1h&exclude_active=true"),
+        ("/projections/bundle", "/projections/bundle"),
+        ("/projections", "/projections"),
+        ("/events/tail", "/events/tail"),
+        ("/search", "/search?q=pandas"),
+        ("/health", "/health"),
+        ("/health/live", "/health/live"),
+        ("/queries", "/queries"),
+        ("/status", "/status"),
+        ("/projections/conflicts", "/projections/conflicts"),
+        ("/projections/tags", "/projections/tags"),
+        ("/queries/{name}/run", "/queries/weekly/run"),
+        ("/health/ready", "/health/ready"),
+        ("/reports/weekly", "/reports/weekly?format=json"),
+        ("/admin/verify", "/admin/verify"),
+    ] {
+        let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let schema = &doc["paths"][path]["get"]["responses"]["200"]["content"]["application/json"]["schema"];
+        assert!(!schema.is_null(), "{} has no documented JSON body", path);
+        check_schema(&doc, schema, &body, uri);
+
+        // The documented envelopes are real types: the payloads must load into them
+        match path {
+            "/projections/sessions" => {
+                let sessions: SessionsEnvelope = serde_json::from_value(body).unwrap();
+                assert_eq!(sessions.count, 3);
+                assert_eq!(sessions.sessions[0].metadata["project"], "api");
+            }
+            "/projections/ratios" => {
+                let ratios: RatiosEnvelope = serde_json::from_value(body).unwrap();
+                // The three STARTs; the STOP closes a session rather than adding one
+                assert_eq!(ratios.analysis.data.total_events, 3);
+            }
+            _ => {}
+        }
+    }
+
+    let request = axum::http::Request::builder().uri("/docs/").body(axum::body::Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_saved_queries_run_like_posted_ones() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-01-01T09:00:00Z START THEORY pandas\n\
+         2024-01-01T10:00:00Z START PRACTICE rust\n\
+         SAVEQUERY daily {\"type\": \"recent\"}\n\
+         SAVEQUERY daily {\"type\": \"by_day\", \"metric\": \"minutes\"}\n\
+         SAVEQUERY weekly-theory {\"type\":\"by_day\",\"category\":\"THEORY\",\"window\":\"7d\"}\n\
+         SAVEQUERY clash {\"type\": \"by_day\", \"window\": \"7d\", \"from\": \"2024-01-01\"}\n\
+         SAVEQUERY broken {\"type\":\n",
+    )
+    .unwrap();
+    let mut state = AppState::new(path);
+    state.clock = Arc::new(FixedClock::new("2024-01-03T12:00:00Z".parse().unwrap()));
+    let app = build_router(state.clone());
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+        }
+    };
+
+    let (status, listed) = get("/queries").await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = listed["queries"].as_array().unwrap().iter().map(|q| q["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["broken", "clash", "daily", "weekly-theory"]);
+    assert!(listed["queries"][0]["error"].as_str().unwrap().starts_with("Invalid JSON"));
+    assert_eq!(listed["queries"][2]["event_index"], 3);
+
+    // Same body as posting the latest definition
+    let (status, ran) = get("/queries/daily/run").await;
+    assert_eq!(status, StatusCode::OK);
+    let posted = serde_json::json!({ "type": "by_day", "metric": "minutes" });
+    let Json(posted) = handle_query(State(state.clone()), extract::Json(posted)).await.unwrap();
+    assert_eq!(ran, serde_json::to_value(posted).unwrap());
+
+    // The last 7 days up to the clock's, THEORY only
+    let (status, ran) = get("/queries/weekly-theory/run").await;
+    assert_eq!(status, StatusCode::OK);
+    let days = ran["data"]["days"].as_array().unwrap();
+    assert_eq!((days.len(), days[0]["date"].as_str(), days[6]["date"].as_str()), (7, Some("2023-12-28"), Some("2024-01-03")));
+    assert_eq!(days[4], serde_json::json!({ "date": "2024-01-01", "total": 1.0, "categories": { "THEORY": 1.0 } }));
+
+    // Rejected the way /query rejects it
+    let (status, ran) = get("/queries/clash/run").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(ran["error"]["message"], "Invalid query: window can't be combined with from or to");
+    let posted = serde_json::json!({ "type": "by_day", "window": "7d", "from": "2024-01-01" });
+    let (_, posted) = error_parts(handle_query(State(state), extract::Json(posted)).await.unwrap_err());
+    assert_eq!(ran, posted);
+
+    assert_eq!(get("/queries/broken/run").await.0, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(get("/queries/missing/run").await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_events_content_negotiation() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let log = "2024-01-01T09:00:00Z START THEORY pandas\r\n\n2024-01-01T10:00:00Z START PRACTICE rust, fast\nNOTE \"quoted\"\n";
+    std::fs::write(&path, log).unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |uri: &str, accept: &str| {
+        axum::http::Request::builder()
+            .uri(uri)
+            .header("accept", accept)
+            .body(axum::body::Body::empty())
+            .unwrap()
+    };
+    let body = |response: axum::response::Response| async {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    };
+
+    // Plain text is the log itself, byte for byte
+    let response = app.clone().oneshot(get("/events", "text/plain")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(body(response).await, log);
+
+    let response = app.clone().oneshot(get("/events?offset=1&limit=1", "text/plain")).await.unwrap();
+    assert_eq!(body(response).await, "2024-01-01T10:00:00Z START PRACTICE rust, fast\n");
+    let response = app.clone().oneshot(get("/events?limit=2", "text/plain")).await.unwrap();
+    assert_eq!(body(response).await, &log[..log.find("NOTE").unwrap()]);
+    let response = app.clone().oneshot(get("/events?category=THEORY", "text/plain")).await.unwrap();
+    assert_eq!(body(response).await, "2024-01-01T09:00:00Z START THEORY pandas\r\n");
+
+    let response = app.clone().oneshot(get("/events", "text/csv")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "text/csv; charset=utf-8");
+    let csv = body(response).await;
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "idx,timestamp,verb,category,activity,raw");
+    assert_eq!(lines[2], "1,2024-01-01T10:00:00+00:00,START,PRACTICE,\"rust,\",");
+    assert_eq!(lines.len(), 4);
+
+    // The query param beats the header
+    let response = app.clone().oneshot(get("/events?format=ndjson", "text/csv")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let response = app.clone().oneshot(get("/events", "text/html")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    let response = app.oneshot(get("/events", "text/html, */*;q=0.1")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "application/json");
+}
+
+#[tokio::test]
+async fn test_projection_content_negotiation() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T09:30:00Z START PRACTICE rust\n").unwrap();
+    let app = build_router(AppState::new(path));
+
+    let get = |uri: &str, accept: &str| {
+        axum::http::Request::builder()
+            .uri(uri)
+            .header("accept", accept)
+            .body(axum::body::Body::empty())
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(get("/projections/sessions", "text/csv")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["vary"], "accept");
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let csv = String::from_utf8(bytes.to_vec()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("activity,category,duration_minutes,"));
+    assert!(lines[1].starts_with("pandas,THEORY,30.0,"));
+
+    let response = app.clone().oneshot(get("/projections/sessions?format=ndjson", "text/csv")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let rows: Vec<serde_json::Value> = bytes
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1]["category"], "PRACTICE");
+
+    // Projections have no raw lines to offer
+    let response = app.clone().oneshot(get("/projections/sessions", "text/plain")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+    // Errors pass through untouched
+    let response = app.oneshot(get("/projections/sessions/9", "text/csv")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_post_body_limit() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let mut state = AppState::new(path.clone());
+    state.max_body_bytes = 1024;
+    let app = build_router(state);
+
+    let post = |uri: &str, body: String| {
+        axum::http::Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body))
+            .unwrap()
+    };
+    let huge = serde_json::json!({ "query": "x".repeat(4096) }).to_string();
+
+    let response = app.clone().oneshot(post("/query", huge.clone())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let response = app.clone().oneshot(post("/events", huge)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(serde_json::from_slice::<crate::error::ErrorBody>(&body).unwrap().error.code, "too_large");
+    assert!(!path.exists());
+
+    // Under the limit is untouched
+    let response = app.oneshot(post("/events", r#"{"event": "START THEORY pandas"}"#.to_string())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_query_explain_plan() {
+    let temp_file = NamedTempFile::new().unwrap();
+    let path = temp_file.path().to_path_buf();
+    append_to_log(&path, "START THEORY pandas\nnot an event\nSTART GAME valorant\nSTART PRACTICE rust\n").unwrap();
+    let state = AppState::new(path);
+
+    let query = serde_json::json!({ "type": "context_switches", "tz": "+02:00", "explain": true });
+    let Json(response) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    let plan = response.plan.unwrap();
+    assert_eq!(plan.query_type, "context_switches");
+    assert_eq!(plan.projector, "SessionProjector");
+    assert_eq!((plan.lines_scanned, plan.lines_unparseable), (4, 1));
+    assert_eq!(plan.filters, serde_json::json!({ "threshold_minutes": 5.0, "tz": "+02:00" }));
+    assert!(plan.elapsed_ms >= 0.0);
+
+    // Legacy text resolves to a type; `recent` with a limit only reads the tail
+    let query = serde_json::json!({ "query": "ratio please", "explain": true });
+    let Json(response) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(response.plan.unwrap().query_type, "ratios");
+    let query = serde_json::json!({ "type": "recent", "limit": 2, "explain": true });
+    let Json(response) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    let plan = response.plan.unwrap();
+    assert_eq!((plan.projector.as_str(), plan.lines_scanned), ("tail", 2));
+
+    // No explain, no plan in the body
+    let query = serde_json::json!({ "type": "allocation", "explain": false });
+    let Json(response) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    let body = serde_json::to_value(&response).unwrap();
+    assert!(body.get("plan").is_none());
+    assert_eq!(body["result_type"], "allocation");
+
+    let query = serde_json::json!({ "type": "allocation", "explain": "yes" });
+    let status = handle_query(State(state), extract::Json(query)).await.unwrap_err().status();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_parse_endpoint() {
+    let parse = |line: &str| parse_line(extract::Json(ParseInput { line: line.to_string() }));
+
+    let Json(result) = parse("2024-01-01T09:00:00+02:00 START THEORY pandas project=api").await;
+    assert!(result.timestamp_detected);
+    let parsed = result.parsed.unwrap();
+    assert_eq!(parsed.timestamp.unwrap().to_rfc3339(), "2024-01-01T07:00:00+00:00");
+    assert_eq!(parsed.verb, EventVerb::Start);
+    assert_eq!(parsed.category.as_deref(), Some("THEORY"));
+    assert_eq!(parsed.activity.as_deref(), Some("pandas"));
+    assert_eq!(parsed.metadata["project"], "api");
+
+    let Json(result) = parse("START THEORY pandas").await;
+    assert!(!result.timestamp_detected);
+    let parsed = result.parsed.unwrap();
+    assert!(parsed.timestamp.is_none());
+    assert_eq!(parsed.activity.as_deref(), Some("pandas"));
+
+    // Lowercase verb: not an event, though the timestamp was fine
+    let Json(result) = parse("2024-01-01T09:00:00Z started theory").await;
+    assert!(result.parsed.is_none());
+    assert!(result.timestamp_detected);
+    assert_eq!(result.line, "2024-01-01T09:00:00Z started theory");
+}
+
+#[tokio::test]
+async fn test_sessions_elapsed_flag() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T10:00:00Z START PRACTICE rust\n").unwrap();
+    let clock = Arc::new(FixedClock::new("2024-01-01T11:30:00Z".parse().unwrap()));
+    let mut state = AppState::new(path);
+    state.clock = clock.clone();
+    let app = build_router(state.clone());
+
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let plain = get("/projections/sessions").await;
+    assert!(plain["sessions"][1]["duration_minutes"].is_null());
+
+    let elapsed = get("/projections/sessions?elapsed=true").await;
+    assert_eq!(elapsed["sessions"][1]["duration_minutes"], 90.0);
+    assert!(elapsed["sessions"][1]["end_time"].is_null());
+    assert_eq!(get("/projections/sessions/current").await["elapsed_minutes"], 90.0);
+
+    // Only the clock moves the answer
+    clock.advance(chrono::Duration::minutes(30));
+    assert_eq!(get("/projections/sessions?elapsed=true").await["sessions"][1]["duration_minutes"], 120.0);
+    let query = serde_json::json!({ "type": "timeline", "elapsed": true });
+    let Json(QueryResponse { result, .. }) = handle_query(State(state.clone()), extract::Json(query)).await.unwrap();
+    assert_eq!(result.data["sessions"][1]["duration_minutes"], 120.0);
+
+    // The cached, flag-less answer is unaffected
+    assert!(get("/projections/sessions").await["sessions"][1]["duration_minutes"].is_null());
+
+    // Appends without a timestamp are stamped by the clock too
+    let Json(response) = close_session(State(state)).await.unwrap();
+    assert!(response.data.unwrap()["event"].as_str().unwrap().starts_with("2024-01-01T12:00:00"));
+}
+
+#[tokio::test]
+async fn test_status_active_and_idle() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2023-12-31T22:00:00Z START GAME chess\n\
+         2024-01-01T09:00:00Z START THEORY pandas\n\
+         2024-01-01T10:30:00Z START GAME chess\n\
+         2024-01-01T11:00:00Z START THEORY numpy\n\
+         2024-01-01T11:10:00Z PAUSE\n\
+         2024-01-01T11:20:00Z RESUME\n",
+    )
+    .unwrap();
+    let clock = Arc::new(FixedClock::new("2024-01-01T11:42:30Z".parse().unwrap()));
+    let mut state = AppState::new(path.clone());
+    state.clock = clock.clone();
+    let app = build_router(state.clone());
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap()
+        }
+    };
+    let status = |body: axum::body::Bytes| serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+    assert_eq!(
+        status(get("/status").await),
+        serde_json::json!({
+            "active": true,
+            "category": "THEORY",
+            "activity": "numpy",
+            // The ten-minute break doesn't count
+            "elapsed_minutes": 32,
+            // The overnight chess session's nine hours past midnight count today
+            "today_minutes": { "GAME": 570, "THEORY": 122 },
+        })
+    );
+    assert_eq!(&get("/status?format=text").await[..], b"THEORY numpy 32m | today GAME 570m THEORY 122m\n");
+
+    // Stopped 17 minutes ago; the DONE line after isn't a session
+    append_to_log(&path, "2024-01-01T12:00:00Z STOP THEORY numpy\n2024-01-01T12:10:00Z DONE kata\n").unwrap();
+    state.log_changed();
+    clock.advance(chrono::Duration::minutes(35));
+    assert_eq!(
+        status(get("/status").await),
+        serde_json::json!({ "active": false, "idle_minutes": 17, "today_minutes": { "GAME": 570, "THEORY": 140 } })
+    );
+    assert_eq!(&get("/status?format=text").await[..], b"idle 17m | today GAME 570m THEORY 140m\n");
+
+    // A new day starts empty
+    clock.advance(chrono::Duration::days(1));
+    assert_eq!(&get("/status?format=text").await[..], b"idle 1457m\n");
+}
+
+#[tokio::test]
+async fn test_autostop_stale_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(&path, "2024-01-01T09:00:00Z START THEORY pandas\n").unwrap();
+    let clock = Arc::new(FixedClock::new("2024-01-01T12:00:00Z".parse().unwrap()));
+    let mut state = AppState::new(path.clone());
+    state.clock = clock.clone();
+    state.autostop.max_session_minutes = Some(240.0);
+
+    // Not yet past the limit
+    assert_eq!(state.autostop_stale_session().unwrap(), None);
+
+    // Hours later: stopped once, at the limit
+    clock.advance(chrono::Duration::hours(11));
+    let line = state.autostop_stale_session().unwrap().unwrap();
+    assert_eq!(line, "2024-01-01T13:00:00+00:00 AUTOSTOP THEORY pandas reason=stale");
+    assert_eq!(state.autostop_stale_session().unwrap(), None);
+    let session = state.session_projector().get_all_sessions().pop().unwrap();
+    assert_eq!((session.is_active, session.duration_minutes), (false, Some(240.0)));
+    assert_eq!(session.closed_by, Some(crate::models::ClosedBy::Auto));
+
+    // A session that already ended, however long, is left alone
+    append_to_log(&path, "2024-01-01T23:00:00Z START GAME chess\n2024-01-02T09:00:00Z STOP GAME chess\n").unwrap();
+    clock.advance(chrono::Duration::days(1));
+    assert_eq!(state.autostop_stale_session().unwrap(), None);
+    assert_eq!(read_log(&path).unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_exclude_active_from_stats_and_allocation() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    append_to_log(
+        &path,
+        "2024-01-01T09:00:00Z START THEORY pandas\n\
+         2024-01-01T10:00:00Z STOP THEORY pandas\n\
+         2024-01-01T10:00:00Z START GAME chess\n",
+    )
+    .unwrap();
+    let mut state = AppState::new(path);
+    state.clock = Arc::new(FixedClock::new("2024-01-01T10:30:00Z".parse().unwrap()));
+    let app = build_router(state);
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            let body = axum::body::to_bytes(app.oneshot(request).await.unwrap().into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    // Included by default, with its 30 minutes so far
+    let allocation = get("/projections/allocation").await;
+    assert_eq!(allocation["allocation"]["data"]["total_minutes"], 90.0);
+    assert_eq!(allocation["allocation"]["data"]["categories"][1]["category"], "GAME");
+    let stats = get("/projections/sessions/stats").await;
+    assert_eq!((stats["stats"]["count"].as_u64(), stats["stats"]["total_minutes"].as_f64()), (Some(2), Some(90.0)));
+    assert_eq!(stats["stats"]["mean_minutes"], 45.0);
+
+    let allocation = get("/projections/allocation?exclude_active=true").await;
+    assert_eq!(allocation["allocation"]["data"]["total_minutes"], 60.0);
+    assert_eq!(allocation["allocation"]["data"]["categories"].as_array().unwrap().len(), 1);
+    let stats = get("/projections/sessions/stats?exclude_active=true").await;
+    assert_eq!((stats["stats"]["count"].as_u64(), stats["stats"]["excluded"].as_u64()), (Some(1), Some(0)));
+    assert_eq!(stats["stats"]["mean_minutes"], 60.0);
+}
+
+#[tokio::test]
+async fn test_raw_log_download() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let get = || axum::http::Request::builder().uri("/log/raw").body(axum::body::Body::empty()).unwrap();
+
+    let app = build_router(AppState::new(path.clone()));
+    let response = app.clone().oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Bigger than one read chunk, with blank lines, CRLF and non-ASCII
+    let mut contents = String::from("START THEORY pandas\r\n\nNOTE café ☕\n");
+    for i in 0..3000 {
+        contents.push_str(&format!("2024-01-01T09:00:00Z NOTE filler line {}\n", i));
+    }
+    contents.push_str("no trailing newline");
+    std::fs::write(&path, &contents).unwrap();
+
+    let response = app.oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(response.headers()["content-disposition"], "attachment; filename=\"master.log\"");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(body.len() > 64 * 1024);
+    assert_eq!(body.as_ref(), std::fs::read(&path).unwrap().as_slice());
+}
+
+#[tokio::test]
+async fn test_metrics_history_is_backfillable() {
+    use tower::ServiceExt;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "2024-01-01T09:00:00Z START THEORY pandas").unwrap();
+    writeln!(temp_file, "2024-01-01T10:00:00Z START PRACTICE rust").unwrap();
+    writeln!(temp_file, "2024-01-01T10:30:00Z STOP PRACTICE rust").unwrap();
+    writeln!(temp_file, "2024-01-03T09:00:00Z START THEORY numpy").unwrap();
+    let app = build_router(AppState::new(temp_file.path().to_path_buf()));
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/metrics/history")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("application/openmetrics-text"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+
+    // The empty day in between is a zero sample, not a gap
+    assert_eq!(&lines[2..5], &[
+        "project_a_daily_sessions{category=\"PRACTICE\"} 1 1704067200",
+        "project_a_daily_sessions{category=\"PRACTICE\"} 0 1704153600",
+        "project_a_daily_sessions{category=\"PRACTICE\"} 0 1704240000",
+    ]);
+    assert!(text.contains("project_a_daily_sessions{category=\"THEORY\"} 1 1704240000\n"));
+    assert!(text.ends_with("# EOF\n"));
+
+    let response = app.clone().oneshot(get("/metrics/history?metric=minutes&from=2024-01-01&to=2024-01-01")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("project_a_daily_minutes{category=\"PRACTICE\"} 30 1704067200"));
+
+    let response = app.oneshot(get("/metrics/history?from=2024-02-01&to=2024-01-01")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_readiness_probe() {
+    use tower::ServiceExt;
+
+    let get = || axum::http::Request::builder().uri("/health/ready").body(axum::body::Body::empty()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    // No log yet, but it can be created; nothing is left behind
+    let path = dir.path().join("logs/master.log");
+    let response = build_router(AppState::new(path.clone())).oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!path.exists());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    // Concurrent probes don't trip over each other's files
+    let app = build_router(AppState::new(path.clone()));
+    let probes: Vec<_> = (0..16).map(|_| tokio::spawn(app.clone().oneshot(get()))).collect();
+    for probe in probes {
+        assert_eq!(probe.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    // An existing log is checked without being touched
+    append_to_log(&path, "START THEORY pandas\n").unwrap();
+    let response = build_router(AppState::new(path.clone())).oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "START THEORY pandas\n");
+
+    // A file where the log directory should be can't be written under,
+    // whatever the permissions
+    let blocker = dir.path().join("blocker");
+    std::fs::write(&blocker, "").unwrap();
+    let app = build_router(AppState::new(blocker.join("master.log")));
+    let response = app.clone().oneshot(get()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert!(body["reason"].is_string());
+
+    // Liveness doesn't care
+    let live = axum::http::Request::builder().uri("/health/live").body(axum::body::Body::empty()).unwrap();
+    assert_eq!(app.oneshot(live).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_dashboard_served_over_empty_log() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let app = build_router(AppState::new(dir.path().join("master.log")));
+    let get = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            app.oneshot(request).await.unwrap()
+        }
+    };
+
+    let response = get("/dashboard").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/html"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page = String::from_utf8(body.to_vec()).unwrap();
+
+    // Everything the page fetches answers before anything is logged
+    for uri in ["/status", "/projections/sessions", "/projections/ratios"] {
+        assert!(page.contains(&format!("load(\"{}\")", uri)), "{} not fetched", uri);
+        assert_eq!(get(uri).await.status(), StatusCode::OK, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_config_shows_effective_settings() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("config.toml");
+    std::fs::write(&file, "week_start = \"sunday\"\nday_start_hour = 4\n").unwrap();
+    let args = vec!["--day-start-hour".to_string(), "5".to_string()];
+    let env = |name: &str| (name == "CONFIG_FILE").then(|| file.display().to_string());
+    let loaded = crate::config::Config::load(&args, env).unwrap();
+
+    let mut state = AppState::new(dir.path().join("master.log"));
+    state.config = Arc::new(loaded.report);
+    let app = build_router(state);
+    let request = axum::http::Request::builder().uri("/config").body(axum::body::Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let report: crate::config::ConfigReport = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(report.file, Some(file.display().to_string()));
+    let setting = |key: &str| serde_json::to_value(&report.settings[key]).unwrap();
+    assert_eq!(setting("day_start_hour"), serde_json::json!({"value": "5", "source": "cli"}));
+    assert_eq!(setting("week_start"), serde_json::json!({"value": "sunday", "source": "file"}));
+    assert_eq!(setting("bind"), serde_json::json!({"value": "127.0.0.1:8080", "source": "default"}));
+}
+
+#[tokio::test]
+async fn test_errors_are_structured_json() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let app = build_router(AppState::new(dir.path().join("master.log")));
+    let send = |request: axum::http::Request<axum::body::Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<crate::error::ErrorBody>(&body).unwrap())
+        }
+    };
+
+    // Nothing logged yet
+    let request = axum::http::Request::builder().uri("/log/raw").body(axum::body::Body::empty()).unwrap();
+    let (status, body) = send(request).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.error.code, "log_missing");
+    assert_eq!(body.error.detail, Some(serde_json::json!({ "kind": "NotFound" })));
+
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"event": "START THEORY a\nSTART GAME b"}"#))
+        .unwrap();
+    let (status, body) = send(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.error.code, "invalid_input");
+    assert_eq!(body.error.message, "Event must be a single line");
+    assert_eq!(body.error.detail, None);
+
+    // Extractor rejections get the same body
+    let request = axum::http::Request::builder().uri("/events/tail?n=abc").body(axum::body::Body::empty()).unwrap();
+    let (status, body) = send(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.error.code, "invalid_input");
+    assert!(body.error.message.contains("invalid digit"), "{}", body.error.message);
+
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"evnt": "x"}"#))
+        .unwrap();
+    let (status, body) = send(request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.error.code, "invalid_input");
+    assert!(body.error.message.contains("missing field `event`"), "{}", body.error.message);
+
+    let request = axum::http::Request::builder().method("POST").uri("/sessions/close").body(axum::body::Body::empty()).unwrap();
+    let (status, body) = send(request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!((body.error.code.as_str(), body.error.message.as_str()), ("conflict", "No active session"));
+}
+
+#[tokio::test]
+async fn test_admin_rebuild_picks_up_log_surgery() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    std::fs::write(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T10:00:00Z END\n").unwrap();
+    let app = build_router(AppState::new(path.clone()));
+    let sessions = || async {
+        let request = axum::http::Request::builder().uri("/projections/sessions").body(axum::body::Body::empty()).unwrap();
+        let body = axum::body::to_bytes(app.clone().oneshot(request).await.unwrap().into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()["sessions"].clone()
+    };
+    assert_eq!(sessions().await[0]["category"], "THEORY");
+
+    // Fixed by hand, without WATCH_LOG: the cached projection is stale
+    std::fs::write(
+        &path,
+        "2024-01-01T09:00:00Z START PRACTICE pandas\n2024-01-01T10:00:00Z END\n2024-01-01T11:00:00Z START GAME chess\n",
+    )
+    .unwrap();
+    assert_eq!(sessions().await.as_array().unwrap().len(), 1);
+
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/admin/rebuild")
+        .body(axum::body::Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let report: crate::models::RebuildReport = serde_json::from_slice(&body).unwrap();
+    assert_eq!((report.lines, report.events_indexed), (3, 3));
+    assert!(report.total_ms >= report.read_ms);
+
+    let sessions = sessions().await;
+    assert_eq!(sessions.as_array().unwrap().len(), 2);
+    assert_eq!(sessions[0]["category"], "PRACTICE");
+}
+
+#[tokio::test]
+async fn test_metrics_track_appends_and_requests() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let app = build_router(AppState::new(dir.path().join("master.log")));
+    let post = |event: &str| {
+        axum::http::Request::builder()
+            .method("POST")
+            .uri("/events")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(serde_json::json!({ "event": event }).to_string()))
+            .unwrap()
+    };
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    assert_eq!(app.clone().oneshot(post("START THEORY pandas")).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(app.clone().oneshot(post("NOTE one\ntwo")).await.unwrap().status(), StatusCode::BAD_REQUEST);
+    app.clone().oneshot(get("/projections/sessions/0")).await.unwrap();
+    app.clone().oneshot(get("/projections/sessions/0")).await.unwrap();
+
+    let response = app.oneshot(get("/metrics")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    let sample = |series: &str| {
+        text.lines().find_map(|line| line.strip_prefix(series)).map(str::trim).unwrap_or_else(|| panic!("{} in {}", series, text))
+    };
+
+    assert_eq!(sample("project_a_events_appended_total"), "1");
+    assert_eq!(sample("project_a_append_errors_total{reason=\"invalid\"}"), "1");
+    assert_eq!(sample("project_a_log_lines"), "1");
+    let bytes: u64 = sample("project_a_log_bytes").parse().unwrap();
+    assert_eq!(bytes, std::fs::metadata(dir.path().join("master.log")).unwrap().len());
+    assert_eq!(sample("project_a_session_active"), "1");
+    // Routes by template, the two session reads in one series
+    assert_eq!(
+        sample("project_a_http_request_duration_seconds_count{method=\"GET\",route=\"/projections/sessions/:idx\"}"),
+        "2"
+    );
+    assert_eq!(sample("project_a_http_request_duration_seconds_count{method=\"POST\",route=\"/events\"}"), "2");
+}
+
+#[tokio::test]
+async fn test_transitions_reject_gaps_too_long_to_represent() {
+    use tower::ServiceExt;
+
+    let temp_file = NamedTempFile::new().unwrap();
+    let app = build_router(AppState::new(temp_file.path().to_path_buf()));
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/projections/transitions?ignore_gaps=true&max_gap_hours=1e16")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = app.oneshot(get("/projections/transitions?ignore_gaps=true&max_gap_hours=1e6")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_duration_histogram_rejects_unordered_buckets() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    std::fs::write(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T09:20:00Z STOP THEORY pandas\n").unwrap();
+    let app = build_router(AppState::new(path));
+    let get = |uri: &str| axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+
+    let response = app.clone().oneshot(get("/projections/duration-histogram?buckets=1h,30m")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["message"], "Buckets must be in ascending order: 30m doesn't come after 1h");
+
+    let response = app.oneshot(get("/projections/duration-histogram")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let labels: Vec<&str> = body["histogram"]["buckets"].as_array().unwrap().iter().map(|b| b["label"].as_str().unwrap()).collect();
+    assert_eq!(labels, ["<5m", "5m-15m", "15m-30m", "30m-1h", "1h-2h", "2h+"]);
+    assert_eq!(body["histogram"]["buckets"][2]["sessions"], 1);
+}
+
+#[tokio::test]
+async fn test_bearer_tokens_and_scopes() {
+    use crate::auth::{ApiToken, Scope};
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let open = AppState::new(dir.path().join("master.log"));
+    let token = |token: &str, scope| ApiToken { name: None, token: token.to_string(), scope };
+    let mut state = open.clone();
+    state.tokens = Arc::new(vec![token("reader-token", Scope::Read), token("writer-token", Scope::Write)]);
+    let app = build_router(state);
+    let request = |method: &str, uri: &str, bearer: Option<&str>| {
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        if method == "POST" {
+            request = request.header("content-type", "application/json");
+        }
+        if let Some(bearer) = bearer {
+            request = request.header("authorization", format!("Bearer {}", bearer));
+        }
+        request.body(axum::body::Body::from(r#"{"event": "START THEORY pandas"}"#)).unwrap()
+    };
+    let send = |request: axum::http::Request<axum::body::Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let challenge = response.headers().get("www-authenticate").cloned();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, challenge, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // Missing or unknown token: 401 with the challenge
+    let (status, challenge, body) = send(request("GET", "/events", None)).await;
+    assert_eq!((status, challenge.unwrap().to_str().unwrap()), (StatusCode::UNAUTHORIZED, "Bearer"));
+    assert_eq!(body["error"]["code"], "unauthorized");
+    let (status, _, _) = send(request("GET", "/events", Some("guess"))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A read token can't append, or reach admin endpoints
+    let (status, _, body) = send(request("POST", "/events", Some("reader-token"))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "forbidden");
+    assert_eq!(body["error"]["message"], "This needs the write scope; the token has read");
+    assert_eq!(send(request("POST", "/admin/rebuild", Some("writer-token"))).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send(request("GET", "/config", Some("writer-token"))).await.0, StatusCode::FORBIDDEN);
+
+    // The right scope, or a broader one, goes through
+    assert_eq!(send(request("POST", "/events", Some("writer-token"))).await.0, StatusCode::OK);
+    assert_eq!(send(request("GET", "/events", Some("writer-token"))).await.0, StatusCode::OK);
+    assert_eq!(send(request("POST", "/query", Some("reader-token"))).await.0, StatusCode::OK);
+
+    // Health checks never need a token, nor does the dashboard page
+    assert_eq!(send(request("GET", "/health", None)).await.0, StatusCode::OK);
+    assert_eq!(send(request("GET", "/dashboard", None)).await.0, StatusCode::OK);
+
+    // Any case of the scheme
+    let mut lowercase = request("GET", "/events", None);
+    lowercase.headers_mut().insert("authorization", "bearer reader-token".parse().unwrap());
+    assert_eq!(send(lowercase).await.0, StatusCode::OK);
+
+    // No tokens configured: open, as before
+    let app = build_router(open);
+    let response = app.clone().oneshot(request("POST", "/events", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.oneshot(request("GET", "/config", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_reads_fail_over_to_the_fallback_log() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (primary, backup) = (dir.path().join("master.log"), dir.path().join("backup.log"));
+    std::fs::write(&backup, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T10:00:00Z STOP THEORY pandas\n").unwrap();
+    let mut state = AppState::new(primary.clone());
+    state.set_fallback_log_path(Some(backup.clone()));
+    let app = build_router(state);
+    let get = |uri: &str| {
+        let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", response.status());
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    // The primary is absent: projections and raw reads come from the copy
+    let sessions = get("/projections/sessions").await;
+    assert_eq!(sessions["sessions"][0]["duration_minutes"], 60.0);
+    assert_eq!(get("/events/tail?n=1").await[0]["line"], "2024-01-01T10:00:00Z STOP THEORY pandas");
+    assert_eq!(get("/search?q=pandas").await["matches"].as_array().map(Vec::len), Some(2));
+    // Catch-ups and counts too, numbered as in the copy
+    let since = get("/events?since=1").await;
+    assert_eq!(since["total"], 2);
+    assert_eq!(since["events"][0]["idx"], 1);
+    assert_eq!(since["events"][0]["line"], "2024-01-01T10:00:00Z STOP THEORY pandas");
+    assert_eq!(get("/health").await["events"], 2);
+    assert_eq!(get("/events/tail?n=1").await[0]["idx"], 1);
+
+    // Writes still only go to the primary
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"event": "2024-01-02T09:00:00Z START PRACTICE rust"}"#))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(&primary).unwrap(), "2024-01-02T09:00:00Z START PRACTICE rust\n");
+    assert_eq!(std::fs::read_to_string(&backup).unwrap().lines().count(), 2);
+    assert_eq!(get("/projections/sessions").await["sessions"][0]["category"], "PRACTICE");
+    assert_eq!(get("/events?since=0").await["total"], 1);
+}
+
+#[tokio::test]
+async fn test_appends_are_rate_limited_per_client() {
+    use axum::extract::ConnectInfo;
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut state = AppState::new(dir.path().join("master.log"));
+    // Two at once, then one a minute
+    state.rate_limiter = crate::ratelimit::RateLimiter::new(1, 2);
+    let app = build_router(state);
+    let request = |method: &str, uri: &str, ip: [u8; 4]| {
+        let mut request = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(r#"{"event": "NOTE junk"}"#))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(std::net::SocketAddr::from((ip, 4000))));
+        request
+    };
+    let looping = [10, 0, 0, 1];
+
+    for _ in 0..2 {
+        assert_eq!(app.clone().oneshot(request("POST", "/events", looping)).await.unwrap().status(), StatusCode::OK);
+    }
+    let response = app.clone().oneshot(request("POST", "/events", looping)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((1..=60).contains(&retry_after), "{}", retry_after);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"]["code"], "rate_limited");
+
+    // Others can still append, and reads aren't limited
+    assert_eq!(app.clone().oneshot(request("POST", "/events", [10, 0, 0, 2])).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(app.clone().oneshot(request("GET", "/events", looping)).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(dir.path().join("master.log")).unwrap().lines().count(), 3);
+
+    let response = app.oneshot(request("GET", "/metrics", looping)).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(text.contains("\nproject_a_rate_limited_total 1\n"), "{}", text);
+    assert!(text.contains("\nproject_a_rate_limit_clients 2\n"), "{}", text);
+}
+
+#[tokio::test]
+async fn test_admin_verify_finds_a_corrupt_line() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("master.log");
+    let app = build_router(AppState::new(path.clone()));
+    let verify = || async {
+        let request = axum::http::Request::builder().uri("/admin/verify").body(axum::body::Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap())
+    };
+    assert_eq!(verify().await.0, StatusCode::NOT_FOUND);
+
+    std::fs::write(&path, "2024-01-01T09:00:00Z START THEORY pandas\n2024-01-01T10:00:00Z STOP THEORY pandas\n").unwrap();
+    let (status, report) = verify().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((report["ok"].as_bool(), report["lines"].as_u64()), (Some(true), Some(2)));
+
+    // A half-written line from a crashed editor, then a good one
+    let mut log = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    log.write_all(b"2024-01-01T11:00:00Z START PRA\x00\x00\xc3\n2024-01-01T12:00:00Z START GAME chess\n").unwrap();
+    let (_, report) = verify().await;
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["invalid_utf8"]["count"], 1);
+    assert_eq!(report["invalid_utf8"]["lines"][0]["idx"], 2);
+    assert_eq!(report["control_characters"]["count"], 0);
+    assert_eq!(report["unparseable"]["count"], 0);
+    assert_eq!(report["lines"], 4);
+}
+
+#[tokio::test]
+async fn test_alerts_without_a_log_lack_data() {
+    use tower::ServiceExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let app = build_router(AppState::new(dir.path().join("master.log")));
+    let request = axum::http::Request::builder().uri("/projections/alerts").body(axum::body::Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    let rules = body["alerts"]["rules"].as_array().unwrap();
+    assert!(!rules.is_empty());
+    assert!(rules.iter().all(|rule| rule["state"] == "insufficient_data"), "{}", body);
+    assert_eq!(body["alerts"]["firing"], serde_json::json!([]));
+}
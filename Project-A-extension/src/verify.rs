@@ -0,0 +1,145 @@
+use std::io::BufRead;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::events::{is_comment, parse_event};
+
+/// Problem lines listed per kind; the counts keep going past this
+pub const MAX_LISTED: usize = 1000;
+
+/// What GET /admin/verify found scanning the log
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyReport {
+    /// True when nothing below needs attention
+    pub ok: bool,
+    /// Non-empty lines scanned
+    pub lines: usize,
+    pub bytes: u64,
+    /// Lines that aren't events or comments, so every projection skips them
+    pub unparseable: LineIssues,
+    /// Lines that aren't valid UTF-8; everything else reads them with the
+    /// bad bytes replaced by U+FFFD
+    pub invalid_utf8: LineIssues,
+    /// Lines with control characters other than tab
+    pub control_characters: LineIssues,
+    /// The log doesn't end in a newline: its last line is still being
+    /// written, or a write was cut short
+    pub partial_last_line: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LineIssues {
+    pub count: usize,
+    /// The first MAX_LISTED, in log order
+    pub lines: Vec<LineIssue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LineIssue {
+    /// Event index, numbering non-empty lines as everywhere else
+    pub idx: usize,
+    /// Where the line starts in the file
+    pub byte_offset: u64,
+    pub detail: String,
+}
+
+impl LineIssues {
+    fn push(&mut self, idx: usize, byte_offset: u64, detail: String) {
+        self.count += 1;
+        if self.lines.len() < MAX_LISTED {
+            self.lines.push(LineIssue { idx, byte_offset, detail });
+        }
+    }
+}
+
+/// Check every line of `source`, one line in memory at a time
+pub fn verify(mut source: impl BufRead) -> std::io::Result<VerifyReport> {
+    let mut report = VerifyReport {
+        ok: true,
+        lines: 0,
+        bytes: 0,
+        unparseable: LineIssues::default(),
+        invalid_utf8: LineIssues::default(),
+        control_characters: LineIssues::default(),
+        partial_last_line: false,
+    };
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = source.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        let offset = report.bytes;
+        report.bytes += read as u64;
+        let raw = match buf.strip_suffix(b"\n") {
+            Some(raw) => raw,
+            None => {
+                report.partial_last_line = true;
+                &buf[..]
+            }
+        };
+        // Blank as the reader sees it, Unicode whitespace included
+        if String::from_utf8_lossy(raw).trim().is_empty() {
+            continue;
+        }
+        let idx = report.lines;
+        report.lines += 1;
+
+        let line = match std::str::from_utf8(raw) {
+            Ok(line) => line,
+            Err(e) => {
+                let detail = format!("invalid UTF-8 at byte {} of the line", e.valid_up_to());
+                report.invalid_utf8.push(idx, offset, detail);
+                continue;
+            }
+        };
+        if let Some((column, c)) = line.chars().enumerate().find(|(_, c)| c.is_control() && *c != '\t') {
+            report.control_characters.push(idx, offset, format!("U+{:04X} at character {}", c as u32, column));
+        }
+        if !is_comment(line) && parse_event(line).is_none() {
+            report.unparseable.push(idx, offset, "no uppercase verb".to_string());
+        }
+    }
+
+    report.ok = report.unparseable.count == 0
+        && report.invalid_utf8.count == 0
+        && report.control_characters.count == 0
+        && !report.partial_last_line;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_log() {
+        let log = "2024-01-01T09:00:00Z START THEORY pandas\n\n\u{3000}\u{A0}\n# a comment\n2024-01-01T10:00:00Z STOP THEORY pandas\tnote=tab\n";
+        let report = verify(log.as_bytes()).unwrap();
+        assert!(report.ok, "{:?}", report);
+        assert_eq!((report.lines, report.bytes), (3, log.len() as u64));
+    }
+
+    #[test]
+    fn test_corrupt_lines_reported_with_indices() {
+        let mut log = b"START THEORY pandas\nthis line has no verb\n".to_vec();
+        log.extend_from_slice(b"NOTE bad \xff\xfe bytes\n");
+        log.extend_from_slice(b"NOTE bell \x07 here\n\n");
+        log.extend_from_slice(b"START GAME ches");
+        let report = verify(&log[..]).unwrap();
+
+        assert!(!report.ok);
+        assert_eq!(report.lines, 5);
+        assert_eq!(report.unparseable.count, 1);
+        assert_eq!(report.unparseable.lines[0].idx, 1);
+        assert_eq!(report.unparseable.lines[0].byte_offset, 20);
+        assert_eq!(report.invalid_utf8.lines[0].idx, 2);
+        assert_eq!(report.invalid_utf8.lines[0].detail, "invalid UTF-8 at byte 9 of the line");
+        assert_eq!(report.control_characters.lines[0].idx, 3);
+        assert_eq!(report.control_characters.lines[0].detail, "U+0007 at character 10");
+        assert!(report.partial_last_line);
+    }
+}
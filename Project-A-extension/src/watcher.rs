@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use notify::{RecursiveMode, Watcher};
+
+/// Watch the log for external changes (e.g. the original Project-A appending)
+/// and run `on_change` whenever it changes on disk
+pub fn spawn_log_watcher(
+    log_path: PathBuf,
+    on_change: impl Fn() + Send + 'static,
+) -> notify::Result<tokio::task::JoinHandle<()>> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = tx.send(res);
+    })?;
+
+    // Watch the directory so the log being created or replaced is seen too
+    let dir = match log_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    let file_name = log_path.file_name().map(|n| n.to_os_string());
+
+    Ok(tokio::spawn(async move {
+        // Keep the watcher alive for the lifetime of the task
+        let _watcher = watcher;
+
+        while let Some(res) = rx.recv().await {
+            match res {
+                Ok(event) => {
+                    let touches_log = event
+                        .paths
+                        .iter()
+                        .any(|p| p.file_name().map(|n| n.to_os_string()) == file_name);
+                    if touches_log {
+                        on_change();
+                    }
+                }
+                Err(e) => tracing::warn!(error = %e, "Log watcher error"),
+            }
+        }
+    }))
+}
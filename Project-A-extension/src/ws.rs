@@ -0,0 +1,114 @@
+use std::net::SocketAddr;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use crate::models::Session;
+use crate::AppState;
+
+/// Idle connections are pinged this often so they survive quiet periods
+const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Messages sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Append { event: String },
+}
+
+/// Messages pushed by the server
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Event { idx: usize, line: String },
+    SessionUpdate { session: Option<Session> },
+    Error { message: String },
+}
+
+/// Bidirectional event logging and live updates
+/// Appends count against the same rate limit as POST /events
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "events",
+    responses((status = 101, description = "Switching protocols")),
+)]
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    addr: Option<ConnectInfo<SocketAddr>>,
+) -> Response {
+    let client = crate::ratelimit::client(&state, &headers, addr.map(|ConnectInfo(addr)| addr));
+    ws.on_upgrade(move |socket| handle_socket(socket, state, client))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, client: String) {
+    let mut events = state.broadcaster.subscribe();
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => handle_client_message(&state, &client, &text).await,
+                Some(Ok(Message::Binary(_))) => vec![error("Binary frames are not supported")],
+                // Pongs are answered by axum, close ends the loop below
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => Vec::new(),
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let session = state.session_projector().get_current_session();
+                    vec![
+                        ServerMessage::Event { idx: event.idx, line: event.line },
+                        ServerMessage::SessionUpdate { session },
+                    ]
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    vec![error(&format!("Missed {} events, resync via /events?since=N", skipped))]
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                Vec::new()
+            }
+        };
+
+        for message in outgoing {
+            let text = serde_json::to_string(&message).unwrap_or_default();
+            if socket.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Malformed or rate-limited messages produce an error frame, never a
+/// dropped connection
+async fn handle_client_message(state: &AppState, client: &str, text: &str) -> Vec<ServerMessage> {
+    match serde_json::from_str::<ClientMessage>(text) {
+        Ok(ClientMessage::Append { event }) => {
+            if let Err(e) = state.rate_limiter.check_append(client) {
+                return vec![error(&e.to_string())];
+            }
+            match crate::append_event(state, &event, state.clock.now()).await {
+                // The appended line comes back through the broadcaster
+                Ok(_) => Vec::new(),
+                Err(e) => vec![error(&e.to_string())],
+            }
+        }
+        Err(e) => vec![error(&format!("Invalid message: {}", e))],
+    }
+}
+
+fn error(message: &str) -> ServerMessage {
+    ServerMessage::Error {
+        message: message.to_string(),
+    }
+}